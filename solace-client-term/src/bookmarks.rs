@@ -0,0 +1,157 @@
+use crossterm::event;
+use solace_protocol::bookmark::Bookmark;
+
+use crate::color::hex_to_rgb;
+use crate::config;
+use crate::overlay::{draw_box, draw_line, Overlay, OverlayAction};
+use crate::timestamp;
+use crate::{CellStyle, Rect, RenderBuffer, Renderable};
+
+/// The bookmarks the server keeps for the logged in account, or the
+/// mentions missed while away, newest last, jumping to the selected one in
+/// the chat history on Enter.
+#[derive(Debug)]
+pub(crate) struct Bookmarks {
+    title: &'static str,
+    bookmarks: Vec<Bookmark>,
+    selected: usize,
+}
+
+impl Bookmarks {
+    pub(crate) fn new(bookmarks: Vec<Bookmark>) -> Self {
+        Self {
+            title: "Bookmarks",
+            // Newest at the bottom, where the selection starts
+            selected: bookmarks.len().saturating_sub(1),
+            bookmarks,
+        }
+    }
+
+    pub(crate) fn mentions(mentions: Vec<Bookmark>) -> Self {
+        Self {
+            title: "Mentions",
+            ..Self::new(mentions)
+        }
+    }
+
+    fn format(bookmark: &Bookmark) -> String {
+        let sent = timestamp::format_absolute(bookmark.timestamp, &config::current().layout);
+
+        format!(
+            "#{}  {sent}  @{}  {}",
+            bookmark.message_id, bookmark.nick, bookmark.message
+        )
+    }
+}
+
+impl Renderable for Bookmarks {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = &config::current().colors;
+
+        draw_box(buf, rect, self.title, colors);
+
+        // Borders, a blank line and the hint take up the rest
+        let visible = rect.height.saturating_sub(4) as usize;
+        let scroll = (self.selected + 1).saturating_sub(visible);
+
+        for (row, bookmark) in self.bookmarks.iter().enumerate().skip(scroll).take(visible) {
+            let (fg, cell_style) = if row == self.selected {
+                (hex_to_rgb(&colors.user_name), CellStyle::Bold)
+            } else {
+                (hex_to_rgb(&colors.fg), CellStyle::Normal)
+            };
+
+            draw_line(
+                buf,
+                rect,
+                rect.y + 1 + (row - scroll) as u16,
+                &Self::format(bookmark),
+                fg,
+                cell_style,
+                colors,
+            );
+        }
+
+        draw_line(
+            buf,
+            rect,
+            (rect.y + rect.height).saturating_sub(2),
+            "Enter to jump to it, Esc to close",
+            hex_to_rgb(&colors.server_message),
+            CellStyle::Italic,
+            colors,
+        );
+    }
+}
+
+impl Overlay for Bookmarks {
+    fn rect(&self, screen: &Rect) -> Rect {
+        let height = self.bookmarks.len() as u16 + 4;
+
+        screen.centered(72, height.min(screen.height.saturating_sub(2)))
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> OverlayAction {
+        match key.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => OverlayAction::Close,
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+                OverlayAction::Stay
+            }
+            event::KeyCode::Down | event::KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.bookmarks.len().saturating_sub(1));
+                OverlayAction::Stay
+            }
+            event::KeyCode::Enter => match self.bookmarks.get(self.selected) {
+                Some(bookmark) => OverlayAction::JumpTo(bookmark.message_id),
+                None => OverlayAction::Close,
+            },
+            _ => OverlayAction::Stay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: event::KeyCode) -> event::KeyEvent {
+        event::KeyEvent::new(code, event::KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut bookmarks = Bookmarks::new(
+            [3, 8, 21]
+                .into_iter()
+                .map(|message_id| Bookmark {
+                    message_id,
+                    ..Bookmark::default()
+                })
+                .collect(),
+        );
+
+        assert_eq!(
+            bookmarks.handle_key(key(event::KeyCode::Enter)),
+            OverlayAction::JumpTo(21)
+        );
+
+        for _ in 0..5 {
+            bookmarks.handle_key(key(event::KeyCode::Char('k')));
+        }
+        assert_eq!(
+            bookmarks.handle_key(key(event::KeyCode::Enter)),
+            OverlayAction::JumpTo(3)
+        );
+
+        bookmarks.handle_key(key(event::KeyCode::Down));
+        assert_eq!(
+            bookmarks.handle_key(key(event::KeyCode::Enter)),
+            OverlayAction::JumpTo(8)
+        );
+        assert_eq!(
+            bookmarks.handle_key(key(event::KeyCode::Esc)),
+            OverlayAction::Close
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    fs,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use crossterm::{cursor, event, terminal, QueueableCommand};
+use futures::{future::FutureExt, SinkExt, StreamExt};
+use solace_protocol::{
+    code::RES_PONG,
+    codec::FrameCodec,
+    request::{Request, RequestMessage},
+    response::Response,
+};
+use tokio::{io::split, sync::mpsc, time};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use crate::color::hex_to_rgb;
+use crate::config;
+use crate::overlay::{draw_box, draw_line};
+use crate::transport;
+use crate::{CellStyle, Flushable, Rect, RenderBuffer, Renderable, Screen};
+
+const RECENT_SERVERS_FILE: &str = "recent_servers";
+const MAX_RECENT_SERVERS: usize = 8;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Servers most recently connected to, newest first, or none if they can't
+/// be read as remembering them is only a convenience.
+pub(crate) fn recent_servers() -> Vec<String> {
+    let Ok(base_path) = xdg::BaseDirectories::with_prefix("solace") else {
+        return Vec::new();
+    };
+
+    base_path
+        .find_state_file(RECENT_SERVERS_FILE)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|raw| raw.lines().map(str::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// Moves `server` to the top of the recent servers.
+pub(crate) fn remember(server: &str) -> anyhow::Result<()> {
+    let base_path = xdg::BaseDirectories::with_prefix("solace")
+        .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+    let path = base_path
+        .place_state_file(RECENT_SERVERS_FILE)
+        .with_context(|| "ERROR: Couldn't create the state directory")?;
+    let recent = push_recent(recent_servers(), server);
+
+    fs::write(&path, recent.join("\n"))
+        .with_context(|| format!("ERROR: Failed to write {path:?}"))?;
+
+    Ok(())
+}
+
+fn push_recent(mut recent: Vec<String>, server: &str) -> Vec<String> {
+    recent.retain(|s| s != server);
+    recent.insert(0, server.to_owned());
+    recent.truncate(MAX_RECENT_SERVERS);
+
+    recent
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Status {
+    Probing,
+    Online(Duration),
+    Offline,
+}
+
+/// Whether `server` answers a ping, and how long it took to.
+async fn probe(server: &str) -> Status {
+    let started = Instant::now();
+
+    match time::timeout(PROBE_TIMEOUT, ping(server)).await {
+        Ok(Ok(())) => Status::Online(started.elapsed()),
+        _ => Status::Offline,
+    }
+}
+
+async fn ping(server: &str) -> anyhow::Result<()> {
+    let (reader, writer) = split(transport::connect(server, config!(tls)).await?);
+    let mut req = FramedWrite::new(writer, FrameCodec::<Request>::default());
+    let mut res = FramedRead::new(reader, FrameCodec::<Response>::default());
+
+    req.send(Request::new(rand::random::<u32>(), RequestMessage::Ping))
+        .await?;
+
+    while let Some(response) = res.next().await {
+        if response?.code == RES_PONG {
+            req.send(Request::new(
+                rand::random::<u32>(),
+                RequestMessage::Disconnect,
+            ))
+            .await?;
+
+            return Ok(());
+        }
+    }
+
+    anyhow::bail!("Connection closed before the pong")
+}
+
+#[derive(Debug)]
+struct Entry {
+    server: String,
+    is_recent: bool,
+    status: Status,
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Browsing,
+    Connect(String),
+    Quit,
+}
+
+/// Lists the servers from the config followed by any others used recently,
+/// shown on startup when the config doesn't name a server to connect to.
+#[derive(Debug)]
+struct Browser {
+    entries: Vec<Entry>,
+    selected: usize,
+}
+
+impl Browser {
+    fn new(configured: &[String], recent: &[String]) -> Self {
+        let mut entries = configured
+            .iter()
+            .map(|server| (server, false))
+            .chain(
+                recent
+                    .iter()
+                    .filter(|server| !configured.contains(server))
+                    .map(|server| (server, true)),
+            )
+            .map(|(server, is_recent)| Entry {
+                server: server.clone(),
+                is_recent,
+                status: Status::Probing,
+            })
+            .collect::<Vec<Entry>>();
+
+        if entries.is_empty() {
+            entries.push(Entry {
+                server: config::default_server(),
+                is_recent: false,
+                status: Status::Probing,
+            });
+        }
+
+        Self {
+            entries,
+            selected: 0,
+        }
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> Outcome {
+        match key.code {
+            event::KeyCode::Char('c') if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
+                Outcome::Quit
+            }
+            event::KeyCode::Esc | event::KeyCode::Char('q') => Outcome::Quit,
+            event::KeyCode::Enter => Outcome::Connect(self.entries[self.selected].server.clone()),
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+                Outcome::Browsing
+            }
+            event::KeyCode::Down | event::KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.entries.len() - 1);
+                Outcome::Browsing
+            }
+            _ => Outcome::Browsing,
+        }
+    }
+
+    fn rect(&self, screen: &Rect) -> Rect {
+        screen.centered(64, self.entries.len() as u16 + 6)
+    }
+}
+
+impl Renderable for Browser {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = &config::current().colors;
+        let hint = hex_to_rgb(&colors.server_message);
+        let server_width = self
+            .entries
+            .iter()
+            .map(|entry| entry.server.len() + if entry.is_recent { 9 } else { 0 })
+            .max()
+            .unwrap_or(0)
+            .min(40);
+
+        draw_box(buf, rect, "Servers", colors);
+        draw_line(
+            buf,
+            rect,
+            rect.y + 1,
+            "Pick a server to connect to",
+            hex_to_rgb(&colors.fg),
+            CellStyle::Normal,
+            colors,
+        );
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            let y = rect.y + 3 + i as u16;
+
+            if y + 3 >= rect.y + rect.height {
+                break;
+            }
+
+            let is_selected = i == self.selected;
+            let marker = if is_selected { '>' } else { ' ' };
+            let name = if entry.is_recent {
+                format!("{} (recent)", entry.server)
+            } else {
+                entry.server.clone()
+            };
+            let (status, fg) = match entry.status {
+                Status::Probing => ("...".to_owned(), hint),
+                Status::Online(latency) => (
+                    format!("online {}ms", latency.as_millis()),
+                    hex_to_rgb(&colors.fg),
+                ),
+                Status::Offline => ("offline".to_owned(), hint),
+            };
+            let cell_style = if is_selected {
+                CellStyle::Bold
+            } else {
+                CellStyle::Normal
+            };
+
+            draw_line(
+                buf,
+                rect,
+                y,
+                &format!("{marker} {name:<server_width$}  {status}"),
+                fg,
+                cell_style,
+                colors,
+            );
+        }
+
+        draw_line(
+            buf,
+            rect,
+            rect.y + rect.height.saturating_sub(2),
+            "Up/Down to pick, Enter to connect, Esc to quit",
+            hint,
+            CellStyle::Italic,
+            colors,
+        );
+    }
+}
+
+/// Shows the server browser until a server is picked, returning `None` if
+/// the user quit instead.
+pub(crate) async fn run() -> anyhow::Result<Option<String>> {
+    let mut stdout = io::stdout();
+    let mut size = terminal::size()?;
+    let mut browser = Browser::new(&config::current().servers, &recent_servers());
+    let mut reader = event::EventStream::new();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let _screen = Screen::start(&mut stdout)?;
+
+    for (i, entry) in browser.entries.iter().enumerate() {
+        let tx = tx.clone();
+        let server = entry.server.clone();
+
+        tokio::spawn(async move {
+            // The browser may already be gone by the time a probe finishes
+            let _ = tx.send((i, probe(&server).await));
+        });
+    }
+
+    loop {
+        let screen = Rect {
+            x: 0,
+            y: 0,
+            width: size.0,
+            height: size.1,
+        };
+        let mut buf = RenderBuffer::new(size.0, size.1);
+
+        browser.render_into(&mut buf, &browser.rect(&screen));
+        buf.render_to(&mut stdout)?;
+        stdout.queue(cursor::Hide)?.flush()?;
+
+        tokio::select! {
+            Some((i, status)) = rx.recv() => browser.entries[i].status = status,
+            maybe_event = reader.next().fuse() => {
+                let Some(Ok(event)) = maybe_event else {
+                    return Ok(None);
+                };
+
+                match event {
+                    event::Event::Resize(width, height) => size = (width, height),
+                    event::Event::Key(key) => match browser.handle_key(key) {
+                        Outcome::Browsing => (),
+                        Outcome::Connect(server) => return Ok(Some(server)),
+                        Outcome::Quit => return Ok(None),
+                    },
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn servers(servers: &[&str]) -> Vec<String> {
+        servers.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_push_recent_moves_to_top() {
+        let recent = push_recent(servers(&["a:1", "b:2", "c:3"]), "b:2");
+        assert_eq!(recent, servers(&["b:2", "a:1", "c:3"]));
+
+        let full = (0..MAX_RECENT_SERVERS)
+            .map(|i| format!("s:{i}"))
+            .collect::<Vec<String>>();
+        let recent = push_recent(full, "new:1");
+        assert_eq!(recent.len(), MAX_RECENT_SERVERS);
+        assert_eq!(recent[0], "new:1");
+    }
+
+    #[test]
+    fn test_configured_servers_come_first() {
+        let browser = Browser::new(&servers(&["a:1", "b:2"]), &servers(&["b:2", "c:3"]));
+        let listed = browser
+            .entries
+            .iter()
+            .map(|entry| (entry.server.as_str(), entry.is_recent))
+            .collect::<Vec<_>>();
+
+        assert_eq!(listed, vec![("a:1", false), ("b:2", false), ("c:3", true)]);
+    }
+
+    #[test]
+    fn test_pick_with_arrows() {
+        let mut browser = Browser::new(&servers(&["a:1", "b:2"]), &[]);
+        let key = |code| event::KeyEvent::new(code, event::KeyModifiers::NONE);
+
+        browser.handle_key(key(event::KeyCode::Down));
+        browser.handle_key(key(event::KeyCode::Down));
+        assert_eq!(
+            browser.handle_key(key(event::KeyCode::Enter)),
+            Outcome::Connect("b:2".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_snapshot_browser() {
+        let mut browser = Browser::new(
+            &servers(&["chat.example.com:7878"]),
+            &servers(&["0.0.0.0:7878"]),
+        );
+        browser.entries[0].status = Status::Online(Duration::from_millis(12));
+        browser.entries[1].status = Status::Offline;
+
+        insta::assert_snapshot!(crate::RenderBuffer::snapshot(&browser, 64, 8));
+    }
+}
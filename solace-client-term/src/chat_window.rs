@@ -1,19 +1,66 @@
-use crossterm::style;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::time::Duration;
+
+use crossterm::{event, style};
+use futures::future;
 use futures::sink::SinkExt;
-use solace_message_parser::{parse, AstMessage, AstNode};
+use solace_message_parser::{
+    parse, parse_with_options, AstMessage, AstNode, InlineStyle, MentionPolicy, ParserOptions,
+};
+use solace_protocol::attachment::{self, Attachment};
+use solace_protocol::bookmark::Bookmark;
+use solace_protocol::build_info::BuildInfo;
+use solace_protocol::capability;
+use solace_protocol::channel::{ChannelMode, ChannelText};
 use solace_protocol::code::{
-    RES_ACK_MESSAGE, RES_COMMAND_LIST, RES_NICK_LIST, RES_TOPIC_CHANGE, RES_YOUR_NICK,
+    ERR_MESSAGE_TOO_LONG, ERR_RATE_LIMITED, ERR_WRONG_PASSWORD, RES_ACK_MESSAGE, RES_ATTACHMENT,
+    RES_BOOKMARK_LIST, RES_BUILD_INFO, RES_CHANNEL_MEMBERS, RES_CHANNEL_MESSAGE, RES_CHANNEL_MODE,
+    RES_CHANNEL_NOTICE, RES_CHANNEL_TOPIC, RES_CHAT_MESSAGE_OK, RES_COMMAND_LIST,
+    RES_DIRECT_MESSAGE, RES_EVERYONE_MENTIONED, RES_FRAME_HMAC, RES_GOODBYE, RES_HELLO, RES_JOINED,
+    RES_LINK_PREVIEW, RES_LOGGED_IN, RES_MENTIONED, RES_MENTION_POLICY, RES_MESSAGE_LIMIT,
+    RES_MESSAGE_SENT, RES_MISSED_MENTIONS, RES_MOTD, RES_NICK_CHANGE, RES_NICK_LIST, RES_PARTED,
+    RES_PRESENCE, RES_READ_RECEIPT, RES_SELF_DIRECT_MESSAGE, RES_SELF_MESSAGE, RES_TIP,
+    RES_TOPIC_CHANGE, RES_UPLOADED, RES_YOUR_NICK,
 };
-use solace_protocol::request::RequestMessage;
+use solace_protocol::codec::{FrameCodec, Integrity};
+use solace_protocol::command::CommandSpec;
+use solace_protocol::level::Level;
+use solace_protocol::link_preview::LinkPreview;
+use solace_protocol::presence::{NickListEntry, Presence};
+use solace_protocol::request::{RequestMessage, Secret};
+use solace_protocol::version;
 use solace_protocol::{request::Request, response::Response};
-use tokio::io::{split, ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
+use tokio::io::{split, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::time::Instant;
 use tokio_stream::StreamExt;
 use tokio_util::codec::{FramedRead, FramedWrite};
 
-use crate::{config_hex_color, prompt::Prompt, CellStyle, Rect, Renderable};
+use crate::bookmarks::Bookmarks;
+use crate::browser;
+use crate::clipboard;
+use crate::config::{Alignment, Layout};
+use crate::connection::{self, ConnStats, Reconnect};
+use crate::credentials::{self, Credentials};
+use crate::emoji::{self, EmojiPicker};
+use crate::errors;
+use crate::export;
+use crate::help::Help;
+use crate::inspector::Inspector;
+use crate::layout::{Composite, Constraint, Direction, Frame};
+use crate::notify::{self, Reason, MAIN_BUFFER};
+use crate::opener;
+use crate::overlay::{Confirm, Overlay, OverlayAction, Password};
+use crate::paste;
+use crate::timestamp;
+use crate::transport::{self, Connected, Stream};
+use crate::{
+    config, config_hex_color, log, prompt::Prompt, str_width, CellStyle, Rect, Renderable,
+};
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct ChatHistoryPartStyle {
     fg: style::Color,
     bg: style::Color,
@@ -26,140 +73,485 @@ impl ChatHistoryPartStyle {
     }
 }
 
-#[derive(Debug)]
-struct ChatHistoryPart(String, ChatHistoryPartStyle);
+/// Text stored as a single `String` with styles applied to byte ranges of
+/// it, rather than a `String` per styled part, which keeps long sessions
+/// small and lets the whole message be searched or wrapped as one.
+///
+/// Adjacent runs with the same style are merged as they are pushed.
+#[derive(Debug, Default)]
+struct StyledText {
+    text: String,
+    runs: Vec<(Range<usize>, ChatHistoryPartStyle)>,
+}
 
-impl ChatHistoryPart {
-    fn new(text: String, style: ChatHistoryPartStyle) -> Self {
-        Self(text, style)
+impl StyledText {
+    fn push(&mut self, text: &str, style: ChatHistoryPartStyle) {
+        let start = self.text.len();
+        self.text.push_str(text);
+
+        match self.runs.last_mut() {
+            Some((range, last)) if *last == style => range.end = self.text.len(),
+            _ => self.runs.push((start..self.text.len(), style)),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&str, &ChatHistoryPartStyle)> {
+        self.runs
+            .iter()
+            .map(|(range, style)| (&self.text[range.clone()], style))
     }
 }
 
 /// # Fields
 ///
 /// - `id`: An optional `u32` representing a unique identifier for the message.
-///    Will only be set on outbound messages and is used to reconcile with acks
-///    from the server to show in the UI that the message is pending/sent.
-///    `is_confirmed`: Has the server acked the message sent with this `id`?
+///   Will only be set on outbound messages and is used to reconcile with acks
+///   from the server to show in the UI that the message is pending/sent.
+/// - `is_confirmed`: Has the server acked the message sent with this `id`?
+/// - `is_from_bot`: The author is a bot account, badged in the author gutter.
+/// - `message_id`: The server's id for a chat message, see
+///   `Response::message_id`, which `/bookmark` takes.
+/// - `is_card`: A row of a link preview for the message with `message_id`,
+///   indented past the gutters so that it sits under the message.
+/// - `body`: The body of the message only, the timestamp and author gutters
+///   are laid out at render time so that they follow the current config.
+/// - `raw`: The text of the message as it was sent or received.
+/// - `created_at`: Unix timestamp of when the entry was added, i.e. when our
+///   own messages were sent and when others' were received.
+/// - `acked_at`: Unix timestamp of when the server acked our message.
+/// - `received`: The response the entry was shown for, if any.
+/// - `membership`: Set for joins, parts and nick changes, and for the
+///   summaries they are collapsed into.
+/// - `collapsed`: The entries this summary stands in for, until expanded.
+/// - `read_by`: Who the server last said has read our message.
+/// - `mentions_me`: Someone else's message which mentions our nick, drawn
+///   in `colors.mention_highlight` from edge to edge and kept for
+///   `/mentions`.
 #[derive(Debug)]
 struct ChatHistoryEntry {
     author: Option<String>,
+    body: StyledText,
     id: Option<u32>,
     is_confirmed: bool,
-    parts: Vec<ChatHistoryPart>,
+    is_from_bot: bool,
+    message_id: Option<u64>,
+    is_card: bool,
     timestamp: String,
+    raw: String,
+    created_at: u64,
+    acked_at: Option<u64>,
+    received: Option<Received>,
+    membership: Option<Membership>,
+    collapsed: Vec<ChatHistoryEntry>,
+    read_by: Vec<String>,
+    mentions_me: bool,
+}
+
+/// Comings and goings in the channel, which are collapsed into one line
+/// when there are enough in a row to drown out the conversation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Membership {
+    Join,
+    Part,
+    NickChange,
+}
+
+/// What came with a response shown in the chat history, kept for the
+/// inspector.
+///
+/// # Fields
+///
+/// - `timestamp`: Unix timestamp of when the server sent it.
+#[derive(Clone, Debug)]
+struct Received {
+    code: u16,
+    request_id: u32,
+    origin: String,
+    timestamp: u64,
 }
 
 impl ChatHistoryEntry {
     fn new(ast: AstMessage, author: Option<String>, timestamp: String, id: Option<u32>) -> Self {
-        let mut parts = Self::prefix(timestamp.clone(), author.clone());
-        parts.extend(Self::parts_for_ast(&ast, &author));
+        let body = Self::body_for_ast(&ast, &author);
 
         Self {
             author,
+            body,
             id,
             is_confirmed: false,
+            is_from_bot: false,
+            message_id: None,
+            is_card: false,
             timestamp,
-            parts,
+            raw: String::new(),
+            created_at: timestamp::unix_now(),
+            acked_at: None,
+            received: None,
+            membership: None,
+            collapsed: Vec::new(),
+            read_by: Vec::new(),
+            mentions_me: false,
+        }
+    }
+
+    /// A row of a link preview for `message`, with `text` after a bar in
+    /// the style given.
+    fn card(message: &ChatHistoryEntry, text: &str, style: ChatHistoryPartStyle) -> Self {
+        let mut body = StyledText::default();
+        body.push(
+            "│ ",
+            ChatHistoryPartStyle::new(
+                config_hex_color!(colors.server_message),
+                style::Color::Reset,
+                crate::CellStyle::Normal,
+            ),
+        );
+        body.push(text, style);
+
+        Self {
+            author: None,
+            body,
+            id: None,
+            is_confirmed: true,
+            is_from_bot: false,
+            message_id: message.message_id,
+            is_card: true,
+            timestamp: message.timestamp.clone(),
+            raw: text.to_owned(),
+            created_at: timestamp::unix_now(),
+            acked_at: None,
+            received: message.received.clone(),
+            membership: None,
+            collapsed: Vec::new(),
+            read_by: Vec::new(),
+            mentions_me: false,
         }
     }
 
     fn error(msg: &str) -> Self {
-        let timestamp = chrono::Utc::now().format("%H:%M:%S").to_string();
-        let mut parts = Self::prefix(timestamp.clone(), None);
-        parts.push(ChatHistoryPart::new(
-            msg.to_owned(),
+        let timestamp = timestamp::now(&config::current().layout);
+        let mut body = StyledText::default();
+        body.push(
+            msg,
             ChatHistoryPartStyle {
                 fg: config_hex_color!(colors.error_fg),
                 bg: config_hex_color!(colors.error_bg),
                 attr: crate::CellStyle::Bold,
             },
-        ));
+        );
 
         Self {
             author: None,
+            body,
             id: None,
             is_confirmed: true,
-            parts,
+            is_from_bot: false,
+            message_id: None,
+            is_card: false,
             timestamp,
+            raw: msg.to_owned(),
+            created_at: timestamp::unix_now(),
+            acked_at: None,
+            received: None,
+            membership: None,
+            collapsed: Vec::new(),
+            read_by: Vec::new(),
+            mentions_me: false,
         }
     }
 
-    fn prefix(timestamp: String, author: Option<String>) -> Vec<ChatHistoryPart> {
-        vec![
-            Self::part_from_timestamp(timestamp.clone()),
-            Self::part_from_author(author.clone()),
+    /// A line of the server's message of the day, set apart from what
+    /// people say by a bar down its side.
+    fn motd(line: &str) -> Self {
+        let timestamp = timestamp::now(&config::current().layout);
+        let style = ChatHistoryPartStyle::new(
+            config_hex_color!(colors.server_message),
+            style::Color::Reset,
+            crate::CellStyle::Italic,
+        );
+        let mut body = StyledText::default();
+        body.push(
+            "┃ ",
+            ChatHistoryPartStyle {
+                attr: crate::CellStyle::Bold,
+                ..style
+            },
+        );
+        body.push(line, style);
+
+        Self {
+            author: None,
+            body,
+            id: None,
+            is_confirmed: true,
+            is_from_bot: false,
+            message_id: None,
+            is_card: false,
+            timestamp,
+            raw: line.to_owned(),
+            created_at: timestamp::unix_now(),
+            acked_at: None,
+            received: None,
+            membership: None,
+            collapsed: Vec::new(),
+            read_by: Vec::new(),
+            mentions_me: false,
+        }
+    }
+
+    /// One line standing in for `events`, counting each sort of event.
+    fn summary(events: Vec<ChatHistoryEntry>) -> Self {
+        let counts = [
+            (Membership::Join, "join"),
+            (Membership::Part, "part"),
+            (Membership::NickChange, "nick change"),
         ]
+        .into_iter()
+        .filter_map(|(membership, name)| {
+            let count = events
+                .iter()
+                .filter(|event| event.membership == Some(membership))
+                .count();
+            let plural = if count == 1 { "" } else { "s" };
+
+            (count > 0).then(|| format!("{count} {name}{plural}"))
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+
+        let last = events.last();
+        let mut summary = Self::new(
+            parse(&counts),
+            None,
+            last.map(|event| event.timestamp.clone())
+                .unwrap_or_default(),
+            None,
+        );
+        summary.is_confirmed = true;
+        summary.raw = events
+            .iter()
+            .map(|event| event.raw.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        summary.received = last.and_then(|event| event.received.clone());
+        summary.membership = last.and_then(|event| event.membership);
+        summary.collapsed = events;
+
+        summary
     }
 
-    fn part_from_timestamp(timestamp: String) -> ChatHistoryPart {
-        ChatHistoryPart::new(
-            format!(" {timestamp} "),
-            ChatHistoryPartStyle::new(
-                config_hex_color!(colors.timestamp_fg),
-                config_hex_color!(colors.timestamp_bg),
-                crate::CellStyle::Bold,
+    /// Labelled details for the inspector, in the configured timezone.
+    fn details(&self, layout: &Layout) -> Vec<(&'static str, String)> {
+        let at = |timestamp: Option<u64>| {
+            timestamp.map_or("--".to_owned(), |t| timestamp::format_absolute(t, layout))
+        };
+        let is_ours = self.id.is_some();
+        let from = match (&self.received, &self.author) {
+            (Some(received), _) if !received.origin.is_empty() => received.origin.clone(),
+            (_, Some(author)) => author.clone(),
+            _ => "--".to_owned(),
+        };
+
+        vec![
+            ("From", from),
+            (
+                "Code",
+                self.received
+                    .as_ref()
+                    .map_or("--".to_owned(), |r| r.code.to_string()),
+            ),
+            (
+                "Message id",
+                self.message_id
+                    .map_or("--".to_owned(), |id| format!("#{id}")),
+            ),
+            (
+                "Request id",
+                self.id
+                    .or(self.received.as_ref().map(|r| r.request_id))
+                    .filter(|&id| id != 0)
+                    .map_or("--".to_owned(), |id| id.to_string()),
+            ),
+            (
+                "Sent",
+                at(if is_ours {
+                    Some(self.created_at)
+                } else {
+                    self.received.as_ref().map(|r| r.timestamp)
+                }),
             ),
-        )
+            ("Acked", at(self.acked_at)),
+            ("Received", at(Some(self.created_at).filter(|_| !is_ours))),
+            (
+                "Read by",
+                match self.read_by.is_empty() {
+                    true => "--".to_owned(),
+                    false => self.read_by.join(", "),
+                },
+            ),
+        ]
     }
 
-    fn part_from_author(author: Option<String>) -> ChatHistoryPart {
-        const MAX_AUTHOR_LENGTH: usize = 16;
+    fn prefix(&self, layout: &Layout) -> StyledText {
+        let mut prefix = StyledText::default();
 
-        let formatted_part = if let Some(author) = author.clone() {
-            let truncated_author = if author.len() > MAX_AUTHOR_LENGTH {
-                &author[..MAX_AUTHOR_LENGTH]
-            } else {
-                author.as_str()
-            };
-            format!(" {:>17} ", format!("@{truncated_author}"))
-        } else {
-            format!(" {:>17} ", "--")
-        };
+        if layout.show_timestamps {
+            let padding = " ".repeat(layout.gutter_padding);
+
+            prefix.push(
+                &format!("{padding}{}{padding}", self.timestamp),
+                ChatHistoryPartStyle::new(
+                    config_hex_color!(colors.timestamp_fg),
+                    config_hex_color!(colors.timestamp_bg),
+                    crate::CellStyle::Bold,
+                ),
+            );
+        }
+
+        if let Some(message_id) = self.message_id.filter(|_| layout.show_message_ids) {
+            prefix.push(
+                &format!("#{message_id} "),
+                ChatHistoryPartStyle::new(
+                    config_hex_color!(colors.server_message),
+                    style::Color::Reset,
+                    crate::CellStyle::Normal,
+                ),
+            );
+        }
 
-        ChatHistoryPart::new(
-            formatted_part,
+        prefix.push(
+            &Self::format_author(self.author.as_deref(), self.is_from_bot, layout),
             ChatHistoryPartStyle::new(
-                if author.is_some() {
+                if self.author.is_some() {
                     config_hex_color!(colors.user_name)
                 } else {
                     config_hex_color!(colors.server_message)
                 },
                 style::Color::Reset,
-                if author.is_some() {
+                if self.author.is_some() {
                     crate::CellStyle::Bold
                 } else {
                     crate::CellStyle::Normal
                 },
             ),
-        )
+        );
+
+        if self.is_card {
+            // Blank, but just as wide as the message's own gutters
+            let mut blank = StyledText::default();
+            blank.push(
+                &" ".repeat(str_width(&prefix.text) as usize),
+                ChatHistoryPartStyle::new(
+                    style::Color::Reset,
+                    style::Color::Reset,
+                    crate::CellStyle::Normal,
+                ),
+            );
+
+            return blank;
+        }
+
+        prefix
+    }
+
+    fn format_author(author: Option<&str>, is_from_bot: bool, layout: &Layout) -> String {
+        // The badge takes the place of the end of a long nick, so that the
+        // gutter stays the same width
+        let badge = if is_from_bot { "[bot] " } else { "" };
+        let label = match author {
+            Some(author) => format!(
+                "{badge}@{}",
+                author
+                    .chars()
+                    .take(layout.nick_width.saturating_sub(badge.len()))
+                    .collect::<String>()
+            ),
+            None => "--".to_owned(),
+        };
+        // Room for the `@` marker on top of the nick itself
+        let width = layout.nick_width + 1;
+        let padding = " ".repeat(layout.gutter_padding);
+
+        match layout.nick_alignment {
+            Alignment::Left => format!("{padding}{label:<width$}{padding}"),
+            Alignment::Right => format!("{padding}{label:>width$}{padding}"),
+        }
+    }
+
+    fn render_row(
+        &self,
+        buf: &mut crate::RenderBuffer,
+        x0: u16,
+        y: u16,
+        width: u16,
+        layout: &Layout,
+        is_selected: bool,
+    ) {
+        let mut x = x0;
+
+        let prefix = self.prefix(layout);
+
+        for (text, part_style) in prefix.iter().chain(self.body.iter()) {
+            for ch in text.chars() {
+                if x >= x0 + width {
+                    return;
+                }
+
+                let bg = if is_selected {
+                    config_hex_color!(colors.timestamp_bg)
+                } else if self.mentions_me {
+                    config_hex_color!(colors.mention_highlight)
+                } else if !self.is_confirmed && self.id.is_some() {
+                    // @TODO: Generate unconfirmed colors
+                    style::Color::Reset
+                } else {
+                    part_style.bg
+                };
+                let fg = if !self.is_confirmed && self.id.is_some() {
+                    style::Color::Black
+                } else {
+                    part_style.fg
+                };
+
+                x += buf.put_at(x, y, ch, bg, fg, part_style.attr);
+            }
+        }
+
+        if self.mentions_me && !is_selected {
+            let bg = config_hex_color!(colors.mention_highlight);
+            while x < x0 + width {
+                x += buf.put_at(x, y, ' ', bg, style::Color::Reset, crate::CellStyle::Normal);
+            }
+        }
     }
 
-    fn parts_for_ast(ast: &AstMessage, author: &Option<String>) -> Vec<ChatHistoryPart> {
+    fn body_for_ast(ast: &AstMessage, author: &Option<String>) -> StyledText {
+        let mut body = StyledText::default();
+
         match ast {
             AstMessage::Command(command) => match command {
                 AstNode::Command { args, .. } => {
-                    let mut parts = vec![Self::part_for_node(command.clone(), author)];
-                    parts.extend(
-                        args.iter()
-                            .map(|n| Self::part_for_node(n.clone(), author))
-                            .collect::<Vec<ChatHistoryPart>>(),
-                    );
+                    Self::push_node(&mut body, command, author);
 
-                    parts
+                    for arg in args {
+                        Self::push_node(&mut body, arg, author);
+                    }
                 }
                 _ => unreachable!(),
             },
-            AstMessage::Normal(nodes) => nodes
-                .iter()
-                .map(|n| Self::part_for_node(n.clone(), author))
-                .collect::<Vec<ChatHistoryPart>>(),
+            AstMessage::Normal(nodes) => {
+                for node in nodes {
+                    Self::push_node(&mut body, node, author);
+                }
+            }
         }
+
+        body
     }
 
-    fn part_for_node(node: AstNode, author: &Option<String>) -> ChatHistoryPart {
+    fn push_node(body: &mut StyledText, node: &AstNode, author: &Option<String>) {
         match node {
-            AstNode::Command { raw_name, .. } => ChatHistoryPart::new(
+            AstNode::Command { raw_name, .. } => body.push(
                 raw_name,
                 ChatHistoryPartStyle::new(
                     config_hex_color!(colors.command),
@@ -167,7 +559,7 @@ impl ChatHistoryEntry {
                     crate::CellStyle::Bold,
                 ),
             ),
-            AstNode::UserMention { raw_user_name, .. } => ChatHistoryPart::new(
+            AstNode::UserMention { raw_user_name, .. } => body.push(
                 raw_user_name,
                 ChatHistoryPartStyle::new(
                     config_hex_color!(colors.user_mention),
@@ -175,9 +567,18 @@ impl ChatHistoryEntry {
                     crate::CellStyle::Bold,
                 ),
             ),
+            // Stands out more than a mention of one nick, as it pinged everyone
+            AstNode::EveryoneMention { raw_name, .. } => body.push(
+                raw_name,
+                ChatHistoryPartStyle::new(
+                    config_hex_color!(colors.error_fg),
+                    config_hex_color!(colors.error_bg),
+                    crate::CellStyle::Bold,
+                ),
+            ),
             AstNode::ChannelMention {
                 raw_channel_name, ..
-            } => ChatHistoryPart::new(
+            } => body.push(
                 raw_channel_name,
                 ChatHistoryPartStyle::new(
                     config_hex_color!(colors.channel_mention),
@@ -185,20 +586,63 @@ impl ChatHistoryEntry {
                     crate::CellStyle::Bold,
                 ),
             ),
-            AstNode::Text { value, .. } => ChatHistoryPart::new(
-                value,
+            AstNode::Text { value: text, .. } | AstNode::Quoted { raw_text: text, .. } => body
+                .push(
+                    text,
+                    ChatHistoryPartStyle::new(
+                        if author.is_some() {
+                            config_hex_color!(colors.message)
+                        } else {
+                            config_hex_color!(colors.server_message)
+                        },
+                        style::Color::Reset,
+                        crate::CellStyle::Normal,
+                    ),
+                ),
+            AstNode::Link { url, .. } => body.push(
+                url,
                 ChatHistoryPartStyle::new(
-                    if author.is_some() {
-                        config_hex_color!(colors.message)
-                    } else {
-                        config_hex_color!(colors.server_message)
-                    },
+                    config_hex_color!(colors.message),
                     style::Color::Reset,
-                    crate::CellStyle::Normal,
+                    crate::CellStyle::Underlined,
                 ),
             ),
-            AstNode::Whitespace { span } => ChatHistoryPart::new(
-                " ".repeat(span.len()),
+            // The markers are left out, the style shows where they were
+            AstNode::Formatted {
+                style: inline_style,
+                children,
+                ..
+            } => {
+                let mut inner = StyledText::default();
+                for child in children {
+                    Self::push_node(&mut inner, child, author);
+                }
+
+                // Mentions and links keep their own style
+                for (text, part_style) in inner.iter() {
+                    let is_plain = part_style.attr == crate::CellStyle::Normal;
+                    let part_style = match inline_style {
+                        InlineStyle::Bold if is_plain => ChatHistoryPartStyle {
+                            attr: crate::CellStyle::Bold,
+                            ..*part_style
+                        },
+                        InlineStyle::Italic if is_plain => ChatHistoryPartStyle {
+                            attr: crate::CellStyle::Italic,
+                            ..*part_style
+                        },
+                        InlineStyle::Bold | InlineStyle::Italic => *part_style,
+                        InlineStyle::Code => ChatHistoryPartStyle::new(
+                            config_hex_color!(colors.timestamp_fg),
+                            config_hex_color!(colors.timestamp_bg),
+                            crate::CellStyle::Normal,
+                        ),
+                    };
+
+                    body.push(text, part_style);
+                }
+            }
+            AstNode::Whitespace { span, .. } => body.push(
+                &" ".repeat(span.len()),
                 ChatHistoryPartStyle::new(
                     style::Color::Reset,
                     style::Color::Reset,
@@ -209,40 +653,63 @@ impl ChatHistoryEntry {
     }
 }
 
+/// # Fields
+///
+/// - `read_marker`: Index of the first entry which arrived while the user was
+///   away from the client, a rule is drawn above it until they next send or
+///   scroll down past it.
+/// - `selected`: Index of the entry picked with j/k in normal mode or jumped
+///   to from `/bookmarks`, which is highlighted and kept at the bottom
+///   instead of the latest entry until the user next sends.
+/// - `receiving`: The response being handled, which any entries shown for it
+///   are stamped with.
+/// - `scroll`: How many entries below the bottom of the screen the user has
+///   scrolled back past, 0 sticks to the latest as they arrive.
+/// - `visible`: How many rows fit on screen as of the last render, which a
+///   page is.
+/// - `nick`: Our own nick, to pick out messages which mention it.
+/// - `parser_options`: How the server recognises mentions, which messages
+///   are parsed with so that the same ones are highlighted.
 #[derive(Debug)]
 pub(crate) struct ChatHistory {
     entries: Vec<ChatHistoryEntry>,
+    read_marker: Option<usize>,
+    selected: Option<usize>,
+    receiving: Option<Received>,
+    scroll: usize,
+    visible: Cell<usize>,
+    pub(crate) nick: String,
+    parser_options: ParserOptions,
 }
 
 impl Renderable for ChatHistory {
     fn render_into(&self, buf: &mut crate::RenderBuffer, rect: &crate::Rect) {
-        let height = rect.height as usize;
-
-        for (i, entry) in self.entries.iter().rev().take(height).enumerate() {
-            let mut x = rect.x;
+        let layout = &config::current().layout;
+        let mut rows = (0..rect.height).rev().map(|i| rect.y + i);
+        let end = self.end();
+        self.visible.set(rect.height as usize);
 
-            for part in entry.parts.iter() {
-                for ch in part.0.chars() {
-                    if x >= rect.width {
-                        break;
-                    }
+        if end < self.entries.len() {
+            if let Some(y) = rows.next() {
+                let label = match self.entries.len() - end {
+                    _ if self.selected.is_some() => " newer messages below ".to_owned(),
+                    1 => " 1 line below ".to_owned(),
+                    below => format!(" {below} lines below "),
+                };
+                Self::render_marker(buf, rect.x, y, rect.width, &label);
+            }
+        }
 
-                    let y = rect.y + rect.height - 1 - i as u16;
-                    let bg = if !entry.is_confirmed && entry.id.is_some() {
-                        // @TODO: Generate unconfirmed colors
-                        style::Color::Reset
-                    } else {
-                        part.1.bg
-                    };
-                    let fg = if !entry.is_confirmed && entry.id.is_some() {
-                        style::Color::Black
-                    } else {
-                        part.1.fg
-                    };
+        for (i, entry) in self.entries[..end].iter().enumerate().rev() {
+            let Some(y) = rows.next() else {
+                break;
+            };
 
-                    buf.put_at(x, y, ch, bg, fg, part.1.attr);
+            entry.render_row(buf, rect.x, y, rect.width, layout, self.selected == Some(i));
 
-                    x += 1;
+            if self.read_marker == Some(i) {
+                if let Some(y) = rows.next() {
+                    Self::render_marker(buf, rect.x, y, rect.width, " new messages ");
                 }
             }
         }
@@ -251,222 +718,1952 @@ impl Renderable for ChatHistory {
 
 impl ChatHistory {
     fn new() -> Self {
-        Self { entries: vec![] }
+        Self {
+            entries: vec![],
+            read_marker: None,
+            selected: None,
+            receiving: None,
+            scroll: 0,
+            visible: Cell::new(0),
+            nick: String::new(),
+            parser_options: ParserOptions::default(),
+        }
     }
 
-    pub(crate) fn error(&mut self, msg: &str) {
-        self.entries.push(ChatHistoryEntry::error(msg));
+    fn parse(&self, message: &str) -> AstMessage {
+        parse_with_options(message, self.parser_options)
     }
 
-    fn message(&mut self, msg: &str, timestamp: &str, origin: &str, id: Option<u32>) {
-        let parsed = solace_message_parser::parse(msg);
-        let entry = ChatHistoryEntry::new(
-            parsed,
-            if origin.is_empty() {
-                None
-            } else {
-                Some(origin.to_owned())
-            },
-            timestamp.to_owned(),
-            id,
-        );
-
+    fn push(&mut self, mut entry: ChatHistoryEntry) {
+        entry.received = self.receiving.clone();
+        entry.mentions_me = self.mentions_me(&entry);
         self.entries.push(entry);
+
+        // Keep what the user scrolled back to where it is
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
     }
 
-    fn ack(&mut self, id: u32) {
-        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == Some(id)) {
-            entry.is_confirmed = true;
+    /// One past the entry shown at the bottom of the screen.
+    fn end(&self) -> usize {
+        match self.selected {
+            Some(selected) => selected + 1,
+            None => self.entries.len().saturating_sub(self.scroll),
         }
     }
-}
 
-#[derive(Debug, Default)]
-struct ChatTopic(String);
+    /// How many rows PageUp and PageDown move by.
+    pub(crate) fn page(&self) -> usize {
+        self.visible.get().max(1)
+    }
 
-impl Renderable for ChatTopic {
-    fn render_into(&self, buf: &mut crate::RenderBuffer, rect: &Rect) {
-        for i in 0..rect.width {
-            if let Some(ch) = self.0.chars().nth(i.into()) {
-                buf.put_at(
-                    i,
-                    0,
-                    ch,
-                    config_hex_color!(colors.topic_bg),
-                    config_hex_color!(colors.topic_fg),
-                    CellStyle::Bold,
-                );
-            } else {
-                buf.put_at(
-                    i,
-                    0,
-                    ' ',
-                    config_hex_color!(colors.topic_bg),
-                    config_hex_color!(colors.topic_fg),
-                    CellStyle::Bold,
-                );
-            }
-        }
+    /// Scrolls `rows` entries back, letting go of any selection but keeping
+    /// where it had scrolled to.
+    pub(crate) fn scroll_up(&mut self, rows: usize) {
+        let below = self.entries.len() - self.end();
+        self.selected = None;
+        self.scroll = (below + rows).min(self.entries.len().saturating_sub(1));
     }
-}
 
-#[derive(Debug)]
-pub(crate) struct ChatWindow {
-    buf_message: Vec<u8>,
-    topic: ChatTopic,
-    req: FramedWrite<WriteHalf<TcpStream>, Request>,
-    res: FramedRead<ReadHalf<TcpStream>, Response>,
-    pub(crate) history: ChatHistory,
-    pub(crate) prompt: Prompt,
-}
+    /// Scrolls `rows` entries forward, sticking to the latest again once it
+    /// is reached.
+    pub(crate) fn scroll_down(&mut self, rows: usize) {
+        let below = self.entries.len() - self.end();
+        self.selected = None;
+        self.scroll = below.saturating_sub(rows);
 
-impl ChatWindow {
-    pub(crate) async fn new() -> anyhow::Result<Self> {
-        let stream = TcpStream::connect("0.0.0.0:7878").await?;
+        // Read once it has gone off the top of the screen
+        let top = self.end().saturating_sub(self.page());
+        if self
+            .read_marker
+            .is_some_and(|read_marker| read_marker < top)
+        {
+            self.read_marker = None;
+        }
+    }
 
-        let (reader, writer) = split(stream);
-        let req = FramedWrite::new(writer, Request::default());
-        let res = FramedRead::new(reader, Response::default());
+    pub(crate) fn scroll_to_bottom(&mut self) {
+        self.scroll = 0;
+    }
 
-        let local_commands = vec!["exit".to_owned(), "connect".to_owned()];
-        let mut prompt = Prompt::new();
-        prompt.register_local_commands(local_commands);
+    /// The entry actions such as the inspector apply to, the selected one if
+    /// there is one and otherwise the latest.
+    fn selected(&self) -> Option<&ChatHistoryEntry> {
+        match self.selected {
+            Some(selected) => self.entries.get(selected),
+            None => self.entries.iter().rev().find(|entry| !entry.is_card),
+        }
+    }
 
-        Ok(Self {
-            buf_message: Vec::new(),
-            history: ChatHistory::new(),
-            prompt,
-            req,
-            res,
-            topic: ChatTopic::default(),
-        })
+    pub(crate) fn has_selection(&self) -> bool {
+        self.selected.is_some()
     }
 
-    pub(crate) async fn write(&mut self, to_send: String) -> anyhow::Result<()> {
-        let ast = parse(&to_send);
+    /// Moves the selection up a message, starting from the one at the bottom
+    /// of the screen.
+    pub(crate) fn select_older(&mut self) {
+        let before = self
+            .selected
+            .unwrap_or(self.entries.len().saturating_sub(self.scroll));
 
-        if self.handle_local_command(&ast) {
-            return Ok(());
+        if let Some(i) = self.entries[..before].iter().rposition(|e| !e.is_card) {
+            self.selected = Some(i);
+            self.scroll = 0;
         }
+    }
 
-        let message = match ast {
-            AstMessage::Command(AstNode::Command {
-                parsed_name, args, ..
-            }) => match parsed_name.as_str() {
-                "ping" => Some(RequestMessage::Ping),
-                "disconnect" => Some(RequestMessage::Disconnect),
-                "nick" => Some(RequestMessage::NewNick(
-                    match args
-                        .iter()
-                        .skip_while(|arg| matches!(*arg, AstNode::Whitespace { .. }))
-                        .cloned()
-                        .collect::<Vec<AstNode>>()
-                        .first()
-                    {
-                        Some(AstNode::Text { value, .. }) => value.to_owned(),
-                        _ => todo!(),
-                    },
-                )),
-                "topic" => Some(RequestMessage::NewTopic(
-                    match args
-                        .iter()
-                        .skip_while(|arg| matches!(*arg, AstNode::Whitespace { .. }))
-                        .cloned()
-                        .collect::<Vec<AstNode>>()
-                        .first()
-                    {
-                        Some(AstNode::Text { value, .. }) => value.to_owned(),
-                        _ => todo!(),
-                    },
-                )),
-                "whois" => Some(RequestMessage::WhoIs(
-                    match args
-                        .iter()
-                        .skip_while(|arg| matches!(*arg, AstNode::Whitespace { .. }))
-                        .cloned()
-                        .collect::<Vec<AstNode>>()
-                        .first()
-                    {
-                        Some(AstNode::Text { value, .. }) => value.to_owned(),
-                        _ => todo!(),
-                    },
-                )),
-                _ => unimplemented!(),
-            },
-            AstMessage::Normal(_) => Some(RequestMessage::Message(to_send.to_owned())),
-            _ => unreachable!(),
+    /// Moves the selection down a message, letting go of it past the latest.
+    pub(crate) fn select_newer(&mut self) {
+        let Some(selected) = self.selected else {
+            return;
         };
 
-        if let Some(message) = message {
-            let id = rand::random::<u32>();
-            let timestamp = chrono::Utc::now().format("%H:%M:%S").to_string();
-            let request = Request::new(id, message);
-            self.req.send(request).await?;
+        self.selected = self.entries[selected + 1..]
+            .iter()
+            .position(|e| !e.is_card)
+            .map(|offset| selected + 1 + offset);
+    }
 
-            self.history
-                .message(&to_send, &timestamp, &self.prompt.nick, Some(id));
-        }
+    /// Remembers where the user stopped reading, anything pushed after this
+    /// point is shown below a "new messages" rule. Left where it is if they
+    /// haven't caught up with the last one yet, so that looking away again
+    /// doesn't skip what they missed before.
+    pub(crate) fn set_read_marker(&mut self) {
+        self.read_marker.get_or_insert(self.entries.len());
+    }
 
-        Ok(())
+    pub(crate) fn clear_read_marker(&mut self) {
+        self.read_marker = None;
     }
 
-    pub(crate) async fn read(&mut self) -> anyhow::Result<()> {
-        match self.res.next().await {
-            Some(Ok(res)) => {
-                let Response {
-                    message,
-                    origin,
-                    timestamp,
-                    code,
-                    ..
-                } = res;
-                let timestamp = self.to_local_time(timestamp);
+    /// Shows the entry with `message_id` at the bottom, returning `false` if
+    /// it isn't in the history, e.g. it was sent before the client started.
+    pub(crate) fn jump_to(&mut self, message_id: u64) -> bool {
+        self.selected = self
+            .entries
+            .iter()
+            .position(|entry| entry.message_id == Some(message_id) && !entry.is_card);
+        self.scroll = 0;
 
-                match code {
-                    RES_TOPIC_CHANGE => {
-                        self.topic.0 = message;
-                    }
-                    RES_YOUR_NICK => {
-                        self.prompt.nick = message;
-                    }
-                    RES_COMMAND_LIST => {
-                        let mut commands = message
-                            .split(' ')
-                            .map(|x| x.to_owned())
-                            .collect::<Vec<String>>();
+        self.selected.is_some()
+    }
 
-                        commands.sort_by_key(|a| a.to_lowercase());
+    pub(crate) fn clear_selection(&mut self) {
+        self.selected = None;
+    }
 
-                        self.prompt.commands = commands;
-                    }
-                    RES_NICK_LIST => {
-                        let mut nicks = message
-                            .split(' ')
-                            .map(|x| x.to_owned())
-                            .collect::<Vec<String>>();
+    fn render_marker(buf: &mut crate::RenderBuffer, x: u16, y: u16, width: u16, label: &str) {
+        let fg = config_hex_color!(colors.server_message);
+        let label_start = (width as usize).saturating_sub(label.len()) / 2;
 
-                        nicks.sort_by_key(|a| a.to_lowercase());
+        for i in 0..width {
+            let ch = (i as usize)
+                .checked_sub(label_start)
+                .and_then(|offset| label.chars().nth(offset))
+                .unwrap_or('─');
 
-                        self.prompt.nicks = nicks;
-                    }
-                    RES_ACK_MESSAGE => {
-                        self.history.ack(message.parse::<u32>()?);
-                    }
-                    _ => self.history.message(&message, &timestamp, &origin, None),
-                }
-            }
-            None => {
-                self.history.error("Server closed the connection");
-            }
-            _ => (),
+            buf.put_at(x + i, y, ch, style::Color::Reset, fg, CellStyle::Bold);
         }
+    }
 
-        Ok(())
+    pub(crate) fn error(&mut self, msg: &str) {
+        self.push(ChatHistoryEntry::error(msg));
     }
 
-    fn handle_local_command(&mut self, ast: &AstMessage) -> bool {
-        match ast {
-            AstMessage::Command(AstNode::Command { parsed_name, .. }) => {
+    pub(crate) fn motd(&mut self, motd: &str) {
+        for line in motd.lines() {
+            self.push(ChatHistoryEntry::motd(line));
+        }
+    }
+
+    /// Every message so far, oldest first, as it would be saved to a file.
+    pub(crate) fn export(&self) -> impl Iterator<Item = export::Entry<'_>> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.is_card)
+            .flat_map(|entry| {
+                if entry.collapsed.is_empty() {
+                    std::slice::from_ref(entry)
+                } else {
+                    entry.collapsed.as_slice()
+                }
+            })
+            .map(|entry| export::Entry {
+                timestamp: &entry.timestamp,
+                from: entry.author.as_deref(),
+                message: &entry.body.text,
+            })
+    }
+
+    /// Shows `msg` as if the server had sent it, for feedback on commands
+    /// the client handles itself.
+    pub(crate) fn info(&mut self, msg: &str) {
+        let timestamp = timestamp::now(&config::current().layout);
+        self.message(msg, &timestamp, "", None);
+    }
+
+    fn message(&mut self, msg: &str, timestamp: &str, origin: &str, id: Option<u32>) {
+        let parsed = self.parse(msg);
+        let entry = ChatHistoryEntry::new(
+            parsed,
+            if origin.is_empty() {
+                None
+            } else {
+                Some(origin.to_owned())
+            },
+            timestamp.to_owned(),
+            id,
+        );
+
+        self.push(ChatHistoryEntry {
+            raw: msg.to_owned(),
+            ..entry
+        });
+    }
+
+    /// Shows a join, part or nick change, collapsing it along with those
+    /// before it once there are `layout.collapse_joins_at` in a row.
+    fn membership(&mut self, membership: Membership, msg: &str, timestamp: &str) {
+        self.message(msg, timestamp, "", None);
+
+        if let Some(entry) = self.entries.last_mut() {
+            entry.membership = Some(membership);
+        }
+
+        let threshold = config::current().layout.collapse_joins_at;
+        let start = self
+            .entries
+            .iter()
+            .rposition(|entry| entry.membership.is_none())
+            .map_or(0, |i| i + 1);
+        let run = &self.entries[start..];
+        let count = run
+            .iter()
+            .map(|entry| entry.collapsed.len().max(1))
+            .sum::<usize>();
+
+        if threshold == 0 || count < threshold || run.len() < 2 {
+            return;
+        }
+
+        let end = self.end();
+        let events = self
+            .entries
+            .drain(start..)
+            .flat_map(|entry| {
+                if entry.collapsed.is_empty() {
+                    vec![entry]
+                } else {
+                    entry.collapsed
+                }
+            })
+            .collect();
+        self.entries.push(ChatHistoryEntry::summary(events));
+
+        // Anything marked within the run is now part of the summary
+        for index in [&mut self.read_marker, &mut self.selected]
+            .into_iter()
+            .flatten()
+        {
+            *index = (*index).min(start);
+        }
+
+        if self.scroll > 0 {
+            self.scroll = self.entries.len() - end.min(start);
+        }
+    }
+
+    /// Swaps the selected summary for the joins, parts and nick changes it
+    /// stands in for, returning `false` if it isn't a summary.
+    pub(crate) fn expand_selected(&mut self) -> bool {
+        let Some(selected) = self.selected else {
+            return false;
+        };
+
+        let mut events = std::mem::take(&mut self.entries[selected].collapsed);
+        if events.is_empty() {
+            return false;
+        }
+
+        // So that they aren't collapsed again by the next join
+        for event in &mut events {
+            event.membership = None;
+        }
+
+        let added = events.len() - 1;
+        self.entries.splice(selected..=selected, events);
+
+        if let Some(read_marker) = self.read_marker.as_mut().filter(|i| **i > selected) {
+            *read_marker += added;
+        }
+
+        true
+    }
+
+    /// Like `message`, for one which the server flagged as coming from a bot.
+    fn bot_message(&mut self, msg: &str, timestamp: &str, origin: &str) {
+        self.message(msg, timestamp, origin, None);
+
+        if let Some(entry) = self.entries.last_mut() {
+            entry.is_from_bot = true;
+        }
+    }
+
+    /// Gives the latest entry the id the server sent it with.
+    fn set_message_id(&mut self, message_id: u64) {
+        if let Some(entry) = self.entries.last_mut() {
+            entry.message_id = Some(message_id).filter(|&id| id != 0);
+        }
+    }
+
+    /// Gives our own message sent with `id` the id the server gave it.
+    fn sent(&mut self, id: u32, message_id: u64) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == Some(id)) {
+            entry.message_id = Some(message_id);
+        }
+    }
+
+    /// Notes who has read our message with `message_id`, for the inspector.
+    fn mentions_me(&self, entry: &ChatHistoryEntry) -> bool {
+        let nick = self.nick.to_lowercase();
+        let is_others = entry
+            .author
+            .as_deref()
+            .is_some_and(|author| author.to_lowercase() != nick);
+
+        !nick.is_empty()
+            && is_others
+            && !entry.is_card
+            && self
+                .parse(&entry.raw)
+                .mentions()
+                .any(|mention| mention.name.to_lowercase() == nick)
+    }
+
+    /// The messages in the history which mention us, oldest first, as far
+    /// as they can be jumped to.
+    fn mentions(&self) -> Vec<Bookmark> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.mentions_me)
+            .filter_map(|entry| {
+                Some(Bookmark {
+                    message_id: entry.message_id?,
+                    nick: entry.author.clone()?,
+                    timestamp: entry.created_at,
+                    message: entry.raw.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// How many of our messages the server has yet to ack.
+    fn unacked(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.id.is_some() && !entry.is_confirmed)
+            .count()
+    }
+
+    /// The URLs linked to in the history, latest first, for `/open`.
+    fn links(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| !entry.is_card)
+            .flat_map(|entry| {
+                let ast = self.parse(&entry.raw);
+                let mut links = ast.links().map(str::to_owned).collect::<Vec<String>>();
+                links.reverse();
+                links
+            })
+            .collect()
+    }
+
+    /// The text of the message `author` sent with `message_id`, or of their
+    /// latest if that is `0`, as we are told of mentions after the message.
+    fn sent_by(&self, author: &str, message_id: u64) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| {
+                entry.author.as_deref() == Some(author)
+                    && !entry.is_card
+                    && (message_id == 0 || entry.message_id == Some(message_id))
+            })
+            .map(|entry| entry.raw.as_str())
+    }
+
+    fn read_by(&mut self, message_id: u64, readers: Vec<String>) {
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.message_id == Some(message_id) && !entry.is_card)
+        {
+            entry.read_by = readers;
+        }
+    }
+
+    /// Shows `preview` under the message with `message_id`, after any
+    /// others it already has.
+    fn link_preview(&mut self, message_id: u64, preview: &LinkPreview) {
+        let Some(message) = self
+            .entries
+            .iter()
+            .position(|entry| entry.message_id == Some(message_id) && !entry.is_card)
+        else {
+            return;
+        };
+        let mut at = message + 1;
+        while self.entries.get(at).is_some_and(|entry| entry.is_card) {
+            at += 1;
+        }
+
+        let mut cards = vec![ChatHistoryEntry::card(
+            &self.entries[message],
+            &preview.title,
+            ChatHistoryPartStyle::new(
+                config_hex_color!(colors.fg),
+                style::Color::Reset,
+                crate::CellStyle::Bold,
+            ),
+        )];
+        if !preview.description.is_empty() {
+            cards.push(ChatHistoryEntry::card(
+                &self.entries[message],
+                &preview.description,
+                ChatHistoryPartStyle::new(
+                    config_hex_color!(colors.fg),
+                    style::Color::Reset,
+                    crate::CellStyle::Normal,
+                ),
+            ));
+        }
+
+        // Cards below what's on screen are more to scroll past
+        if self.scroll > 0 && at >= self.end() {
+            self.scroll += cards.len();
+        }
+
+        // Whatever was marked from `at` on has moved down with it
+        for index in [&mut self.read_marker, &mut self.selected]
+            .into_iter()
+            .flatten()
+        {
+            if *index >= at {
+                *index += cards.len();
+            }
+        }
+
+        self.entries.splice(at..at, cards);
+    }
+
+    fn ack(&mut self, id: u32) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == Some(id)) {
+            entry.is_confirmed = true;
+            entry.acked_at = Some(timestamp::unix_now());
+        }
+    }
+
+    /// Greys a message out again after the server turned it away, until it
+    /// is sent a second time.
+    fn unack(&mut self, id: u32) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == Some(id)) {
+            entry.is_confirmed = false;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChatTopic(String);
+
+impl Renderable for ChatTopic {
+    fn render_into(&self, buf: &mut crate::RenderBuffer, rect: &Rect) {
+        let mut chars = self.0.chars();
+        let mut x = rect.x;
+
+        while x < rect.x + rect.width {
+            x += buf.put_at(
+                x,
+                rect.y,
+                chars.next().unwrap_or(' '),
+                config_hex_color!(colors.topic_bg),
+                config_hex_color!(colors.topic_fg),
+                CellStyle::Bold,
+            );
+        }
+    }
+}
+
+/// How many sent requests are kept around in case the server turns them away
+/// and they need sending again.
+const MAX_RECENTLY_SENT: usize = 32;
+
+/// How long `/connect` waits for a server to answer before giving up on it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the open `Password` overlay is asking for.
+#[derive(Debug)]
+enum PasswordPrompt {
+    /// The passphrase for the saved credentials, before logging in.
+    Unlock { account: String },
+    /// The password for an account which has none saved.
+    Account { account: String },
+    /// The password of the account whose nick `/ghost` takes back.
+    Ghost { nick: String },
+    /// The password to protect our nick with.
+    Register,
+    /// The password of the registered nick the server refused us.
+    Auth,
+    /// A passphrase to save the first password with.
+    NewPassphrase { account: String, password: String },
+}
+
+/// The connection to the server, or how getting it back is going.
+#[derive(Debug)]
+enum Link {
+    Up {
+        req: FramedWrite<WriteHalf<Stream>, FrameCodec<Request>>,
+        res: Box<FramedRead<ReadHalf<Stream>, FrameCodec<Response>>>,
+        stats: ConnStats,
+    },
+    Down(Reconnect),
+    /// Left with `/disconnect`, until the next `/connect`.
+    Closed,
+}
+
+impl Link {
+    /// Says hello over `connected` as `nick`, if there is one yet, and asks
+    /// for what this client understands, including signed frames if there
+    /// is a session key to sign them with.
+    async fn up(connected: Connected, nick: Option<&str>) -> anyhow::Result<Self> {
+        let mut stats = ConnStats::new();
+        let (reader, writer) = split(stats.count(connected.stream));
+        let mut req = FramedWrite::new(writer, FrameCodec::default());
+        let mut res = Box::new(FramedRead::new(reader, FrameCodec::default()));
+
+        if let Some(nick) = nick {
+            req.send(Request::new(
+                rand::random::<u32>(),
+                RequestMessage::NewNick(nick.to_owned()),
+            ))
+            .await?;
+            stats.frames_sent += 1;
+        }
+
+        let mut capabilities = vec![
+            capability::COMMAND_HELP.to_owned(),
+            capability::BUILD_INFO.to_owned(),
+        ];
+        if *config!(experimental) {
+            capabilities.push(capability::EXPERIMENTAL.to_owned());
+        }
+        if let Some(key) = connected.session_key {
+            res.decoder_mut().expect_hmac(key);
+            capabilities.push(capability::FRAME_HMAC.to_owned());
+        }
+        req.send(Request::new(
+            rand::random::<u32>(),
+            RequestMessage::Capabilities(capabilities),
+        ))
+        .await?;
+        stats.frames_sent += 1;
+
+        Ok(Self::Up { req, res, stats })
+    }
+}
+
+/// # Fields
+///
+/// - `link`: The connection to `server`, requests made while it is down are
+///   held in `queued` and sent once it is back.
+/// - `recently_sent`: The last requests sent, oldest first.
+/// - `retrying`: Requests the server rate limited, sent again first once the
+///   cooldown is over.
+/// - `queued`: Requests made during the cooldown or while the connection is
+///   down, sent after `retrying`.
+/// - `cooldown_until`: When the server will take requests again, if it has
+///   rate limited us.
+/// - `credentials`: Saved passwords, once unlocked.
+/// - `unsaved_login`: An account and the password typed for it, saved once
+///   the server has let us log in with it.
+/// - `max_message_chars`: The server's limit on chat messages, `0` for none.
+/// - `downloads`: Where to save each attachment asked for, by its id, or
+///   `None` for its own name in the current directory.
+/// - `missed_mentions`: What the server last said mentioned us while we were
+///   away, listed by `/mentions` along with those in the history.
+/// - `is_focused`: Whether the terminal has focus, so that chat messages
+///   arriving are being read.
+/// - `unread_up_to`: The last chat message which arrived without focus, to
+///   mark read once it is back.
+/// - `session_key`: What to sign requests with once the server says to, from
+///   the TLS session `link` is over.
+/// - `has_command_list`: Whether the server has listed its commands yet,
+///   before which commands aren't checked against them.
+#[derive(Debug)]
+pub(crate) struct ChatWindow {
+    buf_message: Vec<u8>,
+    topic: ChatTopic,
+    presence: Presence,
+    link: Link,
+    pub(crate) history: ChatHistory,
+    pub(crate) prompt: Prompt,
+    overlays: Vec<Box<dyn Overlay>>,
+    recently_sent: VecDeque<Request>,
+    retrying: VecDeque<Request>,
+    queued: VecDeque<Request>,
+    cooldown_until: Option<Instant>,
+    server: String,
+    credentials: Option<Credentials>,
+    password_prompt: Option<PasswordPrompt>,
+    unsaved_login: Option<(String, String)>,
+    max_message_chars: usize,
+    downloads: HashMap<String, Option<String>>,
+    missed_mentions: Vec<Bookmark>,
+    is_focused: bool,
+    unread_up_to: Option<u64>,
+    session_key: Option<Vec<u8>>,
+    has_command_list: bool,
+}
+
+impl ChatWindow {
+    pub(crate) async fn new(server: &str) -> anyhow::Result<Self> {
+        log!(Info, "Running {}", crate::build_info());
+        log!(Info, "Connecting to {server}");
+        let connected = transport::connect_keyed(server, config!(tls)).await?;
+        let session_key = connected.session_key.clone();
+        let link = Link::up(connected, config!(nick).as_deref()).await?;
+
+        let local_commands = [
+            "exit\tQuits solace",
+            "connect [addr]\tSwitches to another server, or back to the last one",
+            "disconnect\tLeaves the server until the next /connect",
+            "reload\tReads the config file again",
+            "set <key> <value...>\tChanges a setting and saves it to the config",
+            "export [path...]\tWrites the chat history to a file",
+            "snippet <name>\tFills the prompt with a snippet from the config",
+            "paste <text...>\tUploads text with paste.command and sends the link",
+            "mentions\tLists the messages which mentioned you, including while away",
+            "connstats\tShows what has gone over the connection to the server",
+            "open [n]\tOpens the nth latest link in the chat in the browser, 1 by default",
+        ]
+        .iter()
+        .filter_map(|usage| CommandSpec::parse(usage))
+        .collect::<Vec<CommandSpec>>();
+        let mut prompt = Prompt::new();
+        prompt.register_local_commands(local_commands);
+
+        Ok(Self {
+            buf_message: Vec::new(),
+            history: ChatHistory::new(),
+            overlays: Vec::new(),
+            prompt,
+            link,
+            topic: ChatTopic::default(),
+            presence: Presence::default(),
+            recently_sent: VecDeque::new(),
+            retrying: VecDeque::new(),
+            queued: VecDeque::new(),
+            cooldown_until: None,
+            server: server.to_owned(),
+            credentials: None,
+            password_prompt: None,
+            unsaved_login: None,
+            max_message_chars: 0,
+            downloads: HashMap::new(),
+            missed_mentions: Vec::new(),
+            is_focused: true,
+            unread_up_to: None,
+            session_key,
+            has_command_list: false,
+        })
+    }
+
+    pub(crate) async fn focus_gained(&mut self) -> anyhow::Result<()> {
+        self.is_focused = true;
+
+        match self.unread_up_to.take() {
+            Some(message_id) => self.mark_read(message_id).await,
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn focus_lost(&mut self) {
+        self.is_focused = false;
+        self.history.set_read_marker();
+    }
+
+    /// Tells the server that we have read up to the chat message with
+    /// `message_id`, or holds onto it until the terminal has focus again.
+    async fn mark_read(&mut self, message_id: u64) -> anyhow::Result<()> {
+        if message_id == 0 || !config!(privacy.read_receipts) {
+            return Ok(());
+        }
+
+        if !self.is_focused {
+            self.unread_up_to = Some(message_id);
+            return Ok(());
+        }
+
+        self.send(Request::new(
+            rand::random::<u32>(),
+            RequestMessage::MarkRead(message_id),
+        ))
+        .await
+    }
+
+    /// Sends `request`, or holds onto it until the cooldown is over if the
+    /// server has rate limited us, or until we have reconnected if the
+    /// connection has dropped.
+    async fn send(&mut self, request: Request) -> anyhow::Result<()> {
+        let (req, stats) = match &mut self.link {
+            Link::Up { req, stats, .. } if self.cooldown_until.is_none() => (req, stats),
+            Link::Closed => {
+                self.history
+                    .error("Not connected to a server, /connect <addr> to join one");
+                return Ok(());
+            }
+            _ => {
+                self.queued.push_back(request);
+                return Ok(());
+            }
+        };
+
+        if let Err(err) = req.send(request.clone()).await {
+            self.queued.push_back(request);
+            self.disconnected(&err.to_string());
+            return Ok(());
+        }
+        stats.frames_sent += 1;
+
+        if self.recently_sent.len() == MAX_RECENTLY_SENT {
+            self.recently_sent.pop_front();
+        }
+        self.recently_sent.push_back(request);
+
+        Ok(())
+    }
+
+    /// Starts getting the connection back after it dropped because of
+    /// `reason`.
+    fn disconnected(&mut self, reason: &str) {
+        if !matches!(self.link, Link::Up { .. }) {
+            return;
+        }
+
+        self.history
+            .error(&format!("Lost the connection to {}: {reason}", self.server));
+        self.link = Link::Down(Reconnect::new(&self.server, config!(tls).clone()));
+    }
+
+    /// Connects to `server`, leaving the one we're on if that works, and
+    /// starting afresh there.
+    async fn connect(&mut self, server: &str) {
+        let nick = Some(self.prompt.nick.clone())
+            .filter(|nick| !nick.is_empty())
+            .or_else(|| config!(nick).clone());
+        let tls = config!(tls).clone();
+        let mut session_key = None;
+        let connecting = transport::connect_keyed(server, &tls);
+        let link = match tokio::time::timeout(CONNECT_TIMEOUT, connecting).await {
+            Ok(Ok(connected)) => {
+                session_key.clone_from(&connected.session_key);
+                Link::up(connected, nick.as_deref()).await
+            }
+            Ok(Err(err)) => Err(err.into()),
+            Err(_) => Err(anyhow::anyhow!("Timed out")),
+        };
+        let link = match link {
+            Ok(link) => link,
+            Err(err) => {
+                self.history
+                    .error(&format!("Couldn't connect to {server}: {err:#}"));
+                return;
+            }
+        };
+
+        self.close().await;
+        self.link = link;
+        self.session_key = session_key;
+        self.server = server.to_owned();
+        self.history.info(&format!("Connected to {server}"));
+        log!(Info, "Connected to {server}");
+
+        if let Err(err) = browser::remember(server) {
+            log!(Warn, "{err:#}");
+        }
+    }
+
+    /// Says goodbye to the server, if it's still there, and forgets
+    /// everything about it so that the next one starts afresh.
+    async fn close(&mut self) {
+        if let Link::Up { req, .. } = &mut self.link {
+            // Gone is gone, however we got there
+            let _ = req
+                .send(Request::new(
+                    rand::random::<u32>(),
+                    RequestMessage::Disconnect,
+                ))
+                .await;
+        }
+
+        self.link = Link::Closed;
+        self.topic = ChatTopic::default();
+        self.presence = Presence::default();
+        self.prompt.commands.clear();
+        self.has_command_list = false;
+        self.prompt.nicks.clear();
+        self.recently_sent.clear();
+        self.retrying.clear();
+        self.queued.clear();
+        self.cooldown_until = None;
+        self.max_message_chars = 0;
+        self.set_parser_options(ParserOptions::default());
+        self.missed_mentions.clear();
+    }
+
+    /// Parses the history and prompt the way the server does from now on.
+    fn set_parser_options(&mut self, options: ParserOptions) {
+        self.history.parser_options = options;
+        self.prompt.set_parser_options(options);
+    }
+
+    /// Waits for the connection to come back, then sends everything held
+    /// while it was down.
+    async fn reconnect(&mut self) -> anyhow::Result<()> {
+        // The server forgets who we were, so ask for the same nick again
+        let nick = Some(self.prompt.nick.clone())
+            .filter(|nick| !nick.is_empty())
+            .or_else(|| config!(nick).clone());
+        let Link::Down(reconnect) = &mut self.link else {
+            return Ok(());
+        };
+
+        let connected = reconnect.connected().await;
+        let session_key = connected.session_key.clone();
+        match Link::up(connected, nick.as_deref()).await {
+            Ok(link) => {
+                self.link = link;
+                self.session_key = session_key;
+            }
+            Err(err) => {
+                reconnect.failed(&err);
+                return Ok(());
+            }
+        }
+
+        self.history
+            .info(&format!("Reconnected to {}", self.server));
+        log!(Info, "Reconnected to {}", self.server);
+
+        // It's a new connection, which the old one's rate limit doesn't apply to
+        self.cooldown_until = None;
+        self.send_held().await
+    }
+
+    /// Sends the requests the server rate limited, then those made since.
+    async fn send_held(&mut self) -> anyhow::Result<()> {
+        let retries = self.retrying.len();
+        let held = self
+            .retrying
+            .drain(..)
+            .chain(self.queued.drain(..))
+            .collect::<Vec<Request>>();
+
+        for (i, request) in held.into_iter().enumerate() {
+            self.send(request).await?;
+
+            if let (true, Link::Up { stats, .. }) = (i < retries, &mut self.link) {
+                stats.retransmits += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// When the main loop should next wake up without any input, so that the
+    /// cooldown and the wait to reconnect count down, and held requests go
+    /// out on time.
+    pub(crate) fn next_wake(&self) -> Option<Instant> {
+        let retry_at = match &self.link {
+            Link::Up { .. } | Link::Closed => None,
+            Link::Down(reconnect) => reconnect.retry_at(),
+        };
+
+        [self.cooldown_until, retry_at]
+            .into_iter()
+            .flatten()
+            .map(Self::next_tick)
+            .min()
+    }
+
+    /// When the seconds left until `until` next go down by one.
+    fn next_tick(until: Instant) -> Instant {
+        let remaining = until.saturating_duration_since(Instant::now());
+        let to_next_second = Duration::from_nanos(remaining.as_nanos() as u64 % 1_000_000_000);
+
+        if to_next_second.is_zero() {
+            until
+        } else {
+            Instant::now() + to_next_second
+        }
+    }
+
+    /// Sends everything held back once the cooldown is over.
+    pub(crate) async fn wake(&mut self) -> anyhow::Result<()> {
+        match self.cooldown_until {
+            Some(until) if until <= Instant::now() => self.cooldown_until = None,
+            _ => return Ok(()),
+        }
+
+        self.send_held().await
+    }
+
+    fn connection(&self) -> ConnectionIndicator {
+        match &self.link {
+            Link::Up { .. } => ConnectionIndicator::Up,
+            Link::Closed => ConnectionIndicator::Closed,
+            Link::Down(reconnect) => match reconnect.retry_at() {
+                Some(at) => {
+                    ConnectionIndicator::Waiting(at.saturating_duration_since(Instant::now()))
+                }
+                None => ConnectionIndicator::Connecting,
+            },
+        }
+    }
+
+    fn cooldown(&self) -> Option<Duration> {
+        self.cooldown_until
+            .map(|until| until.saturating_duration_since(Instant::now()))
+    }
+
+    pub(crate) fn open_overlay(&mut self, overlay: impl Overlay + 'static) {
+        self.overlays.push(Box::new(overlay));
+    }
+
+    pub(crate) fn show_help(&mut self) {
+        let help = Help::new(self.prompt.command_specs());
+        self.open_overlay(help);
+    }
+
+    pub(crate) fn show_emoji_picker(&mut self) {
+        self.open_overlay(EmojiPicker::new(emoji::recent_emoji()));
+    }
+
+    /// Moves the selection and acts on the selected message while the
+    /// prompt is in normal mode, handing every other key to the prompt.
+    pub(crate) async fn handle_key_press(
+        &mut self,
+        key_code: event::KeyCode,
+    ) -> anyhow::Result<()> {
+        if !self.prompt.is_idle_in_normal_mode() {
+            self.prompt.handle_key_press(key_code);
+            return Ok(());
+        }
+
+        // @TODO: React to and delete the selected message, once the server
+        // has requests for them
+        match key_code {
+            event::KeyCode::Char('k') => self.history.select_older(),
+            event::KeyCode::Char('j') => self.history.select_newer(),
+            event::KeyCode::Esc if self.history.has_selection() => {
+                self.history.clear_selection();
+            }
+            event::KeyCode::Char('r') if self.history.has_selection() => {
+                match self.history.selected().and_then(|e| e.author.clone()) {
+                    Some(nick) => self.prompt.reply_to(&nick),
+                    None => self
+                        .history
+                        .error("Only messages from a nick can be replied to"),
+                }
+            }
+            event::KeyCode::Char('y') if self.history.has_selection() => {
+                let raw = self.history.selected().map(|e| e.raw.clone());
+
+                if let Some(Err(err)) = raw.map(|raw| clipboard::copy(&raw)) {
+                    self.history
+                        .error(&format!("Couldn't copy the message: {err}"));
+                }
+            }
+            event::KeyCode::Char('o') if self.history.has_selection() => {
+                if !self.history.expand_selected() {
+                    self.history
+                        .error("Only collapsed joins and parts can be expanded");
+                }
+            }
+            event::KeyCode::Char('b') if self.history.has_selection() => {
+                match self.history.selected().and_then(|e| e.message_id) {
+                    Some(message_id) => self.write(format!("/bookmark {message_id}")).await?,
+                    None => self.history.error("Only chat messages can be bookmarked"),
+                }
+            }
+            _ => self.prompt.handle_key_press(key_code),
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn inspect_selected(&mut self) {
+        let layout = &config::current().layout;
+
+        match self.history.selected() {
+            Some(entry) => {
+                let inspector = Inspector::new(entry.details(layout), &entry.raw);
+                self.open_overlay(inspector);
+            }
+            None => self.history.error("There's no message to inspect yet"),
+        }
+    }
+
+    pub(crate) fn has_overlay(&self) -> bool {
+        !self.overlays.is_empty()
+    }
+
+    /// Hands `key` to the topmost overlay, closing it if it is done with.
+    pub(crate) async fn handle_overlay_key(&mut self, key: event::KeyEvent) -> anyhow::Result<()> {
+        let Some(overlay) = self.overlays.last_mut() else {
+            return Ok(());
+        };
+
+        match overlay.handle_key(key) {
+            OverlayAction::Stay => (),
+            OverlayAction::Close => {
+                self.overlays.pop();
+            }
+            OverlayAction::Submit(to_send) => {
+                self.overlays.pop();
+                self.write(to_send).await?;
+            }
+            OverlayAction::Password(password) => {
+                self.overlays.pop();
+                self.handle_password(password).await?;
+            }
+            OverlayAction::JumpTo(message_id) => {
+                self.overlays.pop();
+
+                if !self.history.jump_to(message_id) {
+                    self.history
+                        .error(&format!("#{message_id} isn't in this session's history"));
+                }
+            }
+            OverlayAction::Overwrite { path, attachment } => {
+                self.overlays.pop();
+                self.write_attachment(&path, &attachment, false).await;
+            }
+            OverlayAction::PickEmoji(emoji) => {
+                self.overlays.pop();
+                self.prompt.paste(&emoji);
+
+                if let Err(err) = emoji::remember(&emoji) {
+                    log!(Warn, "{err:#}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends whatever is in the prompt, unless it is long enough to ask
+    /// first. Either way it's in the prompt's history, so nothing is lost by
+    /// saying no.
+    pub(crate) async fn send_prompt(&mut self) -> anyhow::Result<()> {
+        let to_send = self.prompt.current_value();
+        self.prompt.flush();
+        self.history.clear_selection();
+        self.history.scroll_to_bottom();
+
+        let chars = to_send.chars().count();
+        let limit = self.max_message_chars;
+
+        if limit > 0 && chars > limit && self.history.parse(&to_send).command().is_none() {
+            if config::current().paste.command.is_empty() {
+                self.history.error(&format!(
+                    "{chars} characters is over the channel's limit of {limit}, set paste.command to paste it instead"
+                ));
+            } else {
+                self.open_overlay(Confirm::new(
+                    &format!("{chars} characters is over the limit of {limit}, paste it and send the link?"),
+                    format!("/paste {to_send}"),
+                ));
+            }
+
+            return Ok(());
+        }
+
+        let options = self.history.parser_options;
+        match config::current().confirm_send.question(&to_send, options) {
+            Some(question) => self.open_overlay(Confirm::new(&question, to_send)),
+            None => self.write(to_send).await?,
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn write(&mut self, to_send: String) -> anyhow::Result<()> {
+        let ast = self.history.parse(&to_send);
+
+        self.history.clear_read_marker();
+
+        if self.handle_local_command(&ast, &to_send).await {
+            return Ok(());
+        }
+
+        if let Some(command) = ast.command() {
+            let arg_count = command.args().count();
+
+            match self.prompt.command_spec(command.name) {
+                Some(spec) if !spec.accepts(arg_count) => {
+                    self.history.error(&format!("Usage: /{}", spec.usage()));
+                    return Ok(());
+                }
+                Some(_) => (),
+                // The server's commands aren't known until it lists them,
+                // so it is left to say whether this is one
+                None if !self.has_command_list => (),
+                None => {
+                    self.history
+                        .error(&format!("Unknown command: /{}", command.name));
+                    return Ok(());
+                }
+            }
+        }
+
+        let message = match ast {
+            AstMessage::Command(AstNode::Command {
+                raw_name,
+                parsed_name,
+                args,
+                ..
+            }) => match parsed_name.as_str() {
+                "ping" => Some(RequestMessage::Ping),
+                "dnd" => Some(RequestMessage::DoNotDisturb),
+                "online" => Some(RequestMessage::Online),
+                "nick" => match Self::first_arg(&args) {
+                    Some(nick) => Some(RequestMessage::NewNick(nick)),
+                    None => {
+                        self.history.error("Usage: /nick <nick>");
+                        return Ok(());
+                    }
+                },
+                "topic" => Some(RequestMessage::NewTopic(
+                    match Self::sole_quoted_arg(&args) {
+                        Some(topic) => topic,
+                        None => Self::rest_of_command(&to_send, &raw_name),
+                    },
+                )),
+                "whois" => match Self::first_arg(&args) {
+                    Some(nick) => Some(RequestMessage::WhoIs(nick)),
+                    None => {
+                        self.history.error("Usage: /whois <nick>");
+                        return Ok(());
+                    }
+                },
+                "away" => Some(RequestMessage::Away(
+                    Some(Self::rest_of_command(&to_send, &raw_name))
+                        .filter(|reason| !reason.is_empty()),
+                )),
+                "msg" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+                    let first = args
+                        .iter()
+                        .find(|arg| !matches!(arg, AstNode::Whitespace { .. }));
+
+                    let (to, message) = match first {
+                        // A quoted nick may itself contain whitespace
+                        Some(AstNode::Quoted {
+                            raw_text,
+                            parsed_text,
+                            ..
+                        }) => (
+                            parsed_text.as_str(),
+                            &rest[raw_text.len().min(rest.len())..],
+                        ),
+                        _ => rest.split_once(char::is_whitespace).unwrap_or_default(),
+                    };
+
+                    Some(RequestMessage::DirectMessage {
+                        to: to.trim_start_matches('@').to_owned(),
+                        message: message.trim().to_owned(),
+                    })
+                }
+                "login" => {
+                    self.log_in(Self::rest_of_command(&to_send, &raw_name))
+                        .await?;
+                    return Ok(());
+                }
+                "ghost" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+                    let (nick, password) =
+                        rest.split_once(char::is_whitespace).unwrap_or((&rest, ""));
+
+                    self.ghost(
+                        nick.to_owned(),
+                        Some(password.trim()).filter(|p| !p.is_empty()),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+                "mode" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+                    let (nick, mode) = rest.split_once(char::is_whitespace).unwrap_or((&rest, ""));
+
+                    match Level::from_mode(mode.trim()) {
+                        Some(level) => Some(RequestMessage::Mode {
+                            nick: nick.to_owned(),
+                            level,
+                        }),
+                        None => {
+                            self.history.error("Usage: /mode <nick> <+o|-o|+v|-v>");
+                            return Ok(());
+                        }
+                    }
+                }
+                "kick" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+                    let (nick, reason) =
+                        rest.split_once(char::is_whitespace).unwrap_or((&rest, ""));
+
+                    Some(RequestMessage::Kick {
+                        nick: nick.to_owned(),
+                        reason: Some(reason.trim().to_owned()).filter(|r| !r.is_empty()),
+                    })
+                }
+                "devices" => Some(RequestMessage::Devices),
+                "quota" => Some(RequestMessage::Quota),
+                "karma" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+                    let nick = rest.trim_start_matches('@');
+
+                    Some(RequestMessage::Karma(
+                        Some(nick.to_owned()).filter(|n| !n.is_empty()),
+                    ))
+                }
+                "bookmark" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+
+                    match rest.trim_start_matches('#').parse::<u64>() {
+                        Ok(message_id) => Some(RequestMessage::Bookmark(message_id)),
+                        Err(_) => {
+                            self.history.error(&format!(
+                                "Invalid message id: {rest}, turn on layout.show_message_ids to see them"
+                            ));
+                            return Ok(());
+                        }
+                    }
+                }
+                "bookmarks" => Some(RequestMessage::Bookmarks),
+                "upload" => {
+                    let path = Self::rest_of_command(&to_send, &raw_name);
+
+                    match tokio::fs::read(&path).await {
+                        Ok(data) => Some(RequestMessage::Upload {
+                            name: std::path::Path::new(&path)
+                                .file_name()
+                                .map_or_else(|| path.clone(), |n| n.to_string_lossy().into_owned()),
+                            data: attachment::encode_data(&data),
+                        }),
+                        Err(err) => {
+                            self.history.error(&format!("Couldn't read {path}: {err}"));
+                            return Ok(());
+                        }
+                    }
+                }
+                "download" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+                    let (id, path) = rest.split_once(char::is_whitespace).unwrap_or((&rest, ""));
+
+                    if !attachment::is_id(id) {
+                        self.history.error(&format!("Invalid attachment id: {id}"));
+                        return Ok(());
+                    }
+
+                    self.downloads.insert(
+                        id.to_owned(),
+                        Some(path.trim().to_owned()).filter(|p| !p.is_empty()),
+                    );
+                    Some(RequestMessage::Download(id.to_owned()))
+                }
+                "paste" => {
+                    let text = Self::rest_of_command(&to_send, &raw_name);
+                    let command = config::current().paste.command.clone();
+
+                    if command.is_empty() {
+                        self.history
+                            .error("Set paste.command to say where to paste to");
+                        return Ok(());
+                    }
+
+                    match paste::upload(&command, &text).await {
+                        Ok(link) => Some(RequestMessage::Message(link)),
+                        Err(err) => {
+                            self.history.error(&format!("{err:#}"));
+                            return Ok(());
+                        }
+                    }
+                }
+                "register" | "auth" => {
+                    let password = Self::rest_of_command(&to_send, &raw_name);
+
+                    match (parsed_name.as_str(), password.is_empty()) {
+                        ("register", true) => {
+                            self.ask_password(
+                                "Password to register your nick with",
+                                PasswordPrompt::Register,
+                            );
+                            return Ok(());
+                        }
+                        (_, true) => {
+                            self.ask_password(
+                                "Password for the registered nick",
+                                PasswordPrompt::Auth,
+                            );
+                            return Ok(());
+                        }
+                        ("register", false) => Some(RequestMessage::Register(Secret(password))),
+                        (_, false) => Some(RequestMessage::Auth(Secret(password))),
+                    }
+                }
+                "join" => Some(RequestMessage::Join(Self::rest_of_command(
+                    &to_send, &raw_name,
+                ))),
+                "part" => Some(RequestMessage::Part(Self::rest_of_command(
+                    &to_send, &raw_name,
+                ))),
+                "say" | "chantopic" | "chanmode" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+                    let (channel, text) =
+                        rest.split_once(char::is_whitespace).unwrap_or((&rest, ""));
+                    let (channel, text) = (channel.to_owned(), text.trim().to_owned());
+
+                    match parsed_name.as_str() {
+                        "say" => Some(RequestMessage::ChannelMessage {
+                            channel,
+                            message: text,
+                        }),
+                        "chantopic" => Some(RequestMessage::ChannelTopic {
+                            channel,
+                            topic: text,
+                        }),
+                        _ => match ChannelMode::from_change(&text) {
+                            Some((mode, is_enabled)) => Some(RequestMessage::ChannelMode {
+                                channel,
+                                mode,
+                                is_enabled,
+                            }),
+                            None => {
+                                self.history.error(&format!(
+                                    "Invalid mode: {text}, try +m, -m, +t, -t, +r, -r, +a or -a"
+                                ));
+                                return Ok(());
+                            }
+                        },
+                    }
+                }
+                "stats" => Some(RequestMessage::Stats(
+                    Self::rest_of_command(&to_send, &raw_name)
+                        .trim_start_matches('@')
+                        .to_owned(),
+                )),
+                "revoke" => {
+                    let rest = Self::rest_of_command(&to_send, &raw_name);
+
+                    match rest.trim_start_matches('#').parse::<u32>() {
+                        Ok(session_id) => Some(RequestMessage::Revoke(session_id)),
+                        Err(_) => {
+                            self.history
+                                .error(&format!("Invalid session: {rest}, see /devices"));
+                            return Ok(());
+                        }
+                    }
+                }
+                // Defined in the server's config, so only the server knows
+                // what they do
+                name if !self.prompt.is_local_command(name) => Some(RequestMessage::Custom {
+                    name: name.to_owned(),
+                    args: Self::rest_of_command(&to_send, &raw_name),
+                }),
+                // Local commands which weren't handled above
+                _ => None,
+            },
+            AstMessage::Normal(_) => Some(RequestMessage::Message(to_send.to_owned())),
+            _ => unreachable!(),
+        };
+
+        if let Some(message) = message {
+            let id = rand::random::<u32>();
+            let timestamp = timestamp::now(&config::current().layout);
+            // What was sent in place of a command, such as a paste's link
+            let shown = match &message {
+                RequestMessage::Message(sent) => sent.clone(),
+                RequestMessage::ChannelMessage { channel, message } => {
+                    format!("[{channel}] {message}")
+                }
+                // The password would otherwise be kept, and exported, as typed
+                RequestMessage::Register(_) => "/register ••••".to_owned(),
+                RequestMessage::Auth(_) => "/auth ••••".to_owned(),
+                _ => to_send,
+            };
+            let request = Request::new(id, message);
+            self.send(request).await?;
+
+            self.history
+                .message(&shown, &timestamp, &self.prompt.nick, Some(id));
+        }
+
+        Ok(())
+    }
+
+    /// Logs into `account` with its saved password, asking for the passphrase
+    /// to unlock saved passwords or for the password itself first if needed.
+    async fn log_in(&mut self, account: String) -> anyhow::Result<()> {
+        let saved = self
+            .credentials
+            .as_ref()
+            .and_then(|credentials| credentials.get(&self.server, &account))
+            .map(str::to_owned);
+
+        match saved {
+            Some(password) => self.send_login(account, Some(password)).await?,
+            None if self.credentials.is_none() && credentials::exist() => {
+                self.ask_password(
+                    "Passphrase for your saved passwords",
+                    PasswordPrompt::Unlock { account },
+                );
+            }
+            None => self.ask_password(
+                &format!("Password for {account}"),
+                PasswordPrompt::Account { account },
+            ),
+        }
+
+        Ok(())
+    }
+
+    async fn send_login(
+        &mut self,
+        account: String,
+        password: Option<String>,
+    ) -> anyhow::Result<()> {
+        let request = Request::new(
+            rand::random::<u32>(),
+            RequestMessage::Login {
+                account,
+                password: password.map(Secret),
+            },
+        );
+
+        self.send(request).await
+    }
+
+    /// Takes `nick` back from whoever is using it, with the password given
+    /// or else the one saved for it, asking for it if there is neither.
+    async fn ghost(&mut self, nick: String, password: Option<&str>) -> anyhow::Result<()> {
+        if nick.is_empty() {
+            self.history.error("Usage: /ghost <nick> [password]");
+            return Ok(());
+        }
+
+        let password = password.map(str::to_owned).or_else(|| {
+            self.credentials
+                .as_ref()
+                .and_then(|credentials| credentials.get(&self.server, &nick))
+                .map(str::to_owned)
+        });
+
+        match password {
+            Some(password) => self.send_ghost(nick, password).await?,
+            None => self.ask_password(
+                &format!("Password for {nick}"),
+                PasswordPrompt::Ghost { nick },
+            ),
+        }
+
+        Ok(())
+    }
+
+    async fn send_ghost(&mut self, nick: String, password: String) -> anyhow::Result<()> {
+        let request = Request::new(
+            rand::random::<u32>(),
+            RequestMessage::Ghost {
+                nick,
+                password: Secret(password),
+            },
+        );
+
+        self.send(request).await
+    }
+
+    fn ask_password(&mut self, question: &str, prompt: PasswordPrompt) {
+        self.password_prompt = Some(prompt);
+        self.open_overlay(Password::new(question));
+    }
+
+    async fn handle_password(&mut self, value: String) -> anyhow::Result<()> {
+        match self.password_prompt.take() {
+            Some(PasswordPrompt::Unlock { account }) => match Credentials::load(value) {
+                Ok(credentials) => {
+                    self.credentials = Some(credentials);
+                    self.log_in(account).await?;
+                }
+                Err(err) => self.history.error(&err.to_string()),
+            },
+            // Accounts without a password log in with none, and there is
+            // nothing to save
+            Some(PasswordPrompt::Account { account }) if value.is_empty() => {
+                self.send_login(account, None).await?;
+            }
+            Some(PasswordPrompt::Account { account }) => {
+                self.unsaved_login = Some((account.clone(), value.clone()));
+                self.send_login(account, Some(value)).await?;
+            }
+            Some(PasswordPrompt::Ghost { nick }) if value.is_empty() => {
+                self.history
+                    .error(&format!("Taking back {nick} needs its password"));
+            }
+            Some(PasswordPrompt::Ghost { nick }) => {
+                self.unsaved_login = Some((nick.clone(), value.clone()));
+                self.send_ghost(nick, value).await?;
+            }
+            Some(PasswordPrompt::Register | PasswordPrompt::Auth) if value.is_empty() => {
+                self.history.error("A registered nick needs a password");
+            }
+            Some(PasswordPrompt::Register) => {
+                let request = RequestMessage::Register(Secret(value));
+                self.send(Request::new(rand::random::<u32>(), request))
+                    .await?;
+            }
+            Some(PasswordPrompt::Auth) => {
+                let request = RequestMessage::Auth(Secret(value));
+                self.send(Request::new(rand::random::<u32>(), request))
+                    .await?;
+            }
+            Some(PasswordPrompt::NewPassphrase { account, password }) => {
+                let mut credentials = Credentials::new(value);
+                self.save_password(&mut credentials, &account, &password);
+                self.credentials = Some(credentials);
+            }
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    /// Saves the password typed for the account just logged into, asking
+    /// for a passphrase to save it with if it is the first.
+    fn save_login(&mut self) {
+        let Some((account, password)) = self.unsaved_login.take() else {
+            return;
+        };
+
+        match self.credentials.take() {
+            Some(mut credentials) => {
+                self.save_password(&mut credentials, &account, &password);
+                self.credentials = Some(credentials);
+            }
+            None => self.ask_password(
+                "Pick a passphrase to save your password with, or Esc not to",
+                PasswordPrompt::NewPassphrase { account, password },
+            ),
+        }
+    }
+
+    fn save_password(&mut self, credentials: &mut Credentials, account: &str, password: &str) {
+        match credentials.save(&self.server, account, password) {
+            Ok(()) => self
+                .history
+                .info(&format!("Saved the password for {account}")),
+            Err(err) => self.history.error(&err.to_string()),
+        }
+    }
+
+    /// Handles the next response, or reconnects if the connection is down.
+    pub(crate) async fn read(&mut self) -> anyhow::Result<()> {
+        let next = match &mut self.link {
+            Link::Up { res, .. } => res.next().await,
+            Link::Down(_) => return self.reconnect().await,
+            Link::Closed => future::pending().await,
+        };
+
+        match next {
+            Some(Ok(res)) => {
+                if let Link::Up { stats, .. } = &mut self.link {
+                    stats.frames_received += 1;
+                }
+
+                let is_from_bot = res.is_from_bot();
+                let message_id = res.message_id;
+                let Response {
+                    message,
+                    origin,
+                    timestamp,
+                    code,
+                    request_id,
+                    ..
+                } = res;
+                let res_timestamp = timestamp;
+                let timestamp = timestamp::format(timestamp, &config::current().layout);
+                self.history.receiving = Some(Received {
+                    code,
+                    request_id,
+                    origin: origin.clone(),
+                    timestamp: res_timestamp,
+                });
+
+                match code {
+                    ERR_RATE_LIMITED => {
+                        let retry_after = Duration::from_millis(message.parse().unwrap_or(1000));
+                        self.cooldown_until = Some(Instant::now() + retry_after);
+
+                        if let Some(i) = self.recently_sent.iter().position(|r| r.id == request_id)
+                        {
+                            if let Some(request) = self.recently_sent.remove(i) {
+                                self.history.unack(request.id);
+                                self.retrying.push_back(request);
+                            }
+                        }
+                    }
+                    RES_PRESENCE => {
+                        if let Some(presence) = Presence::from_name(&message) {
+                            self.presence = presence;
+
+                            match presence {
+                                Presence::Online => self.history.info("You are now online"),
+                                Presence::DoNotDisturb => self.history.info(
+                                    "Do not disturb is on, mentions and direct messages won't make a sound",
+                                ),
+                                // The server has already said so in `RES_AWAY`
+                                Presence::Away => (),
+                            }
+                        }
+                    }
+                    RES_TOPIC_CHANGE => {
+                        self.topic.0 = message;
+                    }
+                    RES_YOUR_NICK => {
+                        self.history.nick.clone_from(&message);
+                        self.prompt.nick = message;
+                    }
+                    RES_COMMAND_LIST => {
+                        let mut commands = CommandSpec::decode_list(&message);
+
+                        // Ours win, e.g. /disconnect which the server also knows
+                        commands.retain(|spec| !self.prompt.is_local_command(&spec.name));
+                        commands.sort_by_key(|a| a.name.to_lowercase());
+
+                        self.prompt.commands = commands;
+                        self.has_command_list = true;
+                    }
+                    RES_HELLO => {
+                        self.history
+                            .membership(Membership::Join, &message, &timestamp);
+                    }
+                    RES_BUILD_INFO => self.server_build_info(&message).await?,
+                    RES_FRAME_HMAC => {
+                        if let (Link::Up { req, .. }, Some(key)) =
+                            (&mut self.link, &self.session_key)
+                        {
+                            req.encoder_mut()
+                                .set_integrity(Integrity::Hmac(key.clone()));
+                        }
+                    }
+                    RES_GOODBYE => {
+                        self.history
+                            .membership(Membership::Part, &message, &timestamp);
+                    }
+                    RES_NICK_CHANGE => {
+                        self.history
+                            .membership(Membership::NickChange, &message, &timestamp);
+                    }
+                    RES_NICK_LIST => {
+                        let mut nicks = NickListEntry::decode_list(&message);
+
+                        nicks.sort_by_key(|a| a.nick.to_lowercase());
+
+                        self.prompt.nicks = nicks;
+                    }
+                    RES_ACK_MESSAGE => match message.parse::<u32>() {
+                        Ok(id) => self.history.ack(id),
+                        Err(_) => self
+                            .history
+                            .error(&format!("Received a malformed ack: {message}")),
+                    },
+                    RES_DIRECT_MESSAGE => {
+                        self.notify(Reason::DirectMessage, &origin);
+                        self.announce(
+                            Reason::DirectMessage,
+                            &origin,
+                            format!("Direct message from {origin}: {message}"),
+                        );
+
+                        let message = format!("(direct) {message}");
+                        if is_from_bot {
+                            self.history.bot_message(&message, &timestamp, &origin);
+                        } else {
+                            self.history.message(&message, &timestamp, &origin, None);
+                        }
+                    }
+                    RES_SELF_MESSAGE => {
+                        // Sent from another of our devices, so it's ours already
+                        self.history
+                            .message(&message, &timestamp, &self.prompt.nick, None);
+                        self.history.set_message_id(message_id);
+                    }
+                    RES_MESSAGE_SENT => self.history.sent(request_id, message_id),
+                    RES_MESSAGE_LIMIT => {
+                        self.max_message_chars = message.parse().unwrap_or(0);
+                    }
+                    RES_MENTION_POLICY => {
+                        let mentions = MentionPolicy::from_name(&message).unwrap_or_default();
+                        self.set_parser_options(ParserOptions { mentions });
+                    }
+                    ERR_MESSAGE_TOO_LONG => {
+                        self.history.unack(request_id);
+                        self.history.error(&errors::explain(code, &message));
+                    }
+                    RES_LINK_PREVIEW => {
+                        if let Some(preview) = LinkPreview::decode(&message) {
+                            self.history.link_preview(message_id, &preview);
+                        }
+                    }
+                    RES_TIP => self.history.info(&message),
+                    RES_MOTD => self.history.motd(&message),
+                    RES_JOINED | RES_PARTED | RES_CHANNEL_MESSAGE | RES_CHANNEL_NOTICE => {
+                        match ChannelText::decode(&message) {
+                            Some(ChannelText { channel, text }) => {
+                                self.channel_response(code, &channel, &text, &timestamp, &origin);
+                            }
+                            None => self.history.error("Received a malformed channel response"),
+                        }
+                    }
+                    // Whoever changed these has been told in words already
+                    // @TODO: Keep channel topics, members and modes once
+                    // channels have buffers of their own
+                    RES_CHANNEL_MEMBERS | RES_CHANNEL_TOPIC | RES_CHANNEL_MODE => {}
+                    RES_UPLOADED => self.history.info(&format!(
+                        "Uploaded as {message}, others can fetch it with /download {message}"
+                    )),
+                    RES_ATTACHMENT => match Attachment::decode(&message) {
+                        Some(attachment) => self.save_attachment(attachment).await,
+                        None => self.history.error("Received a malformed attachment"),
+                    },
+                    RES_BOOKMARK_LIST => {
+                        let bookmarks = Bookmark::decode_list(&message);
+
+                        if bookmarks.is_empty() {
+                            self.history
+                                .info("No bookmarks yet, add one with /bookmark <id>");
+                        } else {
+                            self.open_overlay(Bookmarks::new(bookmarks));
+                        }
+                    }
+                    RES_SELF_DIRECT_MESSAGE => {
+                        self.history.message(
+                            &format!("(direct to {origin}) {message}"),
+                            &timestamp,
+                            &self.prompt.nick,
+                            None,
+                        );
+                    }
+                    RES_CHAT_MESSAGE_OK => {
+                        if let Some(entry) = self.prompt.nicks.iter_mut().find(|e| e.nick == origin)
+                        {
+                            entry.last_active = res_timestamp;
+                        }
+
+                        if is_from_bot {
+                            self.history.bot_message(&message, &timestamp, &origin);
+                        } else {
+                            self.history.message(&message, &timestamp, &origin, None);
+                        }
+                        self.history.set_message_id(message_id);
+                        self.mark_read(message_id).await?;
+                    }
+                    RES_READ_RECEIPT => {
+                        let readers = message.lines().map(str::to_owned).collect();
+                        self.history.read_by(message_id, readers);
+                    }
+                    // The message itself arrives as a chat message, these only say
+                    // that we are one of the nicks it reaches, which the server
+                    // works out as it knows everyone's nick in the right case
+                    RES_MISSED_MENTIONS => {
+                        self.missed_mentions = Bookmark::decode_list(&message);
+                        self.history
+                            .info(&missed_mentions_summary(&self.missed_mentions));
+                    }
+                    RES_EVERYONE_MENTIONED | RES_MENTIONED => {
+                        self.notify(Reason::Mention, MAIN_BUFFER);
+
+                        if let Some(text) = self.history.sent_by(&origin, message_id) {
+                            let text = format!("{origin} mentioned you: {text}");
+                            self.announce(Reason::Mention, MAIN_BUFFER, text);
+                        }
+                    }
+                    RES_LOGGED_IN => {
+                        self.history.message(&message, &timestamp, &origin, None);
+                        self.save_login();
+                    }
+                    ERR_WRONG_PASSWORD => {
+                        self.unsaved_login = None;
+                        self.history.error(&errors::explain(code, &message));
+                    }
+                    _ if errors::is_error(code) => {
+                        self.history.error(&errors::explain(code, &message));
+                    }
+                    _ => self.history.message(&message, &timestamp, &origin, None),
+                }
+
+                self.history.receiving = None;
+            }
+            // Frames can't be read past an error, so it's as good as closed
+            Some(Err(err)) => self.disconnected(&err.to_string()),
+            None => self.disconnected("the server closed it"),
+        }
+
+        Ok(())
+    }
+
+    /// Logs what the server was built from, mentions it if the server can't
+    /// read the version of the protocol we speak, and tells it about us.
+    async fn server_build_info(&mut self, encoded: &str) -> anyhow::Result<()> {
+        let Some(theirs) = BuildInfo::decode(encoded) else {
+            log!(Warn, "Couldn't read the server's build info: {encoded:?}");
+            return Ok(());
+        };
+        log!(Info, "{} is running {theirs}", self.server);
+
+        let reads = format!(
+            "{} {} reads protocol {}-{}",
+            theirs.name,
+            theirs.version,
+            theirs.protocol.start(),
+            theirs.protocol.end()
+        );
+        match theirs.compare_protocol(version::LATEST) {
+            Ordering::Less => self.history.info(&format!(
+                "This client speaks protocol {}, but {reads}. Updating the client may help if anything goes missing",
+                version::LATEST
+            )),
+            Ordering::Greater => self.history.info(&format!(
+                "This client speaks protocol {}, but {reads}, so some things may not work",
+                version::LATEST
+            )),
+            Ordering::Equal => (),
+        }
+
+        let ours = crate::build_info().encode();
+        self.send(Request::new(
+            rand::random::<u32>(),
+            RequestMessage::BuildInfo(ours),
+        ))
+        .await
+    }
+
+    fn channel_response(
+        &mut self,
+        code: u16,
+        channel: &str,
+        text: &str,
+        timestamp: &str,
+        origin: &str,
+    ) {
+        match code {
+            RES_JOINED if text.is_empty() => self.history.info(&format!("Joined {channel}")),
+            RES_JOINED => self
+                .history
+                .info(&format!("Joined {channel}, the topic is: {text}")),
+            RES_PARTED => self.history.info(&format!("Left {channel}")),
+            RES_CHANNEL_MESSAGE => {
+                self.history
+                    .message(&format!("[{channel}] {text}"), timestamp, origin, None);
+            }
+            _ => self.history.info(&format!("[{channel}] {text}")),
+        }
+    }
+
+    /// Saves `attachment` where `/download` asked for it, asking first if
+    /// there is a file there already. Attachments nobody asked for are
+    /// dropped.
+    async fn save_attachment(&mut self, attachment: Attachment) {
+        let Some(path) = self.downloads.remove(&attachment.id) else {
+            log!(
+                Warn,
+                "Dropped attachment {} which wasn't asked for",
+                attachment.id
+            );
+            return;
+        };
+
+        // Never trust the name to stay in the current directory
+        let path = path.unwrap_or_else(|| {
+            std::path::Path::new(&attachment.name)
+                .file_name()
+                .map_or_else(
+                    || attachment.id.clone(),
+                    |n| n.to_string_lossy().into_owned(),
+                )
+        });
+
+        if !self.write_attachment(&path, &attachment, true).await {
+            self.open_overlay(Confirm::with_action(
+                &format!("{path} already exists, overwrite it?"),
+                OverlayAction::Overwrite { path, attachment },
+            ));
+        }
+    }
+
+    /// Writes `attachment` to `path`, unless `is_new_only` and there is a
+    /// file there already, in which case it's left alone and `false` is
+    /// returned.
+    async fn write_attachment(
+        &mut self,
+        path: &str,
+        attachment: &Attachment,
+        is_new_only: bool,
+    ) -> bool {
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .create_new(is_new_only)
+            .open(path)
+            .await;
+
+        let written = match file {
+            Ok(mut file) => file.write_all(&attachment.data).await,
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => return false,
+            Err(err) => Err(err),
+        };
+
+        match written {
+            Ok(()) => self
+                .history
+                .info(&format!("Saved {} to {path}", attachment.name)),
+            Err(err) => self.history.error(&format!("Couldn't write {path}: {err}")),
+        }
+
+        true
+    }
+
+    fn notify(&self, reason: Reason, buffer: &str) {
+        if self.presence != Presence::DoNotDisturb {
+            notify::notify(reason, buffer);
+        }
+    }
+
+    fn announce(&self, reason: Reason, buffer: &str, text: String) {
+        if self.presence != Presence::DoNotDisturb {
+            notify::announce(reason, buffer, text);
+        }
+    }
+
+    async fn handle_local_command(&mut self, ast: &AstMessage, to_send: &str) -> bool {
+        match ast {
+            AstMessage::Command(AstNode::Command {
+                raw_name,
+                parsed_name,
+                ..
+            }) => {
                 match parsed_name.as_str() {
                     "exit" => {
                         // @TODO: Just leave channel. not program
@@ -478,33 +2675,140 @@ impl ChatWindow {
                         .unwrap();
                         std::process::exit(0);
                     }
-                    /* "connect" => match args.first() {
-                        Some(AstNode::Text { value, .. }) => {
-                            if self.stream.is_some() {
-                                self.history.error("Already connected!");
-                            } else {
-                                let stream = TcpStream::connect(value.trim())
-                                    .and_then(|s| {
-                                        s.set_nonblocking(true)?;
-                                        Ok(s)
-                                    })
-                                    .ok();
-
-                                if stream.is_none() {
-                                    self.history
-                                        .error(format!("Could not connect to {value}").as_str());
+                    "reload" => {
+                        if let Err(err) = config::reload() {
+                            self.history.error(&err.to_string());
+                        }
+
+                        true
+                    }
+                    "export" => {
+                        let path = Self::rest_of_command(to_send, raw_name);
+                        let path = Some(path.as_str()).filter(|path| !path.is_empty());
+
+                        match export::write(&self.history, path) {
+                            Ok(path) => self
+                                .history
+                                .info(&format!("Exported to {}", path.display())),
+                            Err(err) => self.history.error(&err.to_string()),
+                        }
+
+                        true
+                    }
+                    "set" => {
+                        let rest = Self::rest_of_command(to_send, raw_name);
+
+                        let Some((key, value)) = rest.split_once(char::is_whitespace) else {
+                            self.history.error("Usage: /set <key> <value...>");
+                            return true;
+                        };
+
+                        match config::set(key, value.trim()) {
+                            Ok(()) => self.history.info(&format!("Set {key} to {}", value.trim())),
+                            Err(err) => self.history.error(&err.to_string()),
+                        }
+
+                        // A theme's colors win over those in the config file
+                        if let (true, Some(theme)) = (key.starts_with("colors."), config!(theme)) {
+                            self.history
+                                .info(&format!("Colors from the {theme} theme are shown instead"));
+                        }
+
+                        true
+                    }
+                    "snippet" => {
+                        let name = Self::rest_of_command(to_send, raw_name);
+
+                        match config::current().snippets.get(&name) {
+                            Some(text) => self.prompt.fill_snippet(text),
+                            None => self.history.error(&format!("No snippet named {name}")),
+                        }
+
+                        true
+                    }
+                    "connect" => {
+                        let addr = Self::rest_of_command(to_send, raw_name);
+                        let addr = if addr.is_empty() {
+                            self.server.clone()
+                        } else {
+                            addr
+                        };
+
+                        self.connect(&addr).await;
+
+                        true
+                    }
+                    "connstats" => {
+                        let Link::Up { stats, .. } = &self.link else {
+                            self.history.error("Not connected to a server");
+                            return true;
+                        };
+
+                        let uptime = connection::format_uptime(stats.connected_at.elapsed());
+                        let table = stats.table(self.history.unacked());
+                        self.history
+                            .info(&format!("Connected to {} for {uptime}", self.server));
+                        for row in table {
+                            self.history.info(&row);
+                        }
+
+                        true
+                    }
+                    "open" => {
+                        let n = Self::rest_of_command(to_send, raw_name);
+                        let Ok(n) = (if n.is_empty() {
+                            Ok(1)
+                        } else {
+                            n.parse::<usize>()
+                        }) else {
+                            self.history.error("Usage: /open [n]");
+                            return true;
+                        };
+
+                        let links = self.history.links();
+                        match n.checked_sub(1).and_then(|i| links.get(i)) {
+                            Some(url) => {
+                                if let Err(err) = opener::open(url).await {
+                                    self.history.error(&format!("{err:#}"));
                                 }
+                            }
+                            None => self.history.error(&format!("There is no link {n} to open")),
+                        }
 
-                                self.stream = stream;
+                        true
+                    }
+                    "mentions" => {
+                        // Those missed while away may well be in the history too
+                        let mut mentions = self.missed_mentions.clone();
+                        for mention in self.history.mentions() {
+                            if !mentions.iter().any(|m| m.message_id == mention.message_id) {
+                                mentions.push(mention);
                             }
+                        }
+                        mentions.sort_by_key(|mention| mention.timestamp);
 
-                            true
+                        if mentions.is_empty() {
+                            self.history.info("Nobody has mentioned you yet");
+                        } else {
+                            self.open_overlay(Bookmarks::mentions(mentions));
                         }
-                        Some(_) | None => {
-                            self.history.error("Usage: connect <addr>");
-                            false
+
+                        true
+                    }
+                    "disconnect" => {
+                        match self.link {
+                            Link::Closed => self.history.error("Not connected to a server"),
+                            _ => {
+                                self.close().await;
+                                self.history.info(&format!(
+                                    "Disconnected from {}, /connect to go back",
+                                    self.server
+                                ));
+                            }
                         }
-                    }, */
+
+                        true
+                    }
                     _ => false,
                 }
             }
@@ -512,37 +2816,902 @@ impl ChatWindow {
         }
     }
 
-    fn to_local_time(&self, timestamp: u64) -> String {
-        use chrono::{Local, TimeZone, Utc};
+    /// Everything typed after the command name, for commands whose last
+    /// argument is variadic.
+    fn rest_of_command(to_send: &str, raw_name: &str) -> String {
+        to_send
+            .trim_start()
+            .strip_prefix(raw_name)
+            .unwrap_or_default()
+            .trim()
+            .to_owned()
+    }
 
-        let local = Utc
-            .timestamp_opt(timestamp as i64, 0)
-            .unwrap()
-            .with_timezone(&Local);
+    /// The value of the first argument, quoted or not.
+    fn first_arg(args: &[AstNode]) -> Option<String> {
+        match args
+            .iter()
+            .find(|arg| !matches!(arg, AstNode::Whitespace { .. }))
+        {
+            Some(AstNode::Text { value, .. }) => Some(value.to_owned()),
+            Some(AstNode::Quoted { parsed_text, .. }) => Some(parsed_text.to_owned()),
+            _ => None,
+        }
+    }
+
+    /// The value of the only argument, if it was given in quotes.
+    fn sole_quoted_arg(args: &[AstNode]) -> Option<String> {
+        let mut args = args
+            .iter()
+            .filter(|arg| !matches!(arg, AstNode::Whitespace { .. }));
 
-        local.format("%H:%M:%S").to_string()
+        match (args.next(), args.next()) {
+            (Some(AstNode::Quoted { parsed_text, .. }), None) => Some(parsed_text.clone()),
+            _ => None,
+        }
     }
 }
 
-impl Renderable for ChatWindow {
-    fn render_into(&self, buf: &mut crate::RenderBuffer, rect: &crate::Rect) {
-        self.topic.render_into(
+impl Composite for ChatWindow {
+    fn place<'a>(&'a self, frame: &mut Frame<'a>, rect: Rect) {
+        let layout = &config::current().layout;
+        let pending = self.prompt.current_value();
+        let show_preview = layout.show_preview && !pending.trim().is_empty();
+
+        let [status, history, preview, prompt] = rect.split(
+            Direction::Vertical,
+            &[
+                Constraint::Fixed(1),
+                Constraint::Flex(1),
+                Constraint::Fixed(u16::from(show_preview)),
+                Constraint::Fixed(2),
+            ],
+        )[..] else {
+            unreachable!()
+        };
+
+        let connection = self.connection();
+        let cooldown = CooldownIndicator(self.cooldown());
+        let presence = PresenceIndicator(self.presence);
+        let [topic, connection_rect, cooldown_rect, presence_rect] = status.split(
+            Direction::Horizontal,
+            &[
+                Constraint::Flex(1),
+                Constraint::Fixed(connection.width()),
+                Constraint::Fixed(cooldown.width()),
+                Constraint::Fixed(presence.width()),
+            ],
+        )[..] else {
+            unreachable!()
+        };
+
+        frame.place(&self.topic, topic);
+        frame.place(connection, connection_rect);
+        frame.place(cooldown, cooldown_rect);
+        frame.place(presence, presence_rect);
+        frame.place(&self.history, history);
+
+        if show_preview {
+            frame.place(
+                Preview {
+                    nick: &self.prompt.nick,
+                    pending,
+                    options: self.history.parser_options,
+                },
+                preview,
+            );
+        }
+
+        self.prompt.place(frame, prompt);
+
+        for overlay in &self.overlays {
+            frame.modal(overlay.as_ref(), overlay.rect(&rect));
+        }
+    }
+}
+
+/// Counts down the seconds until the server takes messages again after
+/// rate limiting us, with anything sent meanwhile held until then.
+struct CooldownIndicator(Option<Duration>);
+
+impl CooldownIndicator {
+    fn label(&self) -> String {
+        match self.0 {
+            // Rounded up so that it never shows 0s while still waiting
+            Some(cooldown) => format!(" WAIT {}s ", cooldown.as_millis().div_ceil(1000)),
+            None => String::new(),
+        }
+    }
+
+    fn width(&self) -> u16 {
+        str_width(&self.label())
+    }
+}
+
+impl Renderable for CooldownIndicator {
+    fn render_into(&self, buf: &mut crate::RenderBuffer, rect: &Rect) {
+        for (i, ch) in self.label().chars().enumerate() {
+            buf.put_at(
+                rect.x + i as u16,
+                rect.y,
+                ch,
+                config_hex_color!(colors.error_bg),
+                config_hex_color!(colors.error_fg),
+                CellStyle::Bold,
+            );
+        }
+    }
+}
+
+/// Shown in the topic bar while the connection is down, counting down the
+/// seconds until the next attempt to get it back, or after `/disconnect`.
+enum ConnectionIndicator {
+    Up,
+    Waiting(Duration),
+    Connecting,
+    Closed,
+}
+
+impl ConnectionIndicator {
+    fn label(&self) -> String {
+        match self {
+            Self::Up => String::new(),
+            Self::Waiting(wait) => {
+                format!(" RECONNECTING IN {}s ", wait.as_millis().div_ceil(1000))
+            }
+            Self::Connecting => " RECONNECTING ".to_owned(),
+            Self::Closed => " DISCONNECTED ".to_owned(),
+        }
+    }
+
+    fn width(&self) -> u16 {
+        str_width(&self.label())
+    }
+}
+
+impl Renderable for ConnectionIndicator {
+    fn render_into(&self, buf: &mut crate::RenderBuffer, rect: &Rect) {
+        for (i, ch) in self.label().chars().enumerate() {
+            buf.put_at(
+                rect.x + i as u16,
+                rect.y,
+                ch,
+                config_hex_color!(colors.error_bg),
+                config_hex_color!(colors.error_fg),
+                CellStyle::Bold,
+            );
+        }
+    }
+}
+
+/// Shown at the end of the topic bar while not online, so that it's hard to
+/// forget about being away or in do not disturb.
+struct PresenceIndicator(Presence);
+
+impl PresenceIndicator {
+    fn label(&self) -> &'static str {
+        match self.0 {
+            Presence::Online => "",
+            Presence::Away => " AWAY ",
+            Presence::DoNotDisturb => " DND ",
+        }
+    }
+
+    fn width(&self) -> u16 {
+        str_width(self.label())
+    }
+}
+
+impl Renderable for PresenceIndicator {
+    fn render_into(&self, buf: &mut crate::RenderBuffer, rect: &Rect) {
+        for (i, ch) in self.label().chars().enumerate() {
+            buf.put_at(
+                rect.x + i as u16,
+                rect.y,
+                ch,
+                config_hex_color!(colors.topic_fg),
+                config_hex_color!(colors.topic_bg),
+                CellStyle::Bold,
+            );
+        }
+    }
+}
+
+/// The message being typed, rendered through the same path as the history so
+/// that the preview can never disagree with what ends up being sent.
+///
+/// Emoji aren't expanded from shortcodes, as nothing sends them that way:
+/// they are typed as they are or put in by the picker, so the preview shows
+/// them as they are too.
+struct Preview<'a> {
+    nick: &'a str,
+    pending: String,
+    options: ParserOptions,
+}
+
+impl Renderable for Preview<'_> {
+    fn render_into(&self, buf: &mut crate::RenderBuffer, rect: &Rect) {
+        let mut preview = ChatHistoryEntry::new(
+            parse_with_options(&self.pending, self.options),
+            Some(self.nick.to_owned()),
+            timestamp::now(&config::current().layout),
+            None,
+        );
+        preview.is_confirmed = true;
+        preview.render_row(
             buf,
+            rect.x,
+            rect.y,
+            rect.width,
+            &config::current().layout,
+            false,
+        );
+    }
+}
+
+/// One line on what was missed while away, naming everyone who mentioned us
+/// in the order they first did.
+fn missed_mentions_summary(missed: &[Bookmark]) -> String {
+    let mut nicks: Vec<&str> = vec![];
+    for mention in missed {
+        if !nicks.contains(&mention.nick.as_str()) {
+            nicks.push(&mention.nick);
+        }
+    }
+
+    let times = match missed.len() {
+        1 => "once".to_owned(),
+        count => format!("{count} times"),
+    };
+    let by = match nicks.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} and {last}", rest.join(", ")),
+        None => String::new(),
+    };
+
+    format!("You were mentioned {times} while away, by {by}. /mentions lists them")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styled_text_merges_adjacent_runs() {
+        let plain =
+            ChatHistoryPartStyle::new(style::Color::Reset, style::Color::Reset, CellStyle::Normal);
+        let bold =
+            ChatHistoryPartStyle::new(style::Color::Reset, style::Color::Reset, CellStyle::Bold);
+
+        let mut text = StyledText::default();
+        text.push("hello", plain);
+        text.push(" ", plain);
+        text.push("@world", bold);
+
+        assert_eq!(text.text, "hello @world");
+        assert_eq!(
+            text.iter().collect::<Vec<_>>(),
+            vec![("hello ", &plain), ("@world", &bold)]
+        );
+    }
+
+    #[test]
+    fn test_formatted_text_hides_markers() {
+        let author = Some("bob".to_owned());
+        let body = ChatHistoryEntry::body_for_ast(&parse("*very big* _deal_ `x`"), &author);
+        let runs = body
+            .iter()
+            .map(|(text, part_style)| (text, part_style.attr))
+            .collect::<Vec<_>>();
+
+        assert_eq!(body.text, "very big deal x");
+        assert_eq!(
+            runs,
+            vec![
+                ("very", CellStyle::Bold),
+                (" ", CellStyle::Bold),
+                ("big", CellStyle::Bold),
+                (" ", CellStyle::Normal),
+                ("deal", CellStyle::Italic),
+                (" ", CellStyle::Normal),
+                ("x", CellStyle::Normal),
+            ]
+        );
+        assert_eq!(
+            body.iter().last().unwrap().1.bg,
+            config_hex_color!(colors.timestamp_bg)
+        );
+    }
+
+    #[test]
+    fn test_links_latest_first() {
+        let mut history = ChatHistory::new();
+        history.message("see http://a.example", "12:00:00", "alice", None);
+        history.message(
+            "*https://b.example* or `https://c.example` (https://d.example).",
+            "12:00:01",
+            "bob",
+            None,
+        );
+
+        assert_eq!(
+            history.links(),
+            vec!["https://d.example", "https://b.example", "http://a.example"]
+        );
+        assert!(history
+            .entries
+            .last()
+            .unwrap()
+            .body
+            .iter()
+            .any(|(text, part_style)| text == "https://b.example"
+                && part_style.attr == CellStyle::Underlined));
+    }
+
+    #[test]
+    fn test_format_author_right_aligned() {
+        let layout = Layout::default();
+        assert_eq!(
+            ChatHistoryEntry::format_author(Some("user"), false, &layout),
+            format!(" {:>17} ", "@user")
+        );
+    }
+
+    #[test]
+    fn test_format_author_left_aligned() {
+        let layout = Layout {
+            nick_alignment: Alignment::Left,
+            ..Layout::default()
+        };
+        assert_eq!(
+            ChatHistoryEntry::format_author(Some("user"), false, &layout),
+            format!(" {:<17} ", "@user")
+        );
+    }
+
+    #[test]
+    fn test_format_author_truncates_to_nick_width() {
+        let layout = Layout {
+            nick_width: 4,
+            gutter_padding: 0,
+            ..Layout::default()
+        };
+        assert_eq!(
+            ChatHistoryEntry::format_author(Some("username"), false, &layout),
+            "@user"
+        );
+    }
+
+    #[test]
+    fn test_format_author_bot() {
+        let layout = Layout {
+            nick_width: 8,
+            gutter_padding: 0,
+            ..Layout::default()
+        };
+        assert_eq!(
+            ChatHistoryEntry::format_author(Some("username"), true, &layout),
+            "[bot] @us"
+        );
+    }
+
+    #[test]
+    fn test_format_author_server_message() {
+        let layout = Layout {
+            nick_width: 4,
+            ..Layout::default()
+        };
+        assert_eq!(
+            ChatHistoryEntry::format_author(None, false, &layout),
+            "    -- "
+        );
+    }
+
+    fn history() -> ChatHistory {
+        let mut history = ChatHistory::new();
+        history.message("bob has joined", "11:59:00", "", None);
+        history.message("hello @bob, see #general", "12:00:00", "alice", None);
+        history.set_read_marker();
+        history.message("/nick robert", "12:00:05", "bob", None);
+        history.message("still sending", "12:00:10", "alice", Some(1));
+
+        history
+    }
+
+    #[test]
+    fn test_snapshot_history() {
+        for (width, height) in [(80, 6), (40, 6), (40, 3)] {
+            insta::assert_snapshot!(
+                format!("history_{width}x{height}"),
+                crate::RenderBuffer::snapshot(&history(), width, height)
+            );
+        }
+    }
+
+    #[test]
+    fn test_mentions_of_our_nick_are_highlighted() {
+        let mut history = ChatHistory::new();
+        history.nick = "bob".to_owned();
+        history.message("hello @Bob", "12:00:00", "alice", None);
+        history.set_message_id(7);
+        history.message("no id yet @bob", "12:00:01", "alice", None);
+        history.message("note to self @bob", "12:00:02", "bob", None);
+        history.message("hello @bobby", "12:00:03", "carol", None);
+
+        let highlighted = history
+            .entries
+            .iter()
+            .map(|entry| entry.mentions_me)
+            .collect::<Vec<bool>>();
+        assert_eq!(highlighted, vec![true, true, false, false]);
+
+        let mentions = history.mentions();
+        assert_eq!(mentions.len(), 1);
+        assert_eq!(
+            (mentions[0].message_id, mentions[0].nick.as_str()),
+            (7, "alice")
+        );
+
+        // The whole row, not just the text
+        let mut buf = crate::RenderBuffer::new(40, 4);
+        history.render_into(
+            &mut buf,
             &Rect {
-                x: rect.x,
-                y: rect.y,
-                width: rect.width,
-                height: 1,
+                x: 0,
+                y: 0,
+                width: 40,
+                height: 4,
             },
         );
-        self.history.render_into(
-            buf,
+        let highlight = config_hex_color!(colors.mention_highlight);
+        assert_eq!(buf.cells[39].bg, highlight);
+        assert_ne!(buf.cells[3 * 40 + 39].bg, highlight);
+    }
+
+    #[test]
+    fn test_motd_is_set_apart() {
+        let mut history = ChatHistory::new();
+        history.motd("Be nice\n\nNo spam");
+
+        let lines = history
+            .entries
+            .iter()
+            .map(|entry| entry.body.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(lines, vec!["┃ Be nice", "┃ ", "┃ No spam"]);
+        assert!(history.entries.iter().all(|entry| entry.author.is_none()));
+    }
+
+    #[test]
+    fn test_combining_marks_share_a_cell() {
+        let mut history = ChatHistory::new();
+        history.message("cafe\u{301} ok", "12:00:00", "alice", None);
+
+        let mut buf = crate::RenderBuffer::new(40, 1);
+        history.render_into(
+            &mut buf,
             &Rect {
-                x: rect.x,
-                y: rect.y + 1,
-                width: rect.width,
-                height: rect.height,
+                x: 0,
+                y: 0,
+                width: 40,
+                height: 1,
+            },
+        );
+
+        let accented = buf.cells.iter().position(|c| c.marks == "\u{301}").unwrap();
+        let after = buf.cells[accented..accented + 4]
+            .iter()
+            .map(|c| c.ch)
+            .collect::<String>();
+        assert_eq!(after, "e ok");
+    }
+
+    #[test]
+    fn test_preview_matches_history() {
+        let pending = "hello @bob, *see* #general 🎉";
+        let mut history = ChatHistory::new();
+        history.message(pending, "12:00:00", "alice", None);
+        let preview = Preview {
+            nick: "alice",
+            pending: pending.to_owned(),
+            options: history.parser_options,
+        };
+
+        let render = |widget: &dyn Renderable| {
+            let mut buf = crate::RenderBuffer::new(60, 1);
+            widget.render_into(
+                &mut buf,
+                &Rect {
+                    x: 0,
+                    y: 0,
+                    width: 60,
+                    height: 1,
+                },
+            );
+            buf.cells
+        };
+        // Past the timestamp, which is the time it is rendered at
+        let body = |cells: Vec<crate::RenderCell>| {
+            let start = cells
+                .windows(5)
+                .position(|cells| cells.iter().map(|c| c.ch).eq("hello".chars()))
+                .unwrap();
+            cells[start..].to_vec()
+        };
+
+        let previewed = body(render(&preview));
+        assert_eq!(previewed, body(render(&history)));
+
+        // Formatted, rather than shown as typed
+        assert!(!previewed.iter().any(|c| c.ch == '*'));
+        let mention = previewed.iter().position(|c| c.ch == '@').unwrap();
+        assert_ne!(previewed[mention].fg, previewed[0].fg);
+    }
+
+    #[test]
+    fn test_scrollback() {
+        let mut history = history();
+        crate::RenderBuffer::snapshot(&history, 40, 3);
+        assert_eq!(history.page(), 3);
+
+        history.scroll_up(2);
+        assert_eq!(history.end(), 2);
+        assert!(crate::RenderBuffer::snapshot(&history, 40, 3).contains(" 2 lines below "));
+
+        // Stays put while scrolled back, rather than following the latest
+        history.message("anyone there?", "12:00:20", "bob", None);
+        assert_eq!(history.end(), 2);
+
+        history.scroll_up(10);
+        assert_eq!(history.end(), 1);
+        history.scroll_down(10);
+        assert_eq!(history.end(), 5);
+        history.message("yes", "12:00:30", "alice", None);
+        assert_eq!(history.end(), 6);
+
+        // Scrolling lets go of the selection but starts from it
+        history.select_older();
+        history.select_older();
+        history.scroll_up(1);
+        assert!(!history.has_selection());
+        assert_eq!(history.end(), 4);
+    }
+
+    #[test]
+    fn test_read_marker_stays_until_passed() {
+        let mut history = history();
+        crate::RenderBuffer::snapshot(&history, 40, 3);
+        history.message("anyone there?", "12:00:20", "bob", None);
+
+        history.set_read_marker();
+        assert_eq!(history.read_marker, Some(2));
+
+        history.scroll_up(3);
+        history.scroll_down(1);
+        assert_eq!(history.read_marker, Some(2));
+
+        history.message("yes", "12:00:30", "alice", None);
+        history.scroll_down(10);
+        assert_eq!(history.read_marker, None);
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_and_sends_held() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let mut window = ChatWindow::new(&server).await.unwrap();
+
+        drop(listener.accept().await.unwrap());
+        window.read().await.unwrap();
+        assert!(matches!(window.link, Link::Down(_)));
+        assert!(matches!(
+            window.connection(),
+            ConnectionIndicator::Waiting(_)
+        ));
+
+        let held = Request::new(7, RequestMessage::Message("still there?".to_owned()));
+        window.send(held.clone()).await.unwrap();
+
+        let (reconnected, accepted) = tokio::join!(window.read(), listener.accept());
+        reconnected.unwrap();
+        assert!(matches!(window.link, Link::Up { .. }));
+
+        // Hello again first, then what was held
+        let (stream, _) = accepted.unwrap();
+        let mut requests = FramedRead::new(stream, FrameCodec::<Request>::default());
+        let mut said_hello = false;
+        loop {
+            let request = requests.next().await.unwrap().unwrap();
+            if request == held {
+                break;
+            }
+            said_hello |= matches!(request.message, RequestMessage::Capabilities(_));
+        }
+        assert!(said_hello);
+    }
+
+    #[tokio::test]
+    async fn test_server_commands_are_checked_once_listed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let mut window = ChatWindow::new(&server).await.unwrap();
+        let errors = |window: &ChatWindow| {
+            window
+                .history
+                .entries
+                .iter()
+                .filter(|entry| entry.body.text.starts_with("Unknown command"))
+                .count()
+        };
+
+        // Left to the server until it says which it has
+        window.write("/frobnicate".to_owned()).await.unwrap();
+        assert_eq!(errors(&window), 0);
+
+        window.has_command_list = true;
+        window.write("/frobnicate".to_owned()).await.unwrap();
+        assert_eq!(errors(&window), 1);
+    }
+
+    #[tokio::test]
+    async fn test_passwords_are_not_echoed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let mut window = ChatWindow::new(&server).await.unwrap();
+
+        window.write("/register hunter2".to_owned()).await.unwrap();
+        window.write("/auth hunter2".to_owned()).await.unwrap();
+
+        let texts = window
+            .history
+            .entries
+            .iter()
+            .map(|entry| entry.body.text.as_str())
+            .collect::<Vec<_>>();
+        assert!(texts.contains(&"/register ••••"));
+        assert!(texts.contains(&"/auth ••••"));
+        assert!(!texts.iter().any(|text| text.contains("hunter2")));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_stays_disconnected() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let mut window = ChatWindow::new(&server).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        window.write("/disconnect".to_owned()).await.unwrap();
+        assert!(matches!(window.link, Link::Closed));
+        assert!(matches!(window.connection(), ConnectionIndicator::Closed));
+        assert_eq!(window.next_wake(), None);
+
+        let mut requests = FramedRead::new(stream, FrameCodec::<Request>::default());
+        let mut said_goodbye = false;
+        while let Some(Ok(request)) = requests.next().await {
+            said_goodbye |= request.message == RequestMessage::Disconnect;
+        }
+        assert!(said_goodbye);
+
+        // Nothing is held for a server we aren't going back to
+        window.write("anyone?".to_owned()).await.unwrap();
+        assert!(window.queued.is_empty());
+
+        drop(listener);
+        window.write(format!("/connect {server}")).await.unwrap();
+        assert!(matches!(window.link, Link::Closed));
+        assert!(window
+            .history
+            .entries
+            .last()
+            .unwrap()
+            .raw
+            .starts_with("Couldn't connect"));
+    }
+
+    #[test]
+    fn test_joins_collapse() {
+        let mut history = ChatHistory::new();
+        history.message("hi", "12:00:00", "alice", None);
+        history.membership(Membership::Join, "bob has joined", "12:00:01");
+        history.membership(Membership::Join, "carol has joined", "12:00:02");
+        assert_eq!(history.entries.len(), 3);
+
+        history.membership(Membership::Part, "bob has left the channel", "12:00:03");
+        history.membership(
+            Membership::NickChange,
+            "carol is now known as c",
+            "12:00:04",
+        );
+
+        let rows = history
+            .entries
+            .iter()
+            .map(|entry| entry.body.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(rows, vec!["hi", "2 joins, 1 part, 1 nick change"]);
+        assert_eq!(history.export().count(), 5);
+
+        history.select_older();
+        assert!(history.expand_selected());
+        assert_eq!(history.entries.len(), 5);
+        assert_eq!(history.entries[4].body.text, "carol is now known as c");
+
+        // A new run starts after those which were expanded
+        history.membership(Membership::Join, "dave has joined", "12:00:05");
+        assert_eq!(history.entries.len(), 6);
+    }
+
+    #[test]
+    fn test_select_skips_cards() {
+        let mut history = history();
+        history.set_message_id(7);
+        history.link_preview(
+            7,
+            &LinkPreview {
+                url: "https://example.com".to_owned(),
+                title: "Example Domain".to_owned(),
+                description: String::new(),
             },
         );
+
+        history.select_newer();
+        assert_eq!(history.selected, None);
+
+        history.select_older();
+        assert_eq!(history.selected, Some(3));
+        history.select_older();
+        assert_eq!(history.selected, Some(2));
+        for _ in 0..5 {
+            history.select_older();
+        }
+        assert_eq!(history.selected().unwrap().raw, "bob has joined");
+
+        for _ in 0..3 {
+            history.select_newer();
+        }
+        assert_eq!(history.selected, Some(3));
+        history.select_newer();
+        assert_eq!(history.selected, None);
+    }
+
+    #[test]
+    fn test_details_of_selected() {
+        let mut history = ChatHistory::new();
+        history.receiving = Some(Received {
+            code: RES_CHAT_MESSAGE_OK,
+            request_id: 0,
+            origin: "alice".to_owned(),
+            timestamp: 0,
+        });
+        history.message("hi\tthere", "12:00:00", "alice", None);
+        history.receiving = None;
+
+        let details = history.selected().unwrap().details(&Layout::default());
+        assert_eq!(details[0], ("From", "alice".to_owned()));
+        assert_eq!(details[1], ("Code", RES_CHAT_MESSAGE_OK.to_string()));
+        assert_eq!(details[3], ("Request id", "--".to_owned()));
+        assert_eq!(details[5], ("Acked", "--".to_owned()));
+        assert_eq!(history.selected().unwrap().raw, "hi\tthere");
+
+        history.message("mine", "12:00:05", "bob", Some(9));
+        history.ack(9);
+
+        let details = history.selected().unwrap().details(&Layout::default());
+        assert_eq!(details[1], ("Code", "--".to_owned()));
+        assert_eq!(details[3], ("Request id", "9".to_owned()));
+        assert_ne!(details[5], ("Acked", "--".to_owned()));
+        assert_eq!(details[6], ("Received", "--".to_owned()));
+        assert_eq!(details[7], ("Read by", "--".to_owned()));
+
+        history.sent(9, 12);
+        history.read_by(12, vec!["alice".to_owned(), "carol".to_owned()]);
+        let details = history.selected().unwrap().details(&Layout::default());
+        assert_eq!(details[7], ("Read by", "alice, carol".to_owned()));
+    }
+
+    #[test]
+    fn test_link_preview_follows_its_message() {
+        let mut history = ChatHistory::new();
+        history.message("see https://example.com", "12:00:00", "alice", None);
+        history.set_message_id(7);
+        history.set_read_marker();
+        history.message("nice", "12:00:05", "bob", None);
+
+        let preview = LinkPreview {
+            url: "https://example.com".to_owned(),
+            title: "Example Domain".to_owned(),
+            description: "For use in examples".to_owned(),
+        };
+        history.link_preview(7, &preview);
+        history.link_preview(8, &preview);
+
+        let rows = history
+            .entries
+            .iter()
+            .map(|entry| entry.body.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            rows,
+            vec![
+                "see https://example.com",
+                "│ Example Domain",
+                "│ For use in examples",
+                "nice"
+            ]
+        );
+        assert_eq!(history.read_marker, Some(3));
+        assert_eq!(history.export().count(), 2);
+    }
+
+    #[test]
+    fn test_export() {
+        let history = history();
+        let exported = history
+            .export()
+            .map(|entry| (entry.timestamp, entry.from, entry.message))
+            .collect::<Vec<_>>();
+
+        assert_eq!(exported.len(), 4);
+        assert_eq!(exported[0], ("11:59:00", None, "bob has joined"));
+        assert_eq!(
+            exported[1],
+            ("12:00:00", Some("alice"), "hello @bob, see #general")
+        );
+    }
+
+    #[test]
+    fn test_snapshot_topic() {
+        let topic = ChatTopic("Release day, be nice 🚀".to_owned());
+
+        for width in [40, 12] {
+            insta::assert_snapshot!(
+                format!("topic_{width}"),
+                crate::RenderBuffer::snapshot(&topic, width, 1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_cooldown_rounds_up() {
+        assert_eq!(
+            CooldownIndicator(Some(Duration::from_millis(1500))).label(),
+            " WAIT 2s "
+        );
+        assert_eq!(CooldownIndicator(Some(Duration::ZERO)).label(), " WAIT 0s ");
+        assert_eq!(CooldownIndicator(None).width(), 0);
+    }
+
+    #[test]
+    fn test_snapshot_presence_indicator() {
+        let indicator = PresenceIndicator(Presence::DoNotDisturb);
+
+        assert_eq!(PresenceIndicator(Presence::Online).width(), 0);
+        insta::assert_snapshot!(crate::RenderBuffer::snapshot(
+            &indicator,
+            indicator.width(),
+            1
+        ));
+    }
+
+    #[test]
+    fn test_missed_mentions_summary() {
+        let by = |nicks: &[&str]| {
+            nicks
+                .iter()
+                .map(|nick| Bookmark {
+                    nick: (*nick).to_owned(),
+                    ..Bookmark::default()
+                })
+                .collect::<Vec<Bookmark>>()
+        };
+
+        assert_eq!(
+            missed_mentions_summary(&by(&["alice"])),
+            "You were mentioned once while away, by alice. /mentions lists them"
+        );
+        assert_eq!(
+            missed_mentions_summary(&by(&["alice", "bob", "alice", "carol"])),
+            "You were mentioned 4 times while away, by alice, bob and carol. /mentions lists them"
+        );
     }
 }
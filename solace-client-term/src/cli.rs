@@ -0,0 +1,117 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand};
+use once_cell::sync::OnceCell;
+
+use crate::{logger::LogLevel, simulate::NetworkConditions};
+
+static ARGS: OnceCell<Args> = OnceCell::new();
+
+/// Couldn't reach the server, or it hung up on us.
+pub(crate) const EXIT_UNAVAILABLE: u8 = 69;
+/// The server answered with an error, e.g. the nick was taken.
+pub(crate) const EXIT_REJECTED: u8 = 65;
+/// The server didn't answer in time, so it is worth trying again later.
+pub(crate) const EXIT_TIMED_OUT: u8 = 75;
+
+/// Terminal client for solace.
+///
+/// Anything given here takes precedence over the config file.
+#[derive(Debug, Default, Parser)]
+#[command(version, about)]
+pub(crate) struct Args {
+    /// What to do instead of starting the TUI
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
+    /// Server to connect to, or `unix:<PATH>` for a Unix socket
+    #[arg(long, global = true, value_name = "HOST:PORT")]
+    pub(crate) server: Option<String>,
+
+    /// Nick to ask for once connected
+    #[arg(long, global = true)]
+    pub(crate) nick: Option<String>,
+
+    /// Config file to read instead of the one in the XDG config directory
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Theme to take colors from, looked up as themes/<THEME>.toml next to
+    /// the config file
+    #[arg(long, global = true)]
+    pub(crate) theme: Option<String>,
+
+    /// Least severe messages written to the log file
+    #[arg(long, global = true, value_enum)]
+    pub(crate) log_level: Option<LogLevel>,
+
+    /// Connect over plain TCP, even to a server given as `tls://HOST:PORT`
+    #[arg(long, global = true)]
+    pub(crate) no_tls: bool,
+
+    /// Talk to the server over a simulated bad network, given as
+    /// `latency=MS,jitter=MS,truncate=P,disconnect=P`, for trying out
+    /// reconnecting and the like while developing
+    #[arg(long, global = true, hide = true, value_name = "CONDITIONS")]
+    pub(crate) simulate_network: Option<NetworkConditions>,
+
+    /// Keep the last FRAMES frames drawn, 50 if not given, to step through
+    /// with F12 when working out where flicker or stray characters come
+    /// from
+    #[arg(
+        long,
+        hide = true,
+        value_name = "FRAMES",
+        num_args = 0..=1,
+        default_missing_value = "50"
+    )]
+    pub(crate) record_frames: Option<usize>,
+
+    /// Draw without colors, unicode decorations or optional escape
+    /// sequences, for serial consoles and other limited terminals
+    #[arg(long)]
+    pub(crate) safe_mode: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Send one message and exit once the server has taken it.
+    ///
+    /// Exits with 0 on success, 65 if the server rejected the message or
+    /// nick, 69 if the server couldn't be reached and 75 if it didn't answer
+    /// in time.
+    Send {
+        /// The message to send
+        message: String,
+
+        /// Seconds to wait for the server before giving up
+        #[arg(long, default_value = "10", value_parser = parse_seconds)]
+        timeout: Duration,
+    },
+
+    /// Print everything the server sends as line delimited JSON, until the
+    /// connection closes.
+    ///
+    /// Each line is an object with `timestamp`, `code`, `kind`, `message`
+    /// and, for messages from other users, `from`. Exits with 69 when the
+    /// connection is lost.
+    Tail,
+}
+
+fn parse_seconds(arg: &str) -> Result<Duration, String> {
+    arg.parse::<u64>()
+        .map(Duration::from_secs)
+        .map_err(|err| err.to_string())
+}
+
+/// Parses the process arguments, exiting with usage on error. Only the first
+/// call has any effect.
+pub(crate) fn init() {
+    ARGS.get_or_init(Args::parse);
+}
+
+/// The arguments the client was started with, or none at all if `init` was
+/// never called, e.g. in tests.
+pub(crate) fn args() -> &'static Args {
+    ARGS.get_or_init(Args::default)
+}
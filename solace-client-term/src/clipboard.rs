@@ -0,0 +1,22 @@
+use std::io::{self, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::safe_mode;
+
+/// Copies `text` to the clipboard with an OSC 52 escape, which the terminal
+/// handles so that it works over SSH too. Terminals without support for it
+/// ignore it, but not in safe mode, where it could be printed instead.
+pub(crate) fn copy(text: &str) -> io::Result<()> {
+    if safe_mode::is_on() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the clipboard isn't used in safe mode",
+        ));
+    }
+
+    let mut stdout = io::stdout();
+
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush()
+}
@@ -23,6 +23,13 @@ pub(crate) fn hex_to_rgb(s: &str) -> Color {
     }
 }
 
+/// Whether `s` is a color `hex_to_rgb` understands, rather than one it
+/// falls back from.
+pub(crate) fn is_hex_color(s: &str) -> bool {
+    s.strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
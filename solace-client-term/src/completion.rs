@@ -0,0 +1,138 @@
+use solace_message_parser::TextSpan;
+use solace_protocol::presence::NickListEntry;
+
+use crate::{config_hex_color, str_width, CellStyle, Rect, RenderBuffer, Renderable};
+
+/// The most candidates shown at once, anything beyond this scrolls.
+const MAX_VISIBLE: usize = 8;
+
+/// Popup listing the nicks which match the `@mention` under the cursor.
+///
+/// # Fields
+///
+/// - `span`: The span of the mention in the prompt which a candidate replaces.
+/// - `selected`: Index into `candidates` of the highlighted row.
+#[derive(Debug)]
+pub(crate) struct NickCompletion {
+    pub(crate) span: TextSpan,
+    pub(crate) candidates: Vec<NickListEntry>,
+    pub(crate) selected: usize,
+}
+
+impl NickCompletion {
+    pub(crate) fn new(span: TextSpan, candidates: Vec<NickListEntry>) -> Self {
+        Self {
+            span,
+            candidates,
+            selected: 0,
+        }
+    }
+
+    pub(crate) fn selected(&self) -> Option<&NickListEntry> {
+        self.candidates.get(self.selected)
+    }
+
+    pub(crate) fn select_previous(&mut self) {
+        self.selected = self
+            .selected
+            .checked_sub(1)
+            .unwrap_or(self.candidates.len().saturating_sub(1));
+    }
+
+    pub(crate) fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.candidates.len().max(1);
+    }
+
+    /// Keeps the same nick highlighted when the candidate list is refreshed
+    /// while typing, falling back to the first candidate if it dropped out.
+    pub(crate) fn retain_selection(&mut self, previous: &NickCompletion) {
+        if let Some(nick) = previous.selected().map(|e| &e.nick) {
+            self.selected = self
+                .candidates
+                .iter()
+                .position(|e| &e.nick == nick)
+                .unwrap_or(0);
+        }
+    }
+
+    pub(crate) fn height(&self) -> u16 {
+        self.candidates.len().min(MAX_VISIBLE) as u16
+    }
+
+    pub(crate) fn width(&self) -> u16 {
+        self.candidates
+            .iter()
+            .map(|e| str_width(&Self::line_for(e, 0)))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn line_for(entry: &NickListEntry, now: u64) -> String {
+        let marker = entry.level.prefix();
+        let state = if entry.is_away {
+            "away"
+        } else if entry.is_dnd {
+            "dnd"
+        } else if entry.is_idle(now) {
+            "idle"
+        } else {
+            ""
+        };
+
+        format!(" {marker}{:<16} {state:<4} ", entry.nick)
+    }
+}
+
+impl Renderable for NickCompletion {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let now = u64::try_from(chrono::Utc::now().timestamp()).unwrap_or_default();
+        let visible = rect.height as usize;
+        let first = (self.selected + 1).saturating_sub(visible);
+
+        for (row, (i, entry)) in self
+            .candidates
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .enumerate()
+        {
+            let is_selected = i == self.selected;
+            let (bg, fg) = if is_selected {
+                (
+                    config_hex_color!(colors.timestamp_bg),
+                    config_hex_color!(colors.timestamp_fg),
+                )
+            } else if entry.is_away || entry.is_idle(now) {
+                (
+                    config_hex_color!(colors.bg),
+                    config_hex_color!(colors.server_message),
+                )
+            } else {
+                (
+                    config_hex_color!(colors.bg),
+                    config_hex_color!(colors.user_name),
+                )
+            };
+
+            let line = Self::line_for(entry, now);
+            let mut chars = line.chars();
+            let mut x = rect.x;
+
+            while x < rect.x + rect.width {
+                x += buf.put_at(
+                    x,
+                    rect.y + row as u16,
+                    chars.next().unwrap_or(' '),
+                    bg,
+                    fg,
+                    if is_selected {
+                        CellStyle::Bold
+                    } else {
+                        CellStyle::Normal
+                    },
+                );
+            }
+        }
+    }
+}
@@ -1,19 +1,31 @@
 use std::{
+    collections::BTreeMap,
     fs::{self},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
 };
 
 use anyhow::Context;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use solace_message_parser::{parse_with_options, ParserOptions};
 
-pub(crate) static CONFIG: Lazy<Config> =
-    Lazy::new(|| Config::new().expect("Failed to load configuration"));
+use crate::{cli, color::is_hex_color, logger::LogLevel, timestamp};
+
+pub(crate) static CONFIG: Lazy<RwLock<Arc<Config>>> = Lazy::new(|| {
+    // Tests render with fixed colors rather than whatever is on disk
+    #[cfg(test)]
+    let config = Config::fixture();
+    #[cfg(not(test))]
+    let config = Config::new().expect("Failed to load configuration");
+
+    RwLock::new(Arc::new(config))
+});
 
 #[macro_export]
 macro_rules! config {
     ($field:ident $(. $subfield:ident)*) => {
-        &$crate::config::CONFIG.$field $(. $subfield)*
+        &$crate::config::current().$field $(. $subfield)*
     };
 }
 
@@ -24,12 +36,236 @@ macro_rules! config_hex_color {
     };
 }
 
-#[derive(Debug, Deserialize)]
+/// Returns a snapshot of the currently loaded configuration.
+pub(crate) fn current() -> Arc<Config> {
+    Arc::clone(&CONFIG.read().expect("ERROR: Config lock is poisoned"))
+}
+
+/// Themes shipped with the client, which `theme` can name without a file of
+/// its own in the `themes` config directory.
+pub(crate) const BUILTIN_THEMES: &[(&str, &str)] = &[
+    ("dark", include_str!("../themes/dark.toml")),
+    ("light", include_str!("../themes/light.toml")),
+    ("solarized", include_str!("../themes/solarized.toml")),
+];
+
+pub(crate) fn builtin_theme(name: &str) -> Option<Colors> {
+    let (_, raw) = BUILTIN_THEMES.iter().find(|(n, _)| *n == name)?;
+
+    Some(toml::from_str(raw).expect("ERROR: Built in themes should parse"))
+}
+
+/// Whether there is no config file to load yet, in which case the user is
+/// walked through writing one.
+pub(crate) fn is_first_run() -> anyhow::Result<bool> {
+    if cli::args().config.is_some() {
+        return Ok(false);
+    }
+
+    let base_path = xdg::BaseDirectories::with_prefix("solace")
+        .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+
+    Ok(Config::find_path(&base_path).is_none())
+}
+
+/// Writes out the first config file, with the colors of a built in theme
+/// spelled out so they are easy to tweak, returning where it was written.
+pub(crate) fn write_initial(server: &str, nick: &str, theme: &str) -> anyhow::Result<PathBuf> {
+    let base_path = xdg::BaseDirectories::with_prefix("solace")
+        .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+    let path = base_path
+        .place_config_file("config.toml")
+        .with_context(|| "ERROR: Couldn't create the config directory")?;
+
+    fs::write(&path, initial_config(server, nick, theme)?)
+        .with_context(|| format!("ERROR: Failed to write {path:?}"))?;
+
+    Ok(path)
+}
+
+fn initial_config(server: &str, nick: &str, theme: &str) -> anyhow::Result<String> {
+    let (_, colors) = BUILTIN_THEMES
+        .iter()
+        .find(|(name, _)| *name == theme)
+        .with_context(|| format!("ERROR: No theme named {theme:?} found"))?;
+    let mut config = format!("server = {}\n", toml::Value::from(server));
+
+    if !nick.is_empty() {
+        config.push_str(&format!("nick = {}\n", toml::Value::from(nick)));
+    }
+
+    config.push_str(&format!(
+        "\n# Colors from the built in {theme:?} theme\n[colors]\n{colors}"
+    ));
+
+    Ok(config)
+}
+
+/// Re-reads the configuration from disk and swaps it in, so anything that
+/// reads the config at render time picks up the new values on the next frame.
+pub(crate) fn reload() -> anyhow::Result<()> {
+    let config = Config::new()?;
+    *CONFIG.write().expect("ERROR: Config lock is poisoned") = Arc::new(config);
+
+    Ok(())
+}
+
+/// Changes one setting in the config file, e.g. `colors.topic_fg` to
+/// `#ff79c6`, then reloads so that it takes effect straight away.
+pub(crate) fn set(key: &str, value: &str) -> anyhow::Result<()> {
+    let base_path = xdg::BaseDirectories::with_prefix("solace")
+        .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+    let path = match &cli::args().config {
+        Some(path) => path.clone(),
+        None => Config::find_path(&base_path).with_context(|| "ERROR: No config file found!")?,
+    };
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("ERROR: Failed to read file: {path:?}"))?;
+
+    fs::write(&path, set_in(&raw, key, value)?)
+        .with_context(|| format!("ERROR: Failed to write {path:?}"))?;
+
+    reload()
+}
+
+/// `raw` with `key` set to `value`, keeping the rest of the file as it was
+/// written, comments included.
+fn set_in(raw: &str, key: &str, value: &str) -> anyhow::Result<String> {
+    let config: Config = toml::from_str(raw).with_context(|| "ERROR: Failed to parse config")?;
+    let path = key.split('.').collect::<Vec<&str>>();
+
+    // Anything the config can hold is in here, unset options included as nulls
+    let known = serde_json::to_value(&config)?;
+    match path.iter().try_fold(&known, |value, key| value.get(key)) {
+        Some(serde_json::Value::Object(_)) | None => {
+            anyhow::bail!("ERROR: Unknown setting {key:?}")
+        }
+        Some(_) => (),
+    }
+
+    if path[0] == "colors" && !is_hex_color(value) {
+        anyhow::bail!("ERROR: {value:?} isn't a hex color such as #ff79c6");
+    }
+
+    let mut doc = raw
+        .parse::<toml_edit::DocumentMut>()
+        .with_context(|| "ERROR: Failed to parse config")?;
+
+    // Values are tried as written first, e.g. `true` or `16`, and then as a
+    // string so that they don't need quoting
+    let candidates = value
+        .parse::<toml_edit::Value>()
+        .ok()
+        .into_iter()
+        .chain(std::iter::once(toml_edit::Value::from(value)));
+
+    for mut candidate in candidates {
+        candidate.decor_mut().clear();
+
+        let mut item = doc.as_item_mut();
+        for key in &path {
+            // A `[table]` of its own rather than an inline one
+            if item.is_none() {
+                *item = toml_edit::table();
+            }
+            item = &mut item[*key];
+        }
+        *item = toml_edit::Item::Value(candidate);
+
+        let edited = doc.to_string();
+        if toml::from_str::<Config>(&edited).is_ok() {
+            return Ok(edited);
+        }
+    }
+
+    anyhow::bail!("ERROR: {value:?} isn't a valid value for {key}")
+}
+
+/// # Fields
+///
+/// - `experimental`: Opt into commands the server is still rolling out.
+/// - `server`: Address of the server to connect to on startup, or
+///   `unix:<path>` for a Unix socket. Without one the server browser is
+///   shown instead.
+/// - `servers`: Addresses listed in the server browser, ahead of any which
+///   were used recently.
+/// - `nick`: Nick to ask for once connected, otherwise the server picks one.
+/// - `theme`: Name of a file in the `themes` config directory whose colors
+///   replace `colors`.
+/// - `log_level`: Least severe messages written to the log file.
+/// - `snippets`: Text by name for `/snippet` to fill the prompt with, where
+///   each `{placeholder}` is visited in turn with Tab.
+/// - `paste`: Where messages too long for the channel are uploaded to.
+/// - `errors`: Wording for server errors by name, such as `nick_in_use`, in
+///   place of the built in explanations, e.g. to translate them. `{detail}`
+///   is replaced by what the server said.
+#[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct Config {
     pub(crate) colors: Colors,
+    #[serde(default)]
+    pub(crate) layout: Layout,
+    #[serde(default)]
+    pub(crate) notifications: Notifications,
+    #[serde(default)]
+    pub(crate) confirm_send: ConfirmSend,
+    #[serde(default)]
+    pub(crate) experimental: bool,
+    #[serde(default)]
+    pub(crate) server: Option<String>,
+    #[serde(default)]
+    pub(crate) servers: Vec<String>,
+    #[serde(default)]
+    pub(crate) nick: Option<String>,
+    #[serde(default)]
+    pub(crate) theme: Option<String>,
+    #[serde(default)]
+    pub(crate) log_level: LogLevel,
+    #[serde(default)]
+    pub(crate) snippets: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(crate) paste: Paste,
+    #[serde(default)]
+    pub(crate) prompt: Prompt,
+    #[serde(default)]
+    pub(crate) errors: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(crate) tls: Tls,
+    #[serde(default)]
+    pub(crate) privacy: Privacy,
+    #[serde(default)]
+    pub(crate) announce: Announce,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+pub(crate) fn default_server() -> String {
+    "0.0.0.0:7878".to_owned()
+}
+
+/// The server, nick and TLS settings to use when running without the TUI,
+/// where a config file is optional unless one was asked for with `--config`,
+/// as scripts shouldn't need one just to talk to a server.
+pub(crate) fn headless() -> anyhow::Result<(String, Option<String>, Tls)> {
+    let args = cli::args();
+
+    match Config::new() {
+        Ok(config) => Ok((
+            config.server.unwrap_or_else(default_server),
+            config.nick,
+            config.tls,
+        )),
+        Err(_) if args.config.is_none() => Ok((
+            args.server.clone().unwrap_or_else(default_server),
+            args.nick.clone(),
+            Tls::default(),
+        )),
+        Err(err) => Err(err),
+    }
+}
+
+/// # Fields
+///
+/// - `mention_highlight`: The background of messages which mention us,
+///   defaulted so that configs and themes from before it still load.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct Colors {
     pub(crate) bg: String,
     pub(crate) channel_mention: String,
@@ -37,6 +273,8 @@ pub(crate) struct Colors {
     pub(crate) error_bg: String,
     pub(crate) error_fg: String,
     pub(crate) fg: String,
+    #[serde(default = "default_mention_highlight")]
+    pub(crate) mention_highlight: String,
     pub(crate) message: String,
     pub(crate) prompt_nick: String,
     pub(crate) server_message: String,
@@ -48,27 +286,453 @@ pub(crate) struct Colors {
     pub(crate) user_mention: String,
 }
 
+fn default_mention_highlight() -> String {
+    "#3a3a1f".to_owned()
+}
+
+/// # Fields
+///
+/// - `nick_width`: Nicks longer than this are truncated in the author gutter.
+/// - `gutter_padding`: Spaces either side of the timestamp and author gutters.
+/// - `nick_alignment`: Which side of the author gutter nicks are pushed to.
+/// - `show_timestamps`: Whether the timestamp gutter is drawn at all.
+/// - `show_preview`: Whether to render the pending message above the prompt.
+/// - `show_message_ids`: Whether to show the server's id for each chat
+///   message before it, as used by `/bookmark`.
+/// - `timestamp_format`: How the timestamp gutter shows times, as a strftime
+///   format such as `%H:%M`.
+/// - `timezone`: What times are shown in, `local`, `utc` or a fixed offset
+///   such as `+05:30`. Anything else is taken as `local`.
+/// - `collapse_joins_at`: How many joins, parts and nick changes in a row
+///   are collapsed into one line, `0` to never collapse them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Layout {
+    pub(crate) nick_width: usize,
+    pub(crate) gutter_padding: usize,
+    pub(crate) nick_alignment: Alignment,
+    pub(crate) show_timestamps: bool,
+    pub(crate) show_preview: bool,
+    pub(crate) show_message_ids: bool,
+    pub(crate) timestamp_format: String,
+    pub(crate) timezone: String,
+    pub(crate) collapse_joins_at: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            nick_width: 16,
+            gutter_padding: 1,
+            nick_alignment: Alignment::Right,
+            show_timestamps: true,
+            show_preview: true,
+            show_message_ids: false,
+            timestamp_format: timestamp::DEFAULT_FORMAT.to_owned(),
+            timezone: "local".to_owned(),
+            collapse_joins_at: 3,
+        }
+    }
+}
+
+/// Messages over any limit ask to be confirmed before they are sent, to
+/// catch pastes which were meant to go somewhere else and mentions which
+/// would ping half the channel. A limit of 0 turns that check off.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct ConfirmSend {
+    pub(crate) max_lines: usize,
+    pub(crate) max_chars: usize,
+    pub(crate) max_mentions: usize,
+}
+
+impl Default for ConfirmSend {
+    fn default() -> Self {
+        Self {
+            max_lines: 5,
+            max_chars: 1000,
+            max_mentions: 5,
+        }
+    }
+}
+
+impl ConfirmSend {
+    /// What to ask before sending `message`, if it is over a limit. Mentions
+    /// are counted as the server, which parses with `options`, counts them.
+    pub(crate) fn question(&self, message: &str, options: ParserOptions) -> Option<String> {
+        let lines = message.lines().count();
+        let chars = message.chars().count();
+        let ast = parse_with_options(message, options);
+        let mentions = ast.mention_count();
+
+        if self.max_lines > 0 && lines > self.max_lines {
+            Some(format!("Send {lines} lines?"))
+        } else if self.max_chars > 0 && chars > self.max_chars {
+            Some(format!("Send {chars} characters?"))
+        } else if let (true, Some(everyone)) = (self.max_mentions > 0, ast.everyone()) {
+            Some(format!("Mention everyone with @{}?", everyone.name()))
+        } else if self.max_mentions > 0 && mentions > self.max_mentions {
+            Some(format!("Mention {mentions} nicks?"))
+        } else {
+            None
+        }
+    }
+}
+
+/// How servers given as `tls://host:port` are checked.
+///
+/// # Fields
+///
+/// - `verify`: Whether the server has to present a certificate for its host
+///   from a trusted authority. Only turn this off to try out a server, as
+///   anyone in between could then read everything.
+/// - `ca_cert`: PEM file of certificates to trust as well as the usual
+///   authorities, such as a server's own self-signed one.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Tls {
+    pub(crate) verify: bool,
+    pub(crate) ca_cert: Option<PathBuf>,
+}
+
+impl Default for Tls {
+    fn default() -> Self {
+        Self {
+            verify: true,
+            ca_cert: None,
+        }
+    }
+}
+
+/// # Fields
+///
+/// - `command`: Run with `sh -c` and given the message on stdin, printing
+///   the link to send in its place, e.g.
+///   `"curl -sF 'file=@-' https://0x0.st"`. Empty turns pasting off.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Paste {
+    pub(crate) command: String,
+}
+
+/// # Fields
+///
+/// - `sound`: `"bell"` to ring the terminal bell, `"off"`, or otherwise a
+///   command run with `sh -c`, e.g. `"paplay ~/ping.ogg"`.
+/// - `on_mention`: Make a sound when a message mentions us.
+/// - `on_direct_message`: Make a sound when a direct message arrives.
+/// - `muted`: Buffers which never make a sound, `chat` for the main one or a
+///   nick for direct messages from them.
+/// - `do_not_disturb`: Never make a sound, whatever the above say.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Notifications {
+    pub(crate) sound: String,
+    pub(crate) on_mention: bool,
+    pub(crate) on_direct_message: bool,
+    pub(crate) muted: Vec<String>,
+    pub(crate) do_not_disturb: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            sound: "off".to_owned(),
+            on_mention: true,
+            on_direct_message: true,
+            muted: Vec::new(),
+            do_not_disturb: false,
+        }
+    }
+}
+
+/// Reading messages aloud, for accessibility or for keeping an ear on the
+/// chat while doing something else.
+///
+/// # Fields
+///
+/// - `enabled`: Whether to announce anything, e.g. `/set announce.enabled
+///   true` for a while.
+/// - `command`: Run with `sh -c` and given what to say on stdin, e.g.
+///   `"espeak"` or `"say"`. Empty turns announcing off.
+/// - `on_mention`: Announce messages which mention us.
+/// - `on_direct_message`: Announce direct messages.
+///
+/// Muted buffers and do not disturb are kept to as for `Notifications`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Announce {
+    pub(crate) enabled: bool,
+    pub(crate) command: String,
+    pub(crate) on_mention: bool,
+    pub(crate) on_direct_message: bool,
+}
+
+impl Default for Announce {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            command: String::new(),
+            on_mention: true,
+            on_direct_message: true,
+        }
+    }
+}
+
+/// # Fields
+///
+/// - `read_receipts`: Tell the server which chat messages we have read, so
+///   that their senders can see who has. Messages count as read once they
+///   arrive while the terminal has focus, or once it gets focus back.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Privacy {
+    pub(crate) read_receipts: bool,
+}
+
+impl Default for Privacy {
+    fn default() -> Self {
+        Self {
+            read_receipts: true,
+        }
+    }
+}
+
+/// # Fields
+///
+/// - `recall`: Which of the lines sent before Up and Down go through,
+///   `"all"` of them or only those sent in the current `"buffer"`. Either
+///   way only those starting with what was typed before the first Up are
+///   recalled.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Prompt {
+    pub(crate) recall: Recall,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Recall {
+    #[default]
+    All,
+    Buffer,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Alignment {
+    Left,
+    #[default]
+    Right,
+}
+
 impl Config {
+    /// Loads the config file, from the path given on the command line if
+    /// there is one, then applies the rest of the command line on top.
     pub(crate) fn new() -> anyhow::Result<Self> {
+        let args = cli::args();
         let base_path = xdg::BaseDirectories::with_prefix("solace")
             .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
-        let valid_config_paths = vec!["config.toml", ".config.toml"];
 
-        for path in &valid_config_paths {
-            if let Some(full_path) = base_path.find_config_file(path) {
-                if PathBuf::from(&full_path).exists() {
-                    let config_raw = fs::read_to_string(full_path)
-                        .with_context(|| "ERROR: Failed to read file: {path:?}")?;
-                    let config: Config = toml::from_str(&config_raw)
-                        .with_context(|| "ERROR: Failed to parse {path:?}")?;
+        let mut config = match &args.config {
+            Some(path) => Self::load(path)?,
+            None => Self::find(&base_path)?,
+        };
 
-                    return Ok(config);
+        if let Some(server) = &args.server {
+            config.server = Some(server.clone());
+        }
+        if let Some(nick) = &args.nick {
+            config.nick = Some(nick.clone());
+        }
+        if let Some(theme) = &args.theme {
+            config.theme = Some(theme.clone());
+        }
+        if let Some(log_level) = args.log_level {
+            config.log_level = log_level;
+        }
+
+        if let Some(theme) = &config.theme {
+            // A theme file of the user's own wins over a built in one
+            config.colors = match base_path.find_config_file(format!("themes/{theme}.toml")) {
+                Some(theme_path) => {
+                    let theme_raw = fs::read_to_string(&theme_path)
+                        .with_context(|| format!("ERROR: Failed to read file: {theme_path:?}"))?;
+                    toml::from_str(&theme_raw)
+                        .with_context(|| format!("ERROR: Failed to parse {theme_path:?}"))?
                 }
-            }
+                None => builtin_theme(theme)
+                    .with_context(|| format!("ERROR: No theme named {theme:?} found"))?,
+            };
         }
 
-        // @FIXME: Generate default config if we don't find one
+        Ok(config)
+    }
+
+    fn find(base_path: &xdg::BaseDirectories) -> anyhow::Result<Self> {
+        match Self::find_path(base_path) {
+            Some(path) => Self::load(&path),
+            None => anyhow::bail!("ERROR: No config file found!"),
+        }
+    }
+
+    fn find_path(base_path: &xdg::BaseDirectories) -> Option<PathBuf> {
+        let valid_config_paths = ["config.toml", ".config.toml"];
 
-        anyhow::bail!("ERROR: No config file found!")
+        valid_config_paths
+            .iter()
+            .filter_map(|path| base_path.find_config_file(path))
+            .find(|full_path| full_path.exists())
+    }
+
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let config_raw = fs::read_to_string(path)
+            .with_context(|| format!("ERROR: Failed to read file: {path:?}"))?;
+        let config: Config = toml::from_str(&config_raw)
+            .with_context(|| format!("ERROR: Failed to parse {path:?}"))?;
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    fn fixture() -> Self {
+        toml::from_str(
+            r##"
+            [colors]
+            bg = "#000000"
+            channel_mention = "#00aaff"
+            command = "#ffaa00"
+            error_bg = "#aa0000"
+            error_fg = "#ffffff"
+            fg = "#dddddd"
+            message = "#cccccc"
+            prompt_nick = "#00ff00"
+            server_message = "#888888"
+            timestamp_bg = "#222222"
+            timestamp_fg = "#aaaaaa"
+            topic_bg = "#333333"
+            topic_fg = "#eeeeee"
+            user_name = "#ff00ff"
+            user_mention = "#ffff00"
+            "##,
+        )
+        .expect("ERROR: Test config should parse")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solace_message_parser::MentionPolicy;
+
+    #[test]
+    fn test_initial_config_parses() {
+        let raw = initial_config("example.com:7878", "ci \"bot\"", "light").unwrap();
+        let config: Config = toml::from_str(&raw).unwrap();
+
+        assert_eq!(config.server.as_deref(), Some("example.com:7878"));
+        assert_eq!(config.nick.as_deref(), Some("ci \"bot\""));
+        assert_eq!(config.colors.bg, "#fafafa");
+
+        let raw = initial_config("example.com:7878", "", "dark").unwrap();
+        assert_eq!(toml::from_str::<Config>(&raw).unwrap().nick, None);
+    }
+
+    #[test]
+    fn test_set_keeps_the_rest_of_the_file() {
+        let raw = initial_config("example.com:7878", "", "dark").unwrap();
+        let edited = set_in(&raw, "colors.topic_fg", "#ff79c6").unwrap();
+
+        assert!(edited.contains("# Colors from the built in \"dark\" theme"));
+        assert!(edited.contains("topic_fg = \"#ff79c6\""));
+        assert_eq!(edited.lines().count(), raw.lines().count());
+    }
+
+    #[test]
+    fn test_set_picks_the_type_of_the_setting() {
+        let raw = initial_config("example.com:7878", "", "dark").unwrap();
+
+        let edited = set_in(&raw, "layout.nick_width", "12").unwrap();
+        let config: Config = toml::from_str(&edited).unwrap();
+        assert_eq!(config.layout.nick_width, 12);
+        assert!(edited.contains("[layout]\nnick_width = 12"));
+
+        let edited = set_in(&raw, "nick", "1234").unwrap();
+        let config: Config = toml::from_str(&edited).unwrap();
+        assert_eq!(config.nick.as_deref(), Some("1234"));
+
+        assert!(set_in(&raw, "layout.show_preview", "maybe").is_err());
+    }
+
+    #[test]
+    fn test_set_rejects_unknown_settings() {
+        let raw = initial_config("example.com:7878", "", "dark").unwrap();
+
+        assert!(set_in(&raw, "colors.nope", "#ffffff").is_err());
+        assert!(set_in(&raw, "colors", "#ffffff").is_err());
+        assert!(set_in(&raw, "colors.fg", "pink").is_err());
+    }
+
+    #[test]
+    fn test_confirm_send_question() {
+        let confirm_send = ConfirmSend {
+            max_lines: 2,
+            max_chars: 10,
+            max_mentions: 2,
+        };
+        let ask = |message: &str| confirm_send.question(message, ParserOptions::default());
+
+        assert_eq!(ask("a\nb"), None);
+        assert_eq!(ask("a\nb\nc").as_deref(), Some("Send 3 lines?"));
+        assert_eq!(ask("hello world").as_deref(), Some("Send 11 characters?"));
+        assert_eq!(ask("@a @b @a"), None);
+        assert_eq!(ask("@a @b @c").as_deref(), Some("Mention 3 nicks?"));
+        assert_eq!(
+            ask("hi @here").as_deref(),
+            Some("Mention everyone with @here?")
+        );
+
+        // Counted as the server counts them
+        let mentions_only = ConfirmSend {
+            max_lines: 0,
+            max_chars: 0,
+            max_mentions: 2,
+        };
+        let anywhere = ParserOptions {
+            mentions: MentionPolicy::Anywhere,
+        };
+        assert_eq!(
+            mentions_only.question("cc:@a cc:@b cc:@c", ParserOptions::default()),
+            None
+        );
+        assert_eq!(
+            mentions_only
+                .question("cc:@a cc:@b cc:@c", anywhere)
+                .as_deref(),
+            Some("Mention 3 nicks?")
+        );
+
+        let off = ConfirmSend {
+            max_lines: 0,
+            max_chars: 0,
+            max_mentions: 0,
+        };
+        assert_eq!(
+            off.question(&"a\n".repeat(100), ParserOptions::default()),
+            None
+        );
+        assert_eq!(
+            off.question(&"@a ".repeat(100), ParserOptions::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_builtin_themes_parse() {
+        for (name, _) in BUILTIN_THEMES {
+            assert!(builtin_theme(name).is_some());
+        }
     }
 }
@@ -0,0 +1,293 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::{
+    config, log,
+    transport::{self, Connected, Stream},
+};
+
+/// How long to wait before reconnecting the first time, each attempt after
+/// that waits twice as long as the last.
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+/// The longest wait between attempts, so that a server which is down for a
+/// while is still noticed soon after it's back.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How long to wait before reconnecting after `failed` attempts.
+pub(crate) fn backoff(failed: u32) -> Duration {
+    FIRST_DELAY
+        .saturating_mul(2u32.saturating_pow(failed))
+        .min(MAX_DELAY)
+}
+
+/// Getting the connection to a server back after it dropped.
+///
+/// # Fields
+///
+/// - `failed`: How many attempts have failed so far.
+/// - `retry_at`: When the next attempt starts.
+/// - `connecting`: The attempt under way, kept here rather than in the
+///   future which waits on it so that it carries on while the main loop
+///   handles a key press.
+#[derive(Debug)]
+pub(crate) struct Reconnect {
+    server: String,
+    tls: config::Tls,
+    failed: u32,
+    retry_at: Instant,
+    connecting: Option<JoinHandle<io::Result<Connected>>>,
+}
+
+impl Reconnect {
+    pub(crate) fn new(server: &str, tls: config::Tls) -> Self {
+        Self {
+            server: server.to_owned(),
+            tls,
+            failed: 0,
+            retry_at: Instant::now() + backoff(0),
+            connecting: None,
+        }
+    }
+
+    /// When the next attempt starts, `None` while one is under way.
+    pub(crate) fn retry_at(&self) -> Option<Instant> {
+        match self.connecting {
+            Some(_) => None,
+            None => Some(self.retry_at),
+        }
+    }
+
+    /// Gives up on the connection an attempt made, e.g. as it dropped again
+    /// before saying hello, and waits longer before the next.
+    pub(crate) fn failed(&mut self, err: &anyhow::Error) {
+        log!(Warn, "Failed to reconnect to {}: {err:#}", self.server);
+
+        self.failed += 1;
+        self.retry_at = Instant::now() + backoff(self.failed);
+    }
+
+    /// Tries to connect again until it works, backing off after each
+    /// failure. Cancelling this leaves any attempt under way running, and
+    /// calling it again picks up where it left off.
+    pub(crate) async fn connected(&mut self) -> Connected {
+        loop {
+            let connecting = match &mut self.connecting {
+                Some(connecting) => connecting,
+                None => {
+                    tokio::time::sleep_until(self.retry_at).await;
+
+                    let (server, tls) = (self.server.clone(), self.tls.clone());
+                    self.connecting.insert(tokio::spawn(async move {
+                        transport::connect_keyed(&server, &tls).await
+                    }))
+                }
+            };
+
+            let result = connecting.await;
+            self.connecting = None;
+
+            match result {
+                Ok(Ok(connected)) => return connected,
+                Ok(Err(err)) => self.failed(&err.into()),
+                Err(err) => self.failed(&err.into()),
+            }
+        }
+    }
+}
+
+/// What has gone over one connection, for `/connstats`. Bytes are counted
+/// by the stream itself, see `ConnStats::count`, and frames by whoever
+/// sends and receives them.
+///
+/// # Fields
+///
+/// - `retransmits`: Requests sent again after the server rate limited them.
+#[derive(Debug)]
+pub(crate) struct ConnStats {
+    pub(crate) connected_at: Instant,
+    pub(crate) frames_sent: u64,
+    pub(crate) frames_received: u64,
+    pub(crate) retransmits: u64,
+    bytes: Arc<ByteCounts>,
+}
+
+#[derive(Debug, Default)]
+struct ByteCounts {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl ConnStats {
+    pub(crate) fn new() -> Self {
+        Self {
+            connected_at: Instant::now(),
+            frames_sent: 0,
+            frames_received: 0,
+            retransmits: 0,
+            bytes: Arc::default(),
+        }
+    }
+
+    /// `stream` with every byte read from or written to it counted here.
+    pub(crate) fn count(&self, stream: Stream) -> Stream {
+        Box::new(Counted {
+            inner: stream,
+            bytes: Arc::clone(&self.bytes),
+        })
+    }
+
+    pub(crate) fn bytes_sent(&self) -> u64 {
+        self.bytes.sent.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn bytes_received(&self) -> u64 {
+        self.bytes.received.load(Ordering::Relaxed)
+    }
+
+    /// The counts as the rows of a table, along with `unacked`, how many of
+    /// our messages the server has yet to ack.
+    pub(crate) fn table(&self, unacked: usize) -> Vec<String> {
+        let rows = [
+            ("", "Sent".to_owned(), "Received".to_owned()),
+            (
+                "Frames",
+                self.frames_sent.to_string(),
+                self.frames_received.to_string(),
+            ),
+            (
+                "Bytes",
+                self.bytes_sent().to_string(),
+                self.bytes_received().to_string(),
+            ),
+            ("Retransmits", self.retransmits.to_string(), String::new()),
+            ("Acks outstanding", unacked.to_string(), String::new()),
+        ];
+
+        rows.iter()
+            .map(|(label, sent, received)| {
+                format!("{label:<16} {sent:>10} {received:>10}")
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect()
+    }
+}
+
+/// How long a connection has been up, to the second, e.g. `1h 5m`.
+pub(crate) fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s}s"),
+        (h, m, _) => format!("{h}h {m}m"),
+    }
+}
+
+#[derive(Debug)]
+struct Counted<T> {
+    inner: T,
+    bytes: Arc<ByteCounts>,
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Counted<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+
+        let read = (buf.filled().len() - before) as u64;
+        this.bytes.received.fetch_add(read, Ordering::Relaxed);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Counted<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+
+        this.bytes.sent.fetch_add(written as u64, Ordering::Relaxed);
+
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_limit() {
+        assert_eq!(backoff(0), Duration::from_secs(1));
+        assert_eq!(backoff(1), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(16));
+        assert_eq!(backoff(6), MAX_DELAY);
+        assert_eq!(backoff(u32::MAX), MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_bytes_both_ways() {
+        let (client, mut server) = duplex(64);
+        let mut stats = ConnStats::new();
+        let mut stream = stats.count(Box::new(client));
+
+        stream.write_all(b"hello").await.unwrap();
+        server.write_all(b"hi").await.unwrap();
+        let mut read = [0; 2];
+        stream.read_exact(&mut read).await.unwrap();
+        stats.frames_sent = 1;
+
+        assert_eq!(
+            stats.table(3),
+            vec![
+                "                       Sent   Received",
+                "Frames                    1          0",
+                "Bytes                     5          2",
+                "Retransmits               0",
+                "Acks outstanding          3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(Duration::from_secs(42)), "42s");
+        assert_eq!(format_uptime(Duration::from_secs(61)), "1m 1s");
+        assert_eq!(format_uptime(Duration::from_secs(3_900)), "1h 5m");
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, fs, io::Write, path::PathBuf};
+
+use age::secrecy::SecretString;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+const CREDENTIALS_FILE: &str = "credentials.age";
+
+/// Account passwords by server, saved in an age file encrypted with a
+/// passphrase so that they never sit in plain text next to the config.
+///
+/// # Fields
+///
+/// - `servers`: Passwords by account, by server address.
+/// - `passphrase`: What the file was, or will be, encrypted with.
+#[derive(Default, Deserialize, Serialize)]
+pub(crate) struct Credentials {
+    servers: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(skip)]
+    passphrase: String,
+}
+
+// Written by hand to keep the passwords out of the logs
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("servers", &self.servers.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Credentials {
+    /// Starts a new file which will be encrypted with `passphrase`.
+    pub(crate) fn new(passphrase: String) -> Self {
+        Self {
+            servers: BTreeMap::new(),
+            passphrase,
+        }
+    }
+
+    /// Decrypts the saved credentials with `passphrase`.
+    pub(crate) fn load(passphrase: String) -> anyhow::Result<Self> {
+        let path = find_path().with_context(|| "ERROR: There are no saved credentials")?;
+        let encrypted =
+            fs::read(&path).with_context(|| format!("ERROR: Failed to read {path:?}"))?;
+        let raw = decrypt(&encrypted, &passphrase)?;
+        let mut credentials = toml::from_str::<Self>(&String::from_utf8(raw)?)
+            .with_context(|| format!("ERROR: Failed to parse {path:?}"))?;
+        credentials.passphrase = passphrase;
+
+        Ok(credentials)
+    }
+
+    pub(crate) fn get(&self, server: &str, account: &str) -> Option<&str> {
+        self.servers.get(server)?.get(account).map(String::as_str)
+    }
+
+    /// Saves the password for `account` on `server`, replacing any before.
+    pub(crate) fn save(
+        &mut self,
+        server: &str,
+        account: &str,
+        password: &str,
+    ) -> anyhow::Result<()> {
+        self.servers
+            .entry(server.to_owned())
+            .or_default()
+            .insert(account.to_owned(), password.to_owned());
+
+        let base_path = xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+        let path = base_path
+            .place_data_file(CREDENTIALS_FILE)
+            .with_context(|| "ERROR: Couldn't create the data directory")?;
+        let encrypted = encrypt(toml::to_string(self)?.as_bytes(), &self.passphrase, None)?;
+
+        write_private(&path, &encrypted).with_context(|| format!("ERROR: Failed to write {path:?}"))
+    }
+}
+
+/// Whether any credentials have been saved, which then have to be unlocked
+/// before more can be.
+pub(crate) fn exist() -> bool {
+    find_path().is_some()
+}
+
+fn find_path() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("solace")
+        .ok()?
+        .find_data_file(CREDENTIALS_FILE)
+}
+
+/// Encrypts `plaintext` with `passphrase`, with a `work_factor` picked to
+/// take about a second on this machine unless given.
+fn encrypt(plaintext: &[u8], passphrase: &str, work_factor: Option<u8>) -> anyhow::Result<Vec<u8>> {
+    let mut recipient = age::scrypt::Recipient::new(SecretString::from(passphrase.to_owned()));
+
+    if let Some(work_factor) = work_factor {
+        recipient.set_work_factor(work_factor);
+    }
+
+    age::encrypt(&recipient, plaintext).with_context(|| "ERROR: Failed to encrypt credentials")
+}
+
+fn decrypt(encrypted: &[u8], passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    let identity = age::scrypt::Identity::new(SecretString::from(passphrase.to_owned()));
+
+    age::decrypt(&identity, encrypted)
+        .with_context(|| "ERROR: Wrong passphrase for saved credentials")
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &PathBuf, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut credentials = Credentials::new("open sesame".to_owned());
+        credentials
+            .servers
+            .entry("0.0.0.0:7878".to_owned())
+            .or_default()
+            .insert("alice".to_owned(), "hunter2".to_owned());
+
+        // A low work factor, as the default takes about a second
+        let encrypted = encrypt(
+            toml::to_string(&credentials).unwrap().as_bytes(),
+            "open sesame",
+            Some(2),
+        )
+        .unwrap();
+        let decrypted = toml::from_str::<Credentials>(
+            &String::from_utf8(decrypt(&encrypted, "open sesame").unwrap()).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(decrypted.get("0.0.0.0:7878", "alice"), Some("hunter2"));
+        assert!(decrypt(&encrypted, "guess").is_err());
+    }
+}
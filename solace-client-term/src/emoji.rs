@@ -0,0 +1,282 @@
+use std::fs;
+
+use anyhow::Context;
+use crossterm::event;
+
+use crate::color::hex_to_rgb;
+use crate::config;
+use crate::overlay::{draw_box, draw_line, Overlay, OverlayAction};
+use crate::{CellStyle, Rect, RenderBuffer, Renderable};
+
+const RECENT_EMOJI_FILE: &str = "recent_emoji";
+const COLUMNS: usize = 16;
+const MAX_RECENT_EMOJI: usize = COLUMNS;
+
+// Only emoji drawn as a single wide character, as a variation selector or
+// joiner would be counted as a column of its own
+const CATEGORIES: &[(&str, &str)] = &[
+    (
+        "Smileys",
+        "😀😃😄😁😆😅😂🙂🙃😉😊😇😍😘😋😛😜🤔🤨😐😑😶🙄😏😬😌😔😴😷🤒🤯😎🤓😕😟😮😲😳🥺😢😭😱😖😞😓😩😤😡",
+    ),
+    (
+        "Gestures",
+        "👍👎👌🤞🤟🤘🤙👈👉👆👇👋🤚👏🙌👐🤲🙏💪👀🧠",
+    ),
+    (
+        "Nature",
+        "🐶🐱🐭🐹🐰🦊🐻🐼🐨🐯🦁🐮🐷🐸🐵🐔🐧🐦🐤🦆🦉🐺🐝🐛🦋🐌🐢🐍🐙🐬🐳🌵🌲🌳🌴🌱🌿🍀🍁🌸🌻🌞🌝🌈🔥🌊",
+    ),
+    (
+        "Food",
+        "🍏🍎🍐🍊🍋🍌🍉🍇🍓🍒🍑🥭🍍🥥🥝🍅🥑🥦🌽🥕🥐🍞🧀🥚🍳🥓🍔🍟🍕🌭🌮🌯🍜🍣🍩🍪🎂🍰🍫🍿☕🍺🍷",
+    ),
+    (
+        "Things",
+        "⚽🏀🏈🎾🎮🎲🎯🎸🎧🎉🎊🎁🏆🥇💡📌📎🔒🔑🔨💻📱📷📚⏰⌛🚀",
+    ),
+    (
+        "Symbols",
+        "💖💔💙💚💛💜🖤🧡💕✨⭐🌟💥💤💬👻💀🤖👽💩✅❌❓❗💯",
+    ),
+];
+
+/// Emoji most recently picked, newest first, or none if they can't be read
+/// as remembering them is only a convenience.
+pub(crate) fn recent_emoji() -> Vec<String> {
+    let Ok(base_path) = xdg::BaseDirectories::with_prefix("solace") else {
+        return Vec::new();
+    };
+
+    base_path
+        .find_state_file(RECENT_EMOJI_FILE)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|raw| raw.lines().map(str::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// Moves `emoji` to the front of the recent emoji.
+pub(crate) fn remember(emoji: &str) -> anyhow::Result<()> {
+    let base_path = xdg::BaseDirectories::with_prefix("solace")
+        .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+    let path = base_path
+        .place_state_file(RECENT_EMOJI_FILE)
+        .with_context(|| "ERROR: Couldn't create the state directory")?;
+
+    let mut recent = recent_emoji();
+    recent.retain(|e| e != emoji);
+    recent.insert(0, emoji.to_owned());
+    recent.truncate(MAX_RECENT_EMOJI);
+
+    fs::write(&path, recent.join("\n")).with_context(|| format!("ERROR: Failed to write {path:?}"))
+}
+
+/// A grid of emoji by category, with those picked recently first, which
+/// inserts the selected one at the prompt's cursor.
+#[derive(Debug)]
+pub(crate) struct EmojiPicker {
+    categories: Vec<(&'static str, Vec<String>)>,
+    category: usize,
+    selected: usize,
+}
+
+impl EmojiPicker {
+    pub(crate) fn new(recent: Vec<String>) -> Self {
+        let mut categories = CATEGORIES
+            .iter()
+            .map(|(name, emoji)| (*name, emoji.chars().map(String::from).collect()))
+            .collect::<Vec<(&str, Vec<String>)>>();
+
+        if !recent.is_empty() {
+            categories.insert(0, ("Recent", recent));
+        }
+
+        Self {
+            categories,
+            category: 0,
+            selected: 0,
+        }
+    }
+
+    fn emoji(&self) -> &[String] {
+        &self.categories[self.category].1
+    }
+
+    fn switch_category(&mut self, forwards: bool) {
+        let count = self.categories.len();
+
+        self.category = if forwards {
+            (self.category + 1) % count
+        } else {
+            (self.category + count - 1) % count
+        };
+        self.selected = 0;
+    }
+
+    fn select(&mut self, offset: isize) {
+        let last = self.emoji().len().saturating_sub(1);
+
+        self.selected = self.selected.saturating_add_signed(offset).min(last);
+    }
+}
+
+impl Renderable for EmojiPicker {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = &config::current().colors;
+        let fg = hex_to_rgb(&colors.fg);
+        let bg = hex_to_rgb(&colors.bg);
+        let hint = hex_to_rgb(&colors.server_message);
+        let right = rect.x + rect.width.saturating_sub(2);
+
+        draw_box(buf, rect, "Emoji", colors);
+
+        let mut x = rect.x + 2;
+        for (i, (name, _)) in self.categories.iter().enumerate() {
+            let (fg, cell_style) = if i == self.category {
+                (hex_to_rgb(&colors.user_name), CellStyle::Bold)
+            } else {
+                (hint, CellStyle::Normal)
+            };
+
+            for ch in format!("{name}  ").chars() {
+                if x >= right {
+                    break;
+                }
+
+                x += buf.put_at(x, rect.y + 1, ch, bg, fg, cell_style);
+            }
+        }
+
+        // Borders, the categories, blank lines and the hint take up the rest
+        let visible = rect.height.saturating_sub(6) as usize;
+        let rows = self.emoji().chunks(COLUMNS).enumerate().take(visible);
+
+        for (row, emoji) in rows {
+            let y = rect.y + 3 + row as u16;
+
+            for (column, e) in emoji.iter().enumerate() {
+                let x = rect.x + 2 + 3 * column as u16;
+                let is_selected = row * COLUMNS + column == self.selected;
+                // The selection is shown inverted, as emoji ignore colours
+                let (fg, bg) = if is_selected { (bg, fg) } else { (fg, bg) };
+
+                if x + 2 > right {
+                    break;
+                }
+
+                for ch in e.chars() {
+                    buf.put_at(x, y, ch, bg, fg, CellStyle::Normal);
+                }
+            }
+        }
+
+        draw_line(
+            buf,
+            rect,
+            (rect.y + rect.height).saturating_sub(2),
+            "Tab for the next category, Enter to insert, Esc to close",
+            hint,
+            CellStyle::Italic,
+            colors,
+        );
+    }
+}
+
+impl Overlay for EmojiPicker {
+    fn rect(&self, screen: &Rect) -> Rect {
+        let rows = self
+            .categories
+            .iter()
+            .map(|(_, emoji)| emoji.len().div_ceil(COLUMNS))
+            .max()
+            .unwrap_or(0) as u16;
+
+        screen.centered(
+            3 * COLUMNS as u16 + 12,
+            (rows + 6).min(screen.height.saturating_sub(2)),
+        )
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> OverlayAction {
+        match key.code {
+            event::KeyCode::Esc => OverlayAction::Close,
+            event::KeyCode::Tab => {
+                self.switch_category(true);
+                OverlayAction::Stay
+            }
+            event::KeyCode::BackTab => {
+                self.switch_category(false);
+                OverlayAction::Stay
+            }
+            event::KeyCode::Left | event::KeyCode::Char('h') => {
+                self.select(-1);
+                OverlayAction::Stay
+            }
+            event::KeyCode::Right | event::KeyCode::Char('l') => {
+                self.select(1);
+                OverlayAction::Stay
+            }
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.select(-(COLUMNS as isize));
+                OverlayAction::Stay
+            }
+            event::KeyCode::Down | event::KeyCode::Char('j') => {
+                self.select(COLUMNS as isize);
+                OverlayAction::Stay
+            }
+            event::KeyCode::Enter => match self.emoji().get(self.selected) {
+                Some(emoji) => OverlayAction::PickEmoji(emoji.clone()),
+                None => OverlayAction::Close,
+            },
+            _ => OverlayAction::Stay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: event::KeyCode) -> event::KeyEvent {
+        event::KeyEvent::new(code, event::KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut picker = EmojiPicker::new(vec!["🎉".to_owned()]);
+
+        assert_eq!(
+            picker.handle_key(key(event::KeyCode::Enter)),
+            OverlayAction::PickEmoji("🎉".to_owned())
+        );
+
+        picker.handle_key(key(event::KeyCode::Tab));
+        picker.handle_key(key(event::KeyCode::Right));
+        picker.handle_key(key(event::KeyCode::Down));
+        assert_eq!(
+            picker.handle_key(key(event::KeyCode::Enter)),
+            OverlayAction::PickEmoji("🤔".to_owned())
+        );
+
+        // Back around to the recent emoji, at the start again
+        picker.handle_key(key(event::KeyCode::BackTab));
+        picker.handle_key(key(event::KeyCode::Down));
+        assert_eq!(
+            picker.handle_key(key(event::KeyCode::Enter)),
+            OverlayAction::PickEmoji("🎉".to_owned())
+        );
+        assert_eq!(
+            picker.handle_key(key(event::KeyCode::Esc)),
+            OverlayAction::Close
+        );
+    }
+
+    #[test]
+    fn test_snapshot_emoji_picker() {
+        let mut picker = EmojiPicker::new(vec!["🎉".to_owned(), "👍".to_owned()]);
+        picker.handle_key(key(event::KeyCode::Tab));
+        picker.handle_key(key(event::KeyCode::Right));
+
+        insta::assert_snapshot!(crate::RenderBuffer::snapshot(&picker, 60, 9));
+    }
+}
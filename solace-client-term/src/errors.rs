@@ -0,0 +1,152 @@
+use solace_protocol::code::{
+    ERR_ATTACHMENT_TOO_LARGE, ERR_AUTH_REQUIRED, ERR_COMMAND_NOT_FOUND, ERR_INVALID_ARGUMENT,
+    ERR_MESSAGE_TOO_LONG, ERR_NICK_IN_USE, ERR_NICK_NOT_FOUND, ERR_NOT_IN_CHANNEL,
+    ERR_NOT_LOGGED_IN, ERR_NOT_PERMITTED, ERR_PROTOCOL, ERR_QUOTA_EXCEEDED, ERR_RATE_LIMITED,
+    ERR_READ_ONLY, ERR_SESSION_NOT_FOUND, ERR_WHO_IS, ERR_WRONG_PASSWORD,
+};
+
+use crate::config;
+
+/// Shown for an error this client doesn't know, e.g. from a newer server.
+const UNKNOWN: &str = "{detail}";
+
+/// Each error code by the name its explanation is overridden with in the
+/// config, and what is shown for it. `{detail}` is replaced by what the
+/// server said, which is usually the nick or limit involved.
+const CATALOG: &[(u16, &str, &str)] = &[
+    (
+        ERR_COMMAND_NOT_FOUND,
+        "command_not_found",
+        "{detail}. See /help for the commands this server knows",
+    ),
+    (
+        ERR_INVALID_ARGUMENT,
+        "invalid_argument",
+        "{detail}. Check the usage with /help",
+    ),
+    (
+        ERR_NICK_IN_USE,
+        "nick_in_use",
+        "That nick is taken: {detail}. Try /nick <other>, or /ghost <nick> if it's yours",
+    ),
+    (
+        ERR_WHO_IS,
+        "who_is",
+        "{detail}. Nicks in the channel are listed in the sidebar",
+    ),
+    (
+        ERR_PROTOCOL,
+        "protocol",
+        "The server couldn't read a request: {detail}. Your client may be out of date",
+    ),
+    (
+        ERR_NOT_LOGGED_IN,
+        "not_logged_in",
+        "{detail}. Try /login <account> <password>",
+    ),
+    (
+        ERR_SESSION_NOT_FOUND,
+        "session_not_found",
+        "{detail}. Try /devices to list your sessions",
+    ),
+    (
+        ERR_NICK_NOT_FOUND,
+        "nick_not_found",
+        "{detail}. They may have left or changed nick",
+    ),
+    (
+        ERR_QUOTA_EXCEEDED,
+        "quota_exceeded",
+        "{detail}. It resets at midnight UTC",
+    ),
+    (
+        ERR_RATE_LIMITED,
+        "rate_limited",
+        "Sending too fast, wait {detail}ms before trying again",
+    ),
+    (
+        ERR_WRONG_PASSWORD,
+        "wrong_password",
+        "{detail}. Check it and try /login again",
+    ),
+    (
+        ERR_NOT_PERMITTED,
+        "not_permitted",
+        "{detail}. Ask an op to change your level with /mode",
+    ),
+    (
+        ERR_MESSAGE_TOO_LONG,
+        "message_too_long",
+        "{detail}. Split it up, or set paste.command to upload long messages",
+    ),
+    (
+        ERR_ATTACHMENT_TOO_LARGE,
+        "attachment_too_large",
+        "{detail}. Try sharing a link instead",
+    ),
+    (
+        ERR_NOT_IN_CHANNEL,
+        "not_in_channel",
+        "{detail}. Try /join <channel> first",
+    ),
+    (
+        ERR_AUTH_REQUIRED,
+        "auth_required",
+        "{detail}. Use /auth <password> if it's yours, or pick another with /nick",
+    ),
+    (
+        ERR_READ_ONLY,
+        "read_only",
+        "{detail}. Ask one of its ops if something needs announcing",
+    ),
+];
+
+pub(crate) fn is_error(code: u16) -> bool {
+    (300..400).contains(&code)
+}
+
+/// What to show for the error `code`, which the server explained with
+/// `detail`, preferring the config's wording over the catalog's.
+pub(crate) fn explain(code: u16, detail: &str) -> String {
+    let Some((_, name, template)) = CATALOG.iter().find(|(c, ..)| *c == code) else {
+        return fill(UNKNOWN, detail);
+    };
+
+    match config::current().errors.get(*name) {
+        Some(ours) => fill(ours, detail),
+        None => fill(template, detail),
+    }
+}
+
+fn fill(template: &str, detail: &str) -> String {
+    // The server's own sentences end in no punctuation, but be sure
+    template.replace("{detail}", detail.trim_end_matches('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_error_is_explained() {
+        for code in 300..=ERR_READ_ONLY {
+            let (_, name, template) = CATALOG
+                .iter()
+                .find(|(c, ..)| *c == code)
+                .unwrap_or_else(|| panic!("{code} isn't in the catalog"));
+
+            assert!(!name.is_empty());
+            assert!(template.contains("{detail}"), "{name} drops the detail");
+        }
+
+        assert_eq!(
+            explain(
+                ERR_NICK_IN_USE,
+                "bob is in use by someone who isn't logged into it"
+            ),
+            "That nick is taken: bob is in use by someone who isn't logged into it. \
+             Try /nick <other>, or /ghost <nick> if it's yours"
+        );
+        assert_eq!(explain(399, "Something new."), "Something new");
+    }
+}
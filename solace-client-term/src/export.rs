@@ -0,0 +1,84 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::chat_window::ChatHistory;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Format {
+    /// One `timestamp author message` line per message, as it reads on screen.
+    Text,
+    /// One JSON object per line, in the same shape as the `tail` subcommand.
+    Json,
+}
+
+impl Format {
+    fn for_path(path: &std::path::Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json" | "jsonl") => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Entry<'a> {
+    pub(crate) timestamp: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) from: Option<&'a str>,
+    pub(crate) message: &'a str,
+}
+
+/// Writes `history` out to `path`, or to a new file in the `exports` data
+/// directory if there isn't one, as JSON if the file name ends in `.json` or
+/// `.jsonl` and as plain text otherwise. Returns where it was written.
+pub(crate) fn write(history: &ChatHistory, path: Option<&str>) -> anyhow::Result<PathBuf> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let base_path = xdg::BaseDirectories::with_prefix("solace")
+                .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+            let name = chrono::Local::now().format("%Y-%m-%d-%H%M%S");
+
+            base_path
+                .place_data_file(format!("exports/{name}.txt"))
+                .with_context(|| "ERROR: Couldn't create the exports directory")?
+        }
+    };
+
+    let mut out = String::new();
+
+    for entry in history.export() {
+        let line = match Format::for_path(&path) {
+            Format::Text => format!(
+                "{} {} {}",
+                entry.timestamp,
+                entry
+                    .from
+                    .map_or("--".to_owned(), |from| format!("@{from}")),
+                entry.message
+            ),
+            Format::Json => serde_json::to_string(&entry)?,
+        };
+
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    fs::write(&path, out).with_context(|| format!("ERROR: Failed to write {path:?}"))?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_for_path() {
+        assert_eq!(Format::for_path("notes.jsonl".as_ref()), Format::Json);
+        assert_eq!(Format::for_path("notes.txt".as_ref()), Format::Text);
+        assert_eq!(Format::for_path("notes".as_ref()), Format::Text);
+    }
+}
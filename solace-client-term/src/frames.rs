@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+
+use crossterm::event;
+
+use crate::color::hex_to_rgb;
+use crate::config;
+use crate::overlay::{Overlay, OverlayAction};
+use crate::{CellPatch, CellStyle, Rect, RenderBuffer, Renderable};
+
+/// One frame as it was drawn, along with the cells which were flushed to
+/// the terminal for it.
+#[derive(Clone, Debug)]
+struct RecordedFrame {
+    buffer: RenderBuffer,
+    changed: Vec<(u16, u16)>,
+}
+
+/// The last few frames drawn, kept with `--record-frames` so that flicker
+/// and stray characters on a terminal can be stepped through afterwards
+/// with `FrameReplay`.
+#[derive(Debug)]
+pub(crate) struct FrameRecorder {
+    frames: VecDeque<RecordedFrame>,
+    limit: usize,
+}
+
+impl FrameRecorder {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    /// Keeps `buffer`, which `patches` were flushed for. Frames which
+    /// changed nothing are skipped as there is nothing to see in them.
+    pub(crate) fn record(&mut self, buffer: &RenderBuffer, patches: &[CellPatch]) {
+        if patches.is_empty() {
+            return;
+        }
+
+        if self.frames.len() == self.limit {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(RecordedFrame {
+            buffer: buffer.clone(),
+            changed: patches.iter().map(|patch| (patch.x, patch.y)).collect(),
+        });
+    }
+
+    /// Steps through the frames recorded so far, starting with the newest.
+    pub(crate) fn replay(&self) -> FrameReplay {
+        FrameReplay {
+            frames: self.frames.iter().cloned().collect(),
+            shown: self.frames.len().saturating_sub(1),
+            is_status_hidden: false,
+        }
+    }
+}
+
+/// Shows recorded frames over the whole screen, one at a time, with the
+/// cells flushed for each picked out.
+///
+/// # Fields
+///
+/// - `shown`: Index into `frames`, the newest being last.
+/// - `is_status_hidden`: Whether the status line, which covers the top row
+///   of the frame, is hidden to see what is beneath.
+#[derive(Debug)]
+pub(crate) struct FrameReplay {
+    frames: Vec<RecordedFrame>,
+    shown: usize,
+    is_status_hidden: bool,
+}
+
+impl FrameReplay {
+    fn status(&self) -> String {
+        match self.frames.get(self.shown) {
+            Some(frame) => format!(
+                " Frame {} of {}, {} cells changed. ←/→ older/newer, h hides this, Esc closes ",
+                self.shown + 1,
+                self.frames.len(),
+                frame.changed.len()
+            ),
+            None => " No frames recorded yet. Esc closes ".to_owned(),
+        }
+    }
+}
+
+impl Renderable for FrameReplay {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = &config::current().colors;
+        let changed_bg = hex_to_rgb(&colors.error_bg);
+        let changed_fg = hex_to_rgb(&colors.error_fg);
+
+        if let Some(frame) = self.frames.get(self.shown) {
+            let recorded = &frame.buffer;
+            let width = recorded.width.min(rect.width);
+            let height = recorded.height.min(rect.height);
+
+            for y in 0..height {
+                for x in 0..width {
+                    let cell = &recorded.cells[(y * recorded.width + x) as usize];
+                    if cell.is_continuation {
+                        continue;
+                    }
+
+                    let (bg, fg) = match frame.changed.contains(&(x, y)) {
+                        true => (changed_bg, changed_fg),
+                        false => (cell.bg, cell.fg),
+                    };
+                    let width =
+                        buf.put_at(rect.x + x, rect.y + y, cell.ch, bg, fg, cell.cell_style);
+                    for mark in cell.marks.chars() {
+                        buf.put_at(
+                            rect.x + x + width,
+                            rect.y + y,
+                            mark,
+                            bg,
+                            fg,
+                            cell.cell_style,
+                        );
+                    }
+                }
+            }
+        }
+
+        if self.is_status_hidden {
+            return;
+        }
+
+        let bg = hex_to_rgb(&colors.topic_bg);
+        let fg = hex_to_rgb(&colors.topic_fg);
+        let mut x = rect.x;
+        let right = rect.x + rect.width;
+
+        for ch in self.status().chars() {
+            if x >= right {
+                break;
+            }
+
+            x += buf.put_at(x, rect.y, ch, bg, fg, CellStyle::Bold);
+        }
+
+        for x in x..right {
+            buf.put_at(x, rect.y, ' ', bg, fg, CellStyle::Normal);
+        }
+    }
+}
+
+impl Overlay for FrameReplay {
+    fn rect(&self, screen: &Rect) -> Rect {
+        *screen
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> OverlayAction {
+        match key.code {
+            event::KeyCode::Left | event::KeyCode::PageUp => {
+                self.shown = self.shown.saturating_sub(1);
+            }
+            event::KeyCode::Right | event::KeyCode::PageDown => {
+                self.shown = (self.shown + 1).min(self.frames.len().saturating_sub(1));
+            }
+            event::KeyCode::Home => self.shown = 0,
+            event::KeyCode::End => self.shown = self.frames.len().saturating_sub(1),
+            event::KeyCode::Char('h') => self.is_status_hidden = !self.is_status_hidden,
+            event::KeyCode::Esc | event::KeyCode::Char('q') => return OverlayAction::Close,
+            _ => (),
+        }
+
+        OverlayAction::Stay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::style;
+
+    use super::*;
+
+    fn frame(text: &str) -> RenderBuffer {
+        let mut buf = RenderBuffer::new(8, 2);
+        for (x, ch) in text.chars().enumerate() {
+            buf.put_at(
+                x as u16,
+                1,
+                ch,
+                style::Color::Reset,
+                style::Color::White,
+                CellStyle::Normal,
+            );
+        }
+
+        buf
+    }
+
+    fn key(code: event::KeyCode) -> event::KeyEvent {
+        event::KeyEvent::new(code, event::KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_records_frames_which_changed_up_to_the_limit() {
+        let mut recorder = FrameRecorder::new(2);
+        let blank = RenderBuffer::new(8, 2);
+
+        for text in ["one", "two", "three"] {
+            let buf = frame(text);
+            recorder.record(&buf, &blank.diff(&buf));
+        }
+        recorder.record(&blank, &[]);
+
+        let frames = recorder.replay().frames;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].buffer.cells[8].ch, 't');
+        assert_eq!(frames[1].changed.len(), 5);
+    }
+
+    #[test]
+    fn test_snapshot_replay() {
+        let mut recorder = FrameRecorder::new(4);
+        let (first, second) = (frame("hey"), frame("hello"));
+        recorder.record(&first, &RenderBuffer::new(8, 2).diff(&first));
+        recorder.record(&second, &first.diff(&second));
+
+        let mut replay = recorder.replay();
+        insta::assert_snapshot!(RenderBuffer::snapshot(&replay, 8, 2));
+
+        assert_eq!(
+            replay.handle_key(key(event::KeyCode::Left)),
+            OverlayAction::Stay
+        );
+        assert_eq!(replay.shown, 0);
+        replay.handle_key(key(event::KeyCode::Left));
+        assert_eq!(replay.shown, 0);
+        replay.handle_key(key(event::KeyCode::End));
+        assert_eq!(replay.shown, 1);
+        assert_eq!(
+            replay.handle_key(key(event::KeyCode::Esc)),
+            OverlayAction::Close
+        );
+    }
+}
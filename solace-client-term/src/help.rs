@@ -0,0 +1,177 @@
+use std::cell::Cell;
+
+use crossterm::event;
+use solace_protocol::command::CommandSpec;
+
+use crate::color::hex_to_rgb;
+use crate::config;
+use crate::keybindings::{Context, KEYBINDINGS};
+use crate::overlay::{draw_box, draw_line, Overlay, OverlayAction};
+use crate::{CellStyle, Rect, RenderBuffer, Renderable};
+
+const MODES: &[(&str, &str)] = &[
+    ("Insert", "Typing goes into the prompt, the default"),
+    ("Normal", "Vim style movement and editing of the prompt"),
+];
+
+#[derive(Debug, PartialEq)]
+enum Line {
+    Heading(String),
+    Entry(String, String),
+    Blank,
+}
+
+/// Lists the key bindings, modes and every command the server and client
+/// understand, built fresh each time it is opened so that it matches the
+/// commands the server last sent.
+///
+/// # Fields
+///
+/// - `visible`: How many lines fit on screen as of the last render, which
+///   scrolling is limited by.
+#[derive(Debug)]
+pub(crate) struct Help {
+    lines: Vec<Line>,
+    scroll: usize,
+    visible: Cell<usize>,
+}
+
+impl Help {
+    pub(crate) fn new<'a>(commands: impl Iterator<Item = &'a CommandSpec>) -> Self {
+        let mut lines = vec![Line::Heading("Modes".to_owned())];
+
+        for (mode, description) in MODES {
+            lines.push(Line::Entry(mode.to_string(), description.to_string()));
+        }
+
+        let contexts = [
+            Context::Global,
+            Context::Insert,
+            Context::Completion,
+            Context::Normal,
+        ];
+
+        for context in contexts {
+            lines.push(Line::Blank);
+            lines.push(Line::Heading(context.name().to_owned()));
+
+            for binding in KEYBINDINGS.iter().filter(|b| b.context == context) {
+                lines.push(Line::Entry(
+                    binding.keys.to_owned(),
+                    binding.action.to_owned(),
+                ));
+            }
+        }
+
+        lines.push(Line::Blank);
+        lines.push(Line::Heading("Commands".to_owned()));
+
+        for spec in commands {
+            lines.push(Line::Entry(format!("/{}", spec.usage()), spec.help.clone()));
+        }
+
+        Self {
+            lines,
+            scroll: 0,
+            visible: Cell::new(0),
+        }
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.lines.len().saturating_sub(self.visible.get())
+    }
+}
+
+impl Renderable for Help {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = &config::current().colors;
+
+        draw_box(buf, rect, "Help", colors);
+
+        let visible = rect.height.saturating_sub(2) as usize;
+        self.visible.set(visible);
+        let scroll = self.scroll.min(self.max_scroll());
+        let key_width = self
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Entry(key, _) => Some(key.len()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+            .min(24);
+
+        for (row, line) in self.lines.iter().skip(scroll).take(visible).enumerate() {
+            let y = rect.y + 1 + row as u16;
+
+            match line {
+                Line::Heading(heading) => draw_line(
+                    buf,
+                    rect,
+                    y,
+                    heading,
+                    hex_to_rgb(&colors.user_name),
+                    CellStyle::Bold,
+                    colors,
+                ),
+                Line::Entry(key, description) => draw_line(
+                    buf,
+                    rect,
+                    y,
+                    &format!("  {key:<key_width$}  {description}"),
+                    hex_to_rgb(&colors.fg),
+                    CellStyle::Normal,
+                    colors,
+                ),
+                Line::Blank => (),
+            }
+        }
+    }
+}
+
+impl Overlay for Help {
+    fn rect(&self, screen: &Rect) -> Rect {
+        screen.centered(64, screen.height.saturating_sub(2))
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> OverlayAction {
+        match key.code {
+            event::KeyCode::F(1) | event::KeyCode::Esc | event::KeyCode::Char('q') => {
+                OverlayAction::Close
+            }
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.scroll = self.scroll.saturating_sub(1);
+                OverlayAction::Stay
+            }
+            event::KeyCode::Down | event::KeyCode::Char('j') => {
+                self.scroll = (self.scroll + 1).min(self.max_scroll());
+                OverlayAction::Stay
+            }
+            _ => OverlayAction::Stay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_help() {
+        let commands = ["nick <nick>\tChanges your nick", "away [reason...]", "exit"]
+            .iter()
+            .filter_map(|usage| CommandSpec::parse(usage))
+            .collect::<Vec<CommandSpec>>();
+        let help = Help::new(commands.iter());
+
+        insta::assert_snapshot!(crate::RenderBuffer::snapshot(&help, 60, 40));
+    }
+
+    #[test]
+    fn test_closes_on_f1() {
+        let mut help = Help::new(std::iter::empty());
+        let key = event::KeyEvent::new(event::KeyCode::F(1), event::KeyModifiers::NONE);
+        assert_eq!(help.handle_key(key), OverlayAction::Close);
+    }
+}
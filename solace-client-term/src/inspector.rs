@@ -0,0 +1,139 @@
+use crossterm::event;
+
+use crate::color::hex_to_rgb;
+use crate::config;
+use crate::overlay::{draw_box, draw_line, Overlay, OverlayAction};
+use crate::{CellStyle, Rect, RenderBuffer, Renderable};
+
+const LABEL_WIDTH: usize = 12;
+const WIDTH: u16 = 72;
+
+/// Everything known about one entry of the chat history, for working out
+/// why a message didn't arrive or arrived twice.
+///
+/// # Fields
+///
+/// - `fields`: A label and value per line.
+/// - `text`: The raw text of the message, shown last as it may need
+///   wrapping over several lines.
+#[derive(Debug)]
+pub(crate) struct Inspector {
+    fields: Vec<(&'static str, String)>,
+    text: String,
+}
+
+impl Inspector {
+    pub(crate) fn new(fields: Vec<(&'static str, String)>, text: &str) -> Self {
+        Self {
+            fields,
+            // Escaped so that control characters and newlines can be seen
+            text: text.escape_debug().to_string(),
+        }
+    }
+
+    fn text_lines(&self) -> Vec<String> {
+        // Borders, padding and the label either side of the text
+        let width = WIDTH as usize - 4 - LABEL_WIDTH;
+        let chars = self.text.chars().collect::<Vec<char>>();
+
+        if chars.is_empty() {
+            return vec![String::new()];
+        }
+
+        chars
+            .chunks(width)
+            .map(|chunk| chunk.iter().collect())
+            .collect()
+    }
+}
+
+impl Renderable for Inspector {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = &config::current().colors;
+        let label_fg = hex_to_rgb(&colors.server_message);
+        let fg = hex_to_rgb(&colors.fg);
+
+        draw_box(buf, rect, "Message", colors);
+
+        // The bottom border, a blank line and the hint
+        let last = (rect.y + rect.height).saturating_sub(4);
+        let text = self.text_lines();
+        let labelled = self
+            .fields
+            .iter()
+            .map(|(label, value)| (*label, value.as_str()))
+            .chain(
+                text.iter()
+                    .enumerate()
+                    .map(|(i, line)| (if i == 0 { "Text" } else { "" }, line.as_str())),
+            );
+
+        for (row, (label, value)) in labelled.enumerate() {
+            let y = rect.y + 1 + row as u16;
+            if y > last {
+                break;
+            }
+
+            draw_line(buf, rect, y, label, label_fg, CellStyle::Bold, colors);
+            draw_line(
+                buf,
+                &Rect {
+                    x: rect.x + LABEL_WIDTH as u16,
+                    width: rect.width.saturating_sub(LABEL_WIDTH as u16),
+                    ..*rect
+                },
+                y,
+                value,
+                fg,
+                CellStyle::Normal,
+                colors,
+            );
+        }
+
+        draw_line(
+            buf,
+            rect,
+            (rect.y + rect.height).saturating_sub(2),
+            "Esc to close",
+            label_fg,
+            CellStyle::Italic,
+            colors,
+        );
+    }
+}
+
+impl Overlay for Inspector {
+    fn rect(&self, screen: &Rect) -> Rect {
+        let height = (self.fields.len() + self.text_lines().len()) as u16 + 4;
+
+        screen.centered(WIDTH, height.min(screen.height.saturating_sub(2)))
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> OverlayAction {
+        match key.code {
+            event::KeyCode::Esc | event::KeyCode::Enter | event::KeyCode::Char('q') => {
+                OverlayAction::Close
+            }
+            _ => OverlayAction::Stay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_inspector() {
+        let inspector = Inspector::new(
+            vec![
+                ("From", "alice".to_owned()),
+                ("Code", "200".to_owned()),
+                ("Message id", "#42".to_owned()),
+            ],
+            "two\nlines",
+        );
+
+        insta::assert_snapshot!(crate::RenderBuffer::snapshot(&inspector, 72, 8));
+    }
+}
@@ -0,0 +1,78 @@
+/// Where a key binding applies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Context {
+    Global,
+    Insert,
+    Completion,
+    Normal,
+}
+
+impl Context {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Context::Global => "Anywhere",
+            Context::Insert => "Insert mode",
+            Context::Completion => "Nick completion",
+            Context::Normal => "Normal mode",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Keybinding {
+    pub(crate) context: Context,
+    pub(crate) keys: &'static str,
+    pub(crate) action: &'static str,
+}
+
+const fn bind(context: Context, keys: &'static str, action: &'static str) -> Keybinding {
+    Keybinding {
+        context,
+        keys,
+        action,
+    }
+}
+
+/// Every key the client responds to, as shown in the help overlay.
+///
+/// @CLEANUP: This has to be kept in step with the key handlers in `main.rs`
+/// and `prompt.rs` by hand.
+pub(crate) const KEYBINDINGS: &[Keybinding] = &[
+    bind(Context::Global, "F1", "Show or hide this help"),
+    bind(Context::Global, "F2", "Inspect the selected message"),
+    bind(Context::Global, "Ctrl-c", "Quit"),
+    bind(Context::Global, "Ctrl-e", "Pick an emoji to insert"),
+    bind(
+        Context::Global,
+        "PageUp/PageDown",
+        "Scroll a page back/forward",
+    ),
+    bind(
+        Context::Global,
+        "Ctrl-u/Ctrl-d",
+        "Scroll half a page back/forward",
+    ),
+    bind(Context::Insert, "Enter", "Send the message or command"),
+    bind(Context::Insert, "Esc", "Switch to normal mode"),
+    bind(Context::Insert, "Tab", "Complete the command name"),
+    bind(Context::Insert, "Up/Down", "Step through sent messages"),
+    bind(Context::Insert, "Backspace", "Delete before the cursor"),
+    bind(Context::Completion, "Up/Down", "Pick a nick"),
+    bind(Context::Completion, "Tab/Enter", "Insert the picked nick"),
+    bind(Context::Completion, "Esc", "Dismiss the list"),
+    bind(Context::Normal, "i/a", "Insert before/after the cursor"),
+    bind(Context::Normal, "I/A", "Insert at the start/end"),
+    bind(Context::Normal, "h/l", "Move left/right"),
+    bind(Context::Normal, "0/$", "Move to the start/end"),
+    bind(Context::Normal, "F<char>", "Jump back to <char>"),
+    bind(Context::Normal, "x", "Delete under the cursor"),
+    bind(Context::Normal, "D/C", "Delete to the end, C then inserts"),
+    bind(Context::Normal, "dd/X", "Clear the prompt"),
+    bind(Context::Normal, "Up/Down", "Step through sent messages"),
+    bind(Context::Normal, "j/k", "Select a newer/older message"),
+    bind(Context::Normal, "r", "Reply to the selected message"),
+    bind(Context::Normal, "y", "Copy the selected message"),
+    bind(Context::Normal, "b", "Bookmark the selected message"),
+    bind(Context::Normal, "o", "Expand the selected joins and parts"),
+    bind(Context::Normal, "Esc", "Clear the selection"),
+];
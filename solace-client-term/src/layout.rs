@@ -0,0 +1,276 @@
+use crate::{Rect, RenderBuffer, Renderable};
+
+/// How much room one slot of a split asks for along the split direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Constraint {
+    /// Exactly this many cells, or whatever is left if there isn't room.
+    Fixed(u16),
+    /// A share of whatever the fixed slots leave over, weighted against the
+    /// other flex slots.
+    Flex(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+impl Rect {
+    /// Cuts the rect into one slot per constraint, in order, left to right
+    /// or top to bottom. Fixed slots are handed out first so that a small
+    /// terminal squeezes the flexible parts, e.g. the chat history, before
+    /// anything else.
+    pub(crate) fn split(&self, direction: Direction, constraints: &[Constraint]) -> Vec<Rect> {
+        let total = match direction {
+            Direction::Horizontal => self.width,
+            Direction::Vertical => self.height,
+        };
+
+        let mut remaining = total;
+        let mut sizes = constraints
+            .iter()
+            .map(|constraint| match constraint {
+                Constraint::Fixed(size) => {
+                    let size = (*size).min(remaining);
+                    remaining -= size;
+                    size
+                }
+                Constraint::Flex(_) => 0,
+            })
+            .collect::<Vec<u16>>();
+
+        let weights = constraints
+            .iter()
+            .map(|constraint| match constraint {
+                Constraint::Flex(weight) => u32::from(*weight),
+                Constraint::Fixed(_) => 0,
+            })
+            .collect::<Vec<u32>>();
+        let total_weight = weights.iter().sum::<u32>();
+
+        let flex = u32::from(remaining);
+        let mut handed_out = 0;
+
+        // Only flex slots have a weight, so `total_weight` can't be zero here
+        for (size, weight) in sizes.iter_mut().zip(&weights) {
+            if *weight > 0 {
+                *size = (flex * weight / total_weight) as u16;
+                handed_out += *size;
+            }
+        }
+
+        // Rounding leftovers go to the last flex slot
+        if let Some(i) = weights.iter().rposition(|weight| *weight > 0) {
+            sizes[i] += remaining - handed_out;
+        }
+
+        let mut offset = 0;
+
+        sizes
+            .into_iter()
+            .map(|size| {
+                let rect = match direction {
+                    Direction::Horizontal => Rect {
+                        x: self.x + offset,
+                        y: self.y,
+                        width: size,
+                        height: self.height,
+                    },
+                    Direction::Vertical => Rect {
+                        x: self.x,
+                        y: self.y + offset,
+                        width: self.width,
+                        height: size,
+                    },
+                };
+                offset += size;
+                rect
+            })
+            .collect()
+    }
+
+    /// A rect of the given size centered within this one, shrunk to fit.
+    pub(crate) fn centered(&self, width: u16, height: u16) -> Rect {
+        let width = width.min(self.width);
+        let height = height.min(self.height);
+
+        Rect {
+            x: self.x + (self.width - width) / 2,
+            y: self.y + (self.height - height) / 2,
+            width,
+            height,
+        }
+    }
+}
+
+/// A widget made up of other widgets, which places them into a `Frame`
+/// rather than drawing anything itself.
+pub(crate) trait Composite {
+    fn place<'a>(&'a self, frame: &mut Frame<'a>, rect: Rect);
+}
+
+/// Modals are drawn over everything else, including other overlays.
+const MODAL_Z: u8 = u8::MAX;
+
+struct Layer<'a> {
+    z: u8,
+    is_modal: bool,
+    rect: Rect,
+    widget: Box<dyn Renderable + 'a>,
+}
+
+/// Everything to be drawn for one frame and where.
+///
+/// Layers are drawn lowest `z` first, and in the order they were placed for
+/// the same `z`, so that overlays such as popups end up over whatever they
+/// cover. Overlays have their area cleared first so nothing beneath shows
+/// through, modals also dim everything drawn before them.
+#[derive(Default)]
+pub(crate) struct Frame<'a> {
+    layers: Vec<Layer<'a>>,
+}
+
+impl<'a> Frame<'a> {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn place(&mut self, widget: impl Renderable + 'a, rect: Rect) {
+        self.overlay(widget, rect, 0);
+    }
+
+    pub(crate) fn overlay(&mut self, widget: impl Renderable + 'a, rect: Rect, z: u8) {
+        self.layers.push(Layer {
+            z,
+            is_modal: false,
+            rect,
+            widget: Box::new(widget),
+        });
+    }
+
+    pub(crate) fn modal(&mut self, widget: impl Renderable + 'a, rect: Rect) {
+        self.layers.push(Layer {
+            z: MODAL_Z,
+            is_modal: true,
+            rect,
+            widget: Box::new(widget),
+        });
+    }
+
+    pub(crate) fn render_into(mut self, buf: &mut RenderBuffer) {
+        self.layers.sort_by_key(|layer| layer.z);
+
+        for layer in &self.layers {
+            if layer.is_modal {
+                buf.dim();
+            }
+
+            if layer.z > 0 {
+                buf.clear_rect(&layer.rect);
+            }
+
+            layer.widget.render_into(buf, &layer.rect);
+        }
+    }
+}
+
+impl<T: Renderable + ?Sized> Renderable for &T {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        (**self).render_into(buf, rect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(width: u16, height: u16) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    fn heights(rects: &[Rect]) -> Vec<u16> {
+        rects.iter().map(|r| r.height).collect()
+    }
+
+    #[test]
+    fn test_split_fixed_and_flex() {
+        let rects = rect(10, 20).split(
+            Direction::Vertical,
+            &[
+                Constraint::Fixed(1),
+                Constraint::Flex(1),
+                Constraint::Fixed(2),
+            ],
+        );
+
+        assert_eq!(heights(&rects), vec![1, 17, 2]);
+        assert_eq!(
+            rects.iter().map(|r| r.y).collect::<Vec<_>>(),
+            vec![0, 1, 18]
+        );
+    }
+
+    #[test]
+    fn test_split_weights_flex_and_rounds_into_last() {
+        let rects = rect(10, 10).split(
+            Direction::Horizontal,
+            &[Constraint::Flex(1), Constraint::Flex(2)],
+        );
+
+        assert_eq!(
+            rects.iter().map(|r| r.width).collect::<Vec<_>>(),
+            vec![3, 7]
+        );
+        assert_eq!(rects[1].x, 3);
+    }
+
+    #[test]
+    fn test_split_squeezes_fixed_when_too_small() {
+        let rects = rect(10, 2).split(
+            Direction::Vertical,
+            &[
+                Constraint::Fixed(1),
+                Constraint::Flex(1),
+                Constraint::Fixed(2),
+            ],
+        );
+
+        assert_eq!(heights(&rects), vec![1, 0, 1]);
+    }
+
+    #[test]
+    fn test_overlays_are_drawn_on_top() {
+        struct Fill(char);
+
+        impl Renderable for Fill {
+            fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+                for y in rect.y..rect.y + rect.height {
+                    for x in rect.x..rect.x + rect.width {
+                        buf.put_at(
+                            x,
+                            y,
+                            self.0,
+                            crossterm::style::Color::Reset,
+                            crossterm::style::Color::White,
+                            crate::CellStyle::Normal,
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut buf = RenderBuffer::new(4, 3);
+        let mut frame = Frame::new();
+        frame.overlay(Fill('o'), rect(4, 3).centered(2, 1), 1);
+        frame.place(Fill('.'), rect(4, 3));
+        frame.render_into(&mut buf);
+
+        assert_eq!(buf.dump(), "|....|\n|.oo.|\n|....|\n---\n");
+    }
+}
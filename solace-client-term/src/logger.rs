@@ -5,19 +5,60 @@ use std::{
 };
 
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 
 pub(crate) static LOGGER: Lazy<Logger> = Lazy::new(|| Logger::new("/tmp/solace.log"));
 
 #[macro_export]
 macro_rules! log {
-    ($($arg:tt)*) => {
+    ($level:ident, $($arg:tt)*) => {
         {
-            let log_message = format!($($arg)*);
-            $crate::logger::LOGGER.log(&log_message);
+            let level = $crate::logger::LogLevel::$level;
+            if level <= *$crate::config!(log_level) {
+                let log_message = format!($($arg)*);
+                $crate::logger::LOGGER.log(level, &log_message);
+            }
         }
     };
 }
 
+/// How severe a log message is, from least to most verbose, so a configured
+/// level lets through itself and everything before it.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    fn label(self) -> &'static str {
+        match self {
+            LogLevel::Off => "OFF",
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+}
+
 pub(crate) struct Logger {
     file: Mutex<File>,
 }
@@ -35,9 +76,9 @@ impl Logger {
         }
     }
 
-    pub(crate) fn log(&self, message: &str) {
+    pub(crate) fn log(&self, level: LogLevel, message: &str) {
         if let Ok(mut file) = self.file.lock() {
-            if writeln!(file, "{}", message).is_err() {
+            if writeln!(file, "{}: {}", level.label(), message).is_err() {
                 eprintln!("Failed to write to log file");
             }
         } else {
@@ -1,8 +1,10 @@
 #![allow(dead_code)]
 
 use std::{
+    fmt,
     io::{self, Write},
     mem, panic,
+    process::ExitCode,
 };
 
 use crossterm::{
@@ -11,51 +13,110 @@ use crossterm::{
     terminal, QueueableCommand,
 };
 
-use futures::{future::FutureExt, StreamExt};
+use futures::{
+    future::{self, FutureExt},
+    StreamExt,
+};
+use solace_protocol::build_info::BuildInfo;
+use unicode_width::UnicodeWidthChar;
 
 use crate::chat_window::ChatWindow;
+use crate::frames::FrameRecorder;
+use crate::layout::{Composite, Frame};
 
+mod bookmarks;
+mod browser;
 mod chat_window;
+mod cli;
+mod clipboard;
 mod color;
+mod completion;
 mod config;
+mod connection;
+mod credentials;
+mod emoji;
+mod errors;
+mod export;
+mod frames;
+mod help;
+mod inspector;
+mod keybindings;
+mod layout;
 mod logger;
+mod notify;
+mod opener;
+mod overlay;
+mod paste;
 mod prompt;
+mod safe_mode;
+mod send;
+mod simulate;
+mod tail;
+mod timestamp;
+mod tls;
+mod transport;
+mod wizard;
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 enum CellStyle {
     Bold,
     Italic,
+    Underlined,
     #[default]
     Normal,
 }
 
+/// The number of terminal columns `ch` occupies, wide characters such as
+/// CJK ideographs take up two. Zero width characters such as combining
+/// accents take none, as the terminal draws them over the character before
+/// them, see `RenderCell::marks`.
+fn cell_width(ch: char) -> u16 {
+    ch.width().unwrap_or(1) as u16
+}
+
+fn str_width(s: &str) -> u16 {
+    s.chars().map(cell_width).sum()
+}
+
+/// # Fields
+///
+/// - `marks`: Zero width characters drawn over `ch`, such as combining
+///   accents, printed straight after it.
+/// - `is_continuation`: The right half of a wide character drawn in the cell
+///   to its left, never flushed itself as the terminal fills it in for us.
 #[derive(Clone, Debug, PartialEq)]
 struct RenderCell {
     ch: char,
+    marks: String,
     bg: style::Color,
     fg: style::Color,
     cell_style: CellStyle,
+    is_continuation: bool,
 }
 
 impl RenderCell {
     fn new() -> Self {
         Self {
             ch: ' ',
+            marks: String::new(),
             bg: style::Color::Reset,
             fg: style::Color::White,
             cell_style: CellStyle::Normal,
+            is_continuation: false,
         }
     }
 
     fn reset(&mut self) {
         self.ch = ' ';
+        self.marks.clear();
         self.bg = style::Color::Reset;
         self.fg = style::Color::White;
         self.cell_style = CellStyle::Normal;
+        self.is_continuation = false;
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct RenderBuffer {
     cells: Vec<RenderCell>,
     width: u16,
@@ -67,7 +128,7 @@ impl RenderBuffer {
         Self {
             cells: (0..(width * height)).map(|_| RenderCell::new()).collect(),
             width,
-            height: 0,
+            height,
         }
     }
 
@@ -78,7 +139,7 @@ impl RenderBuffer {
             .iter()
             .zip(other.cells.iter())
             .enumerate()
-            .filter(|(_, (a, b))| *a != *b)
+            .filter(|(_, (a, b))| *a != *b && !b.is_continuation)
             .map(|(i, (_, cell))| {
                 CellPatch::new(cell.clone(), i as u16 % self.width, i as u16 / self.width)
             })
@@ -97,6 +158,104 @@ impl RenderBuffer {
         self.cells.iter_mut().for_each(|cell| cell.reset());
     }
 
+    /// Fades everything drawn so far, so that a modal stands out from what
+    /// it covers.
+    fn dim(&mut self) {
+        for cell in &mut self.cells {
+            cell.fg = match cell.fg {
+                style::Color::Rgb { r, g, b } => style::Color::Rgb {
+                    r: r / 2,
+                    g: g / 2,
+                    b: b / 2,
+                },
+                _ => style::Color::DarkGrey,
+            };
+            cell.bg = match cell.bg {
+                style::Color::Rgb { r, g, b } => style::Color::Rgb {
+                    r: r / 2,
+                    g: g / 2,
+                    b: b / 2,
+                },
+                // The terminal's own background is left alone
+                bg => bg,
+            };
+        }
+    }
+
+    fn clear_rect(&mut self, rect: &Rect) {
+        for y in rect.y..(rect.y + rect.height).min(self.height) {
+            for x in rect.x..(rect.x + rect.width).min(self.width) {
+                self.cells[(y * self.width + x) as usize].reset();
+            }
+        }
+    }
+
+    /// Writes the buffer out as plain text, one line per row, followed by the
+    /// style of every run of cells which isn't drawn in the default style, so
+    /// that what was rendered can be compared without a terminal.
+    fn dump(&self) -> String {
+        let default = RenderCell::new();
+        let rows = self.cells.chunks(self.width.max(1) as usize);
+        let mut text = String::new();
+        let mut styles = String::new();
+
+        for (y, row) in rows.enumerate() {
+            text.push('|');
+            for cell in row.iter().filter(|c| !c.is_continuation) {
+                text.push(cell.ch);
+                text.push_str(&cell.marks);
+            }
+            text.push_str("|\n");
+
+            let mut x = 0;
+
+            while x < row.len() {
+                let cell = &row[x];
+                let len = row[x..]
+                    .iter()
+                    .take_while(|c| {
+                        c.bg == cell.bg && c.fg == cell.fg && c.cell_style == cell.cell_style
+                    })
+                    .count();
+
+                if (cell.bg, cell.fg, cell.cell_style)
+                    != (default.bg, default.fg, default.cell_style)
+                {
+                    styles.push_str(&format!(
+                        "{y}:{x}..{} fg={} bg={} {:?}\n",
+                        x + len,
+                        color_name(cell.fg),
+                        color_name(cell.bg),
+                        cell.cell_style,
+                    ));
+                }
+
+                x += len;
+            }
+        }
+
+        format!("{text}---\n{styles}")
+    }
+
+    /// Renders `widget` into a fresh buffer of the given size and dumps it.
+    #[cfg(test)]
+    fn snapshot(widget: &impl Renderable, width: u16, height: u16) -> String {
+        let mut buf = RenderBuffer::new(width, height);
+        widget.render_into(
+            &mut buf,
+            &Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        );
+
+        buf.dump()
+    }
+
+    /// Returns the number of columns taken up by `ch` so that callers laying
+    /// out text can advance by the right amount.
     fn put_at(
         &mut self,
         x: u16,
@@ -105,39 +264,86 @@ impl RenderBuffer {
         bg: style::Color,
         fg: style::Color,
         cell_style: CellStyle,
-    ) {
+    ) -> u16 {
+        // Control characters would move the terminal's cursor about, newlines
+        // from pastes are shown rather than dropped
+        let ch = match ch {
+            '\n' => '↵',
+            ch if ch.is_control() => ' ',
+            ch if safe_mode::is_on() => safe_mode::fallback(ch),
+            ch => ch,
+        };
+        let width = cell_width(ch);
+
+        if width == 0 {
+            self.put_mark(x, y, ch);
+            return 0;
+        }
+
+        // A wide character hanging off the right edge would wrap, so blank it
+        let ch = if width > 1 && x + width > self.width {
+            ' '
+        } else {
+            ch
+        };
         let i = y * self.width + x;
 
-        if let Some(c) = self.cells.get_mut(i as usize) {
-            *c = RenderCell {
-                ch,
-                bg,
-                fg,
-                cell_style,
+        for offset in 0..width {
+            if x + offset >= self.width {
+                break;
             }
+
+            if let Some(c) = self.cells.get_mut((i + offset) as usize) {
+                *c = RenderCell {
+                    ch,
+                    marks: String::new(),
+                    bg,
+                    fg,
+                    cell_style,
+                    is_continuation: offset > 0,
+                }
+            }
+        }
+
+        width
+    }
+
+    /// Draws the zero width `mark` over the character to the left of `x`, or
+    /// drops it if there isn't one, as a mark in a cell of its own would
+    /// shift everything after it a column left of where it was meant to be.
+    fn put_mark(&mut self, x: u16, y: u16, mark: char) {
+        let row = y * self.width;
+        let lead = (row..row + x.min(self.width)).rev().find(|i| {
+            self.cells
+                .get(*i as usize)
+                .is_some_and(|c| !c.is_continuation)
+        });
+
+        if let Some(cell) = lead.and_then(|i| self.cells.get_mut(i as usize)) {
+            cell.marks.push(mark);
         }
     }
 }
 
+fn color_name(color: style::Color) -> String {
+    match color {
+        style::Color::Rgb { r, g, b } => format!("#{r:02x}{g:02x}{b:02x}"),
+        color => format!("{color:?}").to_lowercase(),
+    }
+}
+
 impl Flushable for RenderBuffer {
     fn render_to(&self, qc: &mut impl QueueableCommand) -> anyhow::Result<()> {
         qc.queue(cursor::MoveTo(0, 0))?;
 
-        for RenderCell {
-            ch,
-            bg,
-            fg,
-            cell_style,
-        } in &self.cells
-        {
-            let attr = match cell_style {
-                CellStyle::Bold => style::Attribute::Bold,
-                CellStyle::Italic => style::Attribute::Italic,
-                CellStyle::Normal => style::Attribute::NormalIntensity,
-            };
-            qc.queue(style::PrintStyledContent(
-                ch.on(*bg).with(*fg).attribute(attr),
-            ))?;
+        let plain_bg = config_hex_color!(colors.bg);
+
+        for cell in &self.cells {
+            if cell.is_continuation {
+                continue;
+            }
+
+            qc.queue(style::PrintStyledContent(styled_cell(cell, plain_bg)))?;
         }
 
         Ok(())
@@ -159,27 +365,45 @@ impl CellPatch {
 
 impl Flushable for CellPatch {
     fn render_to(&self, qc: &mut impl QueueableCommand) -> anyhow::Result<()> {
-        let RenderCell {
-            bg,
-            ch,
-            fg,
-            cell_style,
-        } = self.cell;
-        let attr = match cell_style {
-            CellStyle::Bold => style::Attribute::Bold,
-            CellStyle::Italic => style::Attribute::Italic,
-            CellStyle::Normal => style::Attribute::NormalIntensity,
-        };
+        let plain_bg = config_hex_color!(colors.bg);
 
         qc.queue(cursor::MoveTo(self.x, self.y))?
-            .queue(style::PrintStyledContent(
-                ch.on(bg).with(fg).attribute(attr),
-            ))?;
+            .queue(style::PrintStyledContent(styled_cell(&self.cell, plain_bg)))?;
 
         Ok(())
     }
 }
 
+/// What a cell prints, its character followed by the marks drawn over it.
+struct CellText<'a>(&'a RenderCell);
+
+impl fmt::Display for CellText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.0.ch, self.0.marks)
+    }
+}
+
+/// How `cell` is printed, leaving out colors and italics in safe mode where
+/// `plain_bg` is the theme's background.
+fn styled_cell(cell: &RenderCell, plain_bg: style::Color) -> style::StyledContent<CellText<'_>> {
+    let attr = match cell.cell_style {
+        CellStyle::Bold => style::Attribute::Bold,
+        CellStyle::Italic => style::Attribute::Italic,
+        CellStyle::Underlined => style::Attribute::Underlined,
+        CellStyle::Normal => style::Attribute::NormalIntensity,
+    };
+
+    if safe_mode::is_on() {
+        return safe_mode::styled(CellText(cell), cell.bg, plain_bg, attr);
+    }
+
+    style::style(CellText(cell))
+        .on(cell.bg)
+        .with(cell.fg)
+        .attribute(attr)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct Rect {
     x: u16,
     y: u16,
@@ -205,7 +429,15 @@ struct Screen;
 
 impl Screen {
     fn start(stdout: &mut io::Stdout) -> anyhow::Result<Self> {
-        crossterm::execute!(stdout, terminal::EnterAlternateScreen,)?;
+        crossterm::execute!(stdout, terminal::EnterAlternateScreen)?;
+        // Limited terminals could print these rather than understand them
+        if !safe_mode::is_on() {
+            crossterm::execute!(
+                stdout,
+                event::EnableFocusChange,
+                event::EnableBracketedPaste
+            )?;
+        }
         terminal::enable_raw_mode()?;
 
         Ok(Self)
@@ -215,31 +447,63 @@ impl Screen {
 impl Drop for Screen {
     fn drop(&mut self) {
         terminal::disable_raw_mode().unwrap();
+        if !safe_mode::is_on() {
+            crossterm::execute!(
+                io::stdout(),
+                event::DisableFocusChange,
+                event::DisableBracketedPaste
+            )
+            .unwrap();
+        }
         crossterm::execute!(io::stdout(), terminal::LeaveAlternateScreen).unwrap();
     }
 }
 
+/// What this client was built from, which the server is told about.
+fn build_info() -> BuildInfo {
+    BuildInfo::ours(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
 async fn run() -> anyhow::Result<()> {
+    if config::is_first_run()? && !wizard::run().await? {
+        return Ok(());
+    }
+
+    let server = match config!(server) {
+        Some(server) => server.clone(),
+        None => match browser::run().await? {
+            Some(server) => server,
+            None => return Ok(()),
+        },
+    };
+
     let mut size = terminal::size()?;
-    let mut chat_window = ChatWindow::new().await?;
+    let mut chat_window = ChatWindow::new(&server).await?;
+
+    if let Err(err) = browser::remember(&server) {
+        log!(Warn, "{err:#}");
+    }
+
     let mut stdout = io::stdout();
     let mut buf_curr = RenderBuffer::new(size.0, size.1);
     let mut buf_prev = RenderBuffer::new(size.0, size.1);
     let mut should_quit = false;
-    let mut has_notified_no_remote = false;
     let _screen = Screen::start(&mut stdout)?;
     let mut reader = event::EventStream::new();
+    let mut recorder = cli::args().record_frames.map(FrameRecorder::new);
 
     while !should_quit {
+        let wake = chat_window.next_wake();
+
         tokio::select! {
-            result = chat_window.read() => if let Err(err) = result {
-                if !has_notified_no_remote {
-                    chat_window.history.error(&err.to_string());
-                    chat_window
-                        .history
-                        .error("Please try again with the /connect command");
-                    has_notified_no_remote = true;
+            _ = async {
+                match wake {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => future::pending().await,
                 }
+            } => chat_window.wake().await?,
+            result = chat_window.read() => if let Err(err) = result {
+                chat_window.history.error(&err.to_string());
             },
             maybe_event = reader.next().fuse() => if let Some(Ok(event)) = maybe_event {
                 match event {
@@ -250,6 +514,11 @@ async fn run() -> anyhow::Result<()> {
                         buf_prev.render_to(&mut stdout)?;
                         stdout.flush()?;
                     }
+                    event::Event::FocusLost => chat_window.focus_lost(),
+                    event::Event::FocusGained => chat_window.focus_gained().await?,
+                    event::Event::Paste(text) if !chat_window.has_overlay() => {
+                        chat_window.prompt.paste(&text);
+                    }
                     event::Event::Key(key) => {
                         let event::KeyEvent {
                             code, modifiers, ..
@@ -262,13 +531,52 @@ async fn run() -> anyhow::Result<()> {
                                 // @TODO: Revisit quitting method
                                 should_quit = true;
                             }
-                            event::KeyCode::Enter => {
-                                chat_window
-                                    .write(chat_window.prompt.current_value())
-                                    .await?;
-                                chat_window.prompt.flush();
+                            event::KeyCode::F(1) if !chat_window.has_overlay() => {
+                                chat_window.show_help();
+                            }
+                            event::KeyCode::Char('e')
+                                if modifiers.contains(event::KeyModifiers::CONTROL)
+                                    && !chat_window.has_overlay() =>
+                            {
+                                chat_window.show_emoji_picker();
+                            }
+                            event::KeyCode::F(2) if !chat_window.has_overlay() => {
+                                chat_window.inspect_selected();
+                            }
+                            event::KeyCode::F(12) if !chat_window.has_overlay() => {
+                                if let Some(recorder) = &recorder {
+                                    chat_window.open_overlay(recorder.replay());
+                                }
+                            }
+                            event::KeyCode::PageUp if !chat_window.has_overlay() => {
+                                let page = chat_window.history.page();
+                                chat_window.history.scroll_up(page);
+                            }
+                            event::KeyCode::PageDown if !chat_window.has_overlay() => {
+                                let page = chat_window.history.page();
+                                chat_window.history.scroll_down(page);
                             }
-                            _ => chat_window.prompt.handle_key_press(code),
+                            event::KeyCode::Char('u')
+                                if modifiers.contains(event::KeyModifiers::CONTROL)
+                                    && !chat_window.has_overlay() =>
+                            {
+                                let half = chat_window.history.page().div_ceil(2);
+                                chat_window.history.scroll_up(half);
+                            }
+                            event::KeyCode::Char('d')
+                                if modifiers.contains(event::KeyModifiers::CONTROL)
+                                    && !chat_window.has_overlay() =>
+                            {
+                                let half = chat_window.history.page().div_ceil(2);
+                                chat_window.history.scroll_down(half);
+                            }
+                            _ if chat_window.has_overlay() => {
+                                chat_window.handle_overlay_key(key).await?;
+                            }
+                            event::KeyCode::Enter if !chat_window.prompt.is_completing() => {
+                                chat_window.send_prompt().await?;
+                            }
+                            _ => chat_window.handle_key_press(code).await?,
                         }
                     }
                     _ => (),
@@ -278,37 +586,40 @@ async fn run() -> anyhow::Result<()> {
 
         buf_curr.clear();
 
-        chat_window.render_into(
-            &mut buf_curr,
-            &Rect {
+        let mut frame = Frame::new();
+        chat_window.place(
+            &mut frame,
+            Rect {
                 x: 0,
                 y: 0,
                 width: size.0,
-                height: size.1.saturating_sub(3),
+                height: size.1,
             },
         );
+        frame.render_into(&mut buf_curr);
 
-        // @REFACTOR: abstract accesses to prompt behind chat_window
-        chat_window.prompt.render_into(
-            &mut buf_curr,
-            &Rect {
-                x: 0,
-                y: size.1.saturating_sub(2),
-                width: size.0,
-                height: 2,
-            },
-        );
-
-        for patch in &buf_prev.diff(&buf_curr) {
+        let patches = buf_prev.diff(&buf_curr);
+        for patch in &patches {
             patch.render_to(&mut stdout)?;
         }
+        if let Some(recorder) = &mut recorder {
+            recorder.record(&buf_curr, &patches);
+        }
 
-        // @CLEANUP: assumption that prompt is in the last row
-        let (x, cursor_style) = chat_window.prompt.cursor_state();
-        stdout
-            .queue(cursor::MoveTo(x, size.1))?
-            .queue(cursor_style)?
-            .flush()?;
+        // Focus only goes back to the prompt once every overlay has closed
+        if chat_window.has_overlay() {
+            stdout.queue(cursor::Hide)?.flush()?;
+        } else {
+            // @CLEANUP: assumption that prompt is in the last row
+            let (x, cursor_style) = chat_window.prompt.cursor_state();
+            stdout
+                .queue(cursor::Show)?
+                .queue(cursor::MoveTo(x, size.1))?;
+            if !safe_mode::is_on() {
+                stdout.queue(cursor_style)?;
+            }
+            stdout.flush()?;
+        }
 
         mem::swap(&mut buf_curr, &mut buf_prev);
     }
@@ -317,7 +628,17 @@ async fn run() -> anyhow::Result<()> {
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
+    cli::init();
+
+    match &cli::args().command {
+        Some(cli::Command::Send { message, timeout }) => {
+            return Ok(send::run(message, *timeout).await);
+        }
+        Some(cli::Command::Tail) => return Ok(tail::run().await),
+        None => (),
+    }
+
     panic::set_hook(Box::new(|info| {
         crossterm::execute!(io::stdout(), terminal::LeaveAlternateScreen).unwrap();
         terminal::disable_raw_mode().unwrap();
@@ -325,5 +646,7 @@ async fn main() -> anyhow::Result<()> {
         std::process::exit(1);
     }));
 
-    run().await
+    run().await?;
+
+    Ok(ExitCode::SUCCESS)
 }
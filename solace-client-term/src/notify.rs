@@ -0,0 +1,203 @@
+use std::{
+    io::{self, Write},
+    process::Stdio,
+    time::Duration,
+};
+
+use tokio::{io::AsyncWriteExt, sync::Mutex, time};
+
+use crate::config::{self, Announce, Notifications};
+use crate::log;
+
+/// Long enough for a long message read slowly.
+const ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Held while something is being read aloud, so that announcements wait
+/// their turn instead of talking over each other.
+static ANNOUNCING: Mutex<()> = Mutex::const_new(());
+
+/// The buffer holding the main chat, as named in `notifications.muted`.
+pub(crate) const MAIN_BUFFER: &str = "chat";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Reason {
+    Mention,
+    DirectMessage,
+}
+
+/// Makes the configured sound for something arriving in `buffer`, unless
+/// notifications say otherwise.
+pub(crate) fn notify(reason: Reason, buffer: &str) {
+    let notifications = &config::current().notifications;
+
+    if !should_notify(notifications, reason, buffer) {
+        return;
+    }
+
+    log!(Debug, "Notifying of {reason:?} in {buffer}");
+
+    match notifications.sound.as_str() {
+        "off" => (),
+        "bell" => {
+            let mut stdout = io::stdout();
+            // Not worth bothering the user about if the terminal is gone
+            let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        }
+        command => {
+            // Left to run on its own as sounds can take a while to finish
+            let spawned = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+
+            if let Err(err) = spawned {
+                log!(Warn, "Couldn't run notification sound {command:?}: {err}");
+            }
+        }
+    }
+}
+
+/// Reads `text` aloud with the announce command, after anything already
+/// being read, unless announcing is off or `buffer` is muted.
+pub(crate) fn announce(reason: Reason, buffer: &str, text: String) {
+    let config = config::current();
+
+    if !should_announce(&config.announce, &config.notifications, reason, buffer) {
+        return;
+    }
+
+    let command = config.announce.command.clone();
+    tokio::spawn(async move {
+        let _turn = ANNOUNCING.lock().await;
+
+        if let Err(err) = speak(&command, &text).await {
+            log!(Warn, "Couldn't announce with {command:?}: {err}");
+        }
+    });
+}
+
+async fn speak(command: &str, text: &str) -> io::Result<()> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+        // Dropped here so that the command sees the end of the text
+    }
+
+    match time::timeout(ANNOUNCE_TIMEOUT, child.wait()).await {
+        Ok(status) => status.map(|_| ()),
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "took too long")),
+    }
+}
+
+fn should_announce(
+    announce: &Announce,
+    notifications: &Notifications,
+    reason: Reason,
+    buffer: &str,
+) -> bool {
+    let is_wanted = match reason {
+        Reason::Mention => announce.on_mention,
+        Reason::DirectMessage => announce.on_direct_message,
+    };
+
+    announce.enabled
+        && !announce.command.is_empty()
+        && is_wanted
+        && !notifications.do_not_disturb
+        && !notifications.muted.iter().any(|muted| muted == buffer)
+}
+
+fn should_notify(notifications: &Notifications, reason: Reason, buffer: &str) -> bool {
+    let is_wanted = match reason {
+        Reason::Mention => notifications.on_mention,
+        Reason::DirectMessage => notifications.on_direct_message,
+    };
+
+    is_wanted
+        && !notifications.do_not_disturb
+        && !notifications.muted.iter().any(|muted| muted == buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_notify() {
+        let mut notifications = Notifications {
+            muted: vec!["spammer".to_owned()],
+            on_direct_message: false,
+            ..Notifications::default()
+        };
+
+        assert!(should_notify(&notifications, Reason::Mention, MAIN_BUFFER));
+        assert!(!should_notify(&notifications, Reason::Mention, "spammer"));
+        assert!(!should_notify(
+            &notifications,
+            Reason::DirectMessage,
+            "friend"
+        ));
+
+        notifications.do_not_disturb = true;
+        assert!(!should_notify(&notifications, Reason::Mention, MAIN_BUFFER));
+    }
+
+    #[test]
+    fn test_should_announce() {
+        let mut notifications = Notifications {
+            muted: vec!["spammer".to_owned()],
+            ..Notifications::default()
+        };
+        let mut announce = Announce {
+            command: "espeak".to_owned(),
+            on_mention: false,
+            ..Announce::default()
+        };
+
+        assert!(!should_announce(
+            &announce,
+            &notifications,
+            Reason::DirectMessage,
+            "friend"
+        ));
+
+        announce.enabled = true;
+        assert!(should_announce(
+            &announce,
+            &notifications,
+            Reason::DirectMessage,
+            "friend"
+        ));
+        assert!(!should_announce(
+            &announce,
+            &notifications,
+            Reason::DirectMessage,
+            "spammer"
+        ));
+        assert!(!should_announce(
+            &announce,
+            &notifications,
+            Reason::Mention,
+            MAIN_BUFFER
+        ));
+
+        notifications.do_not_disturb = true;
+        assert!(!should_announce(
+            &announce,
+            &notifications,
+            Reason::DirectMessage,
+            "friend"
+        ));
+    }
+}
@@ -0,0 +1,37 @@
+use std::process::Stdio;
+
+use anyhow::Context;
+
+/// The command which opens a URL in the default browser on this platform.
+const OPEN_COMMAND: &[&str] = if cfg!(target_os = "macos") {
+    &["open"]
+} else if cfg!(windows) {
+    // Never through `cmd`, which would run whatever follows an `&` in the URL
+    &["rundll32", "url.dll,FileProtocolHandler"]
+} else {
+    &["xdg-open"]
+};
+
+/// Opens `url` in the default browser. Anything but a web link is refused,
+/// as the openers will just as happily run a local file.
+pub(crate) async fn open(url: &str) -> anyhow::Result<()> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        anyhow::bail!("ERROR: Won't open {url}, which isn't a web link");
+    }
+
+    let status = tokio::process::Command::new(OPEN_COMMAND[0])
+        .args(&OPEN_COMMAND[1..])
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .with_context(|| format!("ERROR: Couldn't run {}", OPEN_COMMAND[0]))?;
+
+    if !status.success() {
+        anyhow::bail!("ERROR: {} failed with {status}", OPEN_COMMAND[0]);
+    }
+
+    Ok(())
+}
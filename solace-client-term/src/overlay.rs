@@ -0,0 +1,329 @@
+use std::fmt::Debug;
+
+use crossterm::{event, style};
+
+use solace_protocol::attachment::Attachment;
+
+use crate::color::hex_to_rgb;
+use crate::config::{self, Colors};
+use crate::{str_width, CellStyle, Rect, RenderBuffer, Renderable};
+
+/// What should happen once an overlay has handled a key.
+#[derive(Debug, PartialEq)]
+pub(crate) enum OverlayAction {
+    /// Stay open with focus.
+    Stay,
+    /// Close, handing focus back to whatever is beneath.
+    Close,
+    /// Close and send this as if it had been typed into the prompt.
+    Submit(String),
+    /// Close, handing back a password which mustn't be shown or sent as is.
+    Password(String),
+    /// Close and show the chat message with this `message_id`.
+    JumpTo(u64),
+    /// Close and insert this emoji at the prompt's cursor.
+    PickEmoji(String),
+    /// Close and save `attachment` to `path`, over whatever is there.
+    Overwrite {
+        path: String,
+        attachment: Attachment,
+    },
+}
+
+/// A modal layer drawn over the dimmed chat window, which takes every key
+/// press until it closes.
+pub(crate) trait Overlay: Renderable + Debug {
+    /// Where to draw within the screen.
+    fn rect(&self, screen: &Rect) -> Rect;
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> OverlayAction;
+}
+
+/// Draws a titled border around `rect`, leaving the inside blank.
+pub(crate) fn draw_box(buf: &mut RenderBuffer, rect: &Rect, title: &str, colors: &Colors) {
+    if rect.width < 2 || rect.height < 2 {
+        return;
+    }
+
+    let fg = hex_to_rgb(&colors.fg);
+    let bg = hex_to_rgb(&colors.bg);
+    let right = rect.x + rect.width - 1;
+    let bottom = rect.y + rect.height - 1;
+
+    for y in rect.y..=bottom {
+        for x in rect.x..=right {
+            let ch = match (x, y) {
+                (x, y) if x == rect.x && y == rect.y => '┌',
+                (x, y) if x == right && y == rect.y => '┐',
+                (x, y) if x == rect.x && y == bottom => '└',
+                (x, y) if x == right && y == bottom => '┘',
+                (_, y) if y == rect.y || y == bottom => '─',
+                (x, _) if x == rect.x || x == right => '│',
+                _ => ' ',
+            };
+
+            buf.put_at(x, y, ch, bg, fg, CellStyle::Normal);
+        }
+    }
+
+    let mut x = rect.x + 2;
+
+    for ch in format!(" {title} ").chars() {
+        if x >= right {
+            break;
+        }
+
+        x += buf.put_at(x, rect.y, ch, bg, fg, CellStyle::Bold);
+    }
+}
+
+/// Writes `text` on row `y` inside of a box drawn by `draw_box`, clipped to
+/// its border.
+pub(crate) fn draw_line(
+    buf: &mut RenderBuffer,
+    rect: &Rect,
+    y: u16,
+    text: &str,
+    fg: style::Color,
+    cell_style: CellStyle,
+    colors: &Colors,
+) {
+    let bg = hex_to_rgb(&colors.bg);
+    let right = rect.x + rect.width.saturating_sub(1);
+    let mut x = rect.x + 2;
+
+    for ch in text.chars() {
+        if x + str_width(&ch.to_string()) > right.saturating_sub(1) {
+            break;
+        }
+
+        x += buf.put_at(x, y, ch, bg, fg, cell_style);
+    }
+}
+
+/// Asks a yes or no question, sending `on_confirm` if the answer is yes.
+#[derive(Debug)]
+pub(crate) struct Confirm {
+    question: String,
+    on_confirm: OverlayAction,
+}
+
+impl Confirm {
+    pub(crate) fn new(question: &str, on_confirm: String) -> Self {
+        Self::with_action(question, OverlayAction::Submit(on_confirm))
+    }
+
+    /// Asks `question`, closing with `on_confirm` rather than sending
+    /// anything if the answer is yes.
+    pub(crate) fn with_action(question: &str, on_confirm: OverlayAction) -> Self {
+        Self {
+            question: question.to_owned(),
+            on_confirm,
+        }
+    }
+}
+
+impl Renderable for Confirm {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = &config::current().colors;
+
+        draw_box(buf, rect, "Confirm", colors);
+        draw_line(
+            buf,
+            rect,
+            rect.y + 1,
+            &self.question,
+            hex_to_rgb(&colors.fg),
+            CellStyle::Normal,
+            colors,
+        );
+        draw_line(
+            buf,
+            rect,
+            rect.y + 3,
+            "[y]es  [n]o",
+            hex_to_rgb(&colors.server_message),
+            CellStyle::Italic,
+            colors,
+        );
+    }
+}
+
+impl Overlay for Confirm {
+    fn rect(&self, screen: &Rect) -> Rect {
+        let width = str_width(&self.question).max(11) + 6;
+
+        screen.centered(width, 5)
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> OverlayAction {
+        match key.code {
+            event::KeyCode::Char('y') | event::KeyCode::Enter => {
+                std::mem::replace(&mut self.on_confirm, OverlayAction::Close)
+            }
+            event::KeyCode::Char('n') | event::KeyCode::Esc => OverlayAction::Close,
+            _ => OverlayAction::Stay,
+        }
+    }
+}
+
+/// Asks for a password or passphrase without showing what is typed.
+#[derive(Debug)]
+pub(crate) struct Password {
+    question: String,
+    value: String,
+}
+
+impl Password {
+    pub(crate) fn new(question: &str) -> Self {
+        Self {
+            question: question.to_owned(),
+            value: String::new(),
+        }
+    }
+}
+
+impl Renderable for Password {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = &config::current().colors;
+        let fg = hex_to_rgb(&colors.fg);
+
+        draw_box(buf, rect, "Password", colors);
+        draw_line(
+            buf,
+            rect,
+            rect.y + 1,
+            &self.question,
+            fg,
+            CellStyle::Normal,
+            colors,
+        );
+        draw_line(
+            buf,
+            rect,
+            rect.y + 2,
+            &"•".repeat(self.value.chars().count()),
+            fg,
+            CellStyle::Bold,
+            colors,
+        );
+        draw_line(
+            buf,
+            rect,
+            rect.y + 4,
+            "Enter to submit, Esc to cancel",
+            hex_to_rgb(&colors.server_message),
+            CellStyle::Italic,
+            colors,
+        );
+    }
+}
+
+impl Overlay for Password {
+    fn rect(&self, screen: &Rect) -> Rect {
+        let width = str_width(&self.question).max(30) + 6;
+
+        screen.centered(width, 6)
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> OverlayAction {
+        match key.code {
+            event::KeyCode::Char('c') if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
+                OverlayAction::Close
+            }
+            event::KeyCode::Esc => OverlayAction::Close,
+            event::KeyCode::Enter => OverlayAction::Password(std::mem::take(&mut self.value)),
+            event::KeyCode::Backspace => {
+                self.value.pop();
+                OverlayAction::Stay
+            }
+            event::KeyCode::Char(ch) => {
+                self.value.push(ch);
+                OverlayAction::Stay
+            }
+            _ => OverlayAction::Stay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Frame;
+
+    fn key(code: event::KeyCode) -> event::KeyEvent {
+        event::KeyEvent::new(code, event::KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_confirm_keys() {
+        let mut confirm = Confirm::new("Send it?", "hello".to_owned());
+        assert_eq!(
+            confirm.handle_key(key(event::KeyCode::Char('x'))),
+            OverlayAction::Stay
+        );
+        assert_eq!(
+            confirm.handle_key(key(event::KeyCode::Char('y'))),
+            OverlayAction::Submit("hello".to_owned())
+        );
+        assert_eq!(
+            confirm.handle_key(key(event::KeyCode::Esc)),
+            OverlayAction::Close
+        );
+    }
+
+    #[test]
+    fn test_password_is_hidden() {
+        let mut password = Password::new("Password for alice");
+        for ch in "hunter2".chars() {
+            password.handle_key(key(event::KeyCode::Char(ch)));
+        }
+
+        let dump = crate::RenderBuffer::snapshot(&password, 36, 6);
+        assert!(dump.contains("•••••••"));
+        assert!(!dump.contains("hunter2"));
+        assert_eq!(
+            password.handle_key(key(event::KeyCode::Enter)),
+            OverlayAction::Password("hunter2".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_snapshot_confirm_over_dimmed_background() {
+        struct Text(&'static str);
+
+        impl Renderable for Text {
+            fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+                for (i, ch) in self.0.chars().enumerate() {
+                    buf.put_at(
+                        rect.x + i as u16,
+                        rect.y,
+                        ch,
+                        style::Color::Reset,
+                        style::Color::Rgb {
+                            r: 200,
+                            g: 200,
+                            b: 200,
+                        },
+                        CellStyle::Normal,
+                    );
+                }
+            }
+        }
+
+        let screen = Rect {
+            x: 0,
+            y: 0,
+            width: 30,
+            height: 7,
+        };
+        let confirm = Confirm::new("Send 40 lines?", String::new());
+        let mut buf = RenderBuffer::new(screen.width, screen.height);
+        let mut frame = Frame::new();
+        frame.place(Text("beneath the overlay"), screen);
+        frame.modal(&confirm, confirm.rect(&screen));
+        frame.render_into(&mut buf);
+
+        insta::assert_snapshot!(buf.dump());
+    }
+}
@@ -0,0 +1,68 @@
+use std::{process::Stdio, time::Duration};
+
+use anyhow::Context;
+use tokio::{io::AsyncWriteExt, time};
+
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Uploads `text` with `command`, run with `sh -c` and given the text on
+/// stdin, returning the first link it prints.
+pub(crate) async fn upload(command: &str, text: &str) -> anyhow::Result<String> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("ERROR: Couldn't run paste command {command:?}"))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command which stops reading early says why through its exit
+        // status, which is more use than a broken pipe
+        let _ = stdin.write_all(text.as_bytes()).await;
+        // Dropped here so that the command sees the end of the text
+    }
+
+    let output = time::timeout(UPLOAD_TIMEOUT, child.wait_with_output())
+        .await
+        .with_context(|| "ERROR: Paste command took too long")??;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "ERROR: Paste command failed with {}: {}",
+            output.status,
+            stderr.lines().next().unwrap_or_default()
+        );
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .map(str::to_owned)
+        .with_context(|| "ERROR: Paste command didn't print a link")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upload() {
+        let link = upload(
+            "read -r first; echo \"Pasted $first at https://paste.example/abc\"",
+            "hello\nworld",
+        )
+        .await
+        .unwrap();
+        assert_eq!(link, "https://paste.example/abc");
+
+        let err = upload("cat", "no links here").await.unwrap_err();
+        assert_eq!(err.to_string(), "ERROR: Paste command didn't print a link");
+
+        let err = upload("echo nope >&2; exit 3", "").await.unwrap_err();
+        assert!(err.to_string().ends_with(": nope"), "{err}");
+    }
+}
@@ -1,20 +1,58 @@
-use crossterm::{cursor, event, style};
-use solace_message_parser::{parse, AstNode};
+use std::cell::RefCell;
+use std::ops::Range;
 
-use crate::{config_hex_color, CellStyle, Mode, Rect, RenderBuffer, Renderable};
+use crossterm::{cursor, event, style};
+use solace_message_parser::{AstMessage, AstNode, Parser, ParserOptions, TextSpan};
+use solace_protocol::command::CommandSpec;
+use solace_protocol::presence::NickListEntry;
+
+use crate::completion::NickCompletion;
+use crate::config::{self, Recall};
+use crate::layout::{Composite, Frame};
+use crate::{
+    cell_width, config_hex_color, notify, str_width, CellStyle, Mode, Rect, RenderBuffer,
+    Renderable,
+};
+
+/// A line sent from the prompt, and the buffer it was typed into.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HistoryEntry {
+    text: String,
+    buffer: String,
+}
 
 #[derive(Debug)]
 pub(crate) struct Prompt {
-    pub(crate) commands: Vec<String>,
-    pub(crate) local_commands: Vec<String>,
-    pub(crate) nicks: Vec<String>,
+    pub(crate) commands: Vec<CommandSpec>,
+    pub(crate) local_commands: Vec<CommandSpec>,
+    pub(crate) nicks: Vec<NickListEntry>,
     pub(crate) nick: String,
     pub(crate) pos: usize,
     command_buffer: Vec<char>,
+    completion: Option<NickCompletion>,
+    // Start of the mention whose popup was closed with Esc, so that it
+    // doesn't immediately reopen while the user keeps typing it
+    dismissed_completion: Option<usize>,
     curr: Vec<char>,
-    history: Vec<String>,
+    history: Vec<HistoryEntry>,
+    // How far back through the entries `recalled` Up has gone, 0 being
+    // what was typed
     history_offset: usize,
+    // What was typed before the first Up, which every recalled entry starts
+    // with and which Down comes back to
+    draft: String,
+    // The buffer being typed into, which is always the main one until
+    // others can be
+    pub(crate) buffer: String,
     mode: Mode,
+    // Kept between keystrokes so that each one only re-lexes from the edit
+    parser: RefCell<Parser>,
+    // The snippet placeholder under the cursor, replaced as a whole by the
+    // next character typed
+    placeholder: Option<Range<usize>>,
+    // Whether Tab moves between placeholders rather than completing, until
+    // the snippet has none left
+    is_in_snippet: bool,
 }
 
 impl Prompt {
@@ -22,19 +60,34 @@ impl Prompt {
         Self {
             command_buffer: vec![],
             commands: vec![],
+            completion: None,
+            dismissed_completion: None,
             local_commands: vec![],
             nicks: vec![],
             curr: vec![],
             history: vec![],
             history_offset: 0,
+            draft: String::new(),
+            buffer: notify::MAIN_BUFFER.to_owned(),
             mode: Mode::Insert,
             nick: String::default(),
+            parser: RefCell::new(Parser::new("")),
+            placeholder: None,
+            is_in_snippet: false,
             pos: 0,
         }
     }
 
     pub(crate) fn flush(&mut self) {
-        self.history.push(self.curr.iter().collect::<String>());
+        let mut text = self.current_value();
+        if let Some(start) = self.secret_start() {
+            text.truncate(start);
+        }
+
+        self.history.push(HistoryEntry {
+            text,
+            buffer: self.buffer.clone(),
+        });
         self.history_offset = 0;
 
         self.clear()
@@ -42,17 +95,114 @@ impl Prompt {
 
     pub(crate) fn handle_key_press(&mut self, key_code: event::KeyCode) {
         match self.mode {
+            Mode::Insert if self.completion.is_some() => self.handle_completion(key_code),
             Mode::Insert => self.handle_insert(key_code),
             Mode::Normal => self.handle_normal(key_code),
         }
+
+        self.refresh_completion();
+    }
+
+    /// Inserts pasted text at the cursor as it is, newlines included, rather
+    /// than as key presses which would send each line on its own.
+    pub(crate) fn paste(&mut self, text: &str) {
+        for ch in text.replace("\r\n", "\n").replace('\r', "\n").chars() {
+            self.insert(ch);
+        }
+
+        self.refresh_completion();
+    }
+
+    /// Replaces the prompt with a snippet, putting the cursor on its first
+    /// placeholder if it has any.
+    pub(crate) fn fill_snippet(&mut self, text: &str) {
+        self.curr = text.chars().collect();
+        self.pos = self.curr.len();
+        self.is_in_snippet = true;
+        self.next_placeholder();
+    }
+
+    /// Moves to the placeholder after the cursor, wrapping around to the
+    /// first, returning whether there was one.
+    fn next_placeholder(&mut self) -> bool {
+        let from = match self.placeholder.take() {
+            Some(placeholder) => placeholder.end,
+            None => self.pos,
+        };
+
+        let found = find_placeholder(&self.curr, from).or_else(|| find_placeholder(&self.curr, 0));
+
+        match found {
+            Some(placeholder) => {
+                self.pos = placeholder.start;
+                self.placeholder = Some(placeholder);
+            }
+            None => self.is_in_snippet = false,
+        }
+
+        self.placeholder.is_some()
+    }
+
+    fn remove_placeholder(&mut self) -> bool {
+        let Some(placeholder) = self.placeholder.take() else {
+            return false;
+        };
+
+        self.pos = placeholder.start;
+        self.curr.drain(placeholder);
+
+        true
+    }
+
+    pub(crate) fn is_completing(&self) -> bool {
+        self.completion.is_some()
     }
 
     pub(crate) fn current_value(&self) -> String {
         self.curr.iter().collect::<String>()
     }
 
+    fn parse(&self) -> AstMessage {
+        self.parser.borrow_mut().update(&self.current_value())
+    }
+
+    /// Where the password starts in a command given one inline, which is
+    /// left out of the history so that it can't be recalled. Recalling the
+    /// rest asks for it instead.
+    fn secret_start(&self) -> Option<usize> {
+        let AstMessage::Command(AstNode::Command {
+            raw_name,
+            parsed_name,
+            ..
+        }) = self.parse()
+        else {
+            return None;
+        };
+
+        let args_before = match parsed_name.as_str() {
+            "register" | "auth" => 0,
+            "login" | "ghost" => 1,
+            _ => return None,
+        };
+
+        let text = self.current_value();
+        let mut rest = &text[text.find(&raw_name)? + raw_name.len()..];
+        for _ in 0..args_before {
+            rest = rest.trim_start();
+            rest = &rest[rest.find(char::is_whitespace)?..];
+        }
+
+        let secret = rest.trim_start();
+        (!secret.is_empty()).then(|| text.len() - secret.len())
+    }
+
     pub(crate) fn cursor_state(&self) -> (u16, cursor::SetCursorStyle) {
-        let x = (self.nick_display().len() + self.pos) as u16;
+        let x = str_width(&self.nick_display())
+            + self.curr[..self.pos.min(self.curr.len())]
+                .iter()
+                .copied()
+                .map(cell_width)
+                .sum::<u16>();
         let style = match self.mode {
             Mode::Insert => cursor::SetCursorStyle::SteadyBar,
             Mode::Normal => cursor::SetCursorStyle::SteadyBlock,
@@ -61,10 +211,60 @@ impl Prompt {
         (x, style)
     }
 
-    pub(crate) fn register_local_commands(&mut self, commands: Vec<String>) {
+    /// Parses what is typed with `options` from now on.
+    pub(crate) fn set_parser_options(&mut self, options: ParserOptions) {
+        self.parser = RefCell::new(Parser::with_options("", options));
+    }
+
+    pub(crate) fn register_local_commands(&mut self, commands: Vec<CommandSpec>) {
         self.local_commands = commands;
     }
 
+    pub(crate) fn is_local_command(&self, name: &str) -> bool {
+        self.local_commands.iter().any(|spec| spec.name == name)
+    }
+
+    pub(crate) fn command_spec(&self, name: &str) -> Option<&CommandSpec> {
+        self.command_specs().find(|spec| spec.name == name)
+    }
+
+    /// Commands from the server followed by those handled by the client.
+    pub(crate) fn command_specs(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.commands.iter().chain(self.local_commands.iter())
+    }
+
+    /// Placeholders for the arguments of the command being typed which are
+    /// still to be filled in, shown greyed out after the cursor.
+    fn arg_hint(&self) -> Option<String> {
+        if self.pos != self.curr.len() {
+            return None;
+        }
+
+        let value = self.current_value();
+        let ast = self.parse();
+        let command = ast.command()?;
+
+        let spec = self.command_spec(command.name)?;
+        let ends_with_space = value.ends_with(char::is_whitespace);
+        let provided = command.args().count();
+
+        // An argument still being typed counts as provided
+        let remaining = spec
+            .args
+            .iter()
+            .skip(provided)
+            .map(|arg| arg.placeholder())
+            .collect::<Vec<String>>();
+
+        if remaining.is_empty() {
+            return None;
+        }
+
+        let separator = if ends_with_space { "" } else { " " };
+
+        Some(format!("{separator}{}", remaining.join(" ")))
+    }
+
     fn nick_display(&self) -> String {
         if self.nick.is_empty() {
             String::default()
@@ -86,23 +286,119 @@ impl Prompt {
     }
 
     fn handle_insert(&mut self, key_code: event::KeyCode) {
+        if !matches!(
+            key_code,
+            event::KeyCode::Char(_) | event::KeyCode::Backspace | event::KeyCode::Tab
+        ) {
+            self.placeholder = None;
+        }
+
         match key_code {
-            event::KeyCode::Char(ch) => self.insert(ch),
+            event::KeyCode::Char(ch) => {
+                self.remove_placeholder();
+                self.insert(ch);
+            }
             event::KeyCode::Esc => {
                 self.switch_to_mode(Mode::Normal);
                 self.pos = self.pos.saturating_sub(1);
             }
+            event::KeyCode::Backspace if self.remove_placeholder() => (),
             event::KeyCode::Backspace => self.remove(),
             event::KeyCode::Up => self.fetch_previous(),
             event::KeyCode::Down => self.fetch_next(),
+            event::KeyCode::Tab if self.is_in_snippet && self.next_placeholder() => (),
             event::KeyCode::Tab => self.attempt_autocomplete(),
             _ => (),
         }
     }
 
+    fn handle_completion(&mut self, key_code: event::KeyCode) {
+        let Some(completion) = self.completion.as_mut() else {
+            return;
+        };
+
+        match key_code {
+            event::KeyCode::Up => completion.select_previous(),
+            event::KeyCode::Down => completion.select_next(),
+            event::KeyCode::Tab | event::KeyCode::Enter => {
+                if let Some(entry) = completion.selected().cloned() {
+                    let span = completion.span.clone();
+                    self.replace_span(&span, &entry.nick);
+                    self.insert(' ');
+                }
+
+                self.completion = None;
+            }
+            event::KeyCode::Esc => {
+                self.dismissed_completion = self.completion.take().map(|c| c.span.c0);
+            }
+            _ => self.handle_insert(key_code),
+        }
+    }
+
+    fn refresh_completion(&mut self) {
+        let previous = self.completion.take();
+
+        if !matches!(self.mode, Mode::Insert) {
+            return;
+        }
+
+        let ast = self.parse();
+        let Some(AstNode::UserMention {
+            span,
+            parsed_user_name,
+            ..
+        }) = ast.node_at_pos(self.pos)
+        else {
+            self.dismissed_completion = None;
+            return;
+        };
+
+        if self.dismissed_completion == Some(span.c0) {
+            return;
+        }
+
+        self.dismissed_completion = None;
+
+        let needle = parsed_user_name.to_lowercase();
+        let candidates = self
+            .nicks
+            .iter()
+            .filter(|e| e.nick.to_lowercase().starts_with(&needle))
+            .cloned()
+            .collect::<Vec<NickListEntry>>();
+
+        if candidates.is_empty() {
+            return;
+        }
+
+        let mut completion = NickCompletion::new(span.clone(), candidates);
+
+        if let Some(previous) = &previous {
+            completion.retain_selection(previous);
+        }
+
+        self.completion = Some(completion);
+    }
+
+    /// Whether the prompt is in normal mode without half of a command such
+    /// as `dd` typed, so that the chat window can take keys it leaves alone.
+    pub(crate) fn is_idle_in_normal_mode(&self) -> bool {
+        matches!(self.mode, Mode::Normal) && self.command_buffer.is_empty()
+    }
+
+    /// Starts a reply to `nick`, mentioning them in place of whatever was
+    /// in the prompt.
+    pub(crate) fn reply_to(&mut self, nick: &str) {
+        self.curr = format!("@{nick} ").chars().collect();
+        self.pos = self.curr.len();
+        self.switch_to_mode(Mode::Insert);
+    }
+
     fn switch_to_mode(&mut self, new_mode: Mode) {
         self.mode = new_mode;
         self.command_buffer.clear();
+        self.placeholder = None;
     }
 
     fn handle_normal(&mut self, key_code: event::KeyCode) {
@@ -152,11 +448,9 @@ impl Prompt {
                     self.command_buffer.push('d');
                 }
             }
-            event::KeyCode::Char('x') => {
-                if !self.curr.is_empty() {
-                    self.curr.remove(self.pos);
-                    self.pos = self.pos.clamp(0, self.curr.len().saturating_sub(1));
-                }
+            event::KeyCode::Char('x') if !self.curr.is_empty() => {
+                self.curr.remove(self.pos);
+                self.pos = self.pos.clamp(0, self.curr.len().saturating_sub(1));
             }
             event::KeyCode::Char('X') => self.clear(),
             event::KeyCode::Char('0') => self.pos = 0,
@@ -171,21 +465,35 @@ impl Prompt {
     fn clear(&mut self) {
         self.curr.clear();
         self.pos = 0;
+        self.is_in_snippet = false;
         self.switch_to_mode(Mode::Insert);
     }
 
+    /// The entries Up and Down go through, oldest first.
+    fn recalled(&self, recall: Recall) -> Vec<&str> {
+        let is_buffer_only = recall == Recall::Buffer;
+
+        self.history
+            .iter()
+            .filter(|entry| !is_buffer_only || entry.buffer == self.buffer)
+            .filter(|entry| entry.text.starts_with(&self.draft))
+            .map(|entry| entry.text.as_str())
+            .collect()
+    }
+
     fn fetch_previous(&mut self) {
-        // @CLEANUP: fetch_previous()/fetch_next()
-        if self.history_offset + 1 > self.history.len() {
+        if self.history_offset == 0 {
+            self.draft = self.curr.iter().collect();
+        }
+
+        let recalled = self.recalled(config::current().prompt.recall);
+        if self.history_offset + 1 > recalled.len() {
             return;
         }
 
+        let entry = recalled[recalled.len() - self.history_offset - 1].to_owned();
         self.history_offset += 1;
-
-        if let Some(entry) = self.history.get(self.history.len() - self.history_offset) {
-            self.curr = entry.chars().collect();
-            self.pos = self.curr.len();
-        }
+        self.show(entry);
     }
 
     fn fetch_next(&mut self) {
@@ -195,10 +503,19 @@ impl Prompt {
 
         self.history_offset -= 1;
 
-        if let Some(entry) = self.history.get(self.history.len() - self.history_offset) {
-            self.curr = entry.chars().collect();
-            self.pos = self.curr.len();
-        }
+        let entry = match self.history_offset {
+            0 => self.draft.clone(),
+            offset => {
+                let recalled = self.recalled(config::current().prompt.recall);
+                recalled[recalled.len() - offset].to_owned()
+            }
+        };
+        self.show(entry);
+    }
+
+    fn show(&mut self, entry: String) {
+        self.curr = entry.chars().collect();
+        self.pos = self.curr.len();
     }
 
     fn delete_until_end(&mut self) {
@@ -234,7 +551,7 @@ impl Prompt {
     }
 
     fn attempt_autocomplete(&mut self) {
-        let ast = parse(&self.curr.iter().collect::<String>());
+        let ast = self.parse();
 
         let (needle, needle_span, haystack) = match ast.node_at_pos(self.pos) {
             Some(node) => match &node {
@@ -246,17 +563,50 @@ impl Prompt {
                     self.commands
                         .iter()
                         .chain(self.local_commands.iter())
-                        .cloned()
+                        .map(|spec| spec.name.clone())
                         .collect::<Vec<String>>(),
                 ),
                 AstNode::UserMention {
                     parsed_user_name,
                     span,
                     ..
-                } => (parsed_user_name, span, self.nicks.clone()),
+                } => (
+                    parsed_user_name,
+                    span,
+                    self.nicks
+                        .iter()
+                        .map(|e| e.nick.clone())
+                        .collect::<Vec<String>>(),
+                ),
                 // @TODO: Implement channel name autocompletion when we have channels
                 AstNode::ChannelMention { .. } => return,
+                AstNode::EveryoneMention { .. } => return,
+                AstNode::Text { span, value }
+                    if ast.command().is_some_and(|c| c.name == "snippet") =>
+                {
+                    let names = config::current()
+                        .snippets
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<String>>();
+
+                    if let Some(found) = names.iter().find(|name| name.starts_with(value.as_str()))
+                    {
+                        self.pos = span.c1;
+                        for _ in span.c0..span.c1 {
+                            self.remove();
+                        }
+                        for ch in found.chars() {
+                            self.insert(ch);
+                        }
+                    }
+
+                    return;
+                }
                 AstNode::Text { .. } => return,
+                AstNode::Quoted { .. } => return,
+                AstNode::Link { .. } => return,
+                AstNode::Formatted { .. } => return,
                 AstNode::Whitespace { .. } => return,
             },
             None => return,
@@ -265,28 +615,52 @@ impl Prompt {
         let completion = haystack.iter().find(|x| x.starts_with(needle));
 
         if let Some(found) = completion {
-            // Skip marker
-            let start = needle_span.c0 + 1;
-            let end = needle_span.c1;
+            let needle_span = needle_span.clone();
+            self.replace_span(&needle_span, found);
+        }
+    }
 
-            self.pos = end;
+    fn replace_span(&mut self, span: &TextSpan, replacement: &str) {
+        // Skip marker
+        let start = span.c0 + 1;
+        let end = span.c1;
 
-            for _ in start..end {
-                self.remove();
-            }
+        self.pos = end;
 
-            for ch in found.chars() {
-                self.insert(ch);
-            }
+        for _ in start..end {
+            self.remove();
+        }
+
+        for ch in replacement.chars() {
+            self.insert(ch);
         }
     }
 }
 
+/// The first `{name}` placeholder starting at or after `from`.
+fn find_placeholder(chars: &[char], from: usize) -> Option<Range<usize>> {
+    let mut start = None;
+
+    for (i, &ch) in chars.iter().enumerate().skip(from) {
+        match ch {
+            '{' => start = Some(i),
+            '}' => match start {
+                Some(s) if i > s + 1 => return Some(s..i + 1),
+                _ => start = None,
+            },
+            ch if ch.is_whitespace() => start = None,
+            _ => (),
+        }
+    }
+
+    None
+}
+
 impl Renderable for Prompt {
     fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
         for i in 0..rect.width {
             buf.put_at(
-                i,
+                rect.x + i,
                 rect.y,
                 '━',
                 style::Color::Reset,
@@ -295,11 +669,11 @@ impl Renderable for Prompt {
             );
         }
 
-        let nick_len = self.nick_display().len();
+        let mut x = rect.x;
 
-        for (i, ch) in self.nick_display().chars().enumerate() {
-            buf.put_at(
-                i as u16 + rect.x,
+        for ch in self.nick_display().chars() {
+            x += buf.put_at(
+                x,
                 rect.y + 1,
                 ch,
                 style::Color::Reset,
@@ -309,13 +683,73 @@ impl Renderable for Prompt {
         }
 
         for (i, &ch) in self.curr.iter().enumerate() {
-            buf.put_at(
-                i as u16 + rect.x + nick_len as u16,
+            let is_placeholder = self
+                .placeholder
+                .as_ref()
+                .is_some_and(|placeholder| placeholder.contains(&i));
+
+            x += buf.put_at(
+                x,
                 rect.y + 1,
                 ch,
                 style::Color::Reset,
-                style::Color::White,
-                CellStyle::default(),
+                if is_placeholder {
+                    config_hex_color!(colors.server_message)
+                } else {
+                    style::Color::White
+                },
+                if is_placeholder {
+                    CellStyle::Italic
+                } else {
+                    CellStyle::default()
+                },
+            );
+        }
+
+        if let Some(hint) = self.arg_hint() {
+            for ch in hint.chars() {
+                if x >= rect.x + rect.width {
+                    break;
+                }
+
+                x += buf.put_at(
+                    x,
+                    rect.y + 1,
+                    ch,
+                    style::Color::Reset,
+                    config_hex_color!(colors.server_message),
+                    CellStyle::Italic,
+                );
+            }
+        }
+    }
+}
+
+impl Composite for Prompt {
+    fn place<'a>(&'a self, frame: &mut Frame<'a>, rect: Rect) {
+        frame.place(self, rect);
+
+        // Floats above the prompt, over the bottom of the chat history
+        if let Some(completion) = &self.completion {
+            let height = completion.height().min(rect.y);
+            let nick_width = str_width(&self.nick_display());
+            let mention_offset = self.curr[..completion.span.c0.min(self.curr.len())]
+                .iter()
+                .copied()
+                .map(cell_width)
+                .sum::<u16>();
+            let x = (rect.x + nick_width + mention_offset)
+                .min(rect.width.saturating_sub(completion.width()));
+
+            frame.overlay(
+                completion,
+                Rect {
+                    x,
+                    y: rect.y - height,
+                    width: completion.width().min(rect.width),
+                    height,
+                },
+                1,
             );
         }
     }
@@ -323,14 +757,66 @@ impl Renderable for Prompt {
 
 #[cfg(test)]
 mod tests {
+    use solace_protocol::level::Level;
+
     use super::*;
 
+    fn spec(usage: &str) -> CommandSpec {
+        CommandSpec::parse(usage).unwrap()
+    }
+
+    #[test]
+    fn test_find_placeholder() {
+        let chars = "hi {nick}, see {} and { x } then {url}"
+            .chars()
+            .collect::<Vec<char>>();
+        assert_eq!(find_placeholder(&chars, 0), Some(3..9));
+        assert_eq!(find_placeholder(&chars, 9), Some(33..38));
+        assert_eq!(find_placeholder(&chars, 38), None);
+    }
+
+    #[test]
+    fn test_passwords_are_left_out_of_history() {
+        let mut prompt = Prompt::new();
+        let mut flushed = |text: &str| {
+            prompt.paste(text);
+            prompt.flush();
+            prompt.history.last().unwrap().text.clone()
+        };
+
+        assert_eq!(flushed("/register hunter2"), "/register ");
+        assert_eq!(flushed("/auth  hunter2 "), "/auth  ");
+        assert_eq!(flushed("/login alice hunter2"), "/login alice ");
+        assert_eq!(flushed("/ghost alice hunter2"), "/ghost alice ");
+        assert_eq!(flushed("/login alice"), "/login alice");
+        assert_eq!(flushed("/nick hunter2"), "/nick hunter2");
+        assert_eq!(flushed("hunter2"), "hunter2");
+    }
+
+    #[test]
+    fn test_snippet_placeholders() {
+        let mut prompt = Prompt::new();
+        prompt.fill_snippet("{greeting} {nick}!");
+        assert_eq!(prompt.placeholder, Some(0..10));
+
+        prompt.handle_insert(event::KeyCode::Char('h'));
+        prompt.handle_insert(event::KeyCode::Char('i'));
+        prompt.handle_insert(event::KeyCode::Tab);
+        assert_eq!(prompt.placeholder, Some(3..9));
+
+        prompt.handle_insert(event::KeyCode::Backspace);
+        prompt.handle_insert(event::KeyCode::Char('x'));
+        assert_eq!(prompt.curr.iter().collect::<String>(), "hi x!");
+        assert!(!prompt.next_placeholder());
+        assert!(!prompt.is_in_snippet);
+    }
+
     #[test]
     fn test_flush() {
         let mut prompt = Prompt::new();
         prompt.curr = vec!['a', 'b', 'c'];
         prompt.flush();
-        assert_eq!(prompt.history, vec!["abc".to_owned()]);
+        assert_eq!(prompt.history, sent(&["abc"]));
         assert_eq!(prompt.history_offset, 0);
         assert_eq!(prompt.curr, Vec::new());
         assert_eq!(prompt.pos, 0);
@@ -433,6 +919,15 @@ mod tests {
         assert_eq!(prompt.pos, 2);
     }
 
+    #[test]
+    fn test_erasing_everything_typed() {
+        let mut prompt = Prompt::new();
+        prompt.handle_key_press(event::KeyCode::Char('@'));
+        prompt.handle_key_press(event::KeyCode::Backspace);
+        assert_eq!(prompt.curr, Vec::new());
+        assert_eq!(prompt.parse(), AstMessage::default());
+    }
+
     #[test]
     fn test_remove() {
         let mut prompt = Prompt::new();
@@ -463,10 +958,20 @@ mod tests {
         assert!(matches!(prompt.mode, Mode::Insert));
     }
 
+    fn sent(texts: &[&str]) -> Vec<HistoryEntry> {
+        texts
+            .iter()
+            .map(|text| HistoryEntry {
+                text: (*text).to_owned(),
+                buffer: notify::MAIN_BUFFER.to_owned(),
+            })
+            .collect()
+    }
+
     #[test]
     fn test_fetch_previous_with_history() {
         let mut prompt = Prompt::new();
-        prompt.history = vec!["first".to_owned(), "second".to_owned()];
+        prompt.history = sent(&["first", "second"]);
         prompt.fetch_previous();
         assert_eq!(prompt.curr, vec!['s', 'e', 'c', 'o', 'n', 'd']);
         assert_eq!(prompt.pos, 6);
@@ -484,7 +989,7 @@ mod tests {
     #[test]
     fn test_fetch_next_with_history() {
         let mut prompt = Prompt::new();
-        prompt.history = vec!["first".to_owned(), "second".to_owned()];
+        prompt.history = sent(&["first", "second"]);
         prompt.fetch_previous();
         prompt.fetch_previous();
         prompt.fetch_next();
@@ -492,6 +997,39 @@ mod tests {
         assert_eq!(prompt.pos, 6);
     }
 
+    #[test]
+    fn test_fetch_only_what_starts_with_the_draft() {
+        let mut prompt = Prompt::new();
+        prompt.history = sent(&["/topic one", "hello", "/join #a", "/topic two"]);
+        prompt.curr = "/to".chars().collect();
+
+        prompt.fetch_previous();
+        assert_eq!(prompt.curr.iter().collect::<String>(), "/topic two");
+        prompt.fetch_previous();
+        assert_eq!(prompt.curr.iter().collect::<String>(), "/topic one");
+        prompt.fetch_previous();
+        assert_eq!(prompt.curr.iter().collect::<String>(), "/topic one");
+
+        // Coming back down past the newest gives back what was typed
+        prompt.fetch_next();
+        prompt.fetch_next();
+        assert_eq!(prompt.curr.iter().collect::<String>(), "/to");
+        assert_eq!(prompt.pos, 3);
+    }
+
+    #[test]
+    fn test_recall_from_this_buffer_only() {
+        let mut prompt = Prompt::new();
+        prompt.curr = "hi".chars().collect();
+        prompt.flush();
+        prompt.buffer = "bob".to_owned();
+        prompt.curr = "hey bob".chars().collect();
+        prompt.flush();
+
+        assert_eq!(prompt.recalled(Recall::All), vec!["hi", "hey bob"]);
+        assert_eq!(prompt.recalled(Recall::Buffer), vec!["hey bob"]);
+    }
+
     #[test]
     fn test_fetch_next_without_history() {
         let mut prompt = Prompt::new();
@@ -562,6 +1100,48 @@ mod tests {
         assert_eq!(prompt.nick_display(), "[user] ");
     }
 
+    #[test]
+    fn test_cursor_state_counts_wide_characters() {
+        let mut prompt = Prompt::new();
+        prompt.nick = "user".to_owned();
+        prompt.handle_key_press(event::KeyCode::Char('漢'));
+        prompt.handle_key_press(event::KeyCode::Char('字'));
+        prompt.handle_key_press(event::KeyCode::Char('a'));
+        assert_eq!(prompt.cursor_state().0, 7 + 5);
+
+        // Drawn over the character before, so taking no column of its own
+        prompt.handle_key_press(event::KeyCode::Char('e'));
+        prompt.handle_key_press(event::KeyCode::Char('\u{301}'));
+        assert_eq!(prompt.cursor_state().0, 7 + 6);
+    }
+
+    #[test]
+    fn test_arg_hint_after_command_name() {
+        let mut prompt = Prompt::new();
+        prompt.commands = vec![spec("nick <nick>")];
+        prompt.curr = "/nick".chars().collect();
+        prompt.pos = prompt.curr.len();
+        assert_eq!(prompt.arg_hint(), Some(" <nick>".to_owned()));
+    }
+
+    #[test]
+    fn test_arg_hint_skips_provided_arguments() {
+        let mut prompt = Prompt::new();
+        prompt.commands = vec![spec("kick <nick> [reason...]")];
+        prompt.curr = "/kick bob ".chars().collect();
+        prompt.pos = prompt.curr.len();
+        assert_eq!(prompt.arg_hint(), Some("[reason...]".to_owned()));
+    }
+
+    #[test]
+    fn test_arg_hint_none_when_all_provided() {
+        let mut prompt = Prompt::new();
+        prompt.commands = vec![spec("nick <nick>")];
+        prompt.curr = "/nick bob".chars().collect();
+        prompt.pos = prompt.curr.len();
+        assert_eq!(prompt.arg_hint(), None);
+    }
+
     #[test]
     fn test_attempt_autocomplete_does_nothing_without_commands() {
         let mut prompt = Prompt::new();
@@ -574,7 +1154,7 @@ mod tests {
     #[test]
     fn test_attempt_autocomplete_does_nothing_if_not_only_slash() {
         let mut prompt = Prompt::new();
-        prompt.commands = vec!["topic".to_owned()];
+        prompt.commands = vec![spec("topic")];
         prompt.curr = vec!['c', 'o'];
         prompt.pos = prompt.curr.len();
         prompt.attempt_autocomplete();
@@ -584,7 +1164,7 @@ mod tests {
     #[test]
     fn test_attempt_autocomplete_does_nothing_with_whitespace() {
         let mut prompt = Prompt::new();
-        prompt.commands = vec!["help".to_owned()];
+        prompt.commands = vec![spec("help")];
         prompt.curr = vec!['/', 'h', 'e', ' '];
         prompt.pos = prompt.curr.len();
         prompt.attempt_autocomplete();
@@ -594,7 +1174,7 @@ mod tests {
     #[test]
     fn test_attempt_autocomplete_successful() {
         let mut prompt = Prompt::new();
-        prompt.commands = vec!["help".to_owned()];
+        prompt.commands = vec![spec("help")];
         prompt.curr = vec!['/', 'h', 'e'];
         prompt.pos = prompt.curr.len();
         prompt.attempt_autocomplete();
@@ -604,20 +1184,110 @@ mod tests {
     #[test]
     fn test_attempt_autocomplete_no_match() {
         let mut prompt = Prompt::new();
-        prompt.commands = vec!["help".to_owned()];
+        prompt.commands = vec![spec("help")];
         prompt.curr = vec!['/', 'x', 'y', 'z'];
         prompt.pos = prompt.curr.len();
         prompt.attempt_autocomplete();
         assert_eq!(prompt.curr, vec!['/', 'x', 'y', 'z']);
     }
 
+    #[test]
+    fn test_typing_mention_opens_completion() {
+        let mut prompt = Prompt::new();
+        prompt.nicks = vec![NickListEntry::new("alice"), NickListEntry::new("bob")];
+        prompt.handle_key_press(event::KeyCode::Char('@'));
+        prompt.handle_key_press(event::KeyCode::Char('A'));
+        assert!(prompt.is_completing());
+        assert_eq!(
+            prompt.completion.as_ref().unwrap().selected(),
+            Some(&NickListEntry::new("alice"))
+        );
+    }
+
+    #[test]
+    fn test_completion_navigate_and_accept() {
+        let mut prompt = Prompt::new();
+        prompt.nicks = vec![NickListEntry::new("alice"), NickListEntry::new("amy")];
+        prompt.handle_key_press(event::KeyCode::Char('@'));
+        prompt.handle_key_press(event::KeyCode::Char('a'));
+        prompt.handle_key_press(event::KeyCode::Down);
+        prompt.handle_key_press(event::KeyCode::Enter);
+        assert_eq!(prompt.current_value(), "@amy ");
+        assert!(!prompt.is_completing());
+    }
+
+    #[test]
+    fn test_completion_stays_closed_after_esc() {
+        let mut prompt = Prompt::new();
+        prompt.nicks = vec![NickListEntry::new("alice")];
+        prompt.handle_key_press(event::KeyCode::Char('@'));
+        prompt.handle_key_press(event::KeyCode::Esc);
+        assert!(!prompt.is_completing());
+        assert!(matches!(prompt.mode, Mode::Insert));
+        prompt.handle_key_press(event::KeyCode::Char('a'));
+        assert!(!prompt.is_completing());
+    }
+
     #[test]
     fn test_attempt_autocomplete_multiple_matches_picks_first() {
         let mut prompt = Prompt::new();
-        prompt.commands = vec!["help".to_owned(), "hello".to_owned()];
+        prompt.commands = vec![spec("help"), spec("hello")];
         prompt.curr = vec!['/', 'h', 'e'];
         prompt.pos = prompt.curr.len();
         prompt.attempt_autocomplete();
         assert!(prompt.curr == vec!['/', 'h', 'e', 'l', 'p']);
     }
+
+    #[test]
+    fn test_snapshot_prompt() {
+        let mut prompt = Prompt::new();
+        prompt.nick = "alice".to_owned();
+        prompt.commands = vec![spec("msg <nick> <message...>")];
+
+        for ch in "/msg bob".chars() {
+            prompt.handle_key_press(event::KeyCode::Char(ch));
+        }
+
+        for width in [40, 16] {
+            insta::assert_snapshot!(
+                format!("prompt_{width}"),
+                crate::RenderBuffer::snapshot(&prompt, width, 2)
+            );
+        }
+    }
+
+    #[test]
+    fn test_snapshot_prompt_completion() {
+        let mut prompt = Prompt::new();
+        prompt.nicks = vec![
+            NickListEntry {
+                level: Level::Op,
+                ..NickListEntry::new("alice")
+            },
+            NickListEntry {
+                is_away: true,
+                ..NickListEntry::new("amy")
+            },
+        ];
+
+        for ch in "hi @a".chars() {
+            prompt.handle_key_press(event::KeyCode::Char(ch));
+        }
+
+        // The completion floats above the prompt, so leave it some room
+        let mut buf = RenderBuffer::new(40, 5);
+        let mut frame = Frame::new();
+        prompt.place(
+            &mut frame,
+            Rect {
+                x: 0,
+                y: 3,
+                width: 40,
+                height: 2,
+            },
+        );
+        frame.render_into(&mut buf);
+
+        insta::assert_snapshot!(buf.dump());
+    }
 }
@@ -0,0 +1,65 @@
+use std::fmt;
+
+use crossterm::style::{self, Stylize};
+
+use crate::cli;
+
+/// Whether to draw for terminals which only understand the basics, such as
+/// serial consoles and old tmux: no colors, no italics, only ASCII for
+/// decorations and none of the optional escape sequences.
+pub(crate) fn is_on() -> bool {
+    cli::args().safe_mode
+}
+
+/// The ASCII stand-in for a decorative glyph, anything else is left alone as
+/// it is what someone actually typed.
+pub(crate) fn fallback(ch: char) -> char {
+    match ch {
+        '─' | '━' => '-',
+        '│' | '┃' => '|',
+        '┌' | '┐' | '└' | '┘' => '+',
+        '•' => '*',
+        '↵' => '~',
+        '…' => '.',
+        ch => ch,
+    }
+}
+
+/// `content` as it is printed in safe mode. Colors are dropped, but cells
+/// which stand out from `plain_bg`, such as a selection or the topic bar, are
+/// drawn in reverse video so that they still do.
+pub(crate) fn styled<D: fmt::Display>(
+    content: D,
+    bg: style::Color,
+    plain_bg: style::Color,
+    attr: style::Attribute,
+) -> style::StyledContent<D> {
+    let attr = match attr {
+        style::Attribute::Italic => style::Attribute::NormalIntensity,
+        attr => attr,
+    };
+    let styled = style::style(content)
+        .on(style::Color::Reset)
+        .with(style::Color::Reset)
+        .attribute(attr);
+
+    if bg == plain_bg {
+        styled
+    } else {
+        styled.attribute(style::Attribute::Reverse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallbacks_are_ascii() {
+        for ch in "─━│┃┌┐└┘•↵…".chars() {
+            assert!(fallback(ch).is_ascii(), "{ch} has no fallback");
+        }
+
+        assert_eq!(fallback('é'), 'é');
+    }
+}
@@ -0,0 +1,117 @@
+use std::{process::ExitCode, time::Duration};
+
+use futures::{SinkExt, StreamExt};
+use solace_protocol::{
+    code::{RES_ACK_MESSAGE, RES_PONG},
+    codec::FrameCodec,
+    request::{Request, RequestMessage},
+    response::Response,
+};
+use tokio::{io::split, time};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use crate::{
+    cli::{EXIT_REJECTED, EXIT_TIMED_OUT, EXIT_UNAVAILABLE},
+    config, transport,
+};
+
+/// Sends `message` as a chat message and exits once the server has taken it,
+/// for scripts which don't want a TUI.
+///
+/// The server only answers requests which fail, so a ping follows the message
+/// and everything up to the pong is checked for errors.
+pub(crate) async fn run(message: &str, timeout: Duration) -> ExitCode {
+    let (server, nick, tls) = match config::headless() {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("{err:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match time::timeout(timeout, send(&server, nick, &tls, message)).await {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(SendError::Unavailable(err))) => {
+            eprintln!("ERROR: Couldn't talk to {server}: {err}");
+            ExitCode::from(EXIT_UNAVAILABLE)
+        }
+        Ok(Err(SendError::Rejected(response))) => {
+            eprintln!("ERROR: {} ({})", response.message, response.code);
+            ExitCode::from(EXIT_REJECTED)
+        }
+        Err(_) => {
+            eprintln!("ERROR: No answer from {server} after {timeout:?}");
+            ExitCode::from(EXIT_TIMED_OUT)
+        }
+    }
+}
+
+enum SendError {
+    Unavailable(anyhow::Error),
+    Rejected(Response),
+}
+
+impl From<anyhow::Error> for SendError {
+    fn from(err: anyhow::Error) -> Self {
+        SendError::Unavailable(err)
+    }
+}
+
+impl From<std::io::Error> for SendError {
+    fn from(err: std::io::Error) -> Self {
+        SendError::Unavailable(err.into())
+    }
+}
+
+async fn send(
+    server: &str,
+    nick: Option<String>,
+    tls: &config::Tls,
+    message: &str,
+) -> Result<(), SendError> {
+    let (reader, writer) = split(transport::connect(server, tls).await?);
+    let mut req = FramedWrite::new(writer, FrameCodec::<Request>::default());
+    let mut res = FramedRead::new(reader, FrameCodec::<Response>::default());
+
+    let ping_id = rand::random::<u32>();
+    let mut requests = Vec::new();
+
+    if let Some(nick) = nick {
+        requests.push(RequestMessage::NewNick(nick));
+    }
+    requests.push(RequestMessage::Message(message.to_owned()));
+
+    for request in requests {
+        req.feed(Request::new(rand::random::<u32>(), request))
+            .await?;
+    }
+    req.send(Request::new(ping_id, RequestMessage::Ping))
+        .await?;
+
+    let mut is_ping_acked = false;
+
+    while let Some(response) = res.next().await {
+        let response = response?;
+
+        match response.code {
+            RES_ACK_MESSAGE if response.message == ping_id.to_string() => is_ping_acked = true,
+            RES_PONG if is_ping_acked => {
+                req.send(Request::new(
+                    rand::random::<u32>(),
+                    RequestMessage::Disconnect,
+                ))
+                .await?;
+
+                return Ok(());
+            }
+            code if is_error(code) => return Err(SendError::Rejected(response)),
+            _ => (),
+        }
+    }
+
+    Err(anyhow::anyhow!("Connection closed before the message was acknowledged").into())
+}
+
+fn is_error(code: u16) -> bool {
+    (300..400).contains(&code)
+}
@@ -0,0 +1,273 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    str::FromStr,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use rand::Rng;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+
+/// The most read from the server at once, each read being delayed,
+/// truncated or cut off as a whole.
+const CHUNK: usize = 4096;
+
+/// A bad network to put between the client and a server, for trying out
+/// reconnecting, resending and the like without needing one. Given as
+/// `latency=MS,jitter=MS,truncate=P,disconnect=P`, any of which can be left
+/// out.
+///
+/// # Fields
+///
+/// - `latency`: How long everything read from the server is held back.
+/// - `jitter`: Up to how much longer to hold back each read, at random.
+/// - `truncate`: The chance of dropping the end of each read, which breaks
+///   whichever frame it was part of.
+/// - `disconnect`: The chance of the connection dropping on each read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct NetworkConditions {
+    pub(crate) latency: Duration,
+    pub(crate) jitter: Duration,
+    pub(crate) truncate: f64,
+    pub(crate) disconnect: f64,
+}
+
+impl NetworkConditions {
+    fn delay(&self) -> Duration {
+        let jitter = rand::thread_rng().gen_range(Duration::ZERO..=self.jitter);
+
+        self.latency + jitter
+    }
+}
+
+impl FromStr for NetworkConditions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut conditions = Self::default();
+
+        for setting in s.split(',').filter(|setting| !setting.is_empty()) {
+            let Some((name, value)) = setting.split_once('=') else {
+                return Err(format!("Expected NAME=VALUE, got {setting}"));
+            };
+
+            match name.trim() {
+                "latency" => conditions.latency = parse_millis(value)?,
+                "jitter" => conditions.jitter = parse_millis(value)?,
+                "truncate" => conditions.truncate = parse_chance(value)?,
+                "disconnect" => conditions.disconnect = parse_chance(value)?,
+                name => {
+                    return Err(format!(
+                        "Unknown condition {name}, expected latency, jitter, truncate or disconnect"
+                    ))
+                }
+            }
+        }
+
+        Ok(conditions)
+    }
+}
+
+fn parse_millis(value: &str) -> Result<Duration, String> {
+    value
+        .trim()
+        .parse::<u64>()
+        .map(Duration::from_millis)
+        .map_err(|err| format!("{value} isn't a number of milliseconds: {err}"))
+}
+
+fn parse_chance(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(chance) if (0.0..=1.0).contains(&chance) => Ok(chance),
+        _ => Err(format!("{value} isn't a chance between 0 and 1")),
+    }
+}
+
+/// A connection to a server over `conditions` rather than whatever network
+/// is really there. All of the delay is put on reads, as though the whole
+/// round trip were spent on the way back, and each read waits for the one
+/// before it so that bursts arrive more slowly as well.
+///
+/// # Fields
+///
+/// - `held`: Read from the server but not yet handed on, waiting for
+///   `delay`.
+/// - `is_cut`: Whether the connection has been dropped, after which every
+///   read and write fails.
+#[derive(Debug)]
+pub(crate) struct Flaky<T> {
+    inner: T,
+    conditions: NetworkConditions,
+    held: Vec<u8>,
+    delay: Option<Pin<Box<Sleep>>>,
+    is_cut: bool,
+}
+
+impl<T> Flaky<T> {
+    pub(crate) fn new(inner: T, conditions: NetworkConditions) -> Self {
+        Self {
+            inner,
+            conditions,
+            held: vec![],
+            delay: None,
+            is_cut: false,
+        }
+    }
+
+    fn cut(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "Simulated disconnect, see --simulate-network",
+        )
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Flaky<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        while this.held.is_empty() {
+            if this.is_cut {
+                return Poll::Ready(Err(this.cut()));
+            }
+
+            let mut chunk = [0; CHUNK];
+            let mut read = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read))?;
+
+            let read = read.filled();
+            if read.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut rng = rand::thread_rng();
+            if rng.gen_bool(this.conditions.disconnect) {
+                this.is_cut = true;
+                continue;
+            }
+
+            let len = match rng.gen_bool(this.conditions.truncate) {
+                true => rng.gen_range(0..read.len()),
+                false => read.len(),
+            };
+            this.held.extend_from_slice(&read[..len]);
+            this.delay = Some(Box::pin(tokio::time::sleep(this.conditions.delay())));
+        }
+
+        if let Some(delay) = &mut this.delay {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+        }
+
+        let len = buf.remaining().min(this.held.len());
+        buf.put_slice(&this.held[..len]);
+        this.held.drain(..len);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Flaky<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.is_cut {
+            true => Poll::Ready(Err(self.cut())),
+            false => Pin::new(&mut self.get_mut().inner).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.is_cut {
+            true => Poll::Ready(Err(self.cut())),
+            false => Pin::new(&mut self.get_mut().inner).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{duplex, AsyncReadExt, AsyncWriteExt},
+        time::Instant,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_parse_conditions() {
+        assert_eq!(
+            "latency=200,jitter=50,disconnect=0.01".parse(),
+            Ok(NetworkConditions {
+                latency: Duration::from_millis(200),
+                jitter: Duration::from_millis(50),
+                truncate: 0.0,
+                disconnect: 0.01,
+            })
+        );
+        assert_eq!("".parse(), Ok(NetworkConditions::default()));
+
+        assert!("truncate=2".parse::<NetworkConditions>().is_err());
+        assert!("latency".parse::<NetworkConditions>().is_err());
+        assert!("loss=0.1".parse::<NetworkConditions>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reads_are_held_back() {
+        let (ours, mut theirs) = duplex(64);
+        let conditions = "latency=30".parse().unwrap();
+        let mut flaky = Flaky::new(ours, conditions);
+
+        theirs.write_all(b"hello").await.unwrap();
+        let start = Instant::now();
+        let mut read = [0; 5];
+        flaky.read_exact(&mut read).await.unwrap();
+
+        assert_eq!(&read, b"hello");
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[tokio::test]
+    async fn test_disconnects_stick() {
+        let (ours, mut theirs) = duplex(64);
+        let conditions = "disconnect=1".parse().unwrap();
+        let mut flaky = Flaky::new(ours, conditions);
+
+        theirs.write_all(b"hello").await.unwrap();
+        let mut read = [0; 5];
+        let err = flaky.read(&mut read).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        assert!(flaky.write_all(b"still there?").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_reads_lose_their_end() {
+        let (ours, mut theirs) = duplex(64);
+        let conditions = "truncate=1".parse().unwrap();
+        let mut flaky = Flaky::new(ours, conditions);
+
+        theirs.write_all(b"hello").await.unwrap();
+        drop(theirs);
+        let mut read = vec![];
+        flaky.read_to_end(&mut read).await.unwrap();
+
+        assert!(read.len() < 5);
+        assert!(b"hello".starts_with(&read));
+    }
+}
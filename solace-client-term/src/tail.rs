@@ -0,0 +1,149 @@
+use std::{
+    io::{self, Write},
+    process::ExitCode,
+};
+
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use solace_protocol::{
+    code::{
+        RES_ACK_MESSAGE, RES_CHANNEL_MESSAGE, RES_CHAT_MESSAGE_OK, RES_DIRECT_MESSAGE, RES_GOODBYE,
+        RES_HELLO, RES_NICK_CHANGE, RES_NICK_LIST, RES_PRESENCE, RES_SELF_DIRECT_MESSAGE,
+        RES_SELF_MESSAGE, RES_TIP, RES_TOPIC_CHANGE, RES_WELCOME, RES_YOUR_NICK,
+    },
+    codec::FrameCodec,
+    request::{Request, RequestMessage},
+    response::Response,
+};
+use tokio::io::split;
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use crate::{cli::EXIT_UNAVAILABLE, config, transport};
+
+/// One line of output, kept flat so it is easy to pick apart with `jq`.
+///
+/// # Fields
+///
+/// - `kind`: A stable name for the sort of event, `code` has the detail.
+/// - `from`: The nick that sent a message, empty for everything else.
+#[derive(Debug, Serialize)]
+struct Event<'a> {
+    timestamp: u64,
+    code: u16,
+    kind: &'static str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    from: &'a str,
+    message: &'a str,
+}
+
+impl<'a> Event<'a> {
+    fn new(response: &'a Response) -> Self {
+        Self {
+            timestamp: response.timestamp,
+            code: response.code,
+            kind: kind_of(response.code),
+            from: &response.origin,
+            message: &response.message,
+        }
+    }
+}
+
+fn kind_of(code: u16) -> &'static str {
+    match code {
+        RES_CHAT_MESSAGE_OK | RES_SELF_MESSAGE => "message",
+        RES_CHANNEL_MESSAGE => "channel_message",
+        RES_DIRECT_MESSAGE | RES_SELF_DIRECT_MESSAGE => "direct_message",
+        RES_HELLO => "join",
+        RES_GOODBYE => "part",
+        RES_NICK_CHANGE => "nick_change",
+        RES_NICK_LIST => "nick_list",
+        RES_PRESENCE => "presence",
+        RES_TOPIC_CHANGE => "topic",
+        RES_TIP => "tip",
+        RES_WELCOME => "welcome",
+        RES_YOUR_NICK => "your_nick",
+        300..=399 => "error",
+        _ => "server",
+    }
+}
+
+/// Streams everything the server sends to stdout, one JSON object per line,
+/// until the server hangs up or whatever is reading stdout goes away.
+pub(crate) async fn run() -> ExitCode {
+    let (server, nick, tls) = match config::headless() {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("{err:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match tail(&server, nick, &tls).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("ERROR: Lost connection to {server}: {err}");
+            ExitCode::from(EXIT_UNAVAILABLE)
+        }
+    }
+}
+
+async fn tail(server: &str, nick: Option<String>, tls: &config::Tls) -> anyhow::Result<()> {
+    let (reader, writer) = split(transport::connect(server, tls).await?);
+    let mut req = FramedWrite::new(writer, FrameCodec::<Request>::default());
+    let mut res = FramedRead::new(reader, FrameCodec::<Response>::default());
+
+    if let Some(nick) = nick {
+        req.send(Request::new(
+            rand::random::<u32>(),
+            RequestMessage::NewNick(nick),
+        ))
+        .await?;
+    }
+
+    let mut stdout = io::stdout().lock();
+
+    while let Some(response) = res.next().await {
+        let response = response?;
+
+        // Acks are for requests we sent, which is of no interest downstream
+        if response.code == RES_ACK_MESSAGE {
+            continue;
+        }
+
+        let line = serde_json::to_string(&Event::new(&response))?;
+
+        // The reader having gone away, e.g. `head`, is a normal way to stop
+        if writeln!(stdout, "{line}").is_err() {
+            return Ok(());
+        }
+    }
+
+    anyhow::bail!("Server closed the connection")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_omits_missing_origin() {
+        let mut response = Response {
+            code: RES_CHAT_MESSAGE_OK,
+            origin: "ci".to_owned(),
+            message: "deploy finished".to_owned(),
+            ..Response::default()
+        };
+
+        assert_eq!(
+            serde_json::to_string(&Event::new(&response)).unwrap(),
+            r#"{"timestamp":0,"code":200,"kind":"message","from":"ci","message":"deploy finished"}"#
+        );
+
+        response.origin.clear();
+        response.code = RES_HELLO;
+        assert_eq!(
+            serde_json::to_string(&Event::new(&response)).unwrap(),
+            r#"{"timestamp":0,"code":3,"kind":"join","message":"deploy finished"}"#
+        );
+    }
+}
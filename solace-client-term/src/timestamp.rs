@@ -0,0 +1,96 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, FixedOffset, Local, Offset, Utc};
+
+use crate::config::Layout;
+
+pub(crate) const DEFAULT_FORMAT: &str = "%H:%M:%S";
+
+/// Used where the date matters as much as the time, such as the message
+/// inspector, so it isn't configurable.
+const ABSOLUTE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
+
+/// Shown in place of a timestamp which can't be a date, e.g. from a server
+/// with a broken clock.
+const INVALID: &str = "--:--:--";
+
+/// `timestamp`, in Unix seconds, as the timestamp gutter shows it.
+pub(crate) fn format(timestamp: u64, layout: &Layout) -> String {
+    format_with(timestamp, &layout.timestamp_format, &layout.timezone)
+}
+
+/// `timestamp` with its date and offset, in the configured timezone.
+pub(crate) fn format_absolute(timestamp: u64, layout: &Layout) -> String {
+    format_with(timestamp, ABSOLUTE_FORMAT, &layout.timezone)
+}
+
+/// The current time as the timestamp gutter shows it.
+pub(crate) fn now(layout: &Layout) -> String {
+    format(unix_now(), layout)
+}
+
+pub(crate) fn unix_now() -> u64 {
+    u64::try_from(Utc::now().timestamp()).unwrap_or_default()
+}
+
+fn format_with(timestamp: u64, format: &str, timezone: &str) -> String {
+    let Some(utc) = i64::try_from(timestamp)
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+    else {
+        return INVALID.to_owned();
+    };
+
+    // A timezone which can't be read is as good as not having set one
+    let offset = offset(timezone, &utc).unwrap_or_else(|| local_offset(&utc));
+    let time = utc.with_timezone(&offset);
+
+    // An unknown specifier only shows up when formatting, so fall back then
+    let mut out = String::new();
+    if write!(out, "{}", time.format(format)).is_err() {
+        out.clear();
+        let _ = write!(out, "{}", time.format(DEFAULT_FORMAT));
+    }
+
+    out
+}
+
+/// The offset of `timezone` at `at`, which is either `local`, `utc` or a
+/// fixed offset such as `+05:30`.
+fn offset(timezone: &str, at: &DateTime<Utc>) -> Option<FixedOffset> {
+    match timezone.trim().to_lowercase().as_str() {
+        "" | "local" => Some(local_offset(at)),
+        "utc" | "z" => Some(Utc.fix()),
+        fixed => fixed.parse::<FixedOffset>().ok(),
+    }
+}
+
+fn local_offset(at: &DateTime<Utc>) -> FixedOffset {
+    at.with_timezone(&Local).offset().fix()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timezones_and_formats() {
+        // 2024-03-01 12:30:15 UTC
+        let timestamp = 1_709_296_215;
+
+        assert_eq!(format_with(timestamp, DEFAULT_FORMAT, "utc"), "12:30:15");
+        assert_eq!(format_with(timestamp, "%H:%M", "+05:30"), "18:00");
+        assert_eq!(
+            format_with(timestamp, ABSOLUTE_FORMAT, "-08:00"),
+            "2024-03-01 04:30:15 -08:00"
+        );
+
+        // Falling back rather than panicking
+        assert_eq!(format_with(timestamp, "%Q", "UTC"), "12:30:15");
+        assert_eq!(
+            format_with(timestamp, DEFAULT_FORMAT, "Mars/Olympus"),
+            format_with(timestamp, DEFAULT_FORMAT, "local")
+        );
+        assert_eq!(format_with(u64::MAX, DEFAULT_FORMAT, "utc"), INVALID);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{io, sync::Arc};
+
+use solace_protocol::codec::{SESSION_KEY_CONTEXT, SESSION_KEY_LABEL, SESSION_KEY_LEN};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        self,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms},
+        pki_types::{pem::PemObject, CertificateDer, ServerName, UnixTime},
+        DigitallySignedStruct, RootCertStore, SignatureScheme,
+    },
+    TlsConnector,
+};
+
+use crate::config;
+
+/// Connects to `addr`, a `host:port`, over TLS, checking that the server's
+/// certificate is for `host` unless `config` turns verification off.
+pub(crate) async fn connect(addr: &str, config: &config::Tls) -> io::Result<TlsStream<TcpStream>> {
+    let name = server_name(addr)?;
+    let connector = TlsConnector::from(Arc::new(client_config(config)?));
+    let stream = TcpStream::connect(addr).await?;
+
+    connector.connect(name, stream).await
+}
+
+/// The key for signing frames over `stream`, see `capability::FRAME_HMAC`.
+pub(crate) fn session_key(stream: &TlsStream<TcpStream>) -> Option<Vec<u8>> {
+    stream
+        .get_ref()
+        .1
+        .export_keying_material(
+            vec![0; SESSION_KEY_LEN],
+            SESSION_KEY_LABEL,
+            Some(SESSION_KEY_CONTEXT),
+        )
+        .ok()
+}
+
+fn client_config(config: &config::Tls) -> io::Result<rustls::ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let algorithms = provider.signature_verification_algorithms;
+    let builder = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(invalid)?;
+
+    if !config.verify {
+        return Ok(builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(Unverified { algorithms }))
+            .with_no_client_auth());
+    }
+
+    let mut roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(path) = &config.ca_cert {
+        for cert in CertificateDer::pem_file_iter(path).map_err(invalid)? {
+            roots.add(cert.map_err(invalid)?).map_err(invalid)?;
+        }
+    }
+
+    Ok(builder.with_root_certificates(roots).with_no_client_auth())
+}
+
+/// The host in `addr`, which the server's certificate has to be for.
+fn server_name(addr: &str) -> io::Result<ServerName<'static>> {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    ServerName::try_from(host.to_owned()).map_err(invalid)
+}
+
+fn invalid(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+/// Takes any certificate, for when `verify` is off. Signatures are still
+/// checked so that the handshake itself is sound.
+#[derive(Debug)]
+struct Unverified {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for Unverified {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_names() {
+        assert_eq!(
+            server_name("chat.example.com:7878").unwrap(),
+            ServerName::try_from("chat.example.com").unwrap()
+        );
+        assert_eq!(
+            server_name("[::1]:7878").unwrap(),
+            ServerName::try_from("::1").unwrap()
+        );
+        assert!(server_name("bad host:7878").is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fmt::Debug, io};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+use crate::{cli, config, simulate::Flaky, tls};
+
+/// Servers given as `unix:<path>` are connected to over a Unix socket.
+const UNIX_PREFIX: &str = "unix:";
+/// Servers given as `tls://<host:port>` are connected to over TLS.
+const TLS_PREFIX: &str = "tls://";
+
+/// Anything a server can be connected to over.
+pub(crate) trait Transport: AsyncRead + AsyncWrite + Debug + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Debug + Unpin + Send> Transport for T {}
+
+pub(crate) type Stream = Box<dyn Transport>;
+
+/// A connection to a server.
+///
+/// # Fields
+///
+/// - `session_key`: Exported from the TLS session for signing frames, see
+///   `capability::FRAME_HMAC`, `None` unless connected over TLS.
+#[derive(Debug)]
+pub(crate) struct Connected {
+    pub(crate) stream: Stream,
+    pub(crate) session_key: Option<Vec<u8>>,
+}
+
+/// Connects to `server`, either `host:port`, `tls://host:port` or
+/// `unix:<path>`, checking TLS servers as `tls` says to. The connection
+/// goes over a simulated network instead if `--simulate-network` says so.
+pub(crate) async fn connect(server: &str, tls: &config::Tls) -> io::Result<Stream> {
+    Ok(connect_keyed(server, tls).await?.stream)
+}
+
+/// Like `connect`, keeping the key to sign frames with if there is one.
+pub(crate) async fn connect_keyed(server: &str, tls: &config::Tls) -> io::Result<Connected> {
+    let connected = connect_directly(server, tls).await?;
+
+    match &cli::args().simulate_network {
+        Some(conditions) => Ok(Connected {
+            stream: Box::new(Flaky::new(connected.stream, conditions.clone())),
+            ..connected
+        }),
+        None => Ok(connected),
+    }
+}
+
+async fn connect_directly(server: &str, tls: &config::Tls) -> io::Result<Connected> {
+    if let Some(path) = server.strip_prefix(UNIX_PREFIX) {
+        return Ok(unkeyed(connect_unix(path).await?));
+    }
+
+    match server.strip_prefix(TLS_PREFIX) {
+        Some(addr) if cli::args().no_tls => Ok(unkeyed(Box::new(TcpStream::connect(addr).await?))),
+        Some(addr) => {
+            let stream = tls::connect(addr, tls).await?;
+
+            Ok(Connected {
+                session_key: tls::session_key(&stream),
+                stream: Box::new(stream),
+            })
+        }
+        None => Ok(unkeyed(Box::new(TcpStream::connect(server).await?))),
+    }
+}
+
+fn unkeyed(stream: Stream) -> Connected {
+    Connected {
+        stream,
+        session_key: None,
+    }
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &str) -> io::Result<Stream> {
+    Ok(Box::new(tokio::net::UnixStream::connect(path).await?))
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_: &str) -> io::Result<Stream> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix sockets aren't supported on this platform",
+    ))
+}
@@ -0,0 +1,280 @@
+use std::io::{self, Write};
+
+use crossterm::{cursor, event, terminal, QueueableCommand};
+use futures::{future::FutureExt, StreamExt};
+
+use crate::color::hex_to_rgb;
+use crate::config::{self, Colors, BUILTIN_THEMES};
+use crate::overlay::{draw_box, draw_line};
+use crate::{str_width, CellStyle, Flushable, Rect, RenderBuffer, Renderable, Screen};
+
+const LABEL_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Server,
+    Nick,
+    Theme,
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Editing,
+    Done,
+    Cancelled,
+}
+
+/// Asks for the few things needed to write a first config file.
+///
+/// Rendered in the colors of whichever theme is picked, as there is no
+/// config to take colors from yet.
+#[derive(Debug)]
+struct Wizard {
+    field: Field,
+    nick: String,
+    server: String,
+    theme: usize,
+}
+
+impl Wizard {
+    fn new() -> Self {
+        Self {
+            field: Field::Server,
+            nick: String::new(),
+            server: config::default_server(),
+            theme: 0,
+        }
+    }
+
+    fn theme_name(&self) -> &'static str {
+        BUILTIN_THEMES[self.theme].0
+    }
+
+    fn colors(&self) -> Colors {
+        config::builtin_theme(self.theme_name()).expect("ERROR: Built in themes should parse")
+    }
+
+    fn text_mut(&mut self) -> Option<&mut String> {
+        match self.field {
+            Field::Server => Some(&mut self.server),
+            Field::Nick => Some(&mut self.nick),
+            Field::Theme => None,
+        }
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) -> Outcome {
+        match key.code {
+            event::KeyCode::Char('c') if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
+                return Outcome::Cancelled;
+            }
+            event::KeyCode::Esc => return Outcome::Cancelled,
+            event::KeyCode::Enter if !self.server.trim().is_empty() => return Outcome::Done,
+            event::KeyCode::Tab | event::KeyCode::Down => {
+                self.field = match self.field {
+                    Field::Server => Field::Nick,
+                    Field::Nick => Field::Theme,
+                    Field::Theme => Field::Server,
+                };
+            }
+            event::KeyCode::BackTab | event::KeyCode::Up => {
+                self.field = match self.field {
+                    Field::Server => Field::Theme,
+                    Field::Nick => Field::Server,
+                    Field::Theme => Field::Nick,
+                };
+            }
+            event::KeyCode::Left if self.field == Field::Theme => {
+                self.theme = (self.theme + BUILTIN_THEMES.len() - 1) % BUILTIN_THEMES.len();
+            }
+            event::KeyCode::Right if self.field == Field::Theme => {
+                self.theme = (self.theme + 1) % BUILTIN_THEMES.len();
+            }
+            event::KeyCode::Backspace => {
+                if let Some(text) = self.text_mut() {
+                    text.pop();
+                }
+            }
+            event::KeyCode::Char(ch) => {
+                if let Some(text) = self.text_mut() {
+                    text.push(ch);
+                }
+            }
+            _ => (),
+        }
+
+        Outcome::Editing
+    }
+
+    fn rect(screen: &Rect) -> Rect {
+        screen.centered(60, 11)
+    }
+
+    /// Where the terminal cursor goes, at the end of the field being typed
+    /// into, if it is a text field.
+    fn cursor(&self, rect: &Rect) -> Option<(u16, u16)> {
+        let (row, text) = match self.field {
+            Field::Server => (3, &self.server),
+            Field::Nick => (4, &self.nick),
+            Field::Theme => return None,
+        };
+
+        Some((
+            rect.x + 2 + LABEL_WIDTH as u16 + str_width(text),
+            rect.y + row,
+        ))
+    }
+}
+
+impl Renderable for Wizard {
+    fn render_into(&self, buf: &mut RenderBuffer, rect: &Rect) {
+        let colors = self.colors();
+        let fg = hex_to_rgb(&colors.fg);
+        let hint = hex_to_rgb(&colors.server_message);
+        let nick = if self.nick.is_empty() && self.field != Field::Nick {
+            "(picked by the server)"
+        } else {
+            &self.nick
+        };
+        let rows = [
+            (1, "Welcome to solace! Let's get you connected.".to_owned()),
+            (3, format!("{:<LABEL_WIDTH$}{}", "Server", self.server)),
+            (4, format!("{:<LABEL_WIDTH$}{nick}", "Nick")),
+            (
+                5,
+                format!("{:<LABEL_WIDTH$}< {} >", "Theme", self.theme_name()),
+            ),
+        ];
+
+        draw_box(buf, rect, "Setup", &colors);
+
+        for (row, text) in rows {
+            let is_focused = matches!(
+                (row, self.field),
+                (3, Field::Server) | (4, Field::Nick) | (5, Field::Theme)
+            );
+            let cell_style = if is_focused {
+                CellStyle::Bold
+            } else {
+                CellStyle::Normal
+            };
+
+            draw_line(buf, rect, rect.y + row, &text, fg, cell_style, &colors);
+        }
+
+        draw_line(
+            buf,
+            rect,
+            rect.y + 7,
+            "Tab/Up/Down to move, Left/Right to pick a theme",
+            hint,
+            CellStyle::Italic,
+            &colors,
+        );
+        draw_line(
+            buf,
+            rect,
+            rect.y + 8,
+            "Enter to save and connect, Esc to quit",
+            hint,
+            CellStyle::Italic,
+            &colors,
+        );
+    }
+}
+
+/// Walks the user through writing a config file on first launch, returning
+/// `false` if they quit instead.
+pub(crate) async fn run() -> anyhow::Result<bool> {
+    let mut stdout = io::stdout();
+    let mut size = terminal::size()?;
+    let mut wizard = Wizard::new();
+    let mut reader = event::EventStream::new();
+    let _screen = Screen::start(&mut stdout)?;
+
+    loop {
+        let screen = Rect {
+            x: 0,
+            y: 0,
+            width: size.0,
+            height: size.1,
+        };
+        let rect = Wizard::rect(&screen);
+        let mut buf = RenderBuffer::new(size.0, size.1);
+
+        wizard.render_into(&mut buf, &rect);
+        buf.render_to(&mut stdout)?;
+
+        match wizard.cursor(&rect) {
+            Some((x, y)) => stdout.queue(cursor::Show)?.queue(cursor::MoveTo(x, y))?,
+            None => stdout.queue(cursor::Hide)?,
+        };
+        stdout.flush()?;
+
+        let Some(Ok(event)) = reader.next().fuse().await else {
+            return Ok(false);
+        };
+
+        match event {
+            event::Event::Resize(width, height) => size = (width, height),
+            event::Event::Key(key) => match wizard.handle_key(key) {
+                Outcome::Editing => (),
+                Outcome::Cancelled => return Ok(false),
+                Outcome::Done => break,
+            },
+            _ => (),
+        }
+    }
+
+    config::write_initial(
+        wizard.server.trim(),
+        wizard.nick.trim(),
+        wizard.theme_name(),
+    )?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(wizard: &mut Wizard, code: event::KeyCode) -> Outcome {
+        wizard.handle_key(event::KeyEvent::new(code, event::KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn test_fill_in_fields() {
+        let mut wizard = Wizard::new();
+
+        for _ in 0..wizard.server.len() {
+            press(&mut wizard, event::KeyCode::Backspace);
+        }
+        for ch in "chat.example.com:7878".chars() {
+            press(&mut wizard, event::KeyCode::Char(ch));
+        }
+
+        press(&mut wizard, event::KeyCode::Tab);
+        press(&mut wizard, event::KeyCode::Char('a'));
+        press(&mut wizard, event::KeyCode::Tab);
+        press(&mut wizard, event::KeyCode::Left);
+
+        assert_eq!(wizard.server, "chat.example.com:7878");
+        assert_eq!(wizard.nick, "a");
+        assert_eq!(wizard.theme, BUILTIN_THEMES.len() - 1);
+        assert_eq!(press(&mut wizard, event::KeyCode::Enter), Outcome::Done);
+    }
+
+    #[test]
+    fn test_needs_a_server() {
+        let mut wizard = Wizard::new();
+        wizard.server.clear();
+
+        assert_eq!(press(&mut wizard, event::KeyCode::Enter), Outcome::Editing);
+        assert_eq!(press(&mut wizard, event::KeyCode::Esc), Outcome::Cancelled);
+    }
+
+    #[test]
+    fn test_snapshot_wizard() {
+        insta::assert_snapshot!(crate::RenderBuffer::snapshot(&Wizard::new(), 60, 11));
+    }
+}
@@ -4,6 +4,19 @@ use std::iter::Peekable;
 
 use unicode_segmentation::{Graphemes, UnicodeSegmentation};
 
+use crate::options::{MentionPolicy, ParserOptions};
+
+/// Link schemes, which are matched whatever their case.
+const LINK_SCHEMES: &[&str] = &["https://", "http://"];
+/// What often comes before a link in a sentence without being part of it,
+/// such as the bracket of `(https://example.com)` or a formatting marker.
+const LINK_OPENERS: &[char] = &['(', '[', '<', '"', '\'', '*', '_', '`'];
+/// What often comes after a link in a sentence without being part of it. A
+/// `)` is only left out when the link has no `(` for it to close.
+const LINK_CLOSERS: &[char] = &[
+    '.', ',', ';', ':', '!', '?', ')', ']', '>', '"', '\'', '*', '_', '`',
+];
+
 macro_rules! token {
     ($k: expr, $c0: expr, $c1: expr) => {
         Token::new($k, TextSpan::new($c0, $c1))
@@ -53,6 +66,10 @@ impl Token {
     fn new(kind: TokenKind, span: TextSpan) -> Self {
         Self { kind, span }
     }
+
+    pub(crate) fn is_command(&self) -> bool {
+        matches!(self.kind, TokenKind::Command(_))
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -62,26 +79,55 @@ pub(crate) enum TokenKind {
     Command(String),
     UserMention(String),
     ChannelMention(String),
-    Whitespace(usize),
+    /// An http(s) URL, without anything around it in the word, see
+    /// `split_link`.
+    Link(String),
+    /// A `"` delimited argument to a command, `value` has the quotes and
+    /// escapes removed. Quotes anywhere else are just text.
+    Quoted {
+        raw: String,
+        value: String,
+    },
+    Whitespace(String),
     Eof,
 }
 
+/// # Fields
+///
+/// - `is_in_command`: Whether the message starts with a command, so that
+///   what follows are its arguments, which may be quoted.
 pub(crate) struct Lexer<'a> {
     pub(crate) tokens: Vec<Token>,
 
     content: Peekable<Graphemes<'a>>,
+    is_in_command: bool,
     len: usize,
+    options: ParserOptions,
     pos: usize,
 }
 
 impl<'a> Lexer<'a> {
-    pub(crate) fn new(content: &'a str) -> Self {
+    pub(crate) fn new(content: &'a str, options: ParserOptions) -> Self {
+        Self::new_at(content, 0, false, options)
+    }
+
+    /// Lexes `content` as though it started at grapheme `offset` of a larger
+    /// message, so that the spans line up with the rest of it.
+    /// `is_in_command` says whether that message starts with a command.
+    pub(crate) fn new_at(
+        content: &'a str,
+        offset: usize,
+        is_in_command: bool,
+        options: ParserOptions,
+    ) -> Self {
         Self {
             tokens: vec![],
 
             content: content.graphemes(true).peekable(),
-            len: content.len(),
-            pos: 0,
+            is_in_command,
+            len: offset + content.len(),
+            options,
+            pos: offset,
         }
     }
 
@@ -96,6 +142,14 @@ impl<'a> Lexer<'a> {
             }
 
             let start = self.pos;
+
+            if self.is_in_command && self.current() == Some("\"") {
+                let (raw, value) = self.consume_quoted();
+                self.tokens
+                    .push(token!(TokenKind::Quoted { raw, value }, start, self.pos));
+                continue;
+            }
+
             let kind = match self.current() {
                 Some("/") => TokenKind::Command,
                 Some("@") => TokenKind::UserMention,
@@ -104,10 +158,32 @@ impl<'a> Lexer<'a> {
                 None => break,
             };
 
-            let word = self.consume_word();
-            let len = word.chars().count();
+            // Spans count graphemes like everything else here, not chars
+            let is_link = self.is_at_link();
+            let word = self.consume_word(!is_link);
+
+            match split_link(&word).filter(|_| is_link) {
+                Some((before, url, after)) => {
+                    let mut c0 = start;
+                    for (kind, text) in [
+                        (TokenKind::Text as fn(String) -> TokenKind, before),
+                        (TokenKind::Link, url),
+                        (TokenKind::Text, after),
+                    ] {
+                        let c1 = c0 + text.graphemes(true).count();
+                        if c1 > c0 {
+                            self.tokens.push(token!(kind(text.to_owned()), c0, c1));
+                        }
+                        c0 = c1;
+                    }
+                }
+                None => self.tokens.push(token!(kind(word), start, self.pos)),
+            }
 
-            self.tokens.push(token!(kind(word), start, start + len));
+            // Only a command at the very start takes arguments
+            if start == 0 && self.tokens.last().is_some_and(Token::is_command) {
+                self.is_in_command = true;
+            }
         }
 
         self.tokens.push(token!(TokenKind::Eof, self.pos, self.pos));
@@ -126,11 +202,34 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn consume_word(&mut self) -> String {
+    /// Whether the word about to be consumed starts with a link, perhaps
+    /// after some `LINK_OPENERS`.
+    fn is_at_link(&self) -> bool {
+        let ahead = self
+            .content
+            .clone()
+            .map(|g| g.chars().next().unwrap_or(' '))
+            .skip_while(|c| LINK_OPENERS.contains(c))
+            .take(LINK_SCHEMES[0].len())
+            .collect::<String>();
+
+        LINK_SCHEMES.iter().any(|scheme| {
+            ahead
+                .get(..scheme.len())
+                .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+        })
+    }
+
+    /// Consumes up to the next whitespace. A link is one word however the
+    /// options say that mentions split words, so `may_split` is false then.
+    fn consume_word(&mut self, may_split: bool) -> String {
         let mut s = String::new();
+        let splits_at_mentions = may_split && self.options.mentions == MentionPolicy::Anywhere;
 
         loop {
             match self.current() {
+                // Ends the word here so that the mention becomes its own token
+                Some("@" | "#") if splits_at_mentions && !s.is_empty() => break,
                 Some(str) if !str.trim().is_empty() => s.push_str(str),
                 _ => break,
             }
@@ -141,19 +240,53 @@ impl<'a> Lexer<'a> {
         s
     }
 
+    /// Consumes up to and including the closing quote, or to the end if
+    /// there isn't one, where `\` escapes whatever follows it.
+    fn consume_quoted(&mut self) -> (String, String) {
+        let mut raw = String::new();
+        let mut value = String::new();
+        let mut is_escaped = false;
+
+        while let Some(str) = self.current().map(str::to_owned) {
+            let is_opening = raw.is_empty();
+            raw.push_str(&str);
+            self.advance();
+
+            match str.as_str() {
+                _ if is_escaped => {
+                    value.push_str(&str);
+                    is_escaped = false;
+                }
+                "\\" => is_escaped = true,
+                "\"" if is_opening => (),
+                "\"" => break,
+                _ => value.push_str(&str),
+            }
+        }
+
+        // A trailing backslash has nothing to escape so is taken literally
+        if is_escaped {
+            value.push('\\');
+        }
+
+        (raw, value)
+    }
+
     fn eat_whitespace(&mut self) -> Option<Token> {
         let start = self.pos;
         let mut len = 0;
+        let mut value = String::new();
 
         loop {
             match self.current() {
                 Some(s) if s.trim().is_empty() => {
+                    value.push_str(s);
                     len += 1;
                     self.advance();
                 }
                 _ => {
                     if len > 0 {
-                        return Some(token!(TokenKind::Whitespace(len), start, start + len));
+                        return Some(token!(TokenKind::Whitespace(value), start, start + len));
                     } else {
                         return None;
                     }
@@ -163,5 +296,47 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Splits `word` into whatever comes before the link in it, the link, and
+/// whatever comes after it, if there is a link with more than a scheme.
+fn split_link(word: &str) -> Option<(&str, &str, &str)> {
+    let start = word.find(|c| !LINK_OPENERS.contains(&c))?;
+    let rest = &word[start..];
+    let scheme = LINK_SCHEMES.iter().find(|scheme| {
+        rest.get(..scheme.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+    })?;
+
+    let mut end = rest.len();
+    while let Some(last) = rest[..end].chars().next_back() {
+        let url = &rest[..end];
+        let is_closer = LINK_CLOSERS.contains(&last)
+            && (last != ')' || url.matches('(').count() < url.matches(')').count());
+        if !is_closer {
+            break;
+        }
+
+        end -= last.len_utf8();
+    }
+
+    (end > scheme.len()).then(|| (&word[..start], &rest[..end], &rest[end..]))
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_link() {
+        assert_eq!(
+            split_link("(https://example.com/a_(b))."),
+            Some(("(", "https://example.com/a_(b)", ")."))
+        );
+        assert_eq!(
+            split_link("*HTTP://example.com*"),
+            Some(("*", "HTTP://example.com", "*"))
+        );
+        assert_eq!(split_link("https://"), None);
+        assert_eq!(split_link("https://..."), None);
+        assert_eq!(split_link("xhttps://example.com"), None);
+    }
+}
@@ -1,10 +1,16 @@
 pub use lexer::TextSpan;
-pub use parser::{AstMessage, AstNode, Parser};
+pub use options::{MentionPolicy, ParserOptions};
+pub use parser::{AstMessage, AstNode, CommandRef, Everyone, InlineStyle, Mention, Parser};
 
 mod lexer;
+mod options;
 mod parser;
 
 pub fn parse(message: &str) -> AstMessage {
-    let mut parser = Parser::new(message);
+    parse_with_options(message, ParserOptions::default())
+}
+
+pub fn parse_with_options(message: &str, options: ParserOptions) -> AstMessage {
+    let mut parser = Parser::with_options(message, options);
     parser.parse()
 }
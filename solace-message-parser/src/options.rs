@@ -0,0 +1,34 @@
+/// Where a `@user` or `#channel` mention may start.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MentionPolicy {
+    /// Only at the start of a word, so `me@example.com` is left as text.
+    #[default]
+    WordBoundary,
+    /// Anywhere, so `cc:@amy` still mentions `amy`.
+    Anywhere,
+}
+
+impl MentionPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "word-boundary" => Some(MentionPolicy::WordBoundary),
+            "anywhere" => Some(MentionPolicy::Anywhere),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MentionPolicy::WordBoundary => "word-boundary",
+            MentionPolicy::Anywhere => "anywhere",
+        }
+    }
+}
+
+/// # Fields
+///
+/// - `mentions`: Where mentions are recognised, see `MentionPolicy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParserOptions {
+    pub mentions: MentionPolicy,
+}
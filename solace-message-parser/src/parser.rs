@@ -1,6 +1,12 @@
 #![allow(dead_code)]
 
+use std::fmt;
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
 use crate::lexer::{Lexer, TextSpan, Token, TokenKind};
+use crate::options::ParserOptions;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum AstMessage {
@@ -8,6 +14,91 @@ pub enum AstMessage {
     Normal(Vec<AstNode>),
 }
 
+/// A user or channel mentioned in a message, without its `@`/`#` sigil.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mention<'a> {
+    pub span: &'a TextSpan,
+    pub name: &'a str,
+}
+
+/// Who a mention of the whole channel reaches, ordered from fewest to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Everyone {
+    /// `@here`, everyone who isn't away.
+    Here,
+    /// `@all`, everyone in the channel.
+    All,
+}
+
+impl Everyone {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "here" => Some(Everyone::Here),
+            "all" => Some(Everyone::All),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Everyone::Here => "here",
+            Everyone::All => "all",
+        }
+    }
+}
+
+/// Markdown style formatting of a run of words, see `AstNode::Formatted`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InlineStyle {
+    /// `*bold*`
+    Bold,
+    /// `_italic_`
+    Italic,
+    /// `` `code` ``, which is taken literally so mentions in it aren't.
+    Code,
+}
+
+impl InlineStyle {
+    fn from_marker(marker: char) -> Option<Self> {
+        match marker {
+            '*' => Some(InlineStyle::Bold),
+            '_' => Some(InlineStyle::Italic),
+            '`' => Some(InlineStyle::Code),
+            _ => None,
+        }
+    }
+
+    /// What the formatted words are wrapped in.
+    pub fn marker(self) -> char {
+        match self {
+            InlineStyle::Bold => '*',
+            InlineStyle::Italic => '_',
+            InlineStyle::Code => '`',
+        }
+    }
+}
+
+/// Punctuation which can follow the closing marker of formatted text, as
+/// in `*really*.` or `(or _maybe_)`.
+const AFTER_MARKER: &[char] = &['.', ',', ';', ':', '!', '?', ')', '\'', '"'];
+
+/// The command a message invokes, see `AstMessage::command`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandRef<'a> {
+    pub span: &'a TextSpan,
+    pub name: &'a str,
+    pub raw_args: &'a [AstNode],
+}
+
+impl<'a> CommandRef<'a> {
+    /// The arguments, without the whitespace between them.
+    pub fn args(&self) -> impl Iterator<Item = &'a AstNode> {
+        self.raw_args
+            .iter()
+            .filter(|arg| !matches!(arg, AstNode::Whitespace { .. }))
+    }
+}
+
 impl Default for AstMessage {
     fn default() -> Self {
         Self::Normal(vec![])
@@ -15,10 +106,168 @@ impl Default for AstMessage {
 }
 
 impl AstMessage {
+    /// Every node in the message, including the arguments of a command and
+    /// the words of formatted text, each following the node it is in.
+    pub fn nodes(&self) -> impl Iterator<Item = &AstNode> {
+        let (command, rest) = match self {
+            AstMessage::Command(command @ AstNode::Command { args, .. }) => {
+                (Some(command), args.as_slice())
+            }
+            AstMessage::Command(node) => (Some(node), [].as_slice()),
+            AstMessage::Normal(nodes) => (None, nodes.as_slice()),
+        };
+
+        command.into_iter().chain(rest).flat_map(|node| {
+            let children = match node {
+                AstNode::Formatted { children, .. } => children.as_slice(),
+                _ => [].as_slice(),
+            };
+
+            std::iter::once(node).chain(children)
+        })
+    }
+
+    pub fn mentions(&self) -> impl Iterator<Item = Mention<'_>> {
+        self.nodes().filter_map(|node| match node {
+            AstNode::UserMention {
+                span,
+                parsed_user_name,
+                ..
+            } => Some(Mention {
+                span,
+                name: parsed_user_name,
+            }),
+            _ => None,
+        })
+    }
+
+    /// How many different users are mentioned, however often each one is.
+    pub fn mention_count(&self) -> usize {
+        let mut names = self.mentions().map(|m| m.name).collect::<Vec<&str>>();
+        names.sort_unstable();
+        names.dedup();
+
+        names.len()
+    }
+
+    /// The widest mention of the whole channel, if there is one.
+    pub fn everyone(&self) -> Option<Everyone> {
+        self.nodes()
+            .filter_map(|node| match node {
+                AstNode::EveryoneMention { everyone, .. } => Some(*everyone),
+                _ => None,
+            })
+            .max()
+    }
+
+    /// The URLs linked to, in the order they appear, leaving out any in
+    /// code as it is taken literally.
+    pub fn links(&self) -> impl Iterator<Item = &str> {
+        self.nodes().filter_map(|node| match node {
+            AstNode::Link { url, .. } => Some(url.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = Mention<'_>> {
+        self.nodes().filter_map(|node| match node {
+            AstNode::ChannelMention {
+                span,
+                parsed_channel_name,
+                ..
+            } => Some(Mention {
+                span,
+                name: parsed_channel_name,
+            }),
+            _ => None,
+        })
+    }
+
+    pub fn command(&self) -> Option<CommandRef<'_>> {
+        match self {
+            AstMessage::Command(AstNode::Command {
+                span,
+                parsed_name,
+                args,
+                ..
+            }) => Some(CommandRef {
+                span,
+                name: parsed_name,
+                raw_args: args,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The innermost node at `pos`, so a word of formatted text rather than
+    /// the text as a whole.
     pub fn node_at_pos(&self, pos: usize) -> Option<&AstNode> {
         match self {
             AstMessage::Command(command) => command.contains_pos(pos).then_some(command),
-            AstMessage::Normal(nodes) => nodes.iter().find(|n| n.contains_pos(pos)),
+            AstMessage::Normal(nodes) => {
+                let node = nodes.iter().find(|n| n.contains_pos(pos))?;
+
+                match node {
+                    AstNode::Formatted { children, .. } => {
+                        children.iter().find(|n| n.contains_pos(pos)).or(Some(node))
+                    }
+                    _ => Some(node),
+                }
+            }
+        }
+    }
+
+    /// Like `to_string` but with every run of whitespace collapsed into a
+    /// single space and none at either end, e.g. for quoting a message.
+    pub fn normalized(&self) -> String {
+        let nodes = match self {
+            AstMessage::Command(command) => std::slice::from_ref(command),
+            AstMessage::Normal(nodes) => nodes.as_slice(),
+        };
+
+        let mut normalized = String::new();
+        AstNode::write_normalized(nodes, &mut normalized);
+
+        normalized.trim().to_owned()
+    }
+}
+
+/// Renders the exact text which was parsed, so that `parse(s).to_string()`
+/// gives back `s`.
+impl fmt::Display for AstMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AstMessage::Command(command) => write!(f, "{command}"),
+            AstMessage::Normal(nodes) => nodes.iter().try_for_each(|node| write!(f, "{node}")),
+        }
+    }
+}
+
+impl fmt::Display for AstNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AstNode::Command { raw_name, args, .. } => {
+                write!(f, "{raw_name}")?;
+                args.iter().try_for_each(|arg| write!(f, "{arg}"))
+            }
+            AstNode::UserMention { raw_user_name, .. } => write!(f, "{raw_user_name}"),
+            AstNode::EveryoneMention { raw_name, .. } => write!(f, "{raw_name}"),
+            AstNode::ChannelMention {
+                raw_channel_name, ..
+            } => write!(f, "{raw_channel_name}"),
+            AstNode::Text { value, .. } => write!(f, "{value}"),
+            AstNode::Link { url, .. } => write!(f, "{url}"),
+            AstNode::Quoted { raw_text, .. } => write!(f, "{raw_text}"),
+            AstNode::Formatted {
+                style, children, ..
+            } => {
+                let marker = style.marker();
+
+                write!(f, "{marker}")?;
+                children.iter().try_for_each(|child| write!(f, "{child}"))?;
+                write!(f, "{marker}")
+            }
+            AstNode::Whitespace { value, .. } => write!(f, "{value}"),
         }
     }
 }
@@ -36,6 +285,12 @@ pub enum AstNode {
         raw_user_name: String,
         parsed_user_name: String,
     },
+    /// `@here` or `@all`, which the server only lets some nicks send.
+    EveryoneMention {
+        span: TextSpan,
+        raw_name: String,
+        everyone: Everyone,
+    },
     ChannelMention {
         span: TextSpan,
         raw_channel_name: String,
@@ -45,27 +300,220 @@ pub enum AstNode {
         span: TextSpan,
         value: String,
     },
+    /// An http(s) URL, see `AstMessage::links`.
+    Link {
+        span: TextSpan,
+        url: String,
+    },
+    /// A quoted argument such as `"hello world"`, which is taken as a single
+    /// value even though it may contain whitespace.
+    Quoted {
+        span: TextSpan,
+        raw_text: String,
+        parsed_text: String,
+    },
+    /// Words between a pair of markers, see `InlineStyle`, which `children`
+    /// are without. Code only ever has a single `Text` child.
+    Formatted {
+        span: TextSpan,
+        style: InlineStyle,
+        children: Vec<AstNode>,
+    },
     Whitespace {
         span: TextSpan,
+        value: String,
     },
 }
 
 impl AstNode {
-    fn contains_pos(&self, pos: usize) -> bool {
+    fn write_normalized(nodes: &[AstNode], out: &mut String) {
+        for node in nodes {
+            match node {
+                AstNode::Command { raw_name, args, .. } => {
+                    out.push_str(raw_name);
+                    Self::write_normalized(args, out);
+                }
+                AstNode::Formatted {
+                    style, children, ..
+                } => {
+                    out.push(style.marker());
+                    Self::write_normalized(children, out);
+                    out.push(style.marker());
+                }
+                AstNode::Whitespace { .. } => out.push(' '),
+                _ => out.push_str(&node.to_string()),
+            }
+        }
+    }
+
+    fn span(&self) -> &TextSpan {
         match self {
-            AstNode::Command { span, .. } => span.contains(pos),
-            AstNode::UserMention { span, .. } => span.contains(pos),
-            AstNode::ChannelMention { span, .. } => span.contains(pos),
-            AstNode::Text { span, .. } => span.contains(pos),
-            AstNode::Whitespace { span } => span.contains(pos),
+            AstNode::Command { span, .. } => span,
+            AstNode::UserMention { span, .. } => span,
+            AstNode::EveryoneMention { span, .. } => span,
+            AstNode::ChannelMention { span, .. } => span,
+            AstNode::Text { span, .. } => span,
+            AstNode::Link { span, .. } => span,
+            AstNode::Quoted { span, .. } => span,
+            AstNode::Formatted { span, .. } => span,
+            AstNode::Whitespace { span, .. } => span,
+        }
+    }
+
+    fn contains_pos(&self, pos: usize) -> bool {
+        self.span().contains(pos)
+    }
+
+    /// Wraps each run of nodes between a pair of markers in a `Formatted`
+    /// node. The markers have to start and end `Text` nodes on the same line
+    /// with something between them, so that `2 * 3` or a `_` on its own
+    /// are left alone.
+    fn format_inline(nodes: Vec<AstNode>) -> Vec<AstNode> {
+        let mut formatted = Vec::with_capacity(nodes.len());
+        let mut start = 0;
+
+        while start < nodes.len() {
+            match Self::closing_marker(&nodes, start) {
+                Some((style, end, len)) => {
+                    let mut run = nodes[start..=end].to_vec();
+                    let after = run.last_mut().and_then(|last| last.split_text(len));
+
+                    formatted.push(Self::formatted(style, run));
+                    formatted.extend(after);
+                    start = end + 1;
+                }
+                None => {
+                    formatted.push(nodes[start].clone());
+                    start += 1;
+                }
+            }
+        }
+
+        formatted
+    }
+
+    /// The style opened by `nodes[start]`, the index of the node which
+    /// closes it and how many bytes of that node are up to and including
+    /// the closing marker, if both markers are there.
+    fn closing_marker(nodes: &[AstNode], start: usize) -> Option<(InlineStyle, usize, usize)> {
+        let AstNode::Text { value, .. } = &nodes[start] else {
+            return None;
+        };
+        // A marker followed by a combining mark isn't one on its own
+        let mut graphemes = value.graphemes(true);
+        let marker = graphemes.next()?;
+        let style = InlineStyle::from_marker(marker.chars().next()?)
+            .filter(|style| marker.len() == style.marker().len_utf8())?;
+        // Where the closing marker ends, allowing for punctuation after it
+        let marker_end = |value: &str| {
+            let trimmed = value.trim_end_matches(AFTER_MARKER);
+            trimmed.ends_with(marker).then_some(trimmed.len())
+        };
+        let closes = |value: &str| {
+            marker_end(value).filter(|&len| value[..len].graphemes(true).nth(1).is_some())
+        };
+
+        // A marker on its own only counts right up against something, as the
+        // word it was part of was split up, e.g. in `*https://example.com*`
+        let hugs = |node: Option<&AstNode>| {
+            node.is_some_and(|node| !matches!(node, AstNode::Whitespace { .. }))
+        };
+
+        let rest = graphemes.as_str();
+        if let Some(len) = closes(rest) {
+            return Some((style, start, marker.len() + len));
+        }
+        if rest.is_empty() && !hugs(nodes.get(start + 1)) {
+            return None;
+        }
+
+        for (end, node) in nodes.iter().enumerate().skip(start + 1) {
+            match node {
+                AstNode::Whitespace { value, .. } if value.contains('\n') => return None,
+                AstNode::Text { value, .. } => {
+                    let lone = || {
+                        marker_end(value).filter(|&len| {
+                            len == marker.len() && end > start + 1 && hugs(nodes.get(end - 1))
+                        })
+                    };
+                    if let Some(len) = closes(value).or_else(lone) {
+                        return Some((style, end, len));
+                    }
+                }
+                AstNode::Whitespace { .. } => (),
+                // Code is taken literally, so it can end in e.g. a mention
+                node if style == InlineStyle::Code => {
+                    let text = node.to_string();
+                    if closes(&text) == Some(text.len()) {
+                        return Some((style, end, text.len()));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        None
+    }
+
+    /// Splits off the text after its first `len` bytes as a node of its own.
+    fn split_text(&mut self, len: usize) -> Option<AstNode> {
+        let AstNode::Text { span, value } = self else {
+            return None;
+        };
+        if len >= value.len() {
+            return None;
+        }
+
+        let after = value.split_off(len);
+        let c0 = span.c1 - after.graphemes(true).count();
+        span.c1 = c0;
+
+        Some(AstNode::Text {
+            span: TextSpan::new(c0, c0 + after.graphemes(true).count()),
+            value: after,
+        })
+    }
+
+    fn formatted(style: InlineStyle, mut children: Vec<AstNode>) -> AstNode {
+        let span = TextSpan::new(
+            children[0].span().c0,
+            children[children.len() - 1].span().c1,
+        );
+
+        if style == InlineStyle::Code {
+            let text = children.iter().map(ToString::to_string).collect::<String>();
+            children = vec![AstNode::Text {
+                span: TextSpan::new(span.c0 + 1, span.c1 - 1),
+                value: text[1..text.len() - 1].to_owned(),
+            }];
+        } else {
+            if let Some(AstNode::Text { span, value }) = children.first_mut() {
+                value.remove(0);
+                span.c0 += 1;
+            }
+            if let Some(AstNode::Text { span, value }) = children.last_mut() {
+                value.pop();
+                span.c1 -= 1;
+            }
+            children
+                .retain(|child| !matches!(child, AstNode::Text { value, .. } if value.is_empty()));
+        }
+
+        AstNode::Formatted {
+            span,
+            style,
+            children,
         }
     }
 }
 
+#[derive(Debug)]
 pub struct Parser {
     pub ast: AstMessage,
 
     pub current_pos: usize,
+    options: ParserOptions,
+    source: String,
     tokens: Vec<Token>,
 }
 
@@ -78,13 +526,19 @@ where
 
 impl Parser {
     pub fn new(message: &str) -> Self {
-        let tokens = Lexer::new(message).lex();
+        Self::with_options(message, ParserOptions::default())
+    }
+
+    pub fn with_options(message: &str, options: ParserOptions) -> Self {
+        let tokens = Lexer::new(message, options).lex();
 
         assert!(!tokens.is_empty());
 
         Self {
             ast: AstMessage::default(),
             current_pos: 0,
+            options,
+            source: message.to_owned(),
             tokens,
         }
     }
@@ -93,6 +547,63 @@ impl Parser {
         AstMessage::parse(self).unwrap()
     }
 
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Replaces the graphemes in `range` with `replacement` and parses the
+    /// result. Tokens which end before the edit are kept and lexing resumes
+    /// from there, so long inputs aren't lexed from scratch on every change.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> AstMessage {
+        let start = byte_offset(&self.source, range.start);
+        let end = byte_offset(&self.source, range.end);
+        self.source.replace_range(start..end, replacement);
+
+        // A token ending right at the edit may be extended by it, and lexing
+        // resumes at the start of a word as where one is split into tokens,
+        // e.g. around a link, can depend on all of it
+        let kept = self
+            .tokens
+            .iter()
+            .take_while(|t| t.kind != TokenKind::Eof && t.span.c1 < range.start)
+            .count();
+        self.tokens.truncate(kept);
+        while self
+            .tokens
+            .last()
+            .is_some_and(|t| !matches!(t.kind, TokenKind::Whitespace(_)))
+        {
+            self.tokens.pop();
+        }
+
+        let resume_at = self.tokens.last().map_or(0, |t| t.span.c1);
+        let rest = &self.source[byte_offset(&self.source, resume_at)..];
+        let is_in_command = self.tokens.first().is_some_and(Token::is_command);
+        let relexed = Lexer::new_at(rest, resume_at, is_in_command, self.options).lex();
+        self.tokens.extend(relexed);
+
+        self.current_pos = 0;
+        self.parse()
+    }
+
+    /// Parses `source` as a single edit of the previous source, which is
+    /// all that changes between keystrokes in the prompt.
+    pub fn update(&mut self, source: &str) -> AstMessage {
+        let old = self.source.graphemes(true).collect::<Vec<&str>>();
+        let new = source.graphemes(true).collect::<Vec<&str>>();
+
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let replacement = new[prefix..new.len() - suffix].concat();
+        self.edit(prefix..old.len() - suffix, &replacement)
+    }
+
     fn current_token(&self) -> Token {
         self.tokens[self.current_pos].clone()
     }
@@ -122,8 +633,8 @@ impl Parse for AstMessage {
 
         match nodes.first() {
             Some(AstNode::Command { .. }) => Some(Self::Command(nodes.first().unwrap().clone())),
-            Some(_) => Some(Self::Normal(nodes)),
-            None => unreachable!(),
+            Some(_) => Some(Self::Normal(AstNode::format_inline(nodes))),
+            None => Some(Self::default()),
         }
     }
 }
@@ -157,14 +668,24 @@ impl Parse for AstNode {
                     0,
                 )
             }
-            TokenKind::UserMention(value) => (
-                Some(AstNode::UserMention {
-                    span,
-                    raw_user_name: value.clone(),
-                    parsed_user_name: value.clone()[1..].to_owned(),
-                }),
-                1,
-            ),
+            TokenKind::UserMention(value) => match Everyone::from_name(&value[1..]) {
+                Some(everyone) => (
+                    Some(AstNode::EveryoneMention {
+                        span,
+                        raw_name: value,
+                        everyone,
+                    }),
+                    1,
+                ),
+                None => (
+                    Some(AstNode::UserMention {
+                        span,
+                        raw_user_name: value.clone(),
+                        parsed_user_name: value.clone()[1..].to_owned(),
+                    }),
+                    1,
+                ),
+            },
             TokenKind::ChannelMention(value) => (
                 Some(AstNode::ChannelMention {
                     span,
@@ -173,7 +694,16 @@ impl Parse for AstNode {
                 }),
                 1,
             ),
-            TokenKind::Whitespace(_) => (Some(AstNode::Whitespace { span }), 1),
+            TokenKind::Link(url) => (Some(AstNode::Link { span, url }), 1),
+            TokenKind::Quoted { raw, value } => (
+                Some(AstNode::Quoted {
+                    span,
+                    raw_text: raw,
+                    parsed_text: value,
+                }),
+                1,
+            ),
+            TokenKind::Whitespace(value) => (Some(AstNode::Whitespace { span, value }), 1),
             TokenKind::Eof => (None, 0),
         };
 
@@ -183,5 +713,327 @@ impl Parse for AstNode {
     }
 }
 
+/// The byte offset of grapheme `index` in `s`, or its length if past the end.
+fn byte_offset(s: &str, index: usize) -> usize {
+    s.grapheme_indices(true)
+        .nth(index)
+        .map_or(s.len(), |(offset, _)| offset)
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::options::MentionPolicy;
+    use crate::{parse, parse_with_options};
+
+    fn edited(source: &str, range: Range<usize>, replacement: &str) -> AstMessage {
+        Parser::new(source).edit(range, replacement)
+    }
+
+    #[test]
+    fn test_edit_matches_full_parse() {
+        let cases = [
+            ("hello world", 5..5, "!"),
+            ("hello world", 6..6, "@"),
+            ("/nick bob", 6..9, "alice"),
+            ("hi @bo", 6..6, "b"),
+            ("a  b", 1..3, ""),
+            ("", 0..0, "/topic new"),
+            ("héllo wörld", 7..7, "#"),
+        ];
+
+        for (source, range, replacement) in cases {
+            let mut expected = source.graphemes(true).collect::<Vec<&str>>();
+            expected.splice(range.clone(), [replacement]);
+
+            assert_eq!(
+                edited(source, range, replacement),
+                parse(&expected.concat()),
+                "{source:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_quoted_argument() {
+        let AstMessage::Command(AstNode::Command { args, .. }) = parse(r#"/topic "hello world" x"#)
+        else {
+            panic!("expected a command");
+        };
+
+        assert_eq!(
+            args,
+            vec![
+                AstNode::Whitespace {
+                    span: TextSpan::new(6, 7),
+                    value: " ".to_owned(),
+                },
+                AstNode::Quoted {
+                    span: TextSpan::new(7, 20),
+                    raw_text: r#""hello world""#.to_owned(),
+                    parsed_text: "hello world".to_owned(),
+                },
+                AstNode::Whitespace {
+                    span: TextSpan::new(20, 21),
+                    value: " ".to_owned(),
+                },
+                AstNode::Text {
+                    span: TextSpan::new(21, 22),
+                    value: "x".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quoted_escapes_and_unterminated() {
+        let quoted = |message: &str| match parse(&format!("/topic {message}")) {
+            AstMessage::Command(AstNode::Command { args, .. }) => match args.get(1) {
+                Some(AstNode::Quoted { parsed_text, .. }) => parsed_text.clone(),
+                other => panic!("expected a quoted node, got {other:?}"),
+            },
+            other => panic!("expected a command, got {other:?}"),
+        };
+
+        assert_eq!(quoted(r#""say \"hi\"""#), r#"say "hi""#);
+        assert_eq!(quoted(r#""back\\slash""#), r"back\slash");
+        assert_eq!(quoted(r#""never closed"#), "never closed");
+        assert_eq!(quoted(r#""trailing\"#), r"trailing\");
+    }
+
+    #[test]
+    fn test_quotes_outside_commands_are_text() {
+        let texts = |message: &str| match parse(message) {
+            AstMessage::Normal(nodes) => nodes
+                .iter()
+                .filter_map(|node| match node {
+                    AstNode::Text { value, .. } => Some(value.clone()),
+                    AstNode::Quoted { .. } => panic!("{message:?} has a quoted node"),
+                    _ => None,
+                })
+                .collect::<Vec<String>>(),
+            other => panic!("expected a normal message, got {other:?}"),
+        };
+
+        assert_eq!(
+            texts(r#"she said "hi there""#),
+            ["she", "said", "\"hi", "there\""]
+        );
+        assert_eq!(texts(r#""hi" she said"#), ["\"hi\"", "she", "said"]);
+    }
+
+    #[test]
+    fn test_render_and_incremental_parse_round_trip() {
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::{Rng, SeedableRng};
+
+        // Pieces chosen to hit every kind of token and the boundaries
+        // between them, including multi codepoint graphemes
+        const PIECES: &[&str] = &[
+            "a",
+            "hi",
+            " ",
+            "  ",
+            "\t",
+            "\n",
+            "/",
+            "@",
+            "#",
+            "\"",
+            "\\",
+            "é",
+            "e\u{301}",
+            "🇬🇧",
+            "👩‍💻",
+            "界",
+            "!",
+            "*",
+            "_",
+            "`",
+            "http://",
+            "HTTPS://x.y",
+            "(",
+            ")",
+            ".",
+        ];
+
+        // Set SEED to one printed by a failure to run the same messages again
+        let seed = std::env::var("SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(rand::random::<u64>);
+        println!("seed: {seed}");
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        for mentions in [MentionPolicy::WordBoundary, MentionPolicy::Anywhere] {
+            let options = ParserOptions { mentions };
+            let mut parser = Parser::with_options("", options);
+
+            for _ in 0..2_000 {
+                let len = rng.gen_range(0..12);
+                let message = (0..len)
+                    .map(|_| *PIECES.choose(&mut rng).unwrap())
+                    .collect::<String>();
+                let parsed = parse_with_options(&message, options);
+
+                assert_eq!(parsed.to_string(), message, "seed {seed}");
+                // Going from one random message to the next is an arbitrary edit
+                assert_eq!(parser.update(&message), parsed, "seed {seed}: {message:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_mention_policy() {
+        let names = |message: &str, mentions| {
+            parse_with_options(message, ParserOptions { mentions })
+                .mentions()
+                .map(|m| m.name.to_owned())
+                .collect::<Vec<String>>()
+        };
+
+        assert!(names("mail me@example.com", MentionPolicy::WordBoundary).is_empty());
+        assert_eq!(
+            names("mail me@example.com", MentionPolicy::Anywhere),
+            vec!["example.com"]
+        );
+        assert_eq!(
+            names("cc:@amy@bob", MentionPolicy::Anywhere),
+            vec!["amy", "bob"]
+        );
+    }
+
+    #[test]
+    fn test_mention_index() {
+        let ast = parse("hey @amy and @bob see #general");
+        let names = |mentions: Vec<Mention>| {
+            mentions
+                .into_iter()
+                .map(|m| m.name.to_owned())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(names(ast.mentions().collect()), vec!["amy", "bob"]);
+        assert_eq!(names(ast.channels().collect()), vec!["general"]);
+        assert_eq!(parse("@amy @bob @amy").mention_count(), 2);
+        assert_eq!(ast.command(), None);
+
+        let ast = parse("/msg @amy  hi");
+        let command = ast.command().unwrap();
+        assert_eq!(command.name, "msg");
+        assert_eq!(command.args().count(), 2);
+        assert_eq!(names(ast.mentions().collect()), vec!["amy"]);
+    }
+
+    #[test]
+    fn test_everyone() {
+        assert_eq!(parse("hi @amy").everyone(), None);
+        assert_eq!(parse("hi @here").everyone(), Some(Everyone::Here));
+        assert_eq!(parse("@here and @all").everyone(), Some(Everyone::All));
+        assert_eq!(parse("@hereafter").everyone(), None);
+        assert_eq!(parse("@all").mention_count(), 0);
+    }
+
+    #[test]
+    fn test_normalized() {
+        assert_eq!(parse("  hello \t  @bob  ").normalized(), "hello @bob");
+        assert_eq!(
+            parse(r#"/topic   "two  spaces"  kept"#).normalized(),
+            r#"/topic "two  spaces" kept"#
+        );
+    }
+
+    #[test]
+    fn test_inline_formatting() {
+        let formatted = |message: &str| match parse(message) {
+            AstMessage::Normal(nodes) => nodes
+                .iter()
+                .filter_map(|node| match node {
+                    AstNode::Formatted {
+                        style, children, ..
+                    } => Some((
+                        *style,
+                        children.iter().map(ToString::to_string).collect::<String>(),
+                    )),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            AstMessage::Command(_) => vec![],
+        };
+
+        assert_eq!(
+            formatted("a *very big* _deal_ with `x  @amy`"),
+            vec![
+                (InlineStyle::Bold, "very big".to_owned()),
+                (InlineStyle::Italic, "deal".to_owned()),
+                (InlineStyle::Code, "x  @amy".to_owned()),
+            ]
+        );
+        assert_eq!(
+            formatted("_really_),"),
+            vec![(InlineStyle::Italic, "really".to_owned())]
+        );
+        for message in [
+            "2 * 3 * 4",
+            "**",
+            "*not\nclosed*",
+            "snake_case",
+            "*\u{301}x*",
+        ] {
+            assert!(formatted(message).is_empty(), "{message:?}");
+        }
+
+        let message = "*hey @amy hi* `@bob`";
+        let ast = parse(message);
+        assert_eq!(ast.to_string(), message);
+        assert_eq!(ast.normalized(), message);
+        let names = ast.mentions().map(|m| m.name).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["amy"]);
+        assert!(matches!(
+            ast.node_at_pos(6),
+            Some(AstNode::UserMention { .. })
+        ));
+    }
+
+    #[test]
+    fn test_links() {
+        let links = |message: &str| {
+            parse(message)
+                .links()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            links("see https://a.example/x?y=1, (http://b.example/c_(d)) and https://"),
+            vec!["https://a.example/x?y=1", "http://b.example/c_(d)"]
+        );
+        assert!(links("`https://a.example` httpsx://b.example").is_empty());
+
+        let ast = parse("*https://a.example/@bob*.");
+        assert_eq!(ast.to_string(), "*https://a.example/@bob*.");
+        assert_eq!(ast.mention_count(), 0);
+        let AstMessage::Normal(nodes) = ast else {
+            panic!("Expected a normal message");
+        };
+        assert!(matches!(
+            nodes.as_slice(),
+            [AstNode::Formatted { style: InlineStyle::Bold, children, .. }, AstNode::Text { .. }]
+                if matches!(children.as_slice(), [AstNode::Link { .. }])
+        ));
+    }
+
+    #[test]
+    fn test_update_follows_typing() {
+        let mut parser = Parser::new("");
+        let typed = "/msg @alice hi there";
+
+        for end in 1..=typed.len() {
+            assert_eq!(parser.update(&typed[..end]), parse(&typed[..end]));
+        }
+
+        assert_eq!(parser.update("/msg hi"), parse("/msg hi"));
+        assert_eq!(parser.update(""), AstMessage::default());
+    }
+}
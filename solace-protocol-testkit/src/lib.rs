@@ -0,0 +1,162 @@
+//! Test vectors and a conformance runner for implementations of the solace
+//! protocol other than `solace-protocol` itself.
+//!
+//! Each vector pairs a request or response with the exact bytes of its
+//! frame. A client written in Rust implements `Client` and checks itself
+//! with `run`. Clients in other languages can read the same vectors as line
+//! delimited JSON from the `solace-protocol-testkit` binary.
+
+use solace_protocol::{codec::FrameCodec, request::Request, response::Response};
+use tokio_util::{
+    bytes::BytesMut,
+    codec::{Decoder, Encoder},
+};
+
+pub mod vectors;
+
+/// An item along with the exact bytes of the frame it is sent as.
+#[derive(Clone, Debug)]
+pub struct Vector<T> {
+    pub name: &'static str,
+    pub frame: Vec<u8>,
+    pub item: T,
+}
+
+/// What a client has to get right to talk to a solace server: writing
+/// requests byte for byte as the server expects them and reading the
+/// responses it sends.
+pub trait Client {
+    /// The whole frame for `request`, terminator included.
+    fn encode_request(&mut self, request: &Request) -> anyhow::Result<Vec<u8>>;
+
+    /// The response in `frame`, which is one whole frame, terminator
+    /// included.
+    fn decode_response(&mut self, frame: &[u8]) -> anyhow::Result<Response>;
+}
+
+/// `solace-protocol`'s own `FrameCodec`, which every vector is taken from.
+#[derive(Debug, Default)]
+pub struct Reference {
+    requests: FrameCodec<Request>,
+    responses: FrameCodec<Response>,
+}
+
+impl Client for Reference {
+    fn encode_request(&mut self, request: &Request) -> anyhow::Result<Vec<u8>> {
+        let mut dst = BytesMut::new();
+        self.requests.encode(request.clone(), &mut dst)?;
+
+        Ok(dst.to_vec())
+    }
+
+    fn decode_response(&mut self, frame: &[u8]) -> anyhow::Result<Response> {
+        let mut src = BytesMut::from(frame);
+
+        match self.responses.decode(&mut src)? {
+            Some(response) if src.is_empty() => Ok(response),
+            Some(_) => anyhow::bail!("ERROR: Bytes left over after the frame"),
+            None => anyhow::bail!("ERROR: Frame is missing its terminator"),
+        }
+    }
+}
+
+/// A vector an implementation got wrong, and how.
+#[derive(Debug)]
+pub struct Failure {
+    pub vector: &'static str,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn check(&mut self, vector: &'static str, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.passed += 1,
+            Err(reason) => self.failures.push(Failure { vector, reason }),
+        }
+    }
+}
+
+/// Runs `client` against every vector.
+pub fn run(client: &mut impl Client) -> Report {
+    let mut report = Report::default();
+
+    for vector in vectors::requests() {
+        let result = match client.encode_request(&vector.item) {
+            Ok(frame) if frame == vector.frame => Ok(()),
+            Ok(frame) => Err(format!(
+                "Encoded as {}, expected {}",
+                hex::encode(frame),
+                hex::encode(&vector.frame)
+            )),
+            Err(err) => Err(format!("Failed to encode: {err:#}")),
+        };
+        report.check(vector.name, result);
+    }
+
+    for vector in vectors::responses() {
+        let result = match client.decode_response(&vector.frame) {
+            Ok(response) if response == vector.item => Ok(()),
+            Ok(response) => Err(format!(
+                "Decoded as {response:?}, expected {:?}",
+                vector.item
+            )),
+            Err(err) => Err(format!("Failed to decode: {err:#}")),
+        };
+        report.check(vector.name, result);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use solace_protocol::codec::Integrity;
+
+    use super::*;
+
+    /// Gets everything right except that it never sends integrity tags.
+    struct Untagged(Reference);
+
+    impl Client for Untagged {
+        fn encode_request(&mut self, request: &Request) -> anyhow::Result<Vec<u8>> {
+            let mut dst = BytesMut::new();
+            FrameCodec::new(Integrity::None).encode(request.clone(), &mut dst)?;
+
+            Ok(dst.to_vec())
+        }
+
+        fn decode_response(&mut self, frame: &[u8]) -> anyhow::Result<Response> {
+            self.0.decode_response(frame)
+        }
+    }
+
+    #[test]
+    fn test_reference_conforms() {
+        let report = run(&mut Reference::default());
+
+        assert!(report.is_ok(), "{:#?}", report.failures);
+        assert_eq!(
+            report.passed,
+            vectors::requests().len() + vectors::responses().len()
+        );
+    }
+
+    #[test]
+    fn test_failures_are_reported() {
+        let report = run(&mut Untagged(Reference::default()));
+
+        assert_eq!(report.failures.len(), vectors::requests().len());
+        assert_eq!(report.failures[0].vector, "ping");
+        assert_eq!(report.passed, vectors::responses().len());
+    }
+}
@@ -0,0 +1,49 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+use solace_protocol_testkit::{vectors, Vector};
+
+/// One vector as a line of JSON, for clients written in other languages.
+///
+/// # Fields
+///
+/// - `kind`: `request` for frames a client has to encode byte for byte,
+///   `response` for frames it has to decode.
+/// - `frame`: The whole frame in hex, terminator included.
+/// - `item`: The request or response, as serde serializes it.
+#[derive(Serialize)]
+struct Line<'a, T> {
+    kind: &'static str,
+    name: &'static str,
+    frame: String,
+    item: &'a T,
+}
+
+fn write_all<T: Serialize>(
+    out: &mut impl Write,
+    kind: &'static str,
+    vectors: &[Vector<T>],
+) -> anyhow::Result<()> {
+    for vector in vectors {
+        let line = Line {
+            kind,
+            name: vector.name,
+            frame: hex::encode(&vector.frame),
+            item: &vector.item,
+        };
+
+        serde_json::to_writer(&mut *out, &line)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut out = io::stdout().lock();
+
+    write_all(&mut out, "request", &vectors::requests())?;
+    write_all(&mut out, "response", &vectors::responses())?;
+
+    Ok(())
+}
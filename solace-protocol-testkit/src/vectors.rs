@@ -0,0 +1,155 @@
+use solace_protocol::{
+    code::{ERR_NICK_IN_USE, RES_CHAT_MESSAGE_OK, RES_PONG, RES_WELCOME},
+    request::{Request, RequestMessage, Secret},
+    response::{Response, FLAG_BOT},
+    version::{V1, V3, V4},
+};
+
+use crate::Vector;
+
+fn vector<T>(name: &'static str, frame: &str, item: T) -> Vector<T> {
+    Vector {
+        name,
+        frame: hex::decode(frame).expect("ERROR: Test vectors should be valid hex"),
+        item,
+    }
+}
+
+/// Requests as a client's `FrameCodec` frames them, with the default CRC32
+/// tag. All but the last name the latest version, which only says which
+/// responses the client reads.
+pub fn requests() -> Vec<Vector<Request>> {
+    vec![
+        vector("ping", "0104010000000000000037373466343533630d0a", Request::new(1, RequestMessage::Ping)),
+        vector(
+            "message",
+            "010402000000010000000c0000000000000068656c6c6f2c20776f726c6438393363623135340d0a",
+            Request::new(2, RequestMessage::Message("hello, world".to_owned())),
+        ),
+        vector(
+            "unicode_message",
+            "010403000000010000000b0000000000000068c3a96c6c6f20f09f918b61343037323062390d0a",
+            Request::new(3, RequestMessage::Message("héllo 👋".to_owned())),
+        ),
+        vector(
+            "nick",
+            "010404000000030000000500000000000000616c69636534393638383539360d0a",
+            Request::new(4, RequestMessage::NewNick("alice".to_owned())),
+        ),
+        vector(
+            "login",
+            "010405000000080000000500000000000000616c69636501070000000000000068756e7465723263353263326663370d0a",
+            Request::new(
+                5,
+                RequestMessage::Login {
+                    account: "alice".to_owned(),
+                    password: Some(Secret("hunter2".to_owned())),
+                },
+            ),
+        ),
+        vector(
+            "channel_message",
+            "0104060000001b000000050000000000000023727573740200000000000000686934663666353062390d0a",
+            Request::new(
+                6,
+                RequestMessage::ChannelMessage {
+                    channel: "#rust".to_owned(),
+                    message: "hi".to_owned(),
+                },
+            ),
+        ),
+        vector(
+            "v1_ping",
+            "0101070000000000000066626237303966340d0a",
+            Request {
+                version: V1,
+                ..Request::new(7, RequestMessage::Ping)
+            },
+        ),
+    ]
+}
+
+/// Responses as a server frames them, with the CRC32 tag a client has to
+/// require. Servers send version 1 unless a client's requests name a later
+/// one, but a client has to read every version it names.
+pub fn responses() -> Vec<Vector<Response>> {
+    vec![
+        vector(
+            "pong",
+            "01010100000000f153650000000005000000000000000000000400000000000000506f6e6738353961623833660d0a",
+            Response {
+                version: V1,
+                request_id: 1,
+                timestamp: 1_700_000_000,
+                code: RES_PONG,
+                message: "Pong".to_owned(),
+                ..Response::default()
+            },
+        ),
+        vector(
+            "chat_message",
+            "01030000000001f1536500000000c800030300000000000000626f62002a00000000000000090000000000000068692040616c69636534373066316664360d0a",
+            Response {
+                version: V3,
+                timestamp: 1_700_000_001,
+                code: RES_CHAT_MESSAGE_OK,
+                origin_length: 3,
+                origin: "bob".to_owned(),
+                message_id: 42,
+                message: "hi @alice".to_owned(),
+                ..Response::default()
+            },
+        ),
+        vector(
+            "bot_message",
+            "01030000000002f1536500000000c8000909000000000000006b61726d612d626f74012b000000000000000f00000000000000626f62206861732031206b61726d6136653335663035610d0a",
+            Response {
+                version: V3,
+                timestamp: 1_700_000_002,
+                code: RES_CHAT_MESSAGE_OK,
+                origin_length: 9,
+                origin: "karma-bot".to_owned(),
+                flags: FLAG_BOT,
+                message_id: 43,
+                message: "bob has 1 karma".to_owned(),
+                ..Response::default()
+            },
+        ),
+        vector(
+            "error",
+            "01010400000003f15365000000002e010000000000000000000f00000000000000616c69636520697320696e2075736533356439623561620d0a",
+            Response {
+                version: V1,
+                request_id: 4,
+                timestamp: 1_700_000_003,
+                code: ERR_NICK_IN_USE,
+                message: "alice is in use".to_owned(),
+                ..Response::default()
+            },
+        ),
+        vector(
+            "welcome",
+            "01010000000004f15365000000000100000000000000000000110000000000000057656c636f6d6520746f20736f6c61636565666330663333620d0a",
+            Response {
+                version: V1,
+                timestamp: 1_700_000_004,
+                code: RES_WELCOME,
+                message: "Welcome to solace".to_owned(),
+                ..Response::default()
+            },
+        ),
+        vector(
+            "v4_pong",
+            "01040100000005f153650000000005000000000000000000000000000000000000000c000000000000000400000000000000506f6e6732353134323165350d0a",
+            Response {
+                version: V4,
+                request_id: 1,
+                timestamp: 1_700_000_005,
+                code: RES_PONG,
+                sequence: 12,
+                message: "Pong".to_owned(),
+                ..Response::default()
+            },
+        ),
+    ]
+}
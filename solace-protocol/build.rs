@@ -0,0 +1,21 @@
+use std::{path::Path, process::Command};
+
+/// Bakes the commit being built into `SOLACE_GIT_HASH`, for `BuildInfo`.
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=SOLACE_GIT_HASH={git_hash}");
+
+    // Only run again for a new commit, rather than for any change at all
+    if Path::new("../.git/HEAD").exists() {
+        println!("cargo:rerun-if-changed=../.git/HEAD");
+        println!("cargo:rerun-if-changed=../.git/refs");
+        println!("cargo:rerun-if-changed=build.rs");
+    }
+}
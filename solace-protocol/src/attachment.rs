@@ -0,0 +1,69 @@
+use sha2::{Digest, Sha256};
+
+/// A file from the server's attachment store, sent in `RES_ATTACHMENT` as
+/// `id\tname\tdata`.
+///
+/// Attachments are addressed by their contents, see `id_for`, and their data
+/// is always sent hex encoded as frames can't hold arbitrary bytes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn encode(&self) -> String {
+        format!("{}\t{}\t{}", self.id, self.name, encode_data(&self.data))
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        let mut parts = encoded.splitn(3, '\t');
+
+        Some(Self {
+            id: parts.next()?.to_owned(),
+            name: parts.next()?.to_owned(),
+            data: decode_data(parts.next()?)?,
+        })
+    }
+}
+
+/// The id of an attachment holding `data`, the hex SHA-256 of it, so that
+/// the same file uploaded twice is only kept once.
+pub fn id_for(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Whether `id` could have come from `id_for`, which is checked before it
+/// is used to find anything.
+pub fn is_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+pub fn encode_data(data: &[u8]) -> String {
+    hex::encode(data)
+}
+
+pub fn decode_data(encoded: &str) -> Option<Vec<u8>> {
+    hex::decode(encoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = b"\r\n\x00binary".to_vec();
+        let attachment = Attachment {
+            id: id_for(&data),
+            name: "notes.bin".to_owned(),
+            data,
+        };
+
+        assert!(is_id(&attachment.id));
+        assert_eq!(Attachment::decode(&attachment.encode()), Some(attachment));
+        assert!(!is_id("../../etc/passwd"));
+        assert!(!is_id(&"A".repeat(64)));
+    }
+}
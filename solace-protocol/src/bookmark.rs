@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+/// A chat message kept for later, sent one per line in `RES_BOOKMARK_LIST`.
+///
+/// Each line is encoded as `message_id\tnick\ttimestamp\tmessage`, the
+/// message going last as it is the only part which may contain a tab.
+///
+/// # Fields
+///
+/// - `message_id`: See `Response::message_id`.
+/// - `nick`: Who sent the message.
+/// - `timestamp`: Unix timestamp of when it was sent.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Bookmark {
+    pub message_id: u64,
+    pub nick: String,
+    pub timestamp: u64,
+    pub message: String,
+}
+
+impl Bookmark {
+    pub fn encode(&self) -> String {
+        // Messages can't span lines in the list, so newlines become spaces
+        let message = self.message.replace('\n', " ");
+
+        format!(
+            "{}\t{}\t{}\t{message}",
+            self.message_id, self.nick, self.timestamp
+        )
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        let mut parts = encoded.splitn(4, '\t');
+
+        Some(Self {
+            message_id: parts.next()?.parse().ok()?,
+            nick: parts.next()?.to_owned(),
+            timestamp: parts.next()?.parse().ok()?,
+            message: parts.next()?.to_owned(),
+        })
+    }
+
+    pub fn encode_list(bookmarks: &[Bookmark]) -> String {
+        bookmarks
+            .iter()
+            .map(Bookmark::encode)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn decode_list(encoded: &str) -> Vec<Bookmark> {
+        encoded.lines().filter_map(Self::decode).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let bookmarks = vec![
+            Bookmark {
+                message_id: 12,
+                nick: "alice".to_owned(),
+                timestamp: 1_700_000_000,
+                message: "see\tthis".to_owned(),
+            },
+            Bookmark {
+                message_id: 40,
+                nick: "bob".to_owned(),
+                timestamp: 1_700_000_100,
+                message: String::new(),
+            },
+        ];
+
+        assert_eq!(
+            Bookmark::decode_list(&Bookmark::encode_list(&bookmarks)),
+            bookmarks
+        );
+        assert_eq!(Bookmark::decode("x\talice\t1\thi"), None);
+    }
+}
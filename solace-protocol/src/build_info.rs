@@ -0,0 +1,117 @@
+use std::{cmp::Ordering, fmt, ops::RangeInclusive};
+
+use crate::version::{LATEST, V1};
+
+/// The commit this was built from, or `unknown` outside of a git checkout.
+pub const GIT_HASH: &str = env!("SOLACE_GIT_HASH");
+
+/// What a client or server was built from, swapped once connected so that
+/// each can log the other's, and a client can warn when the server can't
+/// read the protocol version it speaks.
+///
+/// The server sends its own in `RES_BUILD_INFO` to clients with the
+/// `build-info` capability, which answer with theirs in
+/// `RequestMessage::BuildInfo`.
+///
+/// Encoded as `name\tversion\tgit_hash\tmin-max`, none of which may contain
+/// a tab.
+///
+/// # Fields
+///
+/// - `name`: The crate, e.g. `solace-server`.
+/// - `protocol`: The versions of the protocol it reads, see `version`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildInfo {
+    pub name: String,
+    pub version: String,
+    pub git_hash: String,
+    pub protocol: RangeInclusive<u8>,
+}
+
+impl BuildInfo {
+    /// This build of the crate `name` at `version`, which are its
+    /// `CARGO_PKG_NAME` and `CARGO_PKG_VERSION`.
+    pub fn ours(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            git_hash: GIT_HASH.to_owned(),
+            protocol: V1..=LATEST,
+        }
+    }
+
+    /// Whether `version` of the protocol is older than any `self` reads,
+    /// newer, or one it reads.
+    pub fn compare_protocol(&self, version: u8) -> Ordering {
+        if version < *self.protocol.start() {
+            Ordering::Less
+        } else if version > *self.protocol.end() {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}-{}",
+            self.name,
+            self.version,
+            self.git_hash,
+            self.protocol.start(),
+            self.protocol.end()
+        )
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        let mut parts = encoded.splitn(4, '\t');
+        let (name, version, git_hash) = (parts.next()?, parts.next()?, parts.next()?);
+        let (min, max) = parts.next()?.split_once('-')?;
+
+        Some(Self {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            git_hash: git_hash.to_owned(),
+            protocol: min.parse().ok()?..=max.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({}), protocol {}-{}",
+            self.name,
+            self.version,
+            self.git_hash,
+            self.protocol.start(),
+            self.protocol.end()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let ours = BuildInfo::ours("solace-server", "0.1.0");
+        assert_eq!(BuildInfo::decode(&ours.encode()), Some(ours));
+        assert_eq!(BuildInfo::decode("solace-server\t0.1.0\tabc1234"), None);
+        assert_eq!(BuildInfo::decode("solace-server\t0.1.0\tabc1234\t2"), None);
+    }
+
+    #[test]
+    fn test_compare_protocol() {
+        let server = BuildInfo {
+            protocol: 2..=3,
+            ..BuildInfo::ours("solace-server", "0.1.0")
+        };
+
+        assert_eq!(server.compare_protocol(1), Ordering::Less);
+        assert_eq!(server.compare_protocol(2), Ordering::Equal);
+        assert_eq!(server.compare_protocol(4), Ordering::Greater);
+    }
+}
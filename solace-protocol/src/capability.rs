@@ -0,0 +1,19 @@
+//! Capabilities a client can opt into by sending `RequestMessage::Capabilities`
+//! straight after connecting, before which the server assumes none.
+
+/// Advertise and accept commands which are still being rolled out.
+pub const EXPERIMENTAL: &str = "experimental";
+
+/// Follow each usage in `RES_COMMAND_LIST` with a tab and what the command
+/// does, for `/help`.
+pub const COMMAND_HELP: &str = "command-help";
+
+/// Be sent the server's `BuildInfo` in `RES_BUILD_INFO`, which the client
+/// answers with its own in `RequestMessage::BuildInfo`.
+pub const BUILD_INFO: &str = "build-info";
+
+/// Sign frames with `codec::Integrity::Hmac`, keyed with material exported
+/// from the TLS session under `codec::SESSION_KEY_LABEL`. Only offered over
+/// TLS. The server signs from `RES_FRAME_HMAC` on and the client from the
+/// first request after it.
+pub const FRAME_HMAC: &str = "frame-hmac";
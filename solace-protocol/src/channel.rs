@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+/// The longest a channel name may be, `#` included.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Whether `name` can name a channel, a `#` followed by at least one
+/// character which isn't whitespace, a tab or a comma.
+pub fn is_channel_name(name: &str) -> bool {
+    name.len() <= MAX_NAME_LEN
+        && name.strip_prefix('#').is_some_and(|rest| {
+            !rest.is_empty() && !rest.chars().any(|c| c.is_whitespace() || c == ',')
+        })
+}
+
+/// Something a channel op can turn on for their channel.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum ChannelMode {
+    /// Only channel ops can talk.
+    Moderated,
+    /// Only channel ops can set the topic.
+    TopicLocked,
+    /// Messages elsewhere which mention the channel aren't pointed out in
+    /// it.
+    NoReferences,
+    /// Only channel ops can post and everyone else just reads, as for a
+    /// feed of announcements or statuses.
+    Announcements,
+}
+
+impl ChannelMode {
+    /// The mode an IRC style change names and whether it turns it on, e.g.
+    /// `+m` or `-t`.
+    pub fn from_change(change: &str) -> Option<(Self, bool)> {
+        let (is_enabled, flag) = match change.split_at_checked(1)? {
+            ("+", flag) => (true, flag),
+            ("-", flag) => (false, flag),
+            _ => return None,
+        };
+
+        let mode = match flag {
+            "m" => ChannelMode::Moderated,
+            "t" => ChannelMode::TopicLocked,
+            "r" => ChannelMode::NoReferences,
+            "a" => ChannelMode::Announcements,
+            _ => return None,
+        };
+
+        Some((mode, is_enabled))
+    }
+
+    pub fn flag(self) -> char {
+        match self {
+            ChannelMode::Moderated => 'm',
+            ChannelMode::TopicLocked => 't',
+            ChannelMode::NoReferences => 'r',
+            ChannelMode::Announcements => 'a',
+        }
+    }
+}
+
+/// Text about one channel, such as a message sent to it or its topic,
+/// which the response's code says the meaning of.
+///
+/// Encoded as `channel\ttext`, as channel names can't contain a tab.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelText {
+    pub channel: String,
+    pub text: String,
+}
+
+impl ChannelText {
+    pub fn new(channel: &str, text: String) -> Self {
+        Self {
+            channel: channel.to_owned(),
+            text,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}\t{}", self.channel, self.text)
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        let (channel, text) = encoded.split_once('\t')?;
+
+        Some(Self::new(channel, text.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_names_and_modes() {
+        assert!(is_channel_name("#rust"));
+        assert!(!is_channel_name("rust"));
+        assert!(!is_channel_name("#"));
+        assert!(!is_channel_name("#a,b"));
+        assert!(!is_channel_name(&format!("#{}", "a".repeat(MAX_NAME_LEN))));
+
+        assert_eq!(
+            ChannelMode::from_change("+m"),
+            Some((ChannelMode::Moderated, true))
+        );
+        assert_eq!(
+            ChannelMode::from_change("-t"),
+            Some((ChannelMode::TopicLocked, false))
+        );
+        assert_eq!(
+            ChannelMode::from_change("+r"),
+            Some((ChannelMode::NoReferences, true))
+        );
+        assert_eq!(
+            ChannelMode::from_change("-a"),
+            Some((ChannelMode::Announcements, false))
+        );
+        assert_eq!(ChannelMode::from_change("+o"), None);
+        assert_eq!(ChannelMode::from_change(""), None);
+
+        let text = ChannelText::new("#rust", "hello\tthere".to_owned());
+        assert_eq!(ChannelText::decode(&text.encode()), Some(text));
+        assert_eq!(ChannelText::decode("#rust"), None);
+    }
+}
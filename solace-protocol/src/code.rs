@@ -14,8 +14,108 @@ pub const RES_TOPIC_CHANGE_MESSAGE: u16 = 203;
 pub const RES_COMMAND_LIST: u16 = 204;
 pub const RES_NICK_LIST: u16 = 205;
 pub const RES_WHO_IS: u16 = 206;
+pub const RES_AWAY: u16 = 207;
+pub const RES_DIRECT_MESSAGE: u16 = 208;
+pub const RES_DEVICE_LIST: u16 = 209;
+pub const RES_LOGGED_IN: u16 = 210;
+pub const RES_SESSION_REVOKED: u16 = 211;
+pub const RES_SELF_MESSAGE: u16 = 212;
+pub const RES_SELF_DIRECT_MESSAGE: u16 = 213;
+pub const RES_QUOTA: u16 = 214;
+pub const RES_PRESENCE: u16 = 215;
+pub const RES_MODE_CHANGE: u16 = 216;
+pub const RES_KICKED: u16 = 217;
+pub const RES_CUSTOM_COMMAND: u16 = 218;
+/// Sent along with a chat message mentioning `@here` or `@all`, named in the
+/// message, to each nick it reaches.
+pub const RES_EVERYONE_MENTIONED: u16 = 219;
+pub const RES_STATS: u16 = 220;
+/// Tells the sender of a chat message its `message_id`, with the
+/// `request_id` it was sent with.
+pub const RES_MESSAGE_SENT: u16 = 221;
+pub const RES_BOOKMARKED: u16 = 222;
+/// One bookmark per line, each `message_id`, nick, timestamp and message
+/// separated by tabs.
+pub const RES_BOOKMARK_LIST: u16 = 223;
+/// A `LinkPreview` for a chat message sent earlier, which it follows up on
+/// with the same `message_id`.
+pub const RES_LINK_PREVIEW: u16 = 224;
+/// The most characters a chat message may have, sent on joining. `0` means
+/// there is no limit.
+pub const RES_MESSAGE_LIMIT: u16 = 225;
+/// The id an upload is stored under, with its `request_id`.
+pub const RES_UPLOADED: u16 = 226;
+/// An `Attachment` which was asked for, with the `request_id` it was asked
+/// for with.
+pub const RES_ATTACHMENT: u16 = 227;
+/// A tip for getting started, sent after the welcome to those who are new.
+pub const RES_TIP: u16 = 228;
+/// Sent to a nick which joined a channel, as a `ChannelText` of its topic.
+pub const RES_JOINED: u16 = 229;
+/// Sent to a nick which left a channel, as a `ChannelText` with no text.
+pub const RES_PARTED: u16 = 230;
+/// Everyone in a channel, as a `ChannelText` of their nicks separated by
+/// spaces, each behind the prefix of their level in the channel. Sent to its
+/// members whenever it changes.
+pub const RES_CHANNEL_MEMBERS: u16 = 231;
+/// A `ChannelText` of a chat message sent to a channel, from the origin.
+pub const RES_CHANNEL_MESSAGE: u16 = 232;
+/// A `ChannelText` of something which happened in a channel, in words, e.g.
+/// who joined or who changed the topic.
+pub const RES_CHANNEL_NOTICE: u16 = 233;
+/// A `ChannelText` of a channel's new topic.
+pub const RES_CHANNEL_TOPIC: u16 = 234;
+/// A `ChannelText` of the flags of every mode turned on in a channel, such
+/// as `+mt`.
+pub const RES_CHANNEL_MODE: u16 = 235;
+/// Sent after a chat message to each nick one of its `@mentions` is for,
+/// named in the message, with the chat message's `message_id`. Mentions are
+/// matched ignoring case, so one typed in the wrong case still reaches them.
+pub const RES_MENTIONED: u16 = 236;
+/// The sender has registered or authenticated for its nick, which is now
+/// also the account it is logged into.
+pub const RES_AUTH_OK: u16 = 237;
+/// The server's `BuildInfo`, for clients with the `build-info` capability.
+pub const RES_BUILD_INFO: u16 = 238;
+/// Chat messages which mentioned the nick while it was away or
+/// disconnected, sent once it is back, one `Bookmark` per line.
+pub const RES_MISSED_MENTIONS: u16 = 239;
+/// The message of the day, sent on joining after the greeting. It may span
+/// several lines.
+pub const RES_MOTD: u16 = 240;
+/// Everyone who has read the chat message with `message_id` so far, one
+/// nick per line, sent to its sender each time someone else reads it.
+pub const RES_READ_RECEIPT: u16 = 241;
+/// The server signs every frame from this one on, for clients with the
+/// `frame-hmac` capability, and expects the client to sign its requests
+/// from now on.
+pub const RES_FRAME_HMAC: u16 = 242;
+/// Where mentions are recognised, the name of a `MentionPolicy`, sent on
+/// joining so that clients highlight the mentions the server delivers.
+pub const RES_MENTION_POLICY: u16 = 243;
 
 pub const ERR_COMMAND_NOT_FOUND: u16 = 300;
 pub const ERR_INVALID_ARGUMENT: u16 = 301;
 pub const ERR_NICK_IN_USE: u16 = 302;
 pub const ERR_WHO_IS: u16 = 303;
+pub const ERR_PROTOCOL: u16 = 304;
+pub const ERR_NOT_LOGGED_IN: u16 = 305;
+pub const ERR_SESSION_NOT_FOUND: u16 = 306;
+pub const ERR_NICK_NOT_FOUND: u16 = 307;
+pub const ERR_QUOTA_EXCEEDED: u16 = 308;
+/// Sent with the `request_id` of a request which was dropped for coming too
+/// soon after the others, the message is how many milliseconds to wait before
+/// sending anything else.
+pub const ERR_RATE_LIMITED: u16 = 309;
+pub const ERR_WRONG_PASSWORD: u16 = 310;
+/// The request needs a higher level in the channel than the sender has.
+pub const ERR_NOT_PERMITTED: u16 = 311;
+/// Sent with the `request_id` of a chat message over `RES_MESSAGE_LIMIT`.
+pub const ERR_MESSAGE_TOO_LONG: u16 = 312;
+pub const ERR_ATTACHMENT_TOO_LARGE: u16 = 313;
+/// The request was for a channel the sender isn't in.
+pub const ERR_NOT_IN_CHANNEL: u16 = 314;
+/// The nick asked for is registered, so needs `/auth` with its password.
+pub const ERR_AUTH_REQUIRED: u16 = 315;
+/// The channel is for announcements, which only its ops post.
+pub const ERR_READ_ONLY: u16 = 316;
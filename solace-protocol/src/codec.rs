@@ -0,0 +1,490 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio_util::{
+    bytes::{BufMut, Bytes, BytesMut},
+    codec::{Decoder, Encoder},
+};
+
+use crate::version::{Versioned, LATEST, V1};
+
+const FLAG_CRC32: u8 = 0b01;
+const FLAG_HMAC: u8 = 0b10;
+const TERMINATOR: &[u8] = b"\r\n";
+
+/// The label both ends of a TLS connection export keying material under to
+/// key `Integrity::Hmac`, see RFC 5705, once they have agreed to with the
+/// `frame-hmac` capability.
+pub const SESSION_KEY_LABEL: &[u8] = b"EXPORTER-solace-frame-hmac";
+/// How many bytes of keying material to export for the session key.
+pub const SESSION_KEY_LEN: usize = 32;
+/// The context exported under, given even though it is empty as QUIC always
+/// gives one, and RFC 5705 derives a different key without one.
+pub const SESSION_KEY_CONTEXT: &[u8] = b"";
+
+/// How each frame written by a `FrameCodec` is protected.
+///
+/// Tags are hex encoded so that they can never contain the frame terminator.
+#[derive(Clone, Debug, Default)]
+pub enum Integrity {
+    /// Frames are sent as is.
+    None,
+    /// A CRC32 of the payload catches frames corrupted in transit.
+    #[default]
+    Crc32,
+    /// An HMAC-SHA256 of the payload, keyed with a session key negotiated
+    /// with the `frame-hmac` capability, also catches frames which have been
+    /// tampered with.
+    Hmac(Vec<u8>),
+}
+
+/// Frames bincode encoded `T`s over a byte stream, laid out as the version
+/// of the protocol each names.
+///
+/// The structure of a frame is as follows:
+/// - The first byte holds flags saying which integrity tags are present.
+/// - The payload itself.
+/// - An 8 character CRC32 tag, if flagged.
+/// - A 64 character HMAC tag, if flagged.
+/// - A `\r\n` terminator.
+///
+/// Frames without the tags the codec's `Integrity` calls for are rejected,
+/// a valid HMAC standing in for a CRC32. Any other tags a frame carries are
+/// checked too, bar an HMAC the codec has no key for.
+///
+/// The codec also counts the bytes it frames in each direction, which callers
+/// can drain with `take_bytes_decoded`/`take_bytes_encoded` for accounting.
+///
+/// Items going to many peers can be laid out once with `SharedFrame::new`
+/// and handed to each peer's codec, which only has to frame it.
+///
+/// # Fields
+///
+/// - `pending_key`: A key the peer is about to start signing frames with,
+///   see `expect_hmac`.
+/// - `peer_version`: The newest version of the protocol the peer reads,
+///   which items are laid out as if they name a newer one.
+/// - `sequence`: The number of frames encoded, which those with room for it
+///   are numbered by, see `Versioned::sequence_offset`.
+#[derive(Debug)]
+pub struct FrameCodec<T> {
+    integrity: Integrity,
+    pending_key: Option<Vec<u8>>,
+    peer_version: u8,
+    sequence: u64,
+    bytes_decoded: u64,
+    bytes_encoded: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for FrameCodec<T> {
+    fn default() -> Self {
+        Self::new(Integrity::default())
+    }
+}
+
+impl<T> FrameCodec<T> {
+    pub fn new(integrity: Integrity) -> Self {
+        Self {
+            integrity,
+            pending_key: None,
+            peer_version: LATEST,
+            sequence: 0,
+            bytes_decoded: 0,
+            bytes_encoded: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Protects frames written from now on with `integrity`, and requires it
+    /// of those read.
+    pub fn set_integrity(&mut self, integrity: Integrity) {
+        self.integrity = integrity;
+        self.pending_key = None;
+    }
+
+    /// Switches to `Integrity::Hmac` with `key` once the first frame signed
+    /// with it is read, for a reader which can't tell which frame the peer
+    /// starts signing from.
+    pub fn expect_hmac(&mut self, key: Vec<u8>) {
+        self.pending_key = Some(key);
+    }
+
+    /// Lays items out as no newer than `version` from now on, as the newest
+    /// the peer has said it reads.
+    pub fn set_peer_version(&mut self, version: u8) {
+        self.peer_version = version.clamp(V1, LATEST);
+    }
+
+    /// Returns the bytes decoded since the last call.
+    pub fn take_bytes_decoded(&mut self) -> u64 {
+        std::mem::take(&mut self.bytes_decoded)
+    }
+
+    /// Returns the bytes encoded since the last call.
+    pub fn take_bytes_encoded(&mut self) -> u64 {
+        std::mem::take(&mut self.bytes_encoded)
+    }
+
+    /// Serializes `item` straight into `dst` as the version the peer reads
+    /// and seals it as a frame, returning the length of the frame.
+    fn seal(&mut self, item: &impl Versioned, dst: &mut BytesMut) -> anyhow::Result<usize> {
+        let start = dst.len();
+        let version = item.version().min(self.peer_version);
+
+        dst.put_u8(self.flags());
+        item.write_as(version, dst.writer())?;
+
+        Ok(self.finish(start, item.sequence_offset(version), dst))
+    }
+
+    fn flags(&self) -> u8 {
+        match &self.integrity {
+            Integrity::None => 0,
+            Integrity::Crc32 => FLAG_CRC32,
+            Integrity::Hmac(_) => FLAG_HMAC,
+        }
+    }
+
+    /// Numbers the payload written to `dst` from `start` on, after its flags,
+    /// and adds its tags and terminator, returning the length of the frame.
+    fn finish(
+        &mut self,
+        start: usize,
+        sequence_offset: Option<usize>,
+        dst: &mut BytesMut,
+    ) -> usize {
+        self.sequence += 1;
+        if let Some(offset) = sequence_offset {
+            let at = start + 1 + offset;
+            dst[at..at + 8].copy_from_slice(&self.sequence.to_le_bytes());
+        }
+
+        let payload = &dst[start + 1..];
+        let tag = match &self.integrity {
+            Integrity::None => String::new(),
+            Integrity::Crc32 => crc32_tag(payload),
+            Integrity::Hmac(key) => hmac_tag(key, payload),
+        };
+
+        dst.put(tag.as_bytes());
+        dst.put(TERMINATOR);
+
+        dst.len() - start
+    }
+
+    fn open<'a>(&mut self, frame: &'a [u8]) -> anyhow::Result<&'a [u8]> {
+        let (&flags, mut rest) = frame
+            .split_first()
+            .context("ERROR: Received an empty frame")?;
+
+        let hmac = if flags & FLAG_HMAC != 0 {
+            let (body, tag) = split_tag(rest, 64)?;
+            rest = body;
+            Some(tag)
+        } else {
+            None
+        };
+
+        let crc = if flags & FLAG_CRC32 != 0 {
+            let (body, tag) = split_tag(rest, 8)?;
+            rest = body;
+            Some(tag)
+        } else {
+            None
+        };
+
+        if let Some(tag) = crc {
+            anyhow::ensure!(
+                tag == crc32_tag(rest).as_bytes(),
+                "ERROR: Frame failed its CRC32 check"
+            );
+        }
+
+        let key = match &self.integrity {
+            Integrity::Hmac(key) => Some(key),
+            _ => self.pending_key.as_ref(),
+        };
+
+        // Without a key there is nothing to check the tag against
+        let is_signed = match (key, hmac) {
+            (Some(key), Some(tag)) => {
+                verify_hmac(key, rest, tag)?;
+                true
+            }
+            _ => false,
+        };
+
+        if is_signed {
+            if let Some(key) = self.pending_key.take() {
+                self.integrity = Integrity::Hmac(key);
+            }
+        }
+
+        match self.integrity {
+            Integrity::None => (),
+            Integrity::Crc32 => anyhow::ensure!(
+                crc.is_some() || is_signed,
+                "ERROR: Frame is missing its CRC32"
+            ),
+            Integrity::Hmac(_) => anyhow::ensure!(is_signed, "ERROR: Frame is missing its HMAC"),
+        }
+
+        Ok(rest)
+    }
+}
+
+impl<T: Versioned> Decoder for FrameCodec<T> {
+    type Item = T;
+    type Error = anyhow::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> anyhow::Result<Option<T>> {
+        if let Some(pos) = src.windows(2).position(|w| w == TERMINATOR) {
+            let mut buf = src.split_to(pos + 2).freeze();
+            self.bytes_decoded += buf.len() as u64;
+            buf.truncate(pos);
+
+            let payload = self.open(&buf[..])?;
+            return T::read_versioned(payload).map(Some);
+        }
+
+        Ok(None)
+    }
+}
+
+/// Whether `byte` could be the first byte of a frame, i.e. holds no flags
+/// other than the known integrity ones, so that peers speaking some other
+/// protocol entirely can be told apart from the first byte they send.
+pub fn is_frame_start(byte: u8) -> bool {
+    byte & !(FLAG_CRC32 | FLAG_HMAC) == 0
+}
+
+/// An item laid out once as each version up to the one it names, cheap to
+/// clone, so that it can be queued for any number of peers. Each peer's
+/// codec then only has to number and tag the layout its peer reads.
+#[derive(Clone, Debug)]
+pub struct SharedFrame(Arc<[Layout]>);
+
+#[derive(Debug)]
+struct Layout {
+    payload: Bytes,
+    sequence_offset: Option<usize>,
+}
+
+impl SharedFrame {
+    pub fn new(item: &impl Versioned) -> anyhow::Result<Self> {
+        let layouts = (V1..=item.version().max(V1))
+            .map(|version| {
+                let mut payload = BytesMut::new().writer();
+                item.write_as(version, &mut payload)?;
+
+                Ok(Layout {
+                    payload: payload.into_inner().freeze(),
+                    sequence_offset: item.sequence_offset(version),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self(layouts.into()))
+    }
+}
+
+impl<T: Versioned> Encoder<T> for FrameCodec<T> {
+    type Error = anyhow::Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> anyhow::Result<()> {
+        self.bytes_encoded += self.seal(&item, dst)? as u64;
+
+        Ok(())
+    }
+}
+
+impl<T> Encoder<SharedFrame> for FrameCodec<T> {
+    type Error = anyhow::Error;
+
+    fn encode(&mut self, frame: SharedFrame, dst: &mut BytesMut) -> anyhow::Result<()> {
+        let newest = frame.0.len().min(usize::from(self.peer_version));
+        let layout = &frame.0[newest - 1];
+        let start = dst.len();
+
+        dst.put_u8(self.flags());
+        dst.put(&layout.payload[..]);
+        self.bytes_encoded += self.finish(start, layout.sequence_offset, dst) as u64;
+
+        Ok(())
+    }
+}
+
+fn split_tag(rest: &[u8], len: usize) -> anyhow::Result<(&[u8], &[u8])> {
+    anyhow::ensure!(rest.len() >= len, "ERROR: Frame is too short for its tag");
+
+    Ok(rest.split_at(rest.len() - len))
+}
+
+fn crc32_tag(payload: &[u8]) -> String {
+    format!("{:08x}", crc32fast::hash(payload))
+}
+
+fn hmac_for(key: &[u8], payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("ERROR: HMAC accepts any key size");
+    mac.update(payload);
+
+    mac
+}
+
+fn hmac_tag(key: &[u8], payload: &[u8]) -> String {
+    hex::encode(hmac_for(key, payload).finalize().into_bytes())
+}
+
+/// Checks `tag` in constant time, so that how long it takes gives nothing
+/// away about the right one.
+fn verify_hmac(key: &[u8], payload: &[u8], tag: &[u8]) -> anyhow::Result<()> {
+    let tag = hex::decode(tag).context("ERROR: Frame has a malformed HMAC")?;
+
+    hmac_for(key, payload)
+        .verify_slice(&tag)
+        .map_err(|_| anyhow::anyhow!("ERROR: Frame failed its HMAC check"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::{Request, RequestMessage};
+    use crate::response::{Response, ResponseBuilder};
+
+    fn frame_for(codec: &mut FrameCodec<Request>, message: &str) -> BytesMut {
+        let mut dst = BytesMut::new();
+        codec
+            .encode(
+                Request::new(1, RequestMessage::Message(message.to_owned())),
+                &mut dst,
+            )
+            .unwrap();
+
+        dst
+    }
+
+    #[test]
+    fn test_round_trip_with_crc32() {
+        let mut codec = FrameCodec::<Request>::new(Integrity::Crc32);
+        let mut frame = frame_for(&mut codec, "hello");
+        let decoded = codec.decode(&mut frame).unwrap().unwrap();
+        assert!(matches!(decoded.message, RequestMessage::Message(m) if m == "hello"));
+    }
+
+    #[test]
+    fn test_frames_start_with_a_frame_start() {
+        for integrity in [Integrity::None, Integrity::Crc32, Integrity::Hmac(vec![1])] {
+            let frame = frame_for(&mut FrameCodec::new(integrity), "hello");
+            assert!(is_frame_start(frame[0]));
+        }
+
+        // HTTP and a TLS client hello
+        assert!(!is_frame_start(b'G'));
+        assert!(!is_frame_start(0x16));
+    }
+
+    #[test]
+    fn test_corrupted_frame_is_rejected() {
+        let mut codec = FrameCodec::<Request>::new(Integrity::Crc32);
+        let mut frame = frame_for(&mut codec, "hello");
+        let i = frame.len() - 12;
+        frame[i] ^= 0xFF;
+        assert!(codec.decode(&mut frame).is_err());
+    }
+
+    #[test]
+    fn test_frames_missing_required_tags_are_rejected() {
+        let untagged = || frame_for(&mut FrameCodec::new(Integrity::None), "hello");
+        assert!(FrameCodec::<Request>::new(Integrity::Crc32)
+            .decode(&mut untagged())
+            .is_err());
+        assert!(FrameCodec::<Request>::new(Integrity::Hmac(b"a".to_vec()))
+            .decode(&mut untagged())
+            .is_err());
+        assert!(FrameCodec::<Request>::new(Integrity::None)
+            .decode(&mut untagged())
+            .unwrap()
+            .is_some());
+
+        // A valid HMAC is as good as a CRC32
+        let mut signed = frame_for(&mut FrameCodec::new(Integrity::Hmac(b"a".to_vec())), "hi");
+        let mut codec = FrameCodec::<Request>::new(Integrity::Crc32);
+        codec.expect_hmac(b"a".to_vec());
+        assert!(codec.decode(&mut signed).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_switches_to_hmac_once_the_peer_signs() {
+        let key = b"session".to_vec();
+        let mut codec = FrameCodec::<Request>::default();
+        codec.expect_hmac(key.clone());
+
+        let mut checked = frame_for(&mut FrameCodec::default(), "before");
+        assert!(codec.decode(&mut checked).unwrap().is_some());
+
+        let mut signed = frame_for(&mut FrameCodec::new(Integrity::Hmac(key)), "after");
+        assert!(codec.decode(&mut signed).unwrap().is_some());
+
+        // From then on nothing less will do
+        let mut checked = frame_for(&mut FrameCodec::default(), "again");
+        assert!(codec.decode(&mut checked).is_err());
+    }
+
+    #[test]
+    fn test_hmac_with_wrong_key_is_rejected() {
+        let mut frame = frame_for(&mut FrameCodec::new(Integrity::Hmac(b"a".to_vec())), "hi");
+        let mut codec = FrameCodec::<Request>::new(Integrity::Hmac(b"b".to_vec()));
+        assert!(codec.decode(&mut frame).is_err());
+    }
+
+    #[test]
+    fn test_shared_frame_matches_encoded_frame() {
+        let mut codec = FrameCodec::<Request>::new(Integrity::Crc32);
+        let request = Request::new(1, RequestMessage::Message("hello".to_owned()));
+        let shared = SharedFrame::new(&request).unwrap();
+
+        let mut dst = BytesMut::new();
+        codec.encode(shared, &mut dst).unwrap();
+        assert_eq!(&frame_for(&mut codec, "hello")[..], &dst[..]);
+    }
+
+    #[test]
+    fn test_responses_are_laid_out_as_the_peer_reads() {
+        let response = ResponseBuilder::new(200, "hi".to_owned())
+            .with_message_id(42)
+            .build();
+        let shared = SharedFrame::new(&response).unwrap();
+
+        for version in [V1, LATEST] {
+            let mut codec = FrameCodec::<Response>::default();
+            codec.set_peer_version(version);
+
+            let mut dst = BytesMut::new();
+            codec.encode(response.clone(), &mut dst).unwrap();
+            codec.encode(shared.clone(), &mut dst).unwrap();
+
+            let first = codec.decode(&mut dst).unwrap().unwrap();
+            let second = codec.decode(&mut dst).unwrap().unwrap();
+            assert_eq!((first.version, second.version), (version, version));
+
+            // Only the latest has room for the message ID and numbering
+            if version == LATEST {
+                assert_eq!((first.message_id, second.message_id), (42, 42));
+                assert_eq!((first.sequence, second.sequence), (1, 2));
+            } else {
+                assert_eq!((first.message_id, second.sequence), (0, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hmac_required_when_keyed() {
+        let mut frame = frame_for(&mut FrameCodec::new(Integrity::Crc32), "hi");
+        let mut codec = FrameCodec::<Request>::new(Integrity::Hmac(b"a".to_vec()));
+        assert!(codec.decode(&mut frame).is_err());
+    }
+}
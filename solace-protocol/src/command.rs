@@ -0,0 +1,140 @@
+/// A single argument in a command's usage string.
+///
+/// - `<name>`: A required argument.
+/// - `[name]`: An optional argument.
+/// - `<name...>`/`[name...]`: Swallows the rest of the message, only valid last.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArgSpec {
+    pub name: String,
+    pub is_required: bool,
+    pub is_variadic: bool,
+}
+
+impl ArgSpec {
+    fn parse(raw: &str) -> Option<Self> {
+        let (inner, is_required) =
+            if let Some(inner) = raw.strip_prefix('<').and_then(|r| r.strip_suffix('>')) {
+                (inner, true)
+            } else {
+                (raw.strip_prefix('[')?.strip_suffix(']')?, false)
+            };
+
+        let (name, is_variadic) = match inner.strip_suffix("...") {
+            Some(name) => (name, true),
+            None => (inner, false),
+        };
+
+        Some(Self {
+            name: name.to_owned(),
+            is_required,
+            is_variadic,
+        })
+    }
+
+    pub fn placeholder(&self) -> String {
+        let dots = if self.is_variadic { "..." } else { "" };
+
+        if self.is_required {
+            format!("<{}{dots}>", self.name)
+        } else {
+            format!("[{}{dots}]", self.name)
+        }
+    }
+}
+
+/// Describes a command the client can send, pushed by the server in the
+/// `RES_COMMAND_LIST` response as one usage string per line, e.g.
+/// `nick <nick>` or `away [reason...]`.
+///
+/// # Fields
+///
+/// - `help`: What the command does, following the usage after a tab for
+///   clients with the `COMMAND_HELP` capability. Empty otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandSpec {
+    pub name: String,
+    pub args: Vec<ArgSpec>,
+    pub help: String,
+}
+
+impl CommandSpec {
+    pub fn parse(line: &str) -> Option<Self> {
+        let (usage, help) = line.split_once('\t').unwrap_or((line, ""));
+        let mut words = usage.split_whitespace();
+        let name = words.next()?.to_owned();
+        let args = words
+            .map(ArgSpec::parse)
+            .collect::<Option<Vec<ArgSpec>>>()?;
+
+        Some(Self {
+            name,
+            args,
+            help: help.trim().to_owned(),
+        })
+    }
+
+    pub fn usage(&self) -> String {
+        std::iter::once(self.name.clone())
+            .chain(self.args.iter().map(|a| a.placeholder()))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    pub fn min_args(&self) -> usize {
+        self.args.iter().filter(|a| a.is_required).count()
+    }
+
+    /// `None` when the last argument is variadic.
+    pub fn max_args(&self) -> Option<usize> {
+        match self.args.last() {
+            Some(arg) if arg.is_variadic => None,
+            _ => Some(self.args.len()),
+        }
+    }
+
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        let within_max = match self.max_args() {
+            Some(max) => arg_count <= max,
+            None => true,
+        };
+
+        arg_count >= self.min_args() && within_max
+    }
+
+    pub fn encode_list(specs: &[CommandSpec]) -> String {
+        specs
+            .iter()
+            .map(|s| {
+                if s.help.is_empty() {
+                    s.usage()
+                } else {
+                    format!("{}\t{}", s.usage(), s.help)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn decode_list(encoded: &str) -> Vec<CommandSpec> {
+        encoded.lines().filter_map(Self::parse).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_help_follows_a_tab() {
+        let spec = CommandSpec::parse("away [reason...]\tMarks you as away").unwrap();
+        assert_eq!(spec.usage(), "away [reason...]");
+        assert_eq!(spec.help, "Marks you as away");
+
+        let spec = CommandSpec::parse("away [reason...]").unwrap();
+        assert_eq!(spec.help, "");
+        assert_eq!(
+            CommandSpec::decode_list("ping\tChecks\nnick <nick>").len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// How much say a nick has over the channel, from least to most.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    #[default]
+    Member,
+    /// Trusted to speak, with whatever else the server allows voices.
+    Voice,
+    Op,
+    /// Set in the server's config rather than granted with `/mode`.
+    Founder,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Member => "member",
+            Level::Voice => "voice",
+            Level::Op => "op",
+            Level::Founder => "founder",
+        }
+    }
+
+    /// The level an IRC style mode change leaves a nick at, where `+o` and
+    /// `+v` grant op and voice and `-o` or `-v` take either away.
+    pub fn from_mode(mode: &str) -> Option<Self> {
+        match mode {
+            "+o" => Some(Level::Op),
+            "+v" => Some(Level::Voice),
+            "-o" | "-v" => Some(Level::Member),
+            _ => None,
+        }
+    }
+
+    /// The marker shown before a nick at this level.
+    pub fn prefix(self) -> char {
+        match self {
+            Level::Member => ' ',
+            Level::Voice => '+',
+            Level::Op => '@',
+            Level::Founder => '~',
+        }
+    }
+}
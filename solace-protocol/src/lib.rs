@@ -1,3 +1,14 @@
+pub mod attachment;
+pub mod bookmark;
+pub mod build_info;
+pub mod capability;
+pub mod channel;
 pub mod code;
+pub mod codec;
+pub mod command;
+pub mod level;
+pub mod link_preview;
+pub mod presence;
 pub mod request;
 pub mod response;
+pub mod version;
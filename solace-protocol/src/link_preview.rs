@@ -0,0 +1,48 @@
+/// The title and description of a page linked to in a chat message, sent
+/// in `RES_LINK_PREVIEW` once the server has fetched it.
+///
+/// Encoded as `url\ttitle\tdescription`, none of which may contain a tab or
+/// a newline, as the server collapses whitespace when reading them.
+///
+/// # Fields
+///
+/// - `description`: Empty when the page doesn't have one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: String,
+    pub description: String,
+}
+
+impl LinkPreview {
+    pub fn encode(&self) -> String {
+        format!("{}\t{}\t{}", self.url, self.title, self.description)
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        let mut parts = encoded.splitn(3, '\t');
+
+        Some(Self {
+            url: parts.next()?.to_owned(),
+            title: parts.next()?.to_owned(),
+            description: parts.next()?.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let preview = LinkPreview {
+            url: "https://example.com/a".to_owned(),
+            title: "Example".to_owned(),
+            description: String::new(),
+        };
+
+        assert_eq!(LinkPreview::decode(&preview.encode()), Some(preview));
+        assert_eq!(LinkPreview::decode("https://example.com"), None);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::level::Level;
+
+/// How long a nick can go without sending anything before it is shown as idle.
+pub const IDLE_AFTER_SECS: u64 = 5 * 60;
+
+/// What a connection has told others about whether it is around, sent back
+/// by name in `RES_PRESENCE` whenever it changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Presence {
+    #[default]
+    Online,
+    Away,
+    /// Around, but not wanting mentions or direct messages to make a sound.
+    DoNotDisturb,
+}
+
+impl Presence {
+    pub fn name(self) -> &'static str {
+        match self {
+            Presence::Online => "online",
+            Presence::Away => "away",
+            Presence::DoNotDisturb => "dnd",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Presence::Online, Presence::Away, Presence::DoNotDisturb]
+            .into_iter()
+            .find(|presence| presence.name() == name)
+    }
+}
+
+/// A single entry of the space separated `RES_NICK_LIST` message.
+///
+/// Each entry is encoded as `nick:flags:last_active` where `flags` is a
+/// (possibly empty) set of single character markers:
+/// - `f`: The nick founded the channel, sent along with `o` so that older
+///   clients still show it as an operator.
+/// - `o`: The nick is a channel operator.
+/// - `v`: The nick has been given a voice.
+/// - `a`: The nick has marked themselves as away.
+/// - `d`: The nick doesn't want to be disturbed.
+///
+/// # Fields
+///
+/// - `last_active`: Unix timestamp of the last request received from the nick.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NickListEntry {
+    pub nick: String,
+    pub level: Level,
+    pub is_away: bool,
+    pub is_dnd: bool,
+    pub last_active: u64,
+}
+
+impl NickListEntry {
+    pub fn new(nick: &str) -> Self {
+        Self {
+            nick: nick.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut flags = String::new();
+
+        match self.level {
+            Level::Member => (),
+            Level::Voice => flags.push('v'),
+            Level::Op => flags.push('o'),
+            Level::Founder => flags.push_str("fo"),
+        }
+
+        if self.is_away {
+            flags.push('a');
+        }
+
+        if self.is_dnd {
+            flags.push('d');
+        }
+
+        format!("{}:{flags}:{}", self.nick, self.last_active)
+    }
+
+    /// Entries without any presence information decode to a plain nick so
+    /// that older servers sending bare nicks are still understood.
+    pub fn decode(encoded: &str) -> Self {
+        let parsed = encoded.rsplit_once(':').and_then(|(rest, last_active)| {
+            let (nick, flags) = rest.rsplit_once(':')?;
+            let level = if flags.contains('f') {
+                Level::Founder
+            } else if flags.contains('o') {
+                Level::Op
+            } else if flags.contains('v') {
+                Level::Voice
+            } else {
+                Level::Member
+            };
+
+            Some(Self {
+                nick: nick.to_owned(),
+                level,
+                is_away: flags.contains('a'),
+                is_dnd: flags.contains('d'),
+                last_active: last_active.parse().ok()?,
+            })
+        });
+
+        parsed.unwrap_or_else(|| Self::new(encoded))
+    }
+
+    pub fn encode_list(entries: &[NickListEntry]) -> String {
+        entries
+            .iter()
+            .map(|e| e.encode())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    pub fn decode_list(encoded: &str) -> Vec<NickListEntry> {
+        encoded
+            .split(' ')
+            .filter(|e| !e.is_empty())
+            .map(Self::decode)
+            .collect()
+    }
+
+    pub fn is_idle(&self, now: u64) -> bool {
+        self.last_active != 0 && now.saturating_sub(self.last_active) > IDLE_AFTER_SECS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_round_trip() {
+        for level in [Level::Member, Level::Voice, Level::Op, Level::Founder] {
+            let entry = NickListEntry {
+                level,
+                last_active: 1,
+                ..NickListEntry::new("alice")
+            };
+
+            assert_eq!(NickListEntry::decode(&entry.encode()), entry);
+        }
+
+        assert_eq!(NickListEntry::decode("bob:fo:1").level, Level::Founder);
+        assert_eq!(NickListEntry::decode("bob").level, Level::Member);
+    }
+}
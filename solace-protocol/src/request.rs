@@ -1,12 +1,12 @@
-use std::io::Write;
+use std::{fmt, io::Write};
 
 use anyhow::Context;
 use bincode::{deserialize, serialize, Result};
 use serde::{Deserialize, Serialize};
-use tokio_util::{
-    bytes::BufMut,
-    codec::{Decoder, Encoder},
-};
+
+use crate::channel::ChannelMode;
+use crate::level::Level;
+use crate::version::LATEST;
 
 /// The structure of the request is as follows:
 /// - The first byte represents the version flag.
@@ -15,17 +15,18 @@ use tokio_util::{
 ///
 /// # Fields
 ///
-/// - `version`: A `u8` representing the version of the request protocol.
+/// - `version`: A `u8` representing the newest version of the protocol the
+///   sender reads, see `version`.
 /// - `id`: A `u32` representing a unique identifier for the request.
 /// - `message`: A `ReqeustMessage` containing the message.
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Request {
     pub version: u8,
     pub id: u32,
     pub message: RequestMessage,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub enum RequestMessage {
     #[default]
     Ping,
@@ -33,13 +34,153 @@ pub enum RequestMessage {
     NewTopic(String),
     NewNick(String),
     WhoIs(String),
+    // bincode writes the index of the variant, so new ones only ever go on
+    // the end
     Disconnect,
+    Away(Option<String>),
+    Capabilities(Vec<String>),
+    /// `password` comes from the client's saved credentials, or is asked for
+    /// when there are none.
+    Login {
+        account: String,
+        password: Option<Secret>,
+    },
+    DirectMessage {
+        to: String,
+        message: String,
+    },
+    Devices,
+    Revoke(u32),
+    Quota,
+    DoNotDisturb,
+    Online,
+    /// Takes `nick` back from whoever is using it, for the owner of the
+    /// account by that name.
+    Ghost {
+        nick: String,
+        password: Secret,
+    },
+    /// Sets the level of every session using `nick`, and of its account if
+    /// it is logged into one.
+    Mode {
+        nick: String,
+        level: Level,
+    },
+    Kick {
+        nick: String,
+        reason: Option<String>,
+    },
+    /// A command the server's config defines rather than one built in, with
+    /// its arguments as typed.
+    Custom {
+        name: String,
+        args: String,
+    },
+    /// The karma of a nick, or our own without one.
+    Karma(Option<String>),
+    Stats(String),
+    /// Keeps the chat message with this `message_id` for the account, see
+    /// `Response::message_id`.
+    Bookmark(u64),
+    Bookmarks,
+    /// A file for the attachment store, `data` encoded with
+    /// `attachment::encode_data`.
+    Upload {
+        name: String,
+        data: String,
+    },
+    /// The attachment with this id, see `attachment::id_for`.
+    Download(String),
+    /// Joins the channel by this name, creating it if nobody is in it.
+    Join(String),
+    Part(String),
+    /// A chat message for everyone in `channel`, which the sender must be in.
+    ChannelMessage {
+        channel: String,
+        message: String,
+    },
+    ChannelTopic {
+        channel: String,
+        topic: String,
+    },
+    ChannelMode {
+        channel: String,
+        mode: ChannelMode,
+        is_enabled: bool,
+    },
+    /// Protects the nick in use with a password, which `Auth` then needs
+    /// before anyone can take it.
+    Register(Secret),
+    /// Takes the registered nick last refused by `NewNick`, or else the one
+    /// in use.
+    Auth(Secret),
+    /// The client's `BuildInfo`, encoded, in answer to `RES_BUILD_INFO`.
+    BuildInfo(String),
+    /// Every chat message up to and including the one with this
+    /// `message_id` has been read, which their senders are told of with
+    /// `RES_READ_RECEIPT`.
+    MarkRead(u64),
+}
+
+/// A value such as a password which mustn't end up in logs, so is hidden
+/// from `Debug`. It is still serialized as is.
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl RequestMessage {
+    /// The name of the command this request is sent for, if any, matching
+    /// the names in the server's `RES_COMMAND_LIST`.
+    pub fn command_name(&self) -> Option<&'static str> {
+        match self {
+            RequestMessage::Ping => Some("ping"),
+            RequestMessage::NewTopic(_) => Some("topic"),
+            RequestMessage::NewNick(_) => Some("nick"),
+            RequestMessage::WhoIs(_) => Some("whois"),
+            RequestMessage::Away(_) => Some("away"),
+            RequestMessage::Login { .. } => Some("login"),
+            RequestMessage::DirectMessage { .. } => Some("msg"),
+            RequestMessage::Devices => Some("devices"),
+            RequestMessage::Revoke(_) => Some("revoke"),
+            RequestMessage::Quota => Some("quota"),
+            RequestMessage::Disconnect => Some("disconnect"),
+            RequestMessage::DoNotDisturb => Some("dnd"),
+            RequestMessage::Online => Some("online"),
+            RequestMessage::Ghost { .. } => Some("ghost"),
+            RequestMessage::Mode { .. } => Some("mode"),
+            RequestMessage::Kick { .. } => Some("kick"),
+            RequestMessage::Karma(_) => Some("karma"),
+            RequestMessage::Stats(_) => Some("stats"),
+            RequestMessage::Bookmark(_) => Some("bookmark"),
+            RequestMessage::Bookmarks => Some("bookmarks"),
+            RequestMessage::Upload { .. } => Some("upload"),
+            RequestMessage::Download(_) => Some("download"),
+            RequestMessage::Join(_) => Some("join"),
+            RequestMessage::Part(_) => Some("part"),
+            RequestMessage::ChannelMessage { .. } => Some("say"),
+            RequestMessage::ChannelTopic { .. } => Some("chantopic"),
+            RequestMessage::ChannelMode { .. } => Some("chanmode"),
+            RequestMessage::Register(_) => Some("register"),
+            RequestMessage::Auth(_) => Some("auth"),
+            RequestMessage::Message(_)
+            | RequestMessage::Capabilities(_)
+            | RequestMessage::BuildInfo(_)
+            | RequestMessage::MarkRead(_)
+            | RequestMessage::Custom { .. } => None,
+        }
+    }
 }
 
 impl Request {
     pub fn new(id: u32, message: RequestMessage) -> Self {
         Self {
-            version: 1,
+            version: LATEST,
             id,
             message: message.clone(),
         }
@@ -63,36 +204,24 @@ impl Request {
     }
 }
 
-impl Decoder for Request {
-    type Item = Request;
-    type Error = anyhow::Error;
-
-    fn decode(&mut self, src: &mut tokio_util::bytes::BytesMut) -> anyhow::Result<Option<Request>> {
-        if let Some(pos) = src.windows(2).position(|w| w == b"\r\n") {
-            let mut buf = src.split_to(pos + 2).freeze();
-            buf.truncate(pos);
-
-            let request = Request::decode(&buf[..])?;
-
-            return Ok(Some(request));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_baseline_variants_keep_their_indices() {
+        let baseline = [
+            RequestMessage::Ping,
+            RequestMessage::Message(String::new()),
+            RequestMessage::NewTopic(String::new()),
+            RequestMessage::NewNick(String::new()),
+            RequestMessage::WhoIs(String::new()),
+            RequestMessage::Disconnect,
+        ];
+
+        for (index, message) in (0u32..).zip(baseline) {
+            let encoded = serialize(&message).unwrap();
+            assert_eq!(encoded[..4], index.to_le_bytes(), "{message:?}");
         }
-
-        Ok(None)
-    }
-}
-
-impl Encoder<Request> for Request {
-    type Error = anyhow::Error;
-
-    fn encode(
-        &mut self,
-        item: Request,
-        dst: &mut tokio_util::bytes::BytesMut,
-    ) -> anyhow::Result<()> {
-        let bytes = item.encode()?;
-        dst.reserve(bytes.len());
-        dst.put(&bytes[..]);
-
-        Ok(())
     }
 }
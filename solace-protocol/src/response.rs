@@ -1,18 +1,23 @@
 use std::io::Write;
 
 use anyhow::Context;
-use bincode::{deserialize, serialize, Result};
 use serde::{Deserialize, Serialize};
-use tokio_util::{
-    bytes::BufMut,
-    codec::{Decoder, Encoder},
-};
+
+use crate::version::{Versioned, LATEST};
+
+/// Set in `Response::flags` when the origin is a bot account rather than a
+/// person.
+pub const FLAG_BOT: u8 = 1 << 0;
 
 /// The structure of the response is as follows:
 /// - The first byte represents the version flag.
 /// - The next 4 bytes represent the request ID.
 /// - The next 8 bytes represent the timestamp.
 /// - The next 2 bytes represent the response code.
+/// - The origin, preceded by its length.
+/// - The next byte holds flags about the origin, from version 2 on.
+/// - The next 8 bytes represent the chat message ID, from version 3 on.
+/// - The next 8 bytes represent the sequence number, from version 4 on.
 /// - The remaining bytes represent the message, ending with a `\r\n` terminator.
 ///
 /// # Fields
@@ -21,8 +26,16 @@ use tokio_util::{
 /// - `request_id`: A `u32` representing the request to which we are responding.
 /// - `timestamp`: A `u64` representing the Unix timestamp when the response was generated.
 /// - `code`: A `u16` representing the response code.
+/// - `flags`: A `u8` of `FLAG_*` bits describing the origin, always `0`
+///   before version 2.
+/// - `message_id`: A `u64` numbering chat messages in the order the server
+///   received them, for referring back to one. `0` for anything else, which
+///   is always the case before version 3.
+/// - `sequence`: A `u64` numbering the responses sent over a connection, for
+///   telling when some went missing. `0` when they aren't numbered, which is
+///   always the case before version 4.
 /// - `message`: A `String` containing the message.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Response {
     pub version: u8,
     pub request_id: u32,
@@ -30,6 +43,9 @@ pub struct Response {
     pub code: u16,
     pub origin_length: u8,
     pub origin: String,
+    pub flags: u8,
+    pub message_id: u64,
+    pub sequence: u64,
 
     // @FEATURE: Should message take a format which can be parsed
     // into a different AST node? So that it can be displayed differently
@@ -48,15 +64,20 @@ pub enum ResponseMessage {
 }
 
 impl Response {
-    pub fn encode(&self) -> Result<Vec<u8>> {
-        let mut bytes = serialize(self)?;
+    pub fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.write_versioned(&mut bytes)?;
         bytes.extend(b"\r\n");
 
         Ok(bytes)
     }
 
-    pub fn decode(encoded: &[u8]) -> Result<Response> {
-        deserialize(encoded)
+    pub fn decode(encoded: &[u8]) -> anyhow::Result<Response> {
+        Self::read_versioned(encoded)
+    }
+
+    pub fn is_from_bot(&self) -> bool {
+        self.flags & FLAG_BOT != 0
     }
 
     pub fn write_to(&self, stream: &mut impl Write) -> anyhow::Result<()> {
@@ -66,48 +87,13 @@ impl Response {
     }
 }
 
-impl Decoder for Response {
-    type Item = Response;
-    type Error = anyhow::Error;
-
-    fn decode(
-        &mut self,
-        src: &mut tokio_util::bytes::BytesMut,
-    ) -> anyhow::Result<Option<Response>> {
-        if let Some(pos) = src.windows(2).position(|w| w == b"\r\n") {
-            let mut buf = src.split_to(pos + 2).freeze();
-            buf.truncate(pos);
-
-            let response = Response::decode(&buf[..])?;
-
-            return Ok(Some(response));
-        }
-
-        Ok(None)
-    }
-}
-
-impl Encoder<Response> for Response {
-    type Error = anyhow::Error;
-
-    fn encode(
-        &mut self,
-        item: Response,
-        dst: &mut tokio_util::bytes::BytesMut,
-    ) -> anyhow::Result<()> {
-        let bytes = item.encode()?;
-        dst.reserve(bytes.len());
-        dst.put(&bytes[..]);
-
-        Ok(())
-    }
-}
-
 #[derive(Default)]
 pub struct ResponseBuilder {
     request_id: u32,
     code: u16,
     origin: String,
+    flags: u8,
+    message_id: u64,
     message: String,
 }
 
@@ -132,15 +118,34 @@ impl ResponseBuilder {
         self
     }
 
+    pub fn with_message_id(mut self, message_id: u64) -> Self {
+        self.message_id = message_id;
+
+        self
+    }
+
+    pub fn from_bot(mut self, is_bot: bool) -> Self {
+        if is_bot {
+            self.flags |= FLAG_BOT;
+        }
+
+        self
+    }
+
     pub fn build(self) -> Response {
+        // Laid out as older versions for clients which don't read this one,
+        // see `FrameCodec`
         Response {
-            version: 1,
+            version: LATEST,
             request_id: self.request_id,
             timestamp: u64::try_from(chrono::Utc::now().timestamp())
                 .expect("ERROR: Timestamp exceeds u64::MAX"),
             code: self.code,
             origin_length: u8::try_from(self.origin.len()).expect("ERROR: Origin too long"),
             origin: self.origin,
+            flags: self.flags,
+            message_id: self.message_id,
+            sequence: 0,
             message: self.message,
         }
     }
@@ -0,0 +1,420 @@
+//! How each version of the protocol lays out requests and responses.
+//!
+//! bincode writes no field names or lengths, so a frame can only be read with
+//! exactly the struct it was written from, and adding a field to `Response`
+//! would otherwise break every client built before it. Instead every layout
+//! starts with its version byte and is kept here for good:
+//! - Readers pick the layout by that byte and migrate what they read up to
+//!   the latest, with defaults for fields older versions don't have.
+//! - Writers lay an item out as the older of the version it names and the
+//!   newest the peer has said it reads, migrating it down when that is
+//!   older. Clients name the newest version they read in each request, and
+//!   until a server has heard from one it is sent version 1.
+//!
+//! Version 1 is the layout from before there were versions, so peers built
+//! back then can still be talked to.
+
+use std::io::Write;
+
+use anyhow::Context;
+use bincode::{deserialize, serialize_into};
+use serde::{Deserialize, Serialize};
+
+use crate::{request::Request, response::Response};
+
+/// Responses as they were before versions, see `ResponseV1`.
+pub const V1: u8 = 1;
+/// Adds `Response::flags`.
+pub const V2: u8 = 2;
+/// Adds `Response::message_id`.
+pub const V3: u8 = 3;
+/// Adds `Response::sequence`.
+pub const V4: u8 = 4;
+/// The newest version this build reads.
+pub const LATEST: u8 = V4;
+
+/// Something laid out differently by each version of the protocol.
+pub trait Versioned: Sized {
+    /// The version `self` names, the newest it can be laid out as.
+    fn version(&self) -> u8;
+
+    /// Writes `self` laid out as `version`, which is no newer than the one it
+    /// names.
+    fn write_as(&self, version: u8, dst: impl Write) -> anyhow::Result<()>;
+
+    /// Writes `self` laid out as the version it names.
+    fn write_versioned(&self, dst: impl Write) -> anyhow::Result<()> {
+        self.write_as(self.version(), dst)
+    }
+
+    /// Reads `payload` as whichever version it names, migrated to the
+    /// latest.
+    fn read_versioned(payload: &[u8]) -> anyhow::Result<Self>;
+
+    /// Where the `u64` numbering each frame sent over a connection starts in
+    /// `self` laid out as `version`, for `FrameCodec` to fill in. `None` if
+    /// that version has no room for it.
+    fn sequence_offset(&self, _version: u8) -> Option<usize> {
+        None
+    }
+}
+
+/// The version `payload` names, which is the first byte of every layout.
+fn version_of(payload: &[u8]) -> anyhow::Result<u8> {
+    let &version = payload
+        .first()
+        .context("ERROR: Received an empty payload")?;
+    anyhow::ensure!(
+        (V1..=LATEST).contains(&version),
+        "ERROR: Unsupported protocol version {version}, this build reads up to {LATEST}"
+    );
+
+    Ok(version)
+}
+
+/// `Request` itself is laid out as it always has been, so its version only
+/// says which responses the sender reads. Only ever appending variants to
+/// `RequestMessage` keeps the older ones readable by older servers, which
+/// ignore the version.
+impl Versioned for Request {
+    fn version(&self) -> u8 {
+        self.version
+    }
+
+    fn write_as(&self, _version: u8, dst: impl Write) -> anyhow::Result<()> {
+        serialize_into(dst, self).context("ERROR: Failed to encode request")
+    }
+
+    fn read_versioned(payload: &[u8]) -> anyhow::Result<Self> {
+        version_of(payload)?;
+
+        deserialize(payload).context("ERROR: Failed to decode request")
+    }
+}
+
+/// `Response` as version 1 lays it out, which is how every response was laid
+/// out before there were versions.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ResponseV1 {
+    pub version: u8,
+    pub request_id: u32,
+    pub timestamp: u64,
+    pub code: u16,
+    pub origin_length: u8,
+    pub origin: String,
+    pub message: String,
+}
+
+/// `Response` as version 2 lays it out, with `flags`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ResponseV2 {
+    pub version: u8,
+    pub request_id: u32,
+    pub timestamp: u64,
+    pub code: u16,
+    pub origin_length: u8,
+    pub origin: String,
+    pub flags: u8,
+    pub message: String,
+}
+
+/// `Response` as version 3 lays it out, with `message_id`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ResponseV3 {
+    pub version: u8,
+    pub request_id: u32,
+    pub timestamp: u64,
+    pub code: u16,
+    pub origin_length: u8,
+    pub origin: String,
+    pub flags: u8,
+    pub message_id: u64,
+    pub message: String,
+}
+
+/// A response as any version lays it out, the latest being `Response`
+/// itself.
+#[derive(Debug, PartialEq)]
+pub enum ResponseFrame {
+    V1(ResponseV1),
+    V2(ResponseV2),
+    V3(ResponseV3),
+    V4(Response),
+}
+
+impl ResponseFrame {
+    /// `response` laid out as the version it names, dropping anything that
+    /// version has no room for.
+    pub fn new(response: Response) -> anyhow::Result<Self> {
+        if response.version == V4 {
+            return Ok(Self::V4(response));
+        }
+
+        let Response {
+            version,
+            request_id,
+            timestamp,
+            code,
+            origin_length,
+            origin,
+            flags,
+            message_id,
+            message,
+            ..
+        } = response;
+
+        match version {
+            V1 => Ok(Self::V1(ResponseV1 {
+                version,
+                request_id,
+                timestamp,
+                code,
+                origin_length,
+                origin,
+                message,
+            })),
+            V2 => Ok(Self::V2(ResponseV2 {
+                version,
+                request_id,
+                timestamp,
+                code,
+                origin_length,
+                origin,
+                flags,
+                message,
+            })),
+            V3 => Ok(Self::V3(ResponseV3 {
+                version,
+                request_id,
+                timestamp,
+                code,
+                origin_length,
+                origin,
+                flags,
+                message_id,
+                message,
+            })),
+            version => anyhow::bail!("ERROR: Can't write a response as version {version}"),
+        }
+    }
+
+    pub fn decode(payload: &[u8]) -> anyhow::Result<Self> {
+        let frame = match version_of(payload)? {
+            V1 => deserialize(payload).map(Self::V1),
+            V2 => deserialize(payload).map(Self::V2),
+            V3 => deserialize(payload).map(Self::V3),
+            _ => deserialize(payload).map(Self::V4),
+        };
+
+        frame.context("ERROR: Failed to decode response")
+    }
+
+    pub fn write(&self, dst: impl Write) -> anyhow::Result<()> {
+        match self {
+            Self::V1(response) => serialize_into(dst, response),
+            Self::V2(response) => serialize_into(dst, response),
+            Self::V3(response) => serialize_into(dst, response),
+            Self::V4(response) => serialize_into(dst, response),
+        }
+        .context("ERROR: Failed to encode response")
+    }
+
+    /// The latest `Response`, keeping the version this was laid out as.
+    pub fn migrate(self) -> Response {
+        match self {
+            Self::V1(v1) => Response {
+                version: v1.version,
+                request_id: v1.request_id,
+                timestamp: v1.timestamp,
+                code: v1.code,
+                origin_length: v1.origin_length,
+                origin: v1.origin,
+                message: v1.message,
+                ..Response::default()
+            },
+            Self::V2(v2) => Response {
+                version: v2.version,
+                request_id: v2.request_id,
+                timestamp: v2.timestamp,
+                code: v2.code,
+                origin_length: v2.origin_length,
+                origin: v2.origin,
+                flags: v2.flags,
+                message: v2.message,
+                ..Response::default()
+            },
+            Self::V3(v3) => Response {
+                version: v3.version,
+                request_id: v3.request_id,
+                timestamp: v3.timestamp,
+                code: v3.code,
+                origin_length: v3.origin_length,
+                origin: v3.origin,
+                flags: v3.flags,
+                message_id: v3.message_id,
+                message: v3.message,
+                ..Response::default()
+            },
+            Self::V4(response) => response,
+        }
+    }
+}
+
+impl Versioned for Response {
+    fn version(&self) -> u8 {
+        self.version
+    }
+
+    fn write_as(&self, version: u8, dst: impl Write) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            version <= self.version,
+            "ERROR: Can't write a version {} response as version {version}",
+            self.version
+        );
+
+        match version {
+            LATEST => serialize_into(dst, self).context("ERROR: Failed to encode response"),
+            _ => ResponseFrame::new(Response {
+                version,
+                ..self.clone()
+            })?
+            .write(dst),
+        }
+    }
+
+    fn read_versioned(payload: &[u8]) -> anyhow::Result<Self> {
+        ResponseFrame::decode(payload).map(ResponseFrame::migrate)
+    }
+
+    fn sequence_offset(&self, version: u8) -> Option<usize> {
+        // The version, request ID, timestamp, code, origin with its length
+        // and bincode's, flags and message ID
+        (version >= V4).then(|| 1 + 4 + 8 + 2 + 1 + 8 + self.origin.len() + 1 + 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestMessage;
+
+    fn response(version: u8) -> Response {
+        Response {
+            version,
+            request_id: 7,
+            timestamp: 1_700_000_000,
+            code: 200,
+            origin_length: 3,
+            origin: "bob".to_owned(),
+            flags: 1,
+            message_id: 42,
+            sequence: 9,
+            message: "hi".to_owned(),
+        }
+    }
+
+    fn written(item: &impl Versioned) -> Vec<u8> {
+        let mut payload = vec![];
+        item.write_versioned(&mut payload).unwrap();
+
+        payload
+    }
+
+    #[test]
+    fn test_responses_round_trip_at_every_version() {
+        let v4 = response(V4);
+        assert_eq!(Response::read_versioned(&written(&v4)).unwrap(), v4);
+
+        // Older versions have no room for the fields added after them
+        let v3 = response(V3);
+        let read = Response::read_versioned(&written(&v3)).unwrap();
+        assert_eq!(read, Response { sequence: 0, ..v3 });
+
+        let v2 = response(V2);
+        let read = Response::read_versioned(&written(&v2)).unwrap();
+        assert_eq!(
+            read,
+            Response {
+                message_id: 0,
+                sequence: 0,
+                ..v2
+            }
+        );
+
+        let v1 = response(V1);
+        let read = Response::read_versioned(&written(&v1)).unwrap();
+        assert_eq!(
+            read,
+            Response {
+                flags: 0,
+                message_id: 0,
+                sequence: 0,
+                ..v1
+            }
+        );
+    }
+
+    #[test]
+    fn test_version_1_is_the_layout_from_before_versions() {
+        // The fields `Response` had then, in order
+        let before = (1u8, 7u32, 1_700_000_000u64, 200u16, 3u8, "bob", "hi");
+
+        assert_eq!(written(&response(V1)), bincode::serialize(&before).unwrap());
+    }
+
+    #[test]
+    fn test_each_version_has_its_own_layout() {
+        let layouts = [V1, V2, V3, V4].map(|version| written(&response(version)));
+        let lengths = layouts.each_ref().map(Vec::len);
+        assert_eq!(layouts.each_ref().map(|l| l[0]), [V1, V2, V3, V4]);
+        assert_eq!(lengths.map(|l| l - lengths[0]), [0, 1, 9, 17]);
+
+        assert!(matches!(
+            ResponseFrame::decode(&layouts[0]).unwrap(),
+            ResponseFrame::V1(_)
+        ));
+        assert!(matches!(
+            ResponseFrame::decode(&layouts[1]).unwrap(),
+            ResponseFrame::V2(_)
+        ));
+        assert!(matches!(
+            ResponseFrame::decode(&layouts[2]).unwrap(),
+            ResponseFrame::V3(_)
+        ));
+        assert!(matches!(
+            ResponseFrame::decode(&layouts[3]).unwrap(),
+            ResponseFrame::V4(_)
+        ));
+    }
+
+    #[test]
+    fn test_sequence_offset() {
+        let response = response(V4);
+        let offset = response.sequence_offset(V4).unwrap();
+        let payload = written(&response);
+
+        assert_eq!(payload[offset..offset + 8], 9u64.to_le_bytes());
+        assert_eq!(response.sequence_offset(V3), None);
+    }
+
+    #[test]
+    fn test_written_as_older_versions() {
+        let mut payload = vec![];
+        response(V4).write_as(V1, &mut payload).unwrap();
+        assert_eq!(payload, written(&response(V1)));
+
+        assert!(response(V2).write_as(V3, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_versions_are_refused() {
+        let mut future = written(&response(LATEST));
+        future[0] = LATEST + 1;
+        assert!(Response::read_versioned(&future).is_err());
+
+        let mut request = written(&Request::new(1, RequestMessage::Ping));
+        request[0] = 0;
+        assert!(Request::read_versioned(&request).is_err());
+        assert!(Response::read_versioned(&[]).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    fs,
+    io::Write,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use tracing::warn;
+
+const ACCOUNTS_FILE: &str = "accounts.toml";
+
+/// What a password says about an account.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Verdict {
+    /// Nobody has claimed the account with a password, so anyone can log in.
+    Unclaimed,
+    Correct,
+    Wrong,
+}
+
+/// Accounts claimed with a password, which is then needed to log into them.
+/// An account is claimed by the first login to give a password.
+///
+/// Saved to `accounts.toml` in the XDG data directory, holding an Argon2
+/// hash of each password, readable only by the server's user.
+///
+/// Hashing is slow on purpose, so it is done away from the lock on
+/// `Accounts`: a `Check` or `hasher` is taken out under it instead.
+///
+/// # Fields
+///
+/// - `hashes`: PHC strings by account name, always lowercase as given by
+///   `key`, so that `Alice` can't claim an account beside `alice`.
+/// - `argon2`: Parameters to hash new passwords with, existing hashes carry
+///   their own.
+#[derive(Default)]
+pub(crate) struct Accounts {
+    hashes: BTreeMap<String, String>,
+    argon2: Argon2<'static>,
+}
+
+impl Accounts {
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        let base_path = xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+
+        let hashes = match base_path.find_data_file(ACCOUNTS_FILE) {
+            Some(path) => {
+                let raw = fs::read_to_string(&path)
+                    .with_context(|| format!("ERROR: Failed to read file: {path:?}"))?;
+
+                toml::from_str(&raw).with_context(|| format!("ERROR: Failed to parse {path:?}"))?
+            }
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            hashes: Self::by_key(hashes),
+            ..Self::default()
+        })
+    }
+
+    /// The hashes of a file saved before account names were lowercased. Of
+    /// two names differing only in case, the one already lowercase is kept,
+    /// as the other could only have been claimed beside it.
+    fn by_key(hashes: BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let (mut by_key, others): (BTreeMap<_, _>, BTreeMap<_, _>) = hashes
+            .into_iter()
+            .partition(|(account, _)| *account == Self::key(account));
+
+        for (account, hash) in others {
+            match by_key.entry(Self::key(&account)) {
+                Entry::Occupied(entry) => {
+                    warn!(
+                        "Dropped the account {account}, which clashes with {}",
+                        entry.key()
+                    );
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(hash);
+                }
+            }
+        }
+
+        by_key
+    }
+
+    /// The name `account` is kept under, which logins are made to.
+    pub(crate) fn key(account: &str) -> String {
+        account.trim().to_lowercase()
+    }
+
+    /// What is needed to check a password for `account`.
+    pub(crate) fn check(&self, account: &str) -> Check {
+        Check {
+            hash: self.hashes.get(&Self::key(account)).cloned(),
+            argon2: self.argon2.clone(),
+        }
+    }
+
+    /// The account claiming `nick`, matched ignoring case so that nobody can
+    /// pass as it by changing the case of a letter.
+    pub(crate) fn claimant(&self, nick: &str) -> Option<String> {
+        let key = Self::key(nick);
+        self.hashes.contains_key(&key).then_some(key)
+    }
+
+    /// What to hash a new password with, see `Hasher::hash`.
+    pub(crate) fn hasher(&self) -> Hasher {
+        Hasher(self.argon2.clone())
+    }
+
+    /// Requires the password `hash` is of for every login to `account` from
+    /// now on, unless someone has claimed it already, returning whether it
+    /// was claimed.
+    pub(crate) fn claim(&mut self, account: &str, hash: String) -> anyhow::Result<bool> {
+        let key = Self::key(account);
+        if self.hashes.contains_key(&key) {
+            return Ok(false);
+        }
+
+        self.hashes.insert(key, hash);
+        self.save()?;
+
+        Ok(true)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path()?;
+
+        write_private(&path, toml::to_string(&self.hashes)?.as_bytes())
+            .with_context(|| format!("ERROR: Failed to write {path:?}"))
+    }
+
+    fn path() -> anyhow::Result<PathBuf> {
+        xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?
+            .place_data_file(ACCOUNTS_FILE)
+            .with_context(|| "ERROR: Couldn't create the data directory")
+    }
+}
+
+/// Writes a file only the current user can read, as it holds password
+/// hashes.
+fn write_private(path: &PathBuf, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+
+    // The mode only applies to a file being created, not one saved before
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+    file.write_all(contents)
+}
+
+/// The hash of an account's password, if it was claimed, to check a password
+/// against without holding the lock on `Accounts`.
+pub(crate) struct Check {
+    hash: Option<String>,
+    argon2: Argon2<'static>,
+}
+
+impl Check {
+    /// Slow, so best run with `spawn_blocking`.
+    pub(crate) fn verdict(&self, password: Option<&str>) -> Verdict {
+        let Some(hash) = &self.hash else {
+            return Verdict::Unclaimed;
+        };
+
+        let is_correct = password.is_some_and(|password| {
+            PasswordHash::new(hash).is_ok_and(|hash| {
+                self.argon2
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+        });
+
+        if is_correct {
+            Verdict::Correct
+        } else {
+            Verdict::Wrong
+        }
+    }
+}
+
+/// Hashes new passwords without holding the lock on `Accounts`.
+pub(crate) struct Hasher(Argon2<'static>);
+
+impl Hasher {
+    /// Slow, so best run with `spawn_blocking`.
+    pub(crate) fn hash(&self, password: &str) -> anyhow::Result<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self
+            .0
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|err| anyhow::anyhow!("ERROR: Failed to hash password: {err}"))?;
+
+        Ok(hash.to_string())
+    }
+}
+
+#[cfg(test)]
+impl Accounts {
+    /// Accounts claimed with these passwords, hashed with cheap parameters
+    /// as the defaults are slow in debug builds. Never saved.
+    pub(crate) fn fixture(claimed: &[(&str, &str)]) -> Self {
+        use argon2::{Algorithm, Params, Version};
+
+        let params = Params::new(8, 1, 1, None).unwrap();
+        let mut accounts = Accounts {
+            hashes: BTreeMap::new(),
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        };
+
+        for (account, password) in claimed {
+            let salt = SaltString::generate(&mut OsRng);
+            let hash = accounts
+                .argon2
+                .hash_password(password.as_bytes(), &salt)
+                .unwrap();
+            accounts
+                .hashes
+                .insert(Accounts::key(account), hash.to_string());
+        }
+
+        accounts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let accounts = Accounts::fixture(&[("alice", "hunter2")]);
+        let verify = |account: &str, password| accounts.check(account).verdict(password);
+
+        assert_eq!(verify("alice", Some("hunter2")), Verdict::Correct);
+        assert_eq!(verify("Alice", Some("hunter2")), Verdict::Correct);
+        assert_eq!(verify("alice", Some("hunter3")), Verdict::Wrong);
+        assert_eq!(verify("ALICE", None), Verdict::Wrong);
+        assert_eq!(verify("bob", None), Verdict::Unclaimed);
+
+        assert_eq!(accounts.claimant("ALICE"), Some("alice".to_owned()));
+        assert_eq!(accounts.claimant("bob"), None);
+    }
+
+    #[test]
+    fn test_loads_one_account_per_key() {
+        let hashes = [("Alice", "impostor"), ("alice", "owner"), ("Bob", "bob")]
+            .into_iter()
+            .map(|(account, hash)| (account.to_owned(), hash.to_owned()))
+            .collect();
+        let by_key = Accounts::by_key(hashes);
+
+        assert_eq!(by_key.get("alice").map(String::as_str), Some("owner"));
+        assert_eq!(by_key.get("bob").map(String::as_str), Some("bob"));
+        assert_eq!(by_key.len(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{collections::BTreeMap, fs, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use solace_protocol::attachment::{self, Attachment};
+use tracing::error;
+
+use crate::{config, now, Server};
+
+const ATTACHMENTS_DIR: &str = "attachments";
+const INDEX_FILE: &str = "index.toml";
+const PRUNE_EVERY: Duration = Duration::from_secs(10 * 60);
+
+/// # Fields
+///
+/// - `name`: The file name it was first uploaded with.
+/// - `uploaded_at`: Unix timestamp of the last time it was uploaded, which
+///   it expires from.
+/// - `uploaded_by`: The nick which last uploaded it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct Stored {
+    pub(crate) name: String,
+    pub(crate) size: u64,
+    pub(crate) uploaded_at: u64,
+    pub(crate) uploaded_by: String,
+}
+
+/// Uploaded files, each kept in `dir` under its id from `attachment::id_for`
+/// and listed in an index alongside them, which is only ever written by
+/// the server so that files can't be reached by any other name.
+#[derive(Debug, Default)]
+pub(crate) struct Attachments {
+    dir: PathBuf,
+    index: BTreeMap<String, Stored>,
+}
+
+impl Attachments {
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        let dir = xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?
+            .create_data_directory(ATTACHMENTS_DIR)
+            .with_context(|| "ERROR: Couldn't create the attachments directory")?;
+
+        Self::in_dir(dir)
+    }
+
+    pub(crate) fn in_dir(dir: PathBuf) -> anyhow::Result<Self> {
+        let path = dir.join(INDEX_FILE);
+        let index = if path.exists() {
+            let raw = fs::read_to_string(&path)
+                .with_context(|| format!("ERROR: Failed to read file: {path:?}"))?;
+
+            toml::from_str(&raw).with_context(|| format!("ERROR: Failed to parse {path:?}"))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self { dir, index })
+    }
+
+    /// Keeps `data`, returning its id. Uploading a file which is already
+    /// kept only starts its time to live again.
+    pub(crate) fn store(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        uploaded_by: &str,
+        config: &config::Attachments,
+    ) -> anyhow::Result<String> {
+        let id = attachment::id_for(data);
+        let path = self.dir.join(&id);
+
+        if !path.exists() {
+            fs::write(&path, data).with_context(|| format!("ERROR: Failed to write {path:?}"))?;
+        }
+
+        let name = self
+            .index
+            .get(&id)
+            .map_or_else(|| name.to_owned(), |stored| stored.name.clone());
+
+        self.index.insert(
+            id.clone(),
+            Stored {
+                name,
+                size: data.len() as u64,
+                uploaded_at: now(),
+                uploaded_by: uploaded_by.to_owned(),
+            },
+        );
+        self.make_room(config.max_total_bytes, &id);
+        self.save()?;
+
+        Ok(id)
+    }
+
+    pub(crate) fn get(&self, id: &str) -> anyhow::Result<Option<Attachment>> {
+        let Some(stored) = self.index.get(id).filter(|_| attachment::is_id(id)) else {
+            return Ok(None);
+        };
+
+        let path = self.dir.join(id);
+        let data = fs::read(&path).with_context(|| format!("ERROR: Failed to read {path:?}"))?;
+
+        Ok(Some(Attachment {
+            id: id.to_owned(),
+            name: stored.name.clone(),
+            data,
+        }))
+    }
+
+    /// Removes every file uploaded more than `ttl_secs` before `now`.
+    pub(crate) fn prune(&mut self, ttl_secs: u64, now: u64) -> anyhow::Result<()> {
+        let expired = self
+            .index
+            .iter()
+            .filter(|(_, stored)| stored.uploaded_at.saturating_add(ttl_secs) <= now)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<String>>();
+
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        for id in expired {
+            self.remove(&id);
+        }
+
+        self.save()
+    }
+
+    /// Removes the oldest files until the store fits in `max_total_bytes`,
+    /// other than `keep` which was just uploaded.
+    fn make_room(&mut self, max_total_bytes: u64, keep: &str) {
+        if max_total_bytes == 0 {
+            return;
+        }
+
+        let mut by_age = self
+            .index
+            .iter()
+            .filter(|(id, _)| *id != keep)
+            .map(|(id, stored)| (stored.uploaded_at, id.clone()))
+            .collect::<Vec<(u64, String)>>();
+        by_age.sort();
+
+        let mut total = self.total_bytes();
+
+        for (_, id) in by_age {
+            if total <= max_total_bytes {
+                break;
+            }
+
+            total -= self.index.get(&id).map_or(0, |stored| stored.size);
+            self.remove(&id);
+        }
+    }
+
+    fn total_bytes(&self) -> u64 {
+        self.index.values().map(|stored| stored.size).sum()
+    }
+
+    fn remove(&mut self, id: &str) {
+        self.index.remove(id);
+
+        let path = self.dir.join(id);
+        if let Err(err) = fs::remove_file(&path) {
+            error!("Failed to remove {path:?}: {err}");
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = self.dir.join(INDEX_FILE);
+
+        fs::write(&path, toml::to_string(&self.index)?)
+            .with_context(|| format!("ERROR: Failed to write {path:?}"))
+    }
+}
+
+/// Removes expired attachments every so often.
+pub(crate) async fn prune_periodically(server: Arc<Server>) {
+    let mut interval = tokio::time::interval(PRUNE_EVERY);
+
+    loop {
+        interval.tick().await;
+
+        let ttl_secs = server.config.attachments.ttl_secs;
+        if let Err(err) = server.attachments().prune(ttl_secs, now()) {
+            error!("{err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachments() -> Attachments {
+        let dir =
+            std::env::temp_dir().join(format!("solace-attachments-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+
+        Attachments::in_dir(dir).unwrap()
+    }
+
+    #[test]
+    fn test_store_and_get() {
+        let config = config::Attachments::default();
+        let mut attachments = attachments();
+
+        let id = attachments
+            .store("a.txt", b"hello", "alice", &config)
+            .unwrap();
+        assert_eq!(id, attachment::id_for(b"hello"));
+        assert_eq!(
+            attachments
+                .store("b.txt", b"hello", "bob", &config)
+                .unwrap(),
+            id
+        );
+
+        let attachment = attachments.get(&id).unwrap().unwrap();
+        assert_eq!(attachment.name, "a.txt");
+        assert_eq!(attachment.data, b"hello");
+        assert_eq!(attachments.get(INDEX_FILE).unwrap(), None);
+
+        // Read back from the index
+        let reloaded = Attachments::in_dir(attachments.dir.clone()).unwrap();
+        assert_eq!(reloaded.index, attachments.index);
+
+        fs::remove_dir_all(&attachments.dir).unwrap();
+    }
+
+    #[test]
+    fn test_oldest_make_room_and_expire() {
+        let config = config::Attachments {
+            max_total_bytes: 10,
+            ..config::Attachments::default()
+        };
+        let mut attachments = attachments();
+
+        let first = attachments.store("1", b"12345", "alice", &config).unwrap();
+        attachments.index.get_mut(&first).unwrap().uploaded_at -= 100;
+        let second = attachments.store("2", b"67890", "alice", &config).unwrap();
+        let third = attachments.store("3", b"abc", "alice", &config).unwrap();
+
+        assert_eq!(attachments.get(&first).unwrap(), None);
+        assert!(!attachments.dir.join(&first).exists());
+        assert!(attachments.get(&second).unwrap().is_some());
+
+        attachments.prune(50, now() + 60).unwrap();
+        assert_eq!(attachments.get(&second).unwrap(), None);
+        assert_eq!(attachments.get(&third).unwrap(), None);
+
+        fs::remove_dir_all(&attachments.dir).unwrap();
+    }
+}
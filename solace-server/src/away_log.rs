@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use solace_protocol::bookmark::Bookmark;
+
+/// Chat messages which mentioned a nick while it was away or disconnected,
+/// kept until it is back so that it can be told what it missed. Nicks are
+/// matched ignoring case, as mentions are.
+#[derive(Debug, Default)]
+pub(crate) struct AwayLog {
+    missed: HashMap<String, Vec<Bookmark>>,
+    max_mentions: usize,
+}
+
+impl AwayLog {
+    pub(crate) fn new(max_mentions: usize) -> Self {
+        Self {
+            missed: HashMap::new(),
+            max_mentions,
+        }
+    }
+
+    pub(crate) fn record(&mut self, nick: &str, mention: Bookmark) {
+        if self.max_mentions == 0 {
+            return;
+        }
+
+        let missed = self.missed.entry(nick.to_lowercase()).or_default();
+        if missed.len() == self.max_mentions {
+            missed.remove(0);
+        }
+        missed.push(mention);
+    }
+
+    /// Everything `nick` missed, oldest first, which is forgotten once taken.
+    pub(crate) fn take(&mut self, nick: &str) -> Vec<Bookmark> {
+        self.missed.remove(&nick.to_lowercase()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mention(message_id: u64) -> Bookmark {
+        Bookmark {
+            message_id,
+            nick: "alice".to_owned(),
+            timestamp: 1_700_000_000,
+            message: "hi @bob".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_keeps_the_latest_until_taken() {
+        let mut log = AwayLog::new(2);
+        for message_id in 1..=3 {
+            log.record("bob", mention(message_id));
+        }
+
+        let ids = |missed: Vec<Bookmark>| missed.iter().map(|m| m.message_id).collect::<Vec<_>>();
+        assert_eq!(ids(log.take("Bob")), vec![2, 3]);
+        assert!(log.take("bob").is_empty());
+
+        let mut off = AwayLog::new(0);
+        off.record("bob", mention(1));
+        assert!(off.take("bob").is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::Context;
+use solace_protocol::bookmark::Bookmark;
+
+const BOOKMARKS_FILE: &str = "bookmarks.toml";
+
+/// The most bookmarks an account can keep, the oldest go first.
+const MAX_PER_ACCOUNT: usize = 100;
+
+/// Chat messages kept by each account with `/bookmark`, saved to
+/// `bookmarks.toml` in the XDG data directory. Only messages still in the
+/// backlog can be bookmarked, as that is where they are copied from.
+#[derive(Debug, Default)]
+pub(crate) struct Bookmarks {
+    accounts: BTreeMap<String, Vec<Bookmark>>,
+}
+
+impl Bookmarks {
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        let base_path = xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+
+        let accounts = match base_path.find_data_file(BOOKMARKS_FILE) {
+            Some(path) => {
+                let raw = fs::read_to_string(&path)
+                    .with_context(|| format!("ERROR: Failed to read file: {path:?}"))?;
+
+                toml::from_str(&raw).with_context(|| format!("ERROR: Failed to parse {path:?}"))?
+            }
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self { accounts })
+    }
+
+    pub(crate) fn of(&self, account: &str) -> &[Bookmark] {
+        self.accounts.get(account).map_or(&[], Vec::as_slice)
+    }
+
+    /// Keeps `bookmark` for `account`, returning `false` if it already had it.
+    pub(crate) fn add(&mut self, account: &str, bookmark: Bookmark) -> anyhow::Result<bool> {
+        let bookmarks = self.accounts.entry(account.to_owned()).or_default();
+
+        if bookmarks
+            .iter()
+            .any(|b| b.message_id == bookmark.message_id)
+        {
+            return Ok(false);
+        }
+
+        if bookmarks.len() == MAX_PER_ACCOUNT {
+            bookmarks.remove(0);
+        }
+        bookmarks.push(bookmark);
+
+        self.save()?;
+
+        Ok(true)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path()?;
+
+        fs::write(&path, toml::to_string(&self.accounts)?)
+            .with_context(|| format!("ERROR: Failed to write {path:?}"))
+    }
+
+    fn path() -> anyhow::Result<PathBuf> {
+        xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?
+            .place_data_file(BOOKMARKS_FILE)
+            .with_context(|| "ERROR: Couldn't create the data directory")
+    }
+}
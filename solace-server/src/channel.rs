@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::Context;
+use solace_protocol::level::Level;
+
+const LEVELS_FILE: &str = "levels.toml";
+
+/// Something only nicks at or above a configured level may do, see
+/// `config::Permissions`, which commands name in `command::COMMANDS`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Permission {
+    Topic,
+    Kick,
+    // @TODO: Check this for /invite once there are channels to invite to
+    Invite,
+    Mode,
+    MassMention,
+    Upload,
+    Reference,
+}
+
+impl Permission {
+    pub(crate) fn action(self) -> &'static str {
+        match self {
+            Permission::Topic => "set the topic",
+            Permission::Kick => "kick",
+            Permission::Invite => "invite",
+            Permission::Mode => "change levels",
+            Permission::MassMention => "mention everyone or that many nicks at once",
+            Permission::Upload => "upload attachments",
+            Permission::Reference => "point out messages in the channels they mention",
+        }
+    }
+}
+
+/// Levels granted to accounts with `/mode`, saved to `levels.toml` in the
+/// XDG data directory so that they outlast restarts. A level granted to a
+/// nick which isn't logged in only lasts as long as its connection.
+///
+/// # Fields
+///
+/// - `founders`: Accounts the config makes founders, which can't be changed
+///   with `/mode`.
+#[derive(Debug, Default)]
+pub(crate) struct Levels {
+    accounts: BTreeMap<String, Level>,
+    founders: Vec<String>,
+}
+
+impl Levels {
+    pub(crate) fn load(founders: &[String]) -> anyhow::Result<Self> {
+        let base_path = xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+
+        let accounts = match base_path.find_data_file(LEVELS_FILE) {
+            Some(path) => {
+                let raw = fs::read_to_string(&path)
+                    .with_context(|| format!("ERROR: Failed to read file: {path:?}"))?;
+
+                toml::from_str(&raw).with_context(|| format!("ERROR: Failed to parse {path:?}"))?
+            }
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            accounts,
+            founders: founders.to_vec(),
+        })
+    }
+
+    pub(crate) fn get(&self, account: &str) -> Level {
+        if self.founders.iter().any(|founder| founder == account) {
+            return Level::Founder;
+        }
+
+        self.accounts.get(account).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn set(&mut self, account: &str, level: Level) -> anyhow::Result<()> {
+        match level {
+            Level::Member => self.accounts.remove(account),
+            level => self.accounts.insert(account.to_owned(), level),
+        };
+
+        self.save()
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path()?;
+
+        fs::write(&path, toml::to_string(&self.accounts)?)
+            .with_context(|| format!("ERROR: Failed to write {path:?}"))
+    }
+
+    fn path() -> anyhow::Result<PathBuf> {
+        xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?
+            .place_data_file(LEVELS_FILE)
+            .with_context(|| "ERROR: Couldn't create the data directory")
+    }
+}
+
+/// Whether a nick at level `by` may move a nick from level `from` to `to`,
+/// which both have to be below its own.
+pub(crate) fn can_change_level(by: Level, from: Level, to: Level) -> bool {
+    from < by && to < by
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_can_change_level() {
+        assert!(can_change_level(Level::Op, Level::Member, Level::Voice));
+        assert!(can_change_level(Level::Founder, Level::Member, Level::Op));
+        assert!(can_change_level(Level::Founder, Level::Op, Level::Member));
+        assert!(!can_change_level(Level::Op, Level::Member, Level::Op));
+        assert!(!can_change_level(Level::Op, Level::Op, Level::Member));
+        assert!(!can_change_level(Level::Op, Level::Founder, Level::Member));
+    }
+
+    #[test]
+    fn test_founders_come_from_config() {
+        let mut levels = Levels {
+            accounts: BTreeMap::new(),
+            founders: vec!["alice".to_owned()],
+        };
+        levels.accounts.insert("alice".to_owned(), Level::Voice);
+        levels.accounts.insert("bob".to_owned(), Level::Op);
+
+        assert_eq!(levels.get("alice"), Level::Founder);
+        assert_eq!(levels.get("bob"), Level::Op);
+        assert_eq!(levels.get("carol"), Level::Member);
+    }
+}
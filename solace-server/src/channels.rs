@@ -0,0 +1,306 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use solace_protocol::channel::ChannelMode;
+use tracing::warn;
+
+use crate::config;
+
+const CHANNELS_DIR: &str = "channels";
+
+/// One of the channels nicks can `/join`, alongside the server-wide one
+/// which every client is in.
+///
+/// # Fields
+///
+/// - `name`: As it was first joined, channels are found ignoring case.
+/// - `members`: Everyone in the channel and whether they are an op of it.
+///   Whoever creates a channel is its first op.
+/// - `modes`: Modes turned on by a channel op, see `ChannelMode`.
+#[derive(Debug)]
+pub(crate) struct Channel {
+    pub(crate) name: String,
+    pub(crate) topic: String,
+    pub(crate) members: BTreeMap<SocketAddr, bool>,
+    pub(crate) modes: BTreeSet<ChannelMode>,
+}
+
+impl Channel {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            topic: String::new(),
+            members: BTreeMap::new(),
+            modes: BTreeSet::new(),
+        }
+    }
+
+    /// The flags of every mode turned on, such as `+mt`, or nothing.
+    pub(crate) fn mode_flags(&self) -> String {
+        if self.modes.is_empty() {
+            return String::new();
+        }
+
+        std::iter::once('+')
+            .chain(self.modes.iter().map(|mode| mode.flag()))
+            .collect()
+    }
+}
+
+/// What is saved of a channel, see `Channels::save`.
+#[derive(Debug, Deserialize, Serialize)]
+struct Saved {
+    name: String,
+    topic: String,
+    modes: BTreeSet<ChannelMode>,
+}
+
+/// Every channel with someone in it. A channel is created by the first nick
+/// to join it and goes away with the last to leave, unless channels are
+/// persisted.
+///
+/// Persisted channels are each saved to their own file in `dir` and only
+/// read back when someone next joins, so that the server never holds more
+/// than the channels in use. Those which empty out are kept as they are cold
+/// until there are more than `max_cold`, when the longest cold are dropped.
+///
+/// # Fields
+///
+/// - `cold`: The channels nobody is in, longest cold first.
+/// - `dir`: Where channels are saved, if they are persisted.
+#[derive(Debug, Default)]
+pub(crate) struct Channels {
+    channels: HashMap<String, Channel>,
+    cold: VecDeque<String>,
+    max_cold: usize,
+    dir: Option<PathBuf>,
+}
+
+impl Channels {
+    /// Channels as configured, none of which are read until joined.
+    pub(crate) fn load(config: &config::Channel) -> anyhow::Result<Self> {
+        if !config.persist {
+            return Ok(Self::default());
+        }
+
+        let dir = xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?
+            .create_data_directory(CHANNELS_DIR)
+            .with_context(|| "ERROR: Couldn't create the channels directory")?;
+
+        Ok(Self::in_dir(dir, config.max_cold))
+    }
+
+    pub(crate) fn in_dir(dir: PathBuf, max_cold: usize) -> Self {
+        Self {
+            max_cold,
+            dir: Some(dir),
+            ..Self::default()
+        }
+    }
+
+    fn key(name: &str) -> String {
+        name.to_lowercase()
+    }
+
+    /// Where the channel with `key` is saved, named after its key in hex so
+    /// that any channel name makes a valid file name.
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(format!("{}.toml", hex::encode(key))))
+    }
+
+    /// Writes out the topic and modes of the channel, if channels are
+    /// persisted.
+    pub(crate) fn save(&self, name: &str) -> anyhow::Result<()> {
+        let key = Self::key(name);
+        let (Some(path), Some(channel)) = (self.path(&key), self.channels.get(&key)) else {
+            return Ok(());
+        };
+
+        let saved = Saved {
+            name: channel.name.clone(),
+            topic: channel.topic.clone(),
+            modes: channel.modes.clone(),
+        };
+        fs::write(&path, toml::to_string(&saved)?)
+            .with_context(|| format!("ERROR: Failed to write {path:?}"))
+    }
+
+    /// Reads back the channel with `key`, if it was saved.
+    fn restore(&self, key: &str) -> anyhow::Result<Option<Channel>> {
+        let Some(path) = self.path(key) else {
+            return Ok(None);
+        };
+
+        let raw = match fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("ERROR: Failed to read file: {path:?}"))
+            }
+        };
+        let saved: Saved =
+            toml::from_str(&raw).with_context(|| format!("ERROR: Failed to parse {path:?}"))?;
+
+        Ok(Some(Channel {
+            topic: saved.topic,
+            modes: saved.modes,
+            ..Channel::new(&saved.name)
+        }))
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Channel> {
+        self.channels.get(&Self::key(name))
+    }
+
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut Channel> {
+        self.channels.get_mut(&Self::key(name))
+    }
+
+    /// Adds `addr` to the channel, or `None` if it was already in it.
+    pub(crate) fn join(&mut self, name: &str, addr: SocketAddr) -> Option<&Channel> {
+        let key = Self::key(name);
+
+        if !self.channels.contains_key(&key) {
+            let restored = self.restore(&key).unwrap_or_else(|err| {
+                warn!("Couldn't restore {name}, starting it afresh: {err}");
+                None
+            });
+            let channel = restored.unwrap_or_else(|| Channel::new(name));
+            self.channels.insert(key.clone(), channel);
+        }
+
+        self.cold.retain(|cold| *cold != key);
+        let channel = self.channels.get_mut(&key)?;
+
+        if channel.members.contains_key(&addr) {
+            return None;
+        }
+
+        let is_first = channel.members.is_empty();
+        channel.members.insert(addr, is_first);
+
+        Some(channel)
+    }
+
+    /// Takes `addr` out of the channel, returning the channel's name if it
+    /// was in it.
+    pub(crate) fn part(&mut self, name: &str, addr: SocketAddr) -> Option<String> {
+        let key = Self::key(name);
+        let channel = self.channels.get_mut(&key)?;
+        channel.members.remove(&addr)?;
+        let name = channel.name.clone();
+
+        if channel.members.is_empty() {
+            self.cool(key);
+        }
+
+        Some(name)
+    }
+
+    /// Keeps the channel with `key`, which nobody is in any more, as long as
+    /// there aren't more than `max_cold` others, otherwise drops the longest
+    /// cold. Channels which aren't persisted are dropped straight away, as
+    /// they couldn't be restored once dropped.
+    fn cool(&mut self, key: String) {
+        if self.dir.is_none() {
+            self.channels.remove(&key);
+            return;
+        }
+
+        self.cold.push_back(key);
+
+        while self.cold.len() > self.max_cold {
+            if let Some(coldest) = self.cold.pop_front() {
+                self.channels.remove(&coldest);
+            }
+        }
+    }
+
+    /// Takes `addr` out of every channel, returning the names of those it
+    /// was in.
+    pub(crate) fn part_all(&mut self, addr: SocketAddr) -> Vec<String> {
+        let names = self.of(addr);
+
+        for name in &names {
+            self.part(name, addr);
+        }
+
+        names
+    }
+
+    /// The names of the channels `addr` is in.
+    pub(crate) fn of(&self, addr: SocketAddr) -> Vec<String> {
+        self.channels
+            .values()
+            .filter(|channel| channel.members.contains_key(&addr))
+            .map(|channel| channel.name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_and_part() {
+        let alice = SocketAddr::from(([127, 0, 0, 1], 1));
+        let bob = SocketAddr::from(([127, 0, 0, 1], 2));
+        let mut channels = Channels::default();
+
+        assert!(channels.join("#Rust", alice).is_some());
+        assert!(channels.join("#rust", alice).is_none());
+        let channel = channels.join("#RUST", bob).unwrap();
+        assert_eq!(channel.name, "#Rust");
+        assert_eq!(channel.members.get(&alice), Some(&true));
+        assert_eq!(channel.members.get(&bob), Some(&false));
+
+        channels.join("#go", bob);
+        assert_eq!(channels.part_all(bob).len(), 2);
+        assert!(channels.get("#go").is_none());
+
+        assert_eq!(channels.part("#rust", bob), None);
+        assert_eq!(channels.part("#rust", alice), Some("#Rust".to_owned()));
+        assert!(channels.get("#rust").is_none());
+    }
+
+    #[test]
+    fn test_restores_on_join_and_evicts_cold() {
+        let alice = SocketAddr::from(([127, 0, 0, 1], 1));
+        let dir = std::env::temp_dir().join(format!("solace-channels-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut channels = Channels::in_dir(dir.clone(), 1);
+
+        channels.join("#Rust", alice);
+        let rust = channels.get_mut("#rust").unwrap();
+        rust.topic = "Release day".to_owned();
+        rust.modes.insert(ChannelMode::TopicLocked);
+        channels.save("#rust").unwrap();
+        channels.part("#rust", alice);
+
+        // Kept while cold, until another channel empties out
+        assert!(channels.get("#rust").is_some());
+        channels.join("#go", alice);
+        channels.part("#go", alice);
+        assert!(channels.get("#rust").is_none());
+        assert!(channels.get("#go").is_some());
+
+        // Only read back on joining, by a new server too
+        let mut channels = Channels::in_dir(dir.clone(), 1);
+        assert!(channels.get("#rust").is_none());
+        let rust = channels.join("#RUST", alice).unwrap();
+        assert_eq!(rust.name, "#Rust");
+        assert_eq!(rust.topic, "Release day");
+        assert_eq!(rust.mode_flags(), "+t");
+        assert_eq!(rust.members.get(&alice), Some(&true));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
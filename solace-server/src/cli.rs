@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Chat server for solace.
+///
+/// Everything else is set in server.toml in the XDG config directory.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub(crate) struct Args {
+    /// PEM file holding the certificate chain to present to clients
+    /// connecting over TLS, which plain clients can still connect alongside
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    pub(crate) tls_cert: Option<PathBuf>,
+
+    /// PEM file holding the private key for --tls-cert
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    pub(crate) tls_key: Option<PathBuf>,
+}
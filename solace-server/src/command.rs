@@ -0,0 +1,3380 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use futures::sink::SinkExt;
+use solace_message_parser::Everyone;
+use solace_protocol::attachment;
+use solace_protocol::bookmark::Bookmark;
+use solace_protocol::build_info::BuildInfo;
+use solace_protocol::capability::{BUILD_INFO, EXPERIMENTAL, FRAME_HMAC};
+use solace_protocol::channel::{is_channel_name, ChannelMode, ChannelText};
+use solace_protocol::code::{
+    ERR_ATTACHMENT_TOO_LARGE, ERR_AUTH_REQUIRED, ERR_COMMAND_NOT_FOUND, ERR_INVALID_ARGUMENT,
+    ERR_MESSAGE_TOO_LONG, ERR_NICK_IN_USE, ERR_NICK_NOT_FOUND, ERR_NOT_IN_CHANNEL,
+    ERR_NOT_LOGGED_IN, ERR_NOT_PERMITTED, ERR_RATE_LIMITED, ERR_READ_ONLY, ERR_SESSION_NOT_FOUND,
+    ERR_WRONG_PASSWORD, RES_ATTACHMENT, RES_AUTH_OK, RES_AWAY, RES_BOOKMARKED, RES_BOOKMARK_LIST,
+    RES_BUILD_INFO, RES_CHANNEL_MESSAGE, RES_CHANNEL_MODE, RES_CHANNEL_NOTICE, RES_CHANNEL_TOPIC,
+    RES_CHAT_MESSAGE_OK, RES_COMMAND_LIST, RES_CUSTOM_COMMAND, RES_DEVICE_LIST, RES_FRAME_HMAC,
+    RES_JOINED, RES_KICKED, RES_LOGGED_IN, RES_MESSAGE_SENT, RES_MISSED_MENTIONS, RES_MODE_CHANGE,
+    RES_PARTED, RES_PONG, RES_PRESENCE, RES_QUOTA, RES_READ_RECEIPT, RES_SESSION_REVOKED,
+    RES_STATS, RES_TOPIC_CHANGE, RES_UPLOADED, RES_YOUR_NICK,
+};
+use solace_protocol::codec::Integrity;
+use solace_protocol::level::Level;
+use solace_protocol::request::{RequestMessage, Secret};
+use solace_protocol::response::ResponseBuilder;
+use tracing::{error, info, warn, Span};
+
+use crate::accounts::{Accounts, Verdict};
+use crate::channel::{can_change_level, Permission};
+use crate::{encode_once, format_bytes, respond, Client, Message, MessageClient, Server};
+
+/// A command a client can send.
+///
+/// # Fields
+///
+/// - `usage`: Pushed to clients on connect so that they can complete and
+///   validate it, see `CommandSpec`.
+/// - `help`: What the command does, in a line for `/help`.
+/// - `permission`: Checked before the command is handled, `None` if anyone
+///   may use it.
+/// - `is_experimental`: Still being rolled out, so only advertised to and
+///   accepted from clients which opted into the `EXPERIMENTAL` capability.
+#[derive(Debug)]
+pub(crate) struct Command {
+    pub(crate) usage: &'static str,
+    pub(crate) help: &'static str,
+    pub(crate) permission: Option<Permission>,
+    pub(crate) is_experimental: bool,
+}
+
+impl Command {
+    const fn new(usage: &'static str, help: &'static str) -> Self {
+        Self {
+            usage,
+            help,
+            permission: None,
+            is_experimental: false,
+        }
+    }
+
+    const fn needs(self, permission: Permission) -> Self {
+        Self {
+            permission: Some(permission),
+            ..self
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        self.usage.split(' ').next().unwrap_or_default()
+    }
+}
+
+pub(crate) const COMMANDS: &[Command] = &[
+    Command::new("ping", "Checks that the server is still there"),
+    Command::new("nick <nick>", "Changes your nick"),
+    Command::new("topic <topic...>", "Sets the channel topic").needs(Permission::Topic),
+    Command::new("whois <nick>", "Shows where a nick is connected from"),
+    Command::new(
+        "away [reason...]",
+        "Marks you as away, or back without a reason",
+    ),
+    Command::new("msg <nick> <message...>", "Sends a direct message"),
+    Command::new(
+        "login <account>",
+        "Logs into an account, claiming it if you give a password the first time",
+    ),
+    Command::new(
+        "register [password]",
+        "Protects your nick with a password, making it an account",
+    ),
+    Command::new(
+        "auth [password]",
+        "Takes a registered nick you were refused, or logs into your own",
+    ),
+    Command::new("devices", "Lists the sessions logged into your account"),
+    Command::new("revoke <session>", "Disconnects another of your sessions"),
+    Command::new("quota", "Shows how much of today's traffic you've used"),
+    Command::new("dnd", "Keeps mentions and direct messages quiet"),
+    Command::new("online", "Clears away and do not disturb"),
+    Command::new(
+        "ghost <nick> [password]",
+        "Takes your nick back from whoever is using it",
+    ),
+    Command::new(
+        "mode <nick> <mode>",
+        "Grants or takes away op with +o/-o, voice with +v/-v",
+    )
+    .needs(Permission::Mode),
+    Command::new(
+        "kick <nick> [reason...]",
+        "Disconnects a nick below your level",
+    )
+    .needs(Permission::Kick),
+    Command::new("karma [nick]", "Shows how much karma a nick++ has given"),
+    Command::new(
+        "stats [nick]",
+        "Shows when a nick was first seen and how much it has said, or to ops how the server is doing",
+    ),
+    Command::new(
+        "bookmark <id>",
+        "Keeps the message with this id for your account",
+    ),
+    Command::new("bookmarks", "Lists the messages you've kept"),
+    Command::new(
+        "upload <path...>",
+        "Uploads a file for others to download by its id",
+    )
+    .needs(Permission::Upload),
+    Command::new(
+        "download <id> [path...]",
+        "Saves an uploaded file, by default to its name",
+    ),
+    Command::new(
+        "join <channel>",
+        "Joins a channel, creating it if nobody is in it",
+    ),
+    Command::new("part <channel>", "Leaves a channel"),
+    Command::new(
+        "say <channel> <message...>",
+        "Sends a message to a channel you're in",
+    ),
+    Command::new(
+        "chantopic <channel> <topic...>",
+        "Sets the topic of a channel you're in",
+    ),
+    Command::new(
+        "chanmode <channel> <mode>",
+        "Turns moderation on or off with +m/-m, the topic lock with +t/-t, references from other channels with +r/-r, or announcements only with +a/-a",
+    ),
+    Command::new("disconnect", "Leaves the server"),
+];
+
+pub(crate) fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name() == name)
+}
+
+/// What the client loop does once a request has been handled.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Flow {
+    Continue,
+    Disconnect,
+}
+
+/// Checks that `client` may send `message` and handles it.
+///
+/// Every request is acked before it gets here, and the sender's client
+/// already shows the command it sent, so the sender is only told what it
+/// needs to update its own state, such as `RES_YOUR_NICK`. Announcements of
+/// a change in words, such as who set the topic, go to everyone else.
+pub(crate) async fn dispatch(
+    server: &Server,
+    client: &mut Client,
+    request_id: u32,
+    message: RequestMessage,
+) -> anyhow::Result<Flow> {
+    if let Some(command) = message.command_name().and_then(find) {
+        if command.is_experimental && !client.has_capability(EXPERIMENTAL) {
+            let name = command.name();
+            respond!(
+                client,
+                ERR_COMMAND_NOT_FOUND,
+                format!("Unknown command: /{name}")
+            );
+            return Ok(Flow::Continue);
+        }
+
+        if let Some(permission) = command.permission {
+            let required = server.config.channel.permissions.required(permission);
+
+            if server.level_of(client.addr) < required {
+                respond!(
+                    client,
+                    ERR_NOT_PERMITTED,
+                    format!(
+                        "Only {}s and above can {}",
+                        required.name(),
+                        permission.action()
+                    )
+                );
+                return Ok(Flow::Continue);
+            }
+        }
+    }
+
+    match message {
+        RequestMessage::Ping => ping(client).await?,
+        RequestMessage::Message(message) => {
+            chat_message(server, client, request_id, message).await?;
+        }
+        RequestMessage::NewTopic(topic) => topic_command(server, client, &topic).await?,
+        RequestMessage::NewNick(nick) => nick_command(server, client, &nick).await?,
+        RequestMessage::Away(reason) => away(server, client, reason).await?,
+        RequestMessage::DoNotDisturb => presence(server, client, true).await?,
+        RequestMessage::Online => presence(server, client, false).await?,
+        RequestMessage::Capabilities(capabilities) => {
+            info!("Client opted into: {capabilities:?}");
+
+            client.capabilities = capabilities;
+            respond!(client, RES_COMMAND_LIST, client.command_list(server));
+
+            if client.capabilities.iter().any(|c| c == BUILD_INFO) {
+                respond!(client, RES_BUILD_INFO, crate::build_info().encode());
+            }
+
+            if client.capabilities.iter().any(|c| c == FRAME_HMAC) {
+                frame_hmac(client).await?;
+            }
+        }
+        RequestMessage::MarkRead(message_id) => mark_read(server, client, message_id),
+        RequestMessage::BuildInfo(encoded) => match BuildInfo::decode(&encoded) {
+            Some(info) => info!("Client is running {info}"),
+            None => warn!("Client sent build info that couldn't be read: {encoded:?}"),
+        },
+        RequestMessage::Login { account, password } => {
+            login(server, client, &account, password).await?;
+        }
+        RequestMessage::Ghost { nick, password } => ghost(server, client, &nick, password).await?,
+        RequestMessage::Register(password) => register(server, client, password).await?,
+        RequestMessage::Auth(password) => auth(server, client, password).await?,
+        RequestMessage::Mode { nick, level } => mode(server, client, &nick, level).await?,
+        RequestMessage::Kick { nick, reason } => kick(server, client, &nick, reason).await?,
+        RequestMessage::DirectMessage { to, message } => {
+            direct_message(server, client, to, message).await?;
+        }
+        RequestMessage::Devices => devices(server, client).await?,
+        RequestMessage::Revoke(session_id) => revoke(server, client, session_id).await?,
+        RequestMessage::Quota => quota(server, client).await?,
+        RequestMessage::WhoIs(target) => whois(server, client, target).await,
+        RequestMessage::Custom { name, args } => custom(server, client, &name, &args).await?,
+        RequestMessage::Karma(nick) => karma(server, client, nick).await?,
+        RequestMessage::Stats(nick) => stats(server, client, &nick).await?,
+        RequestMessage::Bookmark(message_id) => bookmark(server, client, message_id).await?,
+        RequestMessage::Bookmarks => bookmarks(server, client).await?,
+        RequestMessage::Upload { name, data } => {
+            upload(server, client, request_id, &name, &data).await?;
+        }
+        RequestMessage::Download(id) => download(server, client, request_id, &id).await?,
+        RequestMessage::Join(channel) => join(server, client, channel.trim()).await?,
+        RequestMessage::Part(channel) => part(server, client, channel.trim()).await?,
+        RequestMessage::ChannelMessage { channel, message } => {
+            channel_message(server, client, request_id, channel.trim(), message).await?;
+        }
+        RequestMessage::ChannelTopic { channel, topic } => {
+            channel_topic(server, client, channel.trim(), topic.trim()).await?;
+        }
+        RequestMessage::ChannelMode {
+            channel,
+            mode,
+            is_enabled,
+        } => channel_mode(server, client, channel.trim(), mode, is_enabled).await?,
+        RequestMessage::Disconnect => {
+            // @TODO: Respond with message on disconnect?
+            server.remove_client(client.addr).await;
+            return Ok(Flow::Disconnect);
+        }
+    }
+
+    Ok(Flow::Continue)
+}
+
+/// Signs frames both ways from here on, if the transport gave us a key to
+/// sign them with.
+async fn frame_hmac(client: &mut Client) -> anyhow::Result<()> {
+    let Some(key) = client.session_key.clone() else {
+        warn!("Client asked for signed frames over a transport without a session key");
+        return Ok(());
+    };
+
+    client.req.decoder_mut().expect_hmac(key.clone());
+    client.res.encoder_mut().set_integrity(Integrity::Hmac(key));
+    respond!(client, RES_FRAME_HMAC, String::new());
+
+    Ok(())
+}
+
+async fn ping(client: &mut Client) -> anyhow::Result<()> {
+    respond!(client, RES_PONG, "Pong".to_owned());
+
+    Ok(())
+}
+
+async fn chat_message(
+    server: &Server,
+    client: &mut Client,
+    request_id: u32,
+    message: String,
+) -> anyhow::Result<()> {
+    let max_chars = server.config.channel.max_message_chars;
+
+    if max_chars > 0 && message.chars().count() > max_chars {
+        client
+            .res
+            .feed(
+                ResponseBuilder::new(
+                    ERR_MESSAGE_TOO_LONG,
+                    format!("Messages can't be longer than {max_chars} characters"),
+                )
+                .with_request_id(request_id)
+                .build(),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let max_mentions = server.config.channel.max_mentions;
+    let ast =
+        solace_message_parser::parse_with_options(&message, server.config.channel.parser_options());
+    let everyone = ast.everyone();
+    let is_over_max = max_mentions > 0 && ast.mention_count() > max_mentions;
+    let mentioned = server.resolve_mentions(ast.mentions().map(|mention| mention.name));
+
+    if everyone.is_some() || is_over_max {
+        let required = server
+            .config
+            .channel
+            .permissions
+            .required(Permission::MassMention);
+
+        if server.level_of(client.addr) < required {
+            let what = match everyone {
+                Some(everyone) => format!("use @{}", everyone.name()),
+                None => format!("mention more than {max_mentions} nicks at once"),
+            };
+
+            respond!(
+                client,
+                ERR_NOT_PERMITTED,
+                format!("Only {}s and above can {what}", required.name())
+            );
+            return Ok(());
+        }
+
+        // The client sends it again once the wait is over
+        if let Some(wait) = server.hold_mass_mention() {
+            client
+                .res
+                .feed(
+                    ResponseBuilder::new(ERR_RATE_LIMITED, wait.as_millis().to_string())
+                        .with_request_id(request_id)
+                        .build(),
+                )
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let nick = server.nick(&client.nick);
+    let granted = server.stats().record_message(&nick, &message);
+
+    for to in granted {
+        let karma = server.stats().get(&to).map_or(0, |stats| stats.karma);
+        respond!(client, RES_STATS, format!("{to} has {karma} karma"));
+    }
+
+    let response = ResponseBuilder::new(RES_CHAT_MESSAGE_OK, message.clone())
+        .with_origin(server.nick(&client.nick).to_string())
+        .from_bot(server.is_bot(client.account.as_deref()));
+
+    let message_id = server
+        .broadcast_chat_message(client.message_client(), message.clone(), response)
+        .await;
+    server
+        .receipts()
+        .record(message_id, client.message_client());
+
+    #[cfg(feature = "previews")]
+    server
+        .previews
+        .queue(&server.config.previews, message_id, &message);
+
+    client
+        .res
+        .feed(
+            ResponseBuilder::new(RES_MESSAGE_SENT, String::new())
+                .with_request_id(request_id)
+                .with_message_id(message_id)
+                .build(),
+        )
+        .await?;
+
+    server.notify_mentioned(&client.message_client(), &mentioned, message_id);
+    server.log_missed_mentions(
+        &client.message_client(),
+        ast.mentions().map(|mention| mention.name),
+        message_id,
+        &message,
+    );
+    if let Some(everyone) = everyone {
+        server.notify_everyone(&client.message_client(), everyone);
+    }
+    channel_references(
+        server,
+        client,
+        "the main chat",
+        ast.channels().map(|mention| mention.name),
+    );
+    auto_respond(server, client, None, &message).await;
+
+    Ok(())
+}
+
+/// Tells the senders of the chat messages `client` hadn't read yet, up to
+/// `message_id`, everyone who has read them now.
+fn mark_read(server: &Server, client: &Client, message_id: u64) {
+    let nick = server.nick(&client.nick);
+    let read = server
+        .receipts()
+        .mark_read(&client.message_client(), &nick, message_id);
+
+    for (from, message_id, readers) in read {
+        let receipt = ResponseBuilder::new(RES_READ_RECEIPT, readers.join("\n"))
+            .with_message_id(message_id)
+            .build();
+        let receipt = Arc::new(Message::Frame(encode_once(receipt)));
+
+        server.clients.for_each(|addr, conn| {
+            let is_sender =
+                *addr == from.addr || (from.account.is_some() && conn.account == from.account);
+
+            if is_sender {
+                server.deliver(conn, Arc::clone(&receipt));
+            }
+        });
+    }
+}
+
+async fn topic_command(server: &Server, client: &mut Client, topic: &str) -> anyhow::Result<()> {
+    let trimmed = topic.trim();
+
+    info!("Topic was changed to: {trimmed}");
+    server.set_topic(trimmed, || {
+        server.broadcast_others_now(
+            Message::TopicChanged {
+                from: client.message_client(),
+                topic: trimmed.to_owned(),
+            },
+            client.addr,
+        );
+    });
+
+    respond!(client, RES_TOPIC_CHANGE, trimmed.to_owned());
+
+    Ok(())
+}
+
+async fn nick_command(server: &Server, client: &mut Client, nick: &str) -> anyhow::Result<()> {
+    let addr = client.addr;
+
+    if let Some(account) = server.clients.with(&addr, |c| c.account.clone()).flatten() {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            format!("Your nick is tied to the account {account}")
+        );
+        return Ok(());
+    }
+
+    // Taking one of these would make every mention of everyone look like one
+    // of that nick instead
+    if let Some(everyone) = Everyone::from_name(nick.trim()) {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            format!("@{} mentions everyone, so can't be a nick", everyone.name())
+        );
+        return Ok(());
+    }
+
+    // Registered nicks are only for whoever has the password
+    let claimant = server.accounts().claimant(nick.trim());
+    if let Some(account) = claimant {
+        respond!(
+            client,
+            ERR_AUTH_REQUIRED,
+            format!("{account} is registered")
+        );
+        client.wants_nick = Some(account);
+        return Ok(());
+    }
+
+    let was = client.nick.clone();
+    let new_nick = server.nicks.intern(nick.trim());
+
+    // Checked and taken under one lock so that two clients can't both take
+    // it. Case is ignored as it is for mentions, which couldn't tell `Bob`
+    // and `bob` apart.
+    let is_taken = {
+        let _announcing = server.announcing();
+        let is_taken = {
+            let mut clients = server.clients.lock_all();
+            let wanted = nick.trim().to_lowercase();
+            let is_taken = clients
+                .iter()
+                .any(|(a, c)| *a != addr && server.nick(&c.nick).to_lowercase() == wanted);
+
+            if !is_taken {
+                if let Some(conn) = clients.get_mut(&addr) {
+                    conn.nick = new_nick.clone();
+                }
+            }
+
+            is_taken
+        };
+
+        if !is_taken {
+            server.broadcast_others_now(
+                Message::NickChanged {
+                    from: MessageClient {
+                        nick: was,
+                        ..client.message_client()
+                    },
+                    new_nick: new_nick.clone(),
+                },
+                addr,
+            );
+            server.broadcast_nick_list_now();
+            server.broadcast_channels_of(addr);
+        }
+
+        is_taken
+    };
+
+    if is_taken {
+        respond!(
+            client,
+            ERR_NICK_IN_USE,
+            format!("{} is in use", nick.trim())
+        );
+        return Ok(());
+    }
+
+    server.stats().seen(nick.trim());
+    client.nick = new_nick.clone();
+    Span::current().record("nick", &*server.nick(&new_nick));
+
+    respond!(client, RES_YOUR_NICK, server.nick(&new_nick).to_string());
+
+    Ok(())
+}
+
+async fn away(server: &Server, client: &mut Client, reason: Option<String>) -> anyhow::Result<()> {
+    let reason = reason
+        .map(|r| r.trim().to_owned())
+        .filter(|r| !r.is_empty());
+
+    let message = match &reason {
+        Some(reason) => format!("You are now marked as away: {reason}"),
+        None => "You are no longer marked as away".to_owned(),
+    };
+
+    let is_back = reason.is_none();
+    let presence = {
+        let _announcing = server.announcing();
+        let presence = server.clients.with_mut(&client.addr, |conn| {
+            conn.away = reason;
+            conn.is_dnd = false;
+            conn.presence()
+        });
+        server.broadcast_nick_list_now();
+
+        presence
+    };
+
+    respond!(client, RES_AWAY, message);
+    if let Some(presence) = presence {
+        respond!(client, RES_PRESENCE, presence.name().to_owned());
+    }
+
+    if is_back {
+        missed_mentions(server, client).await?;
+    }
+
+    Ok(())
+}
+
+/// Handles both `/dnd` and `/online`, either of which clears being away.
+async fn presence(server: &Server, client: &mut Client, is_dnd: bool) -> anyhow::Result<()> {
+    let presence = {
+        let _announcing = server.announcing();
+        let presence = server.clients.with_mut(&client.addr, |conn| {
+            conn.away = None;
+            conn.is_dnd = is_dnd;
+            conn.presence()
+        });
+        server.broadcast_nick_list_now();
+
+        presence
+    };
+
+    if let Some(presence) = presence {
+        respond!(client, RES_PRESENCE, presence.name().to_owned());
+    }
+    missed_mentions(server, client).await?;
+
+    Ok(())
+}
+
+/// Tells `client`, which is back, about any mentions of its nick it
+/// missed.
+async fn missed_mentions(server: &Server, client: &mut Client) -> anyhow::Result<()> {
+    let missed = server.away_log().take(&server.nick(&client.nick));
+
+    if !missed.is_empty() {
+        respond!(client, RES_MISSED_MENTIONS, Bookmark::encode_list(&missed));
+    }
+
+    Ok(())
+}
+
+async fn login(
+    server: &Server,
+    client: &mut Client,
+    account: &str,
+    password: Option<Secret>,
+) -> anyhow::Result<()> {
+    let account = Accounts::key(account);
+    let password = password.as_ref().map(|password| password.0.as_str());
+
+    match verify(server, &account, password).await? {
+        Verdict::Wrong => {
+            respond!(
+                client,
+                ERR_WRONG_PASSWORD,
+                format!("Wrong password for {account}")
+            );
+        }
+        Verdict::Correct => {
+            log_in(server, client, &account).await?;
+        }
+        Verdict::Unclaimed => {
+            let is_logged_in = log_in(server, client, &account).await?;
+
+            // The first login with a password claims the account
+            if let Some(password) = password.filter(|_| is_logged_in) {
+                let hash = hash_password(server, password).await?;
+
+                // Checked again under the lock it is claimed under, as
+                // another first login may have claimed it while hashing
+                let claimed = server.accounts().claim(&account, hash);
+                match claimed {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        log_out(server, client);
+                        respond!(
+                            client,
+                            ERR_WRONG_PASSWORD,
+                            format!("{account} was claimed with another password meanwhile")
+                        );
+                    }
+                    Err(err) => error!("{err}"),
+                }
+                server.guard_nick(&account);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks `password` for `account` without holding up the accounts, or the
+/// worker, while it is hashed.
+async fn verify(server: &Server, account: &str, password: Option<&str>) -> anyhow::Result<Verdict> {
+    let check = server.accounts().check(account);
+    let password = password.map(str::to_owned);
+
+    Ok(tokio::task::spawn_blocking(move || check.verdict(password.as_deref())).await?)
+}
+
+/// Hashes a new `password` without holding up the accounts, or the worker.
+async fn hash_password(server: &Server, password: &str) -> anyhow::Result<String> {
+    let hasher = server.accounts().hasher();
+    let password = password.to_owned();
+
+    tokio::task::spawn_blocking(move || hasher.hash(&password)).await?
+}
+
+/// Takes back a login which turned out not to be allowed, leaving the nick
+/// to `guard_nick`.
+fn log_out(server: &Server, client: &mut Client) {
+    client.account = None;
+    server
+        .clients
+        .with_mut(&client.addr, |conn| conn.account = None);
+}
+
+/// Claims the nick in use as an account with `password`, logging into it.
+async fn register(server: &Server, client: &mut Client, password: Secret) -> anyhow::Result<()> {
+    let nick = Accounts::key(&server.nick(&client.nick));
+
+    if password.0.is_empty() {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            "Registering needs a password".to_owned()
+        );
+        return Ok(());
+    }
+
+    // Hashed first, as `claim` checks and claims under one lock so that two
+    // registrations of the same nick can't both find it unclaimed
+    let hash = hash_password(server, &password.0).await?;
+    let claimed = match server.accounts().claim(&nick, hash) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("{nick} is already registered")),
+        Err(err) => {
+            error!("{err}");
+            Err(format!("Couldn't register {nick}"))
+        }
+    };
+
+    if let Err(message) = claimed {
+        respond!(client, ERR_INVALID_ARGUMENT, message);
+        return Ok(());
+    }
+
+    if log_in(server, client, &nick).await? {
+        server.guard_nick(&nick);
+        respond!(client, RES_AUTH_OK, format!("Registered {nick}"));
+    }
+
+    Ok(())
+}
+
+/// Logs into the registered nick last refused to `client`, or else the one
+/// it is using, with `password`.
+async fn auth(server: &Server, client: &mut Client, password: Secret) -> anyhow::Result<()> {
+    let nick = client
+        .wants_nick
+        .take()
+        .unwrap_or_else(|| server.nick(&client.nick).to_string());
+    let claimant = server.accounts().claimant(&nick);
+
+    let Some(account) = claimant else {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            format!("{nick} isn't registered, /register <password> to protect it")
+        );
+        return Ok(());
+    };
+
+    let verdict = verify(server, &account, Some(&password.0)).await?;
+    if verdict != Verdict::Correct {
+        respond!(
+            client,
+            ERR_WRONG_PASSWORD,
+            format!("Wrong password for {account}")
+        );
+        client.wants_nick = Some(account);
+        return Ok(());
+    }
+
+    if log_in(server, client, &account).await? {
+        respond!(client, RES_AUTH_OK, format!("Authenticated as {account}"));
+    }
+
+    Ok(())
+}
+
+async fn ghost(
+    server: &Server,
+    client: &mut Client,
+    target: &str,
+    password: Secret,
+) -> anyhow::Result<()> {
+    let target = &Accounts::key(target);
+
+    match verify(server, target, Some(&password.0)).await? {
+        Verdict::Unclaimed => {
+            respond!(
+                client,
+                ERR_INVALID_ARGUMENT,
+                format!("Nobody has claimed the account {target} with a password")
+            );
+            return Ok(());
+        }
+        Verdict::Wrong => {
+            respond!(
+                client,
+                ERR_WRONG_PASSWORD,
+                format!("Wrong password for {target}")
+            );
+            return Ok(());
+        }
+        Verdict::Correct => (),
+    }
+
+    // Every other session using the nick goes, including those of the
+    // account itself left behind by a crash
+    let mut ghosts = vec![];
+    if let Some(nick) = server.nicks.get(target) {
+        server.clients.for_each(|a, c| {
+            if *a != client.addr && c.nick == nick {
+                ghosts.push((*a, c.tx.clone()));
+            }
+        });
+    }
+
+    for (ghost, tx) in ghosts {
+        server.remove_client(ghost).await;
+        let _ = tx.send(Arc::new(Message::Ghosted));
+    }
+
+    log_in(server, client, target).await?;
+
+    Ok(())
+}
+
+async fn mode(
+    server: &Server,
+    client: &mut Client,
+    target: &str,
+    level: Level,
+) -> anyhow::Result<()> {
+    let found = server.nicks.get(target).and_then(|nick| {
+        let level = server.level_of_nick(&nick)?;
+        Some((nick, level))
+    });
+    let Some((nick, current)) = found else {
+        respond!(
+            client,
+            ERR_NICK_NOT_FOUND,
+            format!("User {target} not found")
+        );
+        return Ok(());
+    };
+
+    if !can_change_level(server.level_of(client.addr), current, level) {
+        respond!(
+            client,
+            ERR_NOT_PERMITTED,
+            "You can only change levels below your own".to_owned()
+        );
+        return Ok(());
+    }
+
+    let account = server
+        .clients
+        .find_map(|_, c| (c.nick == nick).then(|| c.account.clone()).flatten());
+    if let Some(account) = account {
+        if let Err(err) = server.levels().set(&account, level) {
+            error!("{err}");
+        }
+    }
+
+    let _announcing = server.announcing();
+    let mut sessions = vec![];
+    server.clients.for_each(|a, c| {
+        if c.nick == nick {
+            sessions.push(*a);
+        }
+    });
+    for session in sessions {
+        server.clients.with_mut(&session, |c| c.level = level);
+    }
+
+    let message = format!(
+        "{} made {target} {}",
+        server.nick(&client.nick),
+        level.name()
+    );
+    server.broadcast_others_now(
+        Message::Frame(encode_once(
+            ResponseBuilder::new(RES_MODE_CHANGE, message).build(),
+        )),
+        client.addr,
+    );
+    server.broadcast_nick_list_now();
+
+    Ok(())
+}
+
+async fn kick(
+    server: &Server,
+    client: &mut Client,
+    target: &str,
+    reason: Option<String>,
+) -> anyhow::Result<()> {
+    let found = server.nicks.get(target).and_then(|nick| {
+        let level = server.level_of_nick(&nick)?;
+        Some((nick, level))
+    });
+    let Some((nick, current)) = found else {
+        respond!(
+            client,
+            ERR_NICK_NOT_FOUND,
+            format!("User {target} not found")
+        );
+        return Ok(());
+    };
+
+    if current >= server.level_of(client.addr) {
+        respond!(
+            client,
+            ERR_NOT_PERMITTED,
+            "You can only kick nicks below your own level".to_owned()
+        );
+        return Ok(());
+    }
+
+    let mut kicked = vec![];
+    server.clients.for_each(|a, c| {
+        if c.nick == nick {
+            kicked.push((*a, c.tx.clone()));
+        }
+    });
+
+    // Told before they go so that nobody misses why
+    let by = server.nick(&client.nick);
+    let reason = reason
+        .map(|r| r.trim().to_owned())
+        .filter(|r| !r.is_empty());
+    let message = match &reason {
+        Some(reason) => format!("{target} was kicked by {by}: {reason}"),
+        None => format!("{target} was kicked by {by}"),
+    };
+    server
+        .broadcast_others(
+            Message::Frame(encode_once(
+                ResponseBuilder::new(RES_KICKED, message).build(),
+            )),
+            client.addr,
+        )
+        .await;
+
+    for (session, tx) in kicked {
+        server.remove_client(session).await;
+        let _ = tx.send(Arc::new(Message::Kicked {
+            by: client.nick.clone(),
+            reason: reason.clone(),
+        }));
+    }
+
+    Ok(())
+}
+
+async fn direct_message(
+    server: &Server,
+    client: &mut Client,
+    to: String,
+    message: String,
+) -> anyhow::Result<()> {
+    let from = client.message_client();
+
+    let delivered = match server.nicks.get(&to) {
+        Some(nick) => {
+            server
+                .broadcast_nick(
+                    Message::Direct {
+                        from,
+                        message: message.clone(),
+                    },
+                    &nick,
+                )
+                .await
+        }
+        None => false,
+    };
+
+    if !delivered {
+        respond!(client, ERR_NICK_NOT_FOUND, format!("User {to} not found"));
+        return Ok(());
+    }
+
+    if let Some(account) = &client.account {
+        server
+            .broadcast_account_others(Message::DirectSynced { to, message }, account, client.addr)
+            .await;
+    }
+
+    Ok(())
+}
+
+async fn devices(server: &Server, client: &mut Client) -> anyhow::Result<()> {
+    let addr = client.addr;
+    let Some(account) = server.clients.with(&addr, |c| c.account.clone()).flatten() else {
+        respond!(
+            client,
+            ERR_NOT_LOGGED_IN,
+            "Log in with /login to see your devices".to_owned()
+        );
+        return Ok(());
+    };
+
+    let mut sessions = server.sessions_of(&account, |a, c| (c.session_id, *a, c.connected_at));
+    sessions.sort();
+
+    for (session_id, session_addr, connected_at) in sessions {
+        let this_device = if session_addr == addr {
+            " (this device)"
+        } else {
+            ""
+        };
+        let connected_at = chrono::DateTime::from_timestamp(connected_at as i64, 0)
+            .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default();
+
+        respond!(
+            client,
+            RES_DEVICE_LIST,
+            format!("#{session_id} {session_addr} connected {connected_at}{this_device}")
+        );
+    }
+
+    Ok(())
+}
+
+async fn revoke(server: &Server, client: &mut Client, session_id: u32) -> anyhow::Result<()> {
+    let account = server
+        .clients
+        .with(&client.addr, |c| c.account.clone())
+        .flatten();
+    let target = account.as_deref().and_then(|account| {
+        server.clients.find_map(|_, c| {
+            let is_target = c.account.as_deref() == Some(account) && c.session_id == session_id;
+            is_target.then(|| c.tx.clone())
+        })
+    });
+
+    match (&account, target) {
+        (None, _) => {
+            respond!(
+                client,
+                ERR_NOT_LOGGED_IN,
+                "Log in with /login to manage your devices".to_owned()
+            );
+        }
+        (Some(_), Some(tx)) => {
+            let _ = tx.send(Arc::new(Message::Revoked));
+            respond!(
+                client,
+                RES_SESSION_REVOKED,
+                format!("Revoked session #{session_id}")
+            );
+        }
+        (Some(_), None) => {
+            respond!(
+                client,
+                ERR_SESSION_NOT_FOUND,
+                format!("No session #{session_id} on your account")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn quota(server: &Server, client: &mut Client) -> anyhow::Result<()> {
+    let usage = server.usage_of(client);
+    let limit = match server.quota_for(client) {
+        Some(quota) => format_bytes(quota),
+        None => "unlimited".to_owned(),
+    };
+
+    respond!(
+        client,
+        RES_QUOTA,
+        format!(
+            "Used {} of {limit} today ({} in, {} out)",
+            format_bytes(usage.total()),
+            format_bytes(usage.bytes_in),
+            format_bytes(usage.bytes_out)
+        )
+    );
+
+    Ok(())
+}
+
+async fn whois(server: &Server, client: &mut Client, target: String) {
+    let maybe_addr = server
+        .nicks
+        .get(&target)
+        .and_then(|nick| server.get_by_nick(&nick));
+
+    server
+        .broadcast_to(
+            Message::WhoIs {
+                addr: maybe_addr,
+                nick: target,
+            },
+            client.addr,
+        )
+        .await;
+}
+
+async fn karma(server: &Server, client: &mut Client, nick: Option<String>) -> anyhow::Result<()> {
+    let nick = match nick {
+        Some(nick) => nick.trim().to_owned(),
+        None => server.nick(&client.nick).to_string(),
+    };
+    let karma = server.stats().get(&nick).map_or(0, |stats| stats.karma);
+
+    respond!(client, RES_STATS, format!("{nick} has {karma} karma"));
+
+    Ok(())
+}
+
+async fn stats(server: &Server, client: &mut Client, nick: &str) -> anyhow::Result<()> {
+    let nick = nick.trim();
+
+    // Without a nick, the server's own health, which is only for ops as it
+    // says how busy the server is
+    if nick.is_empty() {
+        if server.level_of(client.addr) < Level::Op {
+            respond!(
+                client,
+                ERR_NOT_PERMITTED,
+                "Only ops and above can see the server's stats".to_owned()
+            );
+            return Ok(());
+        }
+
+        respond!(client, RES_STATS, server.delivery.summary());
+        return Ok(());
+    }
+
+    let stats = server.stats().get(nick).cloned();
+
+    let Some(stats) = stats else {
+        respond!(client, ERR_NICK_NOT_FOUND, format!("No stats for {nick}"));
+        return Ok(());
+    };
+
+    let first_seen = chrono::DateTime::from_timestamp(stats.first_seen as i64, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default();
+
+    respond!(
+        client,
+        RES_STATS,
+        format!(
+            "{nick} was first seen {first_seen}, has sent {} messages and has {} karma",
+            stats.messages, stats.karma
+        )
+    );
+
+    Ok(())
+}
+
+async fn bookmark(server: &Server, client: &mut Client, message_id: u64) -> anyhow::Result<()> {
+    let Some(account) = client.account.clone() else {
+        respond!(
+            client,
+            ERR_NOT_LOGGED_IN,
+            "Log in with /login to keep bookmarks".to_owned()
+        );
+        return Ok(());
+    };
+
+    let kept = server
+        .backlog
+        .lock()
+        .expect("ERROR: Backlog lock poisoned")
+        .get(message_id)
+        .cloned();
+
+    let Some(bookmark) = kept else {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            format!("No message #{message_id}, it may be too old to bookmark")
+        );
+        return Ok(());
+    };
+
+    let nick = bookmark.nick.clone();
+    let is_new = server.bookmarks().add(&account, bookmark);
+
+    match is_new {
+        Ok(true) => {
+            respond!(
+                client,
+                RES_BOOKMARKED,
+                format!("Bookmarked #{message_id} from {nick}")
+            );
+        }
+        Ok(false) => {
+            respond!(
+                client,
+                RES_BOOKMARKED,
+                format!("#{message_id} is already bookmarked")
+            );
+        }
+        Err(err) => error!("{err:#}"),
+    }
+
+    Ok(())
+}
+
+async fn bookmarks(server: &Server, client: &mut Client) -> anyhow::Result<()> {
+    let Some(account) = client.account.clone() else {
+        respond!(
+            client,
+            ERR_NOT_LOGGED_IN,
+            "Log in with /login to see your bookmarks".to_owned()
+        );
+        return Ok(());
+    };
+
+    let list = Bookmark::encode_list(server.bookmarks().of(&account));
+    respond!(client, RES_BOOKMARK_LIST, list);
+
+    Ok(())
+}
+
+async fn upload(
+    server: &Server,
+    client: &mut Client,
+    request_id: u32,
+    name: &str,
+    data: &str,
+) -> anyhow::Result<()> {
+    let max_bytes = server.config.attachments.max_bytes;
+
+    let Some(data) = attachment::decode_data(data) else {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            "Uploads must be hex encoded".to_owned()
+        );
+        return Ok(());
+    };
+
+    if max_bytes == 0 || data.len() as u64 > max_bytes {
+        let reason = if max_bytes == 0 {
+            "Uploads are turned off on this server".to_owned()
+        } else {
+            format!(
+                "Attachments can't be larger than {}",
+                format_bytes(max_bytes)
+            )
+        };
+
+        client
+            .res
+            .feed(
+                ResponseBuilder::new(ERR_ATTACHMENT_TOO_LARGE, reason)
+                    .with_request_id(request_id)
+                    .build(),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    // Only the name itself, as a path could mean something to whoever
+    // downloads it, without the tabs which separate an attachment's parts
+    let name = std::path::Path::new(name)
+        .file_name()
+        .map(|name| name.to_string_lossy().replace(char::is_control, " "))
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| "attachment".to_owned());
+    let nick = server.nick(&client.nick).to_string();
+    let stored = server
+        .attachments()
+        .store(&name, &data, &nick, &server.config.attachments);
+
+    match stored {
+        Ok(id) => {
+            info!(
+                "Client uploaded {name:?} ({}) as {id}",
+                format_bytes(data.len() as u64)
+            );
+
+            client
+                .res
+                .feed(
+                    ResponseBuilder::new(RES_UPLOADED, id)
+                        .with_request_id(request_id)
+                        .build(),
+                )
+                .await?;
+        }
+        Err(err) => error!("{err:#}"),
+    }
+
+    Ok(())
+}
+
+async fn download(
+    server: &Server,
+    client: &mut Client,
+    request_id: u32,
+    id: &str,
+) -> anyhow::Result<()> {
+    let found = server.attachments().get(id);
+
+    match found {
+        Ok(Some(attachment)) => {
+            client
+                .res
+                .feed(
+                    ResponseBuilder::new(RES_ATTACHMENT, attachment.encode())
+                        .with_request_id(request_id)
+                        .build(),
+                )
+                .await?;
+        }
+        Ok(None) => {
+            respond!(
+                client,
+                ERR_INVALID_ARGUMENT,
+                format!("No attachment {id}, it may have expired")
+            );
+        }
+        Err(err) => error!("{err:#}"),
+    }
+
+    Ok(())
+}
+
+/// Tells everyone else in `channel` what `client` did there, in words.
+fn channel_notice(server: &Server, client: &Client, channel: &str, text: String) {
+    let notice = ChannelText::new(channel, text);
+    let notice = ResponseBuilder::new(RES_CHANNEL_NOTICE, notice.encode()).build();
+
+    server.broadcast_channel(
+        channel,
+        Message::Frame(encode_once(notice)),
+        Some(client.addr),
+    );
+}
+
+/// Answers a message `client` sent to `channel`, or the main chat if
+/// `None`, if it sets off one of the auto-responder's triggers. Bots are
+/// never answered so that two of them can't keep each other going.
+async fn auto_respond(server: &Server, client: &mut Client, channel: Option<&str>, message: &str) {
+    if server.is_bot(client.account.as_deref()) {
+        return;
+    }
+
+    let nick = server.nick(&client.nick).to_string();
+    let fired = server
+        .triggers()
+        .fire(channel, &nick, message, Instant::now());
+    let Some(fired) = fired else {
+        return;
+    };
+
+    #[cfg(feature = "webhooks")]
+    if let Some(url) = fired.webhook {
+        let payload = serde_json::json!({
+            "nick": nick,
+            "channel": channel,
+            "message": message,
+        });
+        crate::triggers::post(url, payload);
+    }
+
+    let Some(reply) = fired.reply.filter(|reply| !reply.is_empty()) else {
+        return;
+    };
+    let from = server.config.auto_responder.nick.clone();
+
+    match channel {
+        Some(channel) => {
+            let text = ChannelText::new(channel, reply).encode();
+            let response = ResponseBuilder::new(RES_CHANNEL_MESSAGE, text)
+                .with_origin(from)
+                .from_bot(true)
+                .build();
+            server.broadcast_channel(channel, Message::Frame(encode_once(response)), None);
+        }
+        None => {
+            let response = ResponseBuilder::new(RES_CHAT_MESSAGE_OK, reply)
+                .with_origin(from)
+                .from_bot(true)
+                .build();
+            server
+                .broadcast_all(Message::Frame(encode_once(response)))
+                .await;
+        }
+    }
+}
+
+/// Points out a message `client` sent to `from` in each other channel it
+/// mentions, if the server has references turned on. Channels with `+r`
+/// are left alone, as are moderated ones which `client` couldn't talk in.
+fn channel_references<'a>(
+    server: &Server,
+    client: &Client,
+    from: &str,
+    mentioned: impl Iterator<Item = &'a str>,
+) {
+    let level = server.level_of(client.addr);
+    let config = &server.config.channel;
+
+    if !config.references || level < config.permissions.required(Permission::Reference) {
+        return;
+    }
+
+    let mut referenced: Vec<String> = vec![];
+    {
+        let channels = server.channels();
+
+        for name in mentioned {
+            let Some(channel) = channels.get(&format!("#{name}")) else {
+                continue;
+            };
+
+            let is_op = level >= Level::Op || channel.members.get(&client.addr) == Some(&true);
+            let is_op_only = channel.modes.contains(&ChannelMode::Moderated)
+                || channel.modes.contains(&ChannelMode::Announcements);
+            let is_refused =
+                channel.modes.contains(&ChannelMode::NoReferences) || (is_op_only && !is_op);
+
+            if is_refused
+                || channel.name.to_lowercase() == from.to_lowercase()
+                || referenced.contains(&channel.name)
+            {
+                continue;
+            }
+
+            referenced.push(channel.name.clone());
+        }
+    }
+
+    let nick = server.nick(&client.nick);
+    for name in referenced {
+        channel_notice(
+            server,
+            client,
+            &name,
+            format!("{nick} referenced this channel in {from}"),
+        );
+    }
+}
+
+/// Whether `client` can run `channel`, for its channel ops and the server's,
+/// or `None` once it has been told that it isn't in the channel.
+async fn channel_role(
+    server: &Server,
+    client: &mut Client,
+    channel: &str,
+) -> anyhow::Result<Option<bool>> {
+    let is_op = server
+        .channels()
+        .get(channel)
+        .and_then(|channel| channel.members.get(&client.addr).copied());
+
+    match is_op {
+        Some(is_op) => Ok(Some(is_op || server.level_of(client.addr) >= Level::Op)),
+        None => {
+            respond!(
+                client,
+                ERR_NOT_IN_CHANNEL,
+                format!("You aren't in {channel}")
+            );
+            Ok(None)
+        }
+    }
+}
+
+async fn join(server: &Server, client: &mut Client, channel: &str) -> anyhow::Result<()> {
+    if !is_channel_name(channel) {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            format!("Channel names start with # and have no spaces or commas, unlike {channel}")
+        );
+        return Ok(());
+    }
+
+    let joined = server.channels().join(channel, client.addr).map(|channel| {
+        (
+            channel.name.clone(),
+            channel.topic.clone(),
+            channel.mode_flags(),
+        )
+    });
+
+    let Some((name, topic, modes)) = joined else {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            format!("You're already in {channel}")
+        );
+        return Ok(());
+    };
+
+    respond!(client, RES_JOINED, ChannelText::new(&name, topic).encode());
+    if !modes.is_empty() {
+        respond!(
+            client,
+            RES_CHANNEL_MODE,
+            ChannelText::new(&name, modes).encode()
+        );
+    }
+
+    let nick = server.nick(&client.nick);
+    channel_notice(server, client, &name, format!("{nick} has joined"));
+    server.broadcast_channel_members(&name);
+
+    Ok(())
+}
+
+async fn part(server: &Server, client: &mut Client, channel: &str) -> anyhow::Result<()> {
+    let parted = server.channels().part(channel, client.addr);
+
+    let Some(name) = parted else {
+        respond!(
+            client,
+            ERR_NOT_IN_CHANNEL,
+            format!("You aren't in {channel}")
+        );
+        return Ok(());
+    };
+
+    respond!(
+        client,
+        RES_PARTED,
+        ChannelText::new(&name, String::new()).encode()
+    );
+
+    let nick = server.nick(&client.nick);
+    channel_notice(server, client, &name, format!("{nick} has left"));
+    server.broadcast_channel_members(&name);
+
+    Ok(())
+}
+
+async fn channel_message(
+    server: &Server,
+    client: &mut Client,
+    request_id: u32,
+    channel: &str,
+    message: String,
+) -> anyhow::Result<()> {
+    let Some(is_op) = channel_role(server, client, channel).await? else {
+        return Ok(());
+    };
+
+    let modes = server
+        .channels()
+        .get(channel)
+        .map(|channel| channel.modes.clone())
+        .unwrap_or_default();
+
+    if modes.contains(&ChannelMode::Announcements) && !is_op {
+        respond!(
+            client,
+            ERR_READ_ONLY,
+            format!("{channel} is read-only, only its ops post announcements there")
+        );
+        return Ok(());
+    }
+
+    if modes.contains(&ChannelMode::Moderated) && !is_op {
+        respond!(
+            client,
+            ERR_NOT_PERMITTED,
+            format!("Only channel ops can talk in {channel} while it is moderated")
+        );
+        return Ok(());
+    }
+
+    let max_chars = server.config.channel.max_message_chars;
+
+    if max_chars > 0 && message.chars().count() > max_chars {
+        client
+            .res
+            .feed(
+                ResponseBuilder::new(
+                    ERR_MESSAGE_TOO_LONG,
+                    format!("Messages can't be longer than {max_chars} characters"),
+                )
+                .with_request_id(request_id)
+                .build(),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let response = ResponseBuilder::new(
+        RES_CHANNEL_MESSAGE,
+        ChannelText::new(channel, message.clone()).encode(),
+    )
+    .with_origin(server.nick(&client.nick).to_string())
+    .from_bot(server.is_bot(client.account.as_deref()))
+    .build();
+
+    server.broadcast_channel(
+        channel,
+        Message::Frame(encode_once(response)),
+        Some(client.addr),
+    );
+
+    let name = server
+        .channels()
+        .get(channel)
+        .map_or_else(|| channel.to_owned(), |channel| channel.name.clone());
+    let ast =
+        solace_message_parser::parse_with_options(&message, server.config.channel.parser_options());
+    channel_references(
+        server,
+        client,
+        &name,
+        ast.channels().map(|mention| mention.name),
+    );
+    auto_respond(server, client, Some(&name), &message).await;
+
+    Ok(())
+}
+
+async fn channel_topic(
+    server: &Server,
+    client: &mut Client,
+    channel: &str,
+    topic: &str,
+) -> anyhow::Result<()> {
+    let Some(is_op) = channel_role(server, client, channel).await? else {
+        return Ok(());
+    };
+
+    let name = {
+        let mut channels = server.channels();
+        let Some(channel) = channels.get_mut(channel) else {
+            return Ok(());
+        };
+
+        if channel.modes.contains(&ChannelMode::TopicLocked) && !is_op {
+            None
+        } else {
+            channel.topic = topic.to_owned();
+            let name = channel.name.clone();
+
+            if let Err(err) = channels.save(&name) {
+                error!("{err}");
+            }
+            Some(name)
+        }
+    };
+
+    let Some(name) = name else {
+        respond!(
+            client,
+            ERR_NOT_PERMITTED,
+            format!("Only channel ops can set the topic of {channel}")
+        );
+        return Ok(());
+    };
+
+    let text = ChannelText::new(&name, topic.to_owned()).encode();
+    respond!(client, RES_CHANNEL_TOPIC, text.clone());
+    server.broadcast_channel(
+        &name,
+        Message::Frame(encode_once(
+            ResponseBuilder::new(RES_CHANNEL_TOPIC, text).build(),
+        )),
+        Some(client.addr),
+    );
+
+    let nick = server.nick(&client.nick);
+    channel_notice(
+        server,
+        client,
+        &name,
+        format!("{nick} changed the topic to: {topic}"),
+    );
+
+    Ok(())
+}
+
+async fn channel_mode(
+    server: &Server,
+    client: &mut Client,
+    channel: &str,
+    mode: ChannelMode,
+    is_enabled: bool,
+) -> anyhow::Result<()> {
+    let Some(is_op) = channel_role(server, client, channel).await? else {
+        return Ok(());
+    };
+
+    if !is_op {
+        respond!(
+            client,
+            ERR_NOT_PERMITTED,
+            format!("Only channel ops can change the modes of {channel}")
+        );
+        return Ok(());
+    }
+
+    let changed = {
+        let mut channels = server.channels();
+        let changed = channels.get_mut(channel).map(|channel| {
+            if is_enabled {
+                channel.modes.insert(mode);
+            } else {
+                channel.modes.remove(&mode);
+            }
+
+            (channel.name.clone(), channel.mode_flags())
+        });
+
+        if let Err(err) = channels.save(channel) {
+            error!("{err}");
+        }
+        changed
+    };
+
+    let Some((name, modes)) = changed else {
+        return Ok(());
+    };
+
+    // Everyone needs the new modes, the sender included
+    let modes = ResponseBuilder::new(RES_CHANNEL_MODE, ChannelText::new(&name, modes).encode());
+    server.broadcast_channel(&name, Message::Frame(encode_once(modes.build())), None);
+
+    let nick = server.nick(&client.nick);
+    let sign = if is_enabled { '+' } else { '-' };
+    channel_notice(
+        server,
+        client,
+        &name,
+        format!("{nick} set {sign}{}", mode.flag()),
+    );
+
+    Ok(())
+}
+
+async fn custom(
+    server: &Server,
+    client: &mut Client,
+    name: &str,
+    args: &str,
+) -> anyhow::Result<()> {
+    let response = server.custom_commands().respond(name, args);
+
+    match response {
+        Ok(response) => {
+            respond!(client, RES_CUSTOM_COMMAND, response);
+        }
+        Err(err) => {
+            respond!(client, ERR_COMMAND_NOT_FOUND, err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Logs `client` into `account`, taking the account's name as its nick,
+/// returning whether it could. Any password has already been checked.
+pub(crate) async fn log_in(
+    server: &Server,
+    client: &mut Client,
+    account: &str,
+) -> anyhow::Result<bool> {
+    let addr = client.addr;
+    let account = account.trim().to_owned();
+
+    if account.is_empty() {
+        respond!(
+            client,
+            ERR_INVALID_ARGUMENT,
+            "Account name can't be empty".to_owned()
+        );
+        return Ok(false);
+    }
+
+    let account_nick = server.nicks.intern(&account);
+
+    // Checked and claimed under one lock so that nobody can take the nick in
+    // between. Case is ignored as it is for `/nick`.
+    let level = server.levels().get(&account);
+    let was = client.nick.clone();
+    let announcing = server.announcing();
+    let sessions = {
+        let mut clients = server.clients.lock_all();
+        let wanted = account.to_lowercase();
+        let is_taken = clients.iter().any(|(a, c)| {
+            *a != addr
+                && server.nick(&c.nick).to_lowercase() == wanted
+                && c.account.as_deref() != Some(&account)
+        });
+
+        if is_taken {
+            None
+        } else {
+            let sessions = clients
+                .iter()
+                .filter(|(_, c)| c.account.as_deref() == Some(&account))
+                .count()
+                + 1;
+
+            if let Some(conn) = clients.get_mut(&addr) {
+                conn.account = Some(account.clone());
+                conn.nick = account_nick.clone();
+                conn.level = conn.level.max(level);
+            }
+
+            Some(sessions)
+        }
+    };
+
+    if sessions.is_some() {
+        if was != account_nick {
+            server.broadcast_others_now(
+                Message::NickChanged {
+                    from: MessageClient {
+                        addr,
+                        account: Some(account.clone()),
+                        nick: was,
+                    },
+                    new_nick: account_nick.clone(),
+                },
+                addr,
+            );
+        }
+        server.broadcast_nick_list_now();
+        server.broadcast_channels_of(addr);
+    }
+    drop(announcing);
+
+    let Some(sessions) = sessions else {
+        let message = match server.guard_nick(&account) {
+            0 => format!("{account} is in use by someone who isn't logged into it"),
+            _ => format!(
+                "{account} is in use by someone who isn't logged into it, who has {}s to log in before being renamed",
+                server.config.nick_protection.grace_secs
+            ),
+        };
+        respond!(client, ERR_NICK_IN_USE, message);
+        return Ok(false);
+    };
+
+    client.nick = account_nick.clone();
+    client.account = Some(account.clone());
+    Span::current().record("nick", &*server.nick(&account_nick));
+    server.stats().seen(&account);
+
+    respond!(
+        client,
+        RES_LOGGED_IN,
+        format!("Logged in as {account}, active sessions: {sessions}")
+    );
+    respond!(client, RES_YOUR_NICK, account.clone());
+
+    missed_mentions(server, client).await?;
+    server.guard_nick(&account);
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    use chrono::{TimeZone, Utc};
+
+    use solace_protocol::attachment::Attachment;
+    use solace_protocol::capability::COMMAND_HELP;
+    use solace_protocol::code::{
+        RES_CHANNEL_MEMBERS, RES_MENTIONED, RES_MOTD, RES_NICK_LIST, RES_TOPIC_CHANGE_MESSAGE,
+    };
+    use solace_protocol::codec::FrameCodec;
+    use solace_protocol::command::CommandSpec;
+    use solace_protocol::response::Response;
+    use tokio::io::{duplex, DuplexStream};
+    use tokio_stream::StreamExt;
+    use tokio_util::codec::FramedRead;
+
+    use super::*;
+    use crate::accounts::Accounts;
+    use crate::attachments::Attachments;
+    use crate::bookmarks::Bookmarks;
+    use crate::channel::Levels;
+    use crate::channels::Channels;
+    use crate::config::{Config, Scheduled, Trigger};
+    use crate::stats::Stats;
+    use crate::Connection;
+
+    type Peer = FramedRead<DuplexStream, FrameCodec<Response>>;
+
+    fn server(config: Config) -> Server {
+        Server::new(
+            config,
+            Accounts::default(),
+            Levels::default(),
+            Stats::default(),
+            Bookmarks::default(),
+            Attachments::default(),
+            Channels::default(),
+        )
+    }
+
+    /// A client joined to `server` as `nick`, along with the other end of its
+    /// connection.
+    async fn join(server: &Server, port: u16, nick: &str, level: Level) -> (Client, Peer) {
+        let (ours, theirs) = duplex(64 * 1024);
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let nick = server.nicks.intern(nick);
+        let client = Client::new(addr, Box::new(ours), nick.clone())
+            .await
+            .unwrap();
+
+        server.clients.lock_all().insert(
+            addr,
+            Connection::new(u32::from(port), nick, client.tx.clone(), level),
+        );
+
+        (client, FramedRead::new(theirs, FrameCodec::default()))
+    }
+
+    /// Codes and messages of the responses written to the client so far.
+    async fn responses(client: &mut Client, peer: &mut Peer) -> Vec<(u16, String)> {
+        SinkExt::<Response>::flush(&mut client.res).await.unwrap();
+
+        let mut responses = vec![];
+        while let Ok(Some(Ok(response))) =
+            tokio::time::timeout(Duration::from_millis(10), peer.next()).await
+        {
+            responses.push((response.code, response.message));
+        }
+
+        responses
+    }
+
+    /// Messages sent to the client by other tasks so far.
+    fn messages(client: &mut Client) -> Vec<Arc<Message>> {
+        std::iter::from_fn(|| client.rx.try_recv().ok()).collect()
+    }
+
+    /// Codes of the frames other tasks sent to the client so far, failing if
+    /// it was sent anything it has to put into words itself.
+    async fn delivered(client: &mut Client, peer: &mut Peer) -> Vec<u16> {
+        for message in messages(client) {
+            match &*message {
+                Message::Frame(frame) => client.res.feed(frame.clone()).await.unwrap(),
+                other => panic!("Expected only frames, got {other:?}"),
+            }
+        }
+
+        codes(&responses(client, peer).await)
+    }
+
+    fn codes(responses: &[(u16, String)]) -> Vec<u16> {
+        responses.iter().map(|(code, _)| *code).collect()
+    }
+
+    async fn send(server: &Server, client: &mut Client, message: RequestMessage) -> Flow {
+        dispatch(server, client, 0, message).await.unwrap()
+    }
+
+    #[test]
+    fn test_every_request_is_in_the_table() {
+        let requests = [
+            RequestMessage::Ping,
+            RequestMessage::NewTopic(String::new()),
+            RequestMessage::Mode {
+                nick: String::new(),
+                level: Level::Op,
+            },
+            RequestMessage::Kick {
+                nick: String::new(),
+                reason: None,
+            },
+            RequestMessage::Disconnect,
+        ];
+
+        for request in requests {
+            assert!(find(request.command_name().unwrap()).is_some());
+        }
+
+        assert_eq!(find("topic").unwrap().permission, Some(Permission::Topic));
+        assert!(find("message").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_command_list_with_help() {
+        let server = server(Config::default());
+        let (mut alice, _) = join(&server, 1, "alice", Level::Member).await;
+
+        assert!(alice
+            .command_list(&server)
+            .lines()
+            .any(|line| line == "ping"));
+
+        alice.capabilities = vec![COMMAND_HELP.to_owned()];
+        assert!(alice
+            .command_list(&server)
+            .lines()
+            .any(|line| line == "ping\tChecks that the server is still there"));
+    }
+
+    #[tokio::test]
+    async fn test_build_info_only_when_asked() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+
+        send(&server, &mut alice, RequestMessage::Capabilities(vec![])).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![RES_COMMAND_LIST]
+        );
+
+        let capabilities = vec![BUILD_INFO.to_owned()];
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Capabilities(capabilities),
+        )
+        .await;
+        let sent = responses(&mut alice, &mut peer).await;
+        assert_eq!(codes(&sent), vec![RES_COMMAND_LIST, RES_BUILD_INFO]);
+        let info = BuildInfo::decode(&sent[1].1).unwrap();
+        assert_eq!(info.name, "solace-server");
+
+        let theirs = BuildInfo::ours("solace-client-term", "0.1.0").encode();
+        send(&server, &mut alice, RequestMessage::BuildInfo(theirs)).await;
+        assert!(responses(&mut alice, &mut peer).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_signs_frames_only_with_a_session_key() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+        let capabilities = vec![FRAME_HMAC.to_owned()];
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Capabilities(capabilities.clone()),
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![RES_COMMAND_LIST]
+        );
+
+        let key = vec![7; 32];
+        alice.session_key = Some(key.clone());
+        peer.decoder_mut().expect_hmac(key);
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Capabilities(capabilities),
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![RES_COMMAND_LIST, RES_FRAME_HMAC]
+        );
+
+        // Unsigned frames would be turned away from here on
+        peer.decoder_mut()
+            .set_integrity(Integrity::Hmac(vec![7; 32]));
+        send(&server, &mut alice, RequestMessage::Ping).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![RES_PONG]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+
+        send(&server, &mut alice, RequestMessage::Ping).await;
+        assert_eq!(
+            responses(&mut alice, &mut peer).await,
+            vec![(RES_PONG, "Pong".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_message() {
+        let server = server(Config::default());
+        let (mut alice, _) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, _) = join(&server, 2, "bob", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Message("hi".to_owned()),
+        )
+        .await;
+
+        assert!(messages(&mut alice).is_empty());
+        assert!(matches!(
+            &*messages(&mut bob)[0],
+            Message::Sent { message, .. } if message == "hi"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_senders_are_told_who_read_their_messages() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, _) = join(&server, 2, "bob", Level::Member).await;
+        let (mut carol, _) = join(&server, 3, "carol", Level::Member).await;
+
+        for message in ["one", "two"] {
+            send(
+                &server,
+                &mut alice,
+                RequestMessage::Message(message.to_owned()),
+            )
+            .await;
+        }
+        send(&server, &mut bob, RequestMessage::MarkRead(2)).await;
+        send(&server, &mut carol, RequestMessage::MarkRead(1)).await;
+        send(&server, &mut alice, RequestMessage::MarkRead(2)).await;
+
+        for message in messages(&mut alice) {
+            if let Message::Frame(frame) = &*message {
+                alice.res.feed(frame.clone()).await.unwrap();
+            }
+        }
+        let receipts: Vec<_> = responses(&mut alice, &mut alice_peer)
+            .await
+            .into_iter()
+            .filter(|(code, _)| *code == RES_READ_RECEIPT)
+            .map(|(_, readers)| readers)
+            .collect();
+        assert_eq!(receipts, vec!["bob", "bob", "bob\ncarol"]);
+    }
+
+    #[tokio::test]
+    async fn test_mass_mention() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Op).await;
+        let everyone = RequestMessage::Message("@a @b @c @d @e @f".to_owned());
+
+        send(&server, &mut alice, everyone.clone()).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut alice_peer).await),
+            vec![ERR_NOT_PERMITTED]
+        );
+
+        send(&server, &mut bob, everyone.clone()).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut bob_peer).await),
+            vec![RES_MESSAGE_SENT]
+        );
+        assert_eq!(messages(&mut alice).len(), 1);
+
+        // Too soon after the last one
+        send(&server, &mut bob, everyone).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut bob_peer).await),
+            vec![ERR_RATE_LIMITED]
+        );
+        assert!(messages(&mut alice).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_message_too_long() {
+        let mut config = Config::default();
+        config.channel.max_message_chars = 5;
+        let server = server(config);
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, _) = join(&server, 2, "bob", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Message("héllo".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut alice_peer).await),
+            vec![RES_MESSAGE_SENT]
+        );
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Message("hello!".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut alice_peer).await),
+            vec![ERR_MESSAGE_TOO_LONG]
+        );
+        assert_eq!(messages(&mut bob).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_everyone_mention() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Op).await;
+        let (mut bob, _) = join(&server, 2, "bob", Level::Member).await;
+        let (mut carol, _) = join(&server, 3, "carol", Level::Member).await;
+        server
+            .clients
+            .with_mut(&carol.addr, |c| c.away = Some("lunch".to_owned()));
+
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::Message("@all hi".to_owned()),
+        )
+        .await;
+        assert!(messages(&mut alice).is_empty());
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Message("@here hi".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut alice_peer).await),
+            vec![RES_MESSAGE_SENT]
+        );
+        assert!(messages(&mut alice).is_empty());
+
+        let to_bob = messages(&mut bob);
+        assert!(matches!(&*to_bob[0], Message::Sent { .. }));
+        assert!(matches!(&*to_bob[1], Message::Frame(_)));
+        // Away, so only gets the message
+        assert_eq!(messages(&mut carol).len(), 1);
+
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::NewNick("here".to_owned()),
+        )
+        .await;
+        assert_eq!(server.nick(&bob.nick).to_string(), "bob");
+    }
+
+    #[tokio::test]
+    async fn test_karma_and_stats() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+
+        send(&server, &mut bob, RequestMessage::Message("hi".to_owned())).await;
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Message("thanks bob++".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            responses(&mut alice, &mut alice_peer).await[0],
+            (RES_STATS, "bob has 1 karma".to_owned())
+        );
+
+        send(&server, &mut bob, RequestMessage::Karma(None)).await;
+        send(&server, &mut bob, RequestMessage::Stats("alice".to_owned())).await;
+        send(&server, &mut bob, RequestMessage::Stats("carol".to_owned())).await;
+
+        let responses = responses(&mut bob, &mut bob_peer).await;
+        assert_eq!(responses[0].0, RES_MESSAGE_SENT);
+        assert_eq!(responses[1], (RES_STATS, "bob has 1 karma".to_owned()));
+        assert!(responses[2]
+            .1
+            .ends_with("has sent 1 messages and has 0 karma"));
+        assert_eq!(codes(&responses[3..]), vec![ERR_NICK_NOT_FOUND]);
+    }
+
+    #[tokio::test]
+    async fn test_server_stats_are_for_ops() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Op).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+
+        // A bare /stats gets past the client's arity check
+        let spec = CommandSpec::parse(find("stats").unwrap().usage).unwrap();
+        assert!(spec.accepts(0));
+        assert!(spec.accepts(1));
+
+        send(&server, &mut alice, RequestMessage::Stats(String::new())).await;
+        send(&server, &mut bob, RequestMessage::Stats(String::new())).await;
+
+        assert_eq!(
+            codes(&responses(&mut alice, &mut alice_peer).await),
+            vec![RES_STATS]
+        );
+        assert_eq!(
+            codes(&responses(&mut bob, &mut bob_peer).await),
+            vec![ERR_NOT_PERMITTED]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_channel_messages_only_reach_members() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+        let (mut carol, mut carol_peer) = join(&server, 3, "carol", Level::Member).await;
+        let say = |message: &str| RequestMessage::ChannelMessage {
+            channel: "#rust".to_owned(),
+            message: message.to_owned(),
+        };
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Join("#rust".to_owned()),
+        )
+        .await;
+        send(&server, &mut bob, RequestMessage::Join("#Rust".to_owned())).await;
+        send(&server, &mut alice, say("hi")).await;
+        send(&server, &mut carol, say("hi")).await;
+
+        assert_eq!(
+            codes(&responses(&mut alice, &mut alice_peer).await),
+            vec![RES_JOINED]
+        );
+        assert_eq!(
+            delivered(&mut alice, &mut alice_peer).await,
+            vec![RES_CHANNEL_MEMBERS, RES_CHANNEL_NOTICE, RES_CHANNEL_MEMBERS]
+        );
+        assert_eq!(
+            delivered(&mut bob, &mut bob_peer).await,
+            vec![RES_JOINED, RES_CHANNEL_MEMBERS, RES_CHANNEL_MESSAGE]
+        );
+        assert_eq!(
+            codes(&responses(&mut carol, &mut carol_peer).await),
+            vec![ERR_NOT_IN_CHANNEL]
+        );
+        assert!(messages(&mut carol).is_empty());
+
+        // Only the channel's first member is its op
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::ChannelMode {
+                channel: "#rust".to_owned(),
+                mode: ChannelMode::Moderated,
+                is_enabled: true,
+            },
+        )
+        .await;
+        send(&server, &mut bob, say("hello?")).await;
+        send(&server, &mut bob, RequestMessage::Part("#rust".to_owned())).await;
+
+        assert_eq!(
+            codes(&responses(&mut bob, &mut bob_peer).await),
+            vec![ERR_NOT_PERMITTED, RES_PARTED]
+        );
+        assert_eq!(server.channels().get("#rust").unwrap().mode_flags(), "+m");
+        assert_eq!(server.channels().of(bob.addr), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn test_announcement_channels_are_read_only() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+        let say = |message: &str| RequestMessage::ChannelMessage {
+            channel: "#status".to_owned(),
+            message: message.to_owned(),
+        };
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Join("#status".to_owned()),
+        )
+        .await;
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::Join("#status".to_owned()),
+        )
+        .await;
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::ChannelMode {
+                channel: "#status".to_owned(),
+                mode: ChannelMode::Announcements,
+                is_enabled: true,
+            },
+        )
+        .await;
+        responses(&mut alice, &mut alice_peer).await;
+        responses(&mut bob, &mut bob_peer).await;
+        messages(&mut alice);
+        messages(&mut bob);
+
+        send(&server, &mut bob, say("is it down?")).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut bob_peer).await),
+            vec![ERR_READ_ONLY]
+        );
+        assert!(messages(&mut alice).is_empty());
+
+        send(&server, &mut alice, say("Deploy finished")).await;
+        assert_eq!(
+            delivered(&mut bob, &mut bob_peer).await,
+            vec![RES_CHANNEL_MESSAGE]
+        );
+        assert_eq!(server.channels().get("#status").unwrap().mode_flags(), "+a");
+    }
+
+    #[tokio::test]
+    async fn test_mentioned_channels_are_told() {
+        let mut config = Config::default();
+        config.channel.references = true;
+        let server = server(config);
+        let (mut alice, _) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+        let (mut carol, _) = join(&server, 3, "carol", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Join("#general".to_owned()),
+        )
+        .await;
+        send(&server, &mut bob, RequestMessage::Join("#rust".to_owned())).await;
+        send(
+            &server,
+            &mut carol,
+            RequestMessage::Join("#quiet".to_owned()),
+        )
+        .await;
+        send(
+            &server,
+            &mut carol,
+            RequestMessage::ChannelMode {
+                channel: "#quiet".to_owned(),
+                mode: ChannelMode::NoReferences,
+                is_enabled: true,
+            },
+        )
+        .await;
+        messages(&mut bob);
+        messages(&mut carol);
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::ChannelMessage {
+                channel: "#General".to_owned(),
+                message: "see #rust and #Rust or #quiet and #general".to_owned(),
+            },
+        )
+        .await;
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Message("#rust".to_owned()),
+        )
+        .await;
+
+        for message in messages(&mut bob) {
+            if let Message::Frame(frame) = &*message {
+                bob.res.feed(frame.clone()).await.unwrap();
+            }
+        }
+        let notices: Vec<_> = responses(&mut bob, &mut bob_peer)
+            .await
+            .into_iter()
+            .filter(|(code, _)| *code == RES_CHANNEL_NOTICE)
+            .map(|(_, notice)| ChannelText::decode(&notice).unwrap().text)
+            .collect();
+        assert_eq!(
+            notices,
+            vec![
+                "alice referenced this channel in #general",
+                "alice referenced this channel in the main chat",
+            ]
+        );
+
+        // Channels with +r aren't told
+        assert!(messages(&mut carol)
+            .iter()
+            .all(|message| !matches!(**message, Message::Frame(_))));
+    }
+
+    #[tokio::test]
+    async fn test_missed_mentions_are_kept_until_back() {
+        let server = Server::new(
+            Config::default(),
+            Accounts::fixture(&[("carol", "hunter2")]),
+            Levels::default(),
+            Stats::default(),
+            Bookmarks::default(),
+            Attachments::default(),
+            Channels::default(),
+        );
+        let (mut alice, _) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+        let say = |message: &str| RequestMessage::Message(message.to_owned());
+
+        send(&server, &mut alice, say("before @bob is away")).await;
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::Away(Some("lunch".to_owned())),
+        )
+        .await;
+        send(&server, &mut alice, say("hi @Bob and @carol or @bob")).await;
+        send(&server, &mut alice, say("@dave isn't anyone")).await;
+        responses(&mut bob, &mut bob_peer).await;
+
+        send(&server, &mut bob, RequestMessage::Away(None)).await;
+        let missed = responses(&mut bob, &mut bob_peer).await.pop().unwrap();
+        assert_eq!(missed.0, RES_MISSED_MENTIONS);
+        let missed = Bookmark::decode_list(&missed.1);
+        assert_eq!(missed.len(), 1);
+        assert_eq!(
+            (missed[0].nick.as_str(), missed[0].message.as_str()),
+            ("alice", "hi @Bob and @carol or @bob")
+        );
+
+        // Registered nicks are told once they log in
+        let (mut carol, mut carol_peer) = join(&server, 3, "guest", Level::Member).await;
+        send(
+            &server,
+            &mut carol,
+            RequestMessage::Login {
+                account: "carol".to_owned(),
+                password: Some(Secret("hunter2".to_owned())),
+            },
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut carol, &mut carol_peer).await),
+            vec![RES_LOGGED_IN, RES_YOUR_NICK, RES_MISSED_MENTIONS]
+        );
+        assert!(server.away_log().take("bob").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mentions_resolve_ignoring_case() {
+        let server = server(Config::default());
+        let (mut alice, _) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+        join(&server, 3, "Carol", Level::Member).await;
+        join(&server, 4, "carol", Level::Member).await;
+
+        assert_eq!(
+            server.resolve_mentions(["BOB", "carol", "CAROL", "dave", "Bob"].into_iter()),
+            vec![bob.nick.clone(), server.nicks.intern("carol")]
+        );
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Message("hi @BOB and @alice".to_owned()),
+        )
+        .await;
+
+        for message in messages(&mut bob) {
+            if let Message::Frame(frame) = &*message {
+                bob.res.feed(frame.clone()).await.unwrap();
+            }
+        }
+        assert_eq!(
+            responses(&mut bob, &mut bob_peer).await,
+            vec![(RES_MENTIONED, "bob".to_owned())]
+        );
+        assert!(messages(&mut alice).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mentions_anywhere_when_configured() {
+        for (mentions_anywhere, expected) in [(false, vec![]), (true, vec![RES_MENTIONED])] {
+            let mut config = Config::default();
+            config.channel.mentions_anywhere = mentions_anywhere;
+            let server = server(config);
+            let (mut alice, _) = join(&server, 1, "alice", Level::Member).await;
+            let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+
+            send(
+                &server,
+                &mut alice,
+                RequestMessage::Message("cc:@bob".to_owned()),
+            )
+            .await;
+
+            for message in messages(&mut bob) {
+                if let Message::Frame(frame) = &*message {
+                    bob.res.feed(frame.clone()).await.unwrap();
+                }
+            }
+            assert_eq!(codes(&responses(&mut bob, &mut bob_peer).await), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_undeliverable_messages_are_counted() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Op).await;
+        let (bob, _) = join(&server, 2, "bob", Level::Member).await;
+
+        // Gone without being removed yet, as when its task has just ended
+        drop(bob);
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Message("hi".to_owned()),
+        )
+        .await;
+        send(&server, &mut alice, RequestMessage::Stats(String::new())).await;
+
+        assert_eq!(
+            responses(&mut alice, &mut peer).await[1],
+            (
+                RES_STATS,
+                "1 undeliverable messages, 0 lagging clients and 0 disconnected for falling behind"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bookmark_needs_login_and_a_kept_message() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+
+        send(&server, &mut alice, RequestMessage::Bookmark(1)).await;
+        alice.account = Some("alice".to_owned());
+        send(&server, &mut alice, RequestMessage::Bookmark(1)).await;
+        send(&server, &mut alice, RequestMessage::Bookmarks).await;
+
+        assert_eq!(
+            responses(&mut alice, &mut alice_peer).await,
+            vec![
+                (
+                    ERR_NOT_LOGGED_IN,
+                    "Log in with /login to keep bookmarks".to_owned()
+                ),
+                (
+                    ERR_INVALID_ARGUMENT,
+                    "No message #1, it may be too old to bookmark".to_owned()
+                ),
+                (RES_BOOKMARK_LIST, String::new()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_and_download() {
+        let mut config = Config::default();
+        config.attachments.max_bytes = 8;
+        let server = server(config);
+        let dir = std::env::temp_dir().join(format!("solace-uploads-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        *server.attachments() = Attachments::in_dir(dir.clone()).unwrap();
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Upload {
+                name: "../big.bin".to_owned(),
+                data: attachment::encode_data(b"too large"),
+            },
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut alice_peer).await),
+            vec![ERR_ATTACHMENT_TOO_LARGE]
+        );
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Upload {
+                name: "../notes.txt".to_owned(),
+                data: attachment::encode_data(b"notes"),
+            },
+        )
+        .await;
+        let id = attachment::id_for(b"notes");
+        assert_eq!(
+            responses(&mut alice, &mut alice_peer).await,
+            vec![(RES_UPLOADED, id.clone())]
+        );
+
+        send(&server, &mut bob, RequestMessage::Download(id.clone())).await;
+        let (code, message) = responses(&mut bob, &mut bob_peer).await.remove(0);
+        assert_eq!(code, RES_ATTACHMENT);
+        assert_eq!(
+            Attachment::decode(&message),
+            Some(Attachment {
+                id,
+                name: "notes.txt".to_owned(),
+                data: b"notes".to_vec(),
+            })
+        );
+
+        send(&server, &mut bob, RequestMessage::Download("0".repeat(64))).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut bob_peer).await),
+            vec![ERR_INVALID_ARGUMENT]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_topic_needs_permission() {
+        let mut config = Config::default();
+        config.channel.permissions.topic = Level::Op;
+        let server = server(config);
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, _) = join(&server, 2, "bob", Level::Op).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::NewTopic("mine".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            responses(&mut alice, &mut peer).await,
+            vec![(
+                ERR_NOT_PERMITTED,
+                "Only ops and above can set the topic".to_owned()
+            )]
+        );
+
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::NewTopic(" ours ".to_owned()),
+        )
+        .await;
+        assert_eq!(server.topic(), "ours");
+        assert!(matches!(
+            &*messages(&mut alice)[0],
+            Message::TopicChanged { topic, .. } if topic == "ours"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nick() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::NewNick(" carol ".to_owned()),
+        )
+        .await;
+
+        assert_eq!(&*server.nick(&alice.nick), "carol");
+        assert_eq!(
+            server.clients.with(&alice.addr, |c| c.nick.clone()),
+            Some(alice.nick.clone())
+        );
+        assert_eq!(
+            responses(&mut alice, &mut peer).await,
+            vec![(RES_YOUR_NICK, "carol".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nick_in_use() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+        let (_bob, _) = join(&server, 2, "bob", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::NewNick("Bob".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            responses(&mut alice, &mut peer).await,
+            vec![(ERR_NICK_IN_USE, "Bob is in use".to_owned())]
+        );
+        assert_eq!(&*server.nick(&alice.nick), "alice");
+        assert_eq!(
+            server.clients.with(&alice.addr, |c| c.nick.clone()),
+            Some(alice.nick.clone())
+        );
+
+        // Only someone else having it counts
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::NewNick("Alice".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![RES_YOUR_NICK]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registered_nicks_need_auth() {
+        let server = Server::new(
+            Config::default(),
+            Accounts::fixture(&[("alice", "hunter2")]),
+            Levels::default(),
+            Stats::default(),
+            Bookmarks::default(),
+            Attachments::default(),
+            Channels::default(),
+        );
+        let (mut bob, mut peer) = join(&server, 1, "bob", Level::Member).await;
+        let auth = |password: &str| RequestMessage::Auth(Secret(password.to_owned()));
+
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::NewNick("Alice".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            responses(&mut bob, &mut peer).await,
+            vec![(ERR_AUTH_REQUIRED, "alice is registered".to_owned())]
+        );
+        assert_eq!(&*server.nick(&bob.nick), "bob");
+
+        send(&server, &mut bob, auth("hunter3")).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut peer).await),
+            vec![ERR_WRONG_PASSWORD]
+        );
+
+        // The refused nick is remembered until the right password is given
+        send(&server, &mut bob, auth("hunter2")).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut peer).await),
+            vec![RES_LOGGED_IN, RES_YOUR_NICK, RES_AUTH_OK]
+        );
+        assert_eq!(&*server.nick(&bob.nick), "alice");
+        assert_eq!(bob.account.as_deref(), Some("alice"));
+
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::Register(Secret("hunter2".to_owned())),
+        )
+        .await;
+        assert_eq!(
+            responses(&mut bob, &mut peer).await,
+            vec![(
+                ERR_INVALID_ARGUMENT,
+                "alice is already registered".to_owned()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sender_is_not_told_twice() {
+        let server = server(Config::default());
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Op).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::NewTopic("news".to_owned()),
+        )
+        .await;
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::NewNick("carol".to_owned()),
+        )
+        .await;
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Mode {
+                nick: "bob".to_owned(),
+                level: Level::Voice,
+            },
+        )
+        .await;
+
+        // Only what the sender's client needs to keep up, as it already
+        // shows the commands it sent
+        assert_eq!(
+            codes(&responses(&mut alice, &mut alice_peer).await),
+            vec![RES_TOPIC_CHANGE, RES_YOUR_NICK]
+        );
+        assert_eq!(
+            delivered(&mut alice, &mut alice_peer).await,
+            vec![RES_NICK_LIST, RES_NICK_LIST]
+        );
+
+        let told_bob = messages(&mut bob);
+        assert!(matches!(&*told_bob[0], Message::TopicChanged { .. }));
+        assert!(matches!(&*told_bob[1], Message::NickChanged { .. }));
+        for message in told_bob {
+            if let Message::Frame(frame) = &*message {
+                bob.res.feed(frame.clone()).await.unwrap();
+            }
+        }
+        assert!(codes(&responses(&mut bob, &mut bob_peer).await).contains(&RES_MODE_CHANGE));
+    }
+
+    #[tokio::test]
+    async fn test_away_and_presence() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Away(Some(" lunch ".to_owned())),
+        )
+        .await;
+        assert_eq!(
+            responses(&mut alice, &mut peer).await,
+            vec![
+                (RES_AWAY, "You are now marked as away: lunch".to_owned()),
+                (RES_PRESENCE, "away".to_owned()),
+            ]
+        );
+
+        send(&server, &mut alice, RequestMessage::DoNotDisturb).await;
+        send(&server, &mut alice, RequestMessage::Online).await;
+        assert_eq!(
+            responses(&mut alice, &mut peer).await,
+            vec![
+                (RES_PRESENCE, "dnd".to_owned()),
+                (RES_PRESENCE, "online".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+        join(&server, 2, "carol", Level::Member).await;
+        join(&server, 3, "Dave", Level::Member).await;
+
+        let login = |account: &str| RequestMessage::Login {
+            account: account.to_owned(),
+            password: None,
+        };
+
+        send(&server, &mut alice, login("alice")).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![RES_LOGGED_IN, RES_YOUR_NICK]
+        );
+        assert_eq!(alice.account.as_deref(), Some("alice"));
+
+        // Logged in nicks are tied to their account
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::NewNick("bob".to_owned()),
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![ERR_INVALID_ARGUMENT]
+        );
+
+        // Nor can an account be logged into while a guest is using its name,
+        // in any case
+        send(&server, &mut alice, login("carol")).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![ERR_NICK_IN_USE]
+        );
+        send(&server, &mut alice, login("dave")).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![ERR_NICK_IN_USE]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_ignores_case_of_claimed_accounts() {
+        let server = Server::new(
+            Config::default(),
+            Accounts::fixture(&[("alice", "hunter2")]),
+            Levels::default(),
+            Stats::default(),
+            Bookmarks::default(),
+            Attachments::default(),
+            Channels::default(),
+        );
+        let (mut bob, mut peer) = join(&server, 1, "bob", Level::Member).await;
+        let login = |account: &str, password: &str| RequestMessage::Login {
+            account: account.to_owned(),
+            password: Some(Secret(password.to_owned())),
+        };
+
+        // Changing the case of a letter is no way around the password
+        send(&server, &mut bob, login("Alice", "hunter3")).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut peer).await),
+            vec![ERR_WRONG_PASSWORD]
+        );
+        assert_eq!(bob.account, None);
+        assert_eq!(
+            server.accounts().claimant("Alice"),
+            Some("alice".to_owned())
+        );
+
+        send(&server, &mut bob, login("Alice", "hunter2")).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut peer).await),
+            vec![RES_LOGGED_IN, RES_YOUR_NICK]
+        );
+        assert_eq!(bob.account.as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn test_ghost_needs_a_claimed_account() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Ghost {
+                nick: "bob".to_owned(),
+                password: Secret("hunter2".to_owned()),
+            },
+        )
+        .await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![ERR_INVALID_ARGUMENT]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registered_nicks_are_taken_back_after_a_grace_period() {
+        let server = Server::new(
+            Config::default(),
+            Accounts::fixture(&[("carol", "hunter2")]),
+            Levels::default(),
+            Stats::default(),
+            Bookmarks::default(),
+            Attachments::default(),
+            Channels::default(),
+        );
+        let (mut intruder, _) = join(&server, 1, "carol", Level::Member).await;
+        let (mut carol, mut carol_peer) = join(&server, 2, "bob", Level::Member).await;
+        let log_in = || RequestMessage::Login {
+            account: "carol".to_owned(),
+            password: Some(Secret("hunter2".to_owned())),
+        };
+
+        send(&server, &mut carol, log_in()).await;
+        let refused = responses(&mut carol, &mut carol_peer).await;
+        assert_eq!(codes(&refused), vec![ERR_NICK_IN_USE]);
+        assert!(refused[0]
+            .1
+            .ends_with("who has 60s to log in before being renamed"));
+        assert!(matches!(&*messages(&mut intruder)[0], Message::Frame(_)));
+
+        // Not before the grace period is over
+        server.enforce_nick_guard(Instant::now());
+        assert!(messages(&mut intruder).is_empty());
+
+        server.enforce_nick_guard(Instant::now() + Duration::from_secs(60));
+        let renamed = messages(&mut intruder);
+        let Message::Renamed { nick, account } = &*renamed[0] else {
+            panic!("Expected a rename, got {renamed:?}");
+        };
+        assert_eq!(account, "carol");
+        assert_ne!(&*server.nick(nick), "carol");
+
+        send(&server, &mut carol, log_in()).await;
+        assert_eq!(
+            codes(&responses(&mut carol, &mut carol_peer).await),
+            vec![RES_LOGGED_IN, RES_YOUR_NICK]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mode() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Op).await;
+        let (mut bob, mut bob_peer) = join(&server, 2, "bob", Level::Member).await;
+
+        let mode = |nick: &str, level| RequestMessage::Mode {
+            nick: nick.to_owned(),
+            level,
+        };
+
+        send(&server, &mut alice, mode("bob", Level::Voice)).await;
+        assert_eq!(server.level_of(bob.addr), Level::Voice);
+        assert!(matches!(&*messages(&mut bob)[0], Message::Frame(_)));
+
+        // Not above one's own level, nor by anyone below the configured one
+        send(&server, &mut alice, mode("bob", Level::Op)).await;
+        send(&server, &mut alice, mode("nobody", Level::Voice)).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![ERR_NOT_PERMITTED, ERR_NICK_NOT_FOUND]
+        );
+
+        send(&server, &mut bob, mode("alice", Level::Member)).await;
+        assert_eq!(
+            codes(&responses(&mut bob, &mut bob_peer).await),
+            vec![ERR_NOT_PERMITTED]
+        );
+        assert_eq!(server.level_of(alice.addr), Level::Op);
+    }
+
+    #[tokio::test]
+    async fn test_kick() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Op).await;
+        let (mut bob, _) = join(&server, 2, "bob", Level::Op).await;
+        let (mut carol, _) = join(&server, 3, "carol", Level::Member).await;
+
+        let kick = |nick: &str| RequestMessage::Kick {
+            nick: nick.to_owned(),
+            reason: Some("spam".to_owned()),
+        };
+
+        send(&server, &mut alice, kick("bob")).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![ERR_NOT_PERMITTED]
+        );
+
+        send(&server, &mut alice, kick("carol")).await;
+        assert!(server.clients.with(&carol.addr, |_| ()).is_none());
+        assert!(messages(&mut carol).iter().any(|message| matches!(
+            &**message,
+            Message::Kicked { reason: Some(reason), .. } if reason == "spam"
+        )));
+        assert!(!messages(&mut bob).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_direct_message() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, _) = join(&server, 2, "bob", Level::Member).await;
+
+        let direct = |to: &str| RequestMessage::DirectMessage {
+            to: to.to_owned(),
+            message: "psst".to_owned(),
+        };
+
+        send(&server, &mut alice, direct("bob")).await;
+        assert!(matches!(
+            &*messages(&mut bob)[0],
+            Message::Direct { message, .. } if message == "psst"
+        ));
+
+        send(&server, &mut alice, direct("nobody")).await;
+        assert_eq!(
+            codes(&responses(&mut alice, &mut peer).await),
+            vec![ERR_NICK_NOT_FOUND]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_devices_and_revoke() {
+        let server = server(Config::default());
+        let (mut phone, mut phone_peer) = join(&server, 1, "a", Level::Member).await;
+        let (mut laptop, mut laptop_peer) = join(&server, 2, "b", Level::Member).await;
+
+        send(&server, &mut phone, RequestMessage::Devices).await;
+        send(&server, &mut phone, RequestMessage::Revoke(2)).await;
+        assert_eq!(
+            codes(&responses(&mut phone, &mut phone_peer).await),
+            vec![ERR_NOT_LOGGED_IN, ERR_NOT_LOGGED_IN]
+        );
+
+        for client in [&mut phone, &mut laptop] {
+            let login = RequestMessage::Login {
+                account: "alice".to_owned(),
+                password: None,
+            };
+            send(&server, client, login).await;
+        }
+        responses(&mut phone, &mut phone_peer).await;
+        responses(&mut laptop, &mut laptop_peer).await;
+
+        send(&server, &mut phone, RequestMessage::Devices).await;
+        send(&server, &mut phone, RequestMessage::Revoke(2)).await;
+        send(&server, &mut phone, RequestMessage::Revoke(9)).await;
+        assert_eq!(
+            codes(&responses(&mut phone, &mut phone_peer).await),
+            vec![
+                RES_DEVICE_LIST,
+                RES_DEVICE_LIST,
+                RES_SESSION_REVOKED,
+                ERR_SESSION_NOT_FOUND
+            ]
+        );
+        assert!(messages(&mut laptop)
+            .iter()
+            .any(|message| matches!(&**message, Message::Revoked)));
+    }
+
+    #[tokio::test]
+    async fn test_quota() {
+        let server = server(Config::default());
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+
+        send(&server, &mut alice, RequestMessage::Quota).await;
+        assert_eq!(
+            responses(&mut alice, &mut peer).await,
+            vec![(
+                RES_QUOTA,
+                "Used 0 B of unlimited today (0 B in, 0 B out)".to_owned()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_whois() {
+        let server = server(Config::default());
+        let (mut alice, _) = join(&server, 1, "alice", Level::Member).await;
+        let (bob, _) = join(&server, 2, "bob", Level::Member).await;
+
+        send(&server, &mut alice, RequestMessage::WhoIs("bob".to_owned())).await;
+        assert!(matches!(
+            &*messages(&mut alice)[0],
+            Message::WhoIs { addr: Some(addr), .. } if *addr == bob.addr
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_custom() {
+        let mut config = Config::default();
+        config.commands.insert(
+            "rules".to_owned(),
+            crate::custom::CustomCommand {
+                help: "Shows the rules".to_owned(),
+                response: "Be nice".to_owned(),
+                ..Default::default()
+            },
+        );
+        let server = server(config);
+        let (mut alice, mut peer) = join(&server, 1, "alice", Level::Member).await;
+
+        let custom = |name: &str| RequestMessage::Custom {
+            name: name.to_owned(),
+            args: String::new(),
+        };
+
+        send(&server, &mut alice, custom("rules")).await;
+        send(&server, &mut alice, custom("nope")).await;
+        assert_eq!(
+            responses(&mut alice, &mut peer).await,
+            vec![
+                (RES_CUSTOM_COMMAND, "Be nice".to_owned()),
+                (ERR_COMMAND_NOT_FOUND, "Unknown command: /nope".to_owned()),
+            ]
+        );
+
+        alice.capabilities = vec![COMMAND_HELP.to_owned()];
+        assert!(alice
+            .command_list(&server)
+            .ends_with("\nrules\tShows the rules"));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect() {
+        let server = server(Config::default());
+        let (mut alice, _) = join(&server, 1, "alice", Level::Member).await;
+
+        assert_eq!(
+            send(&server, &mut alice, RequestMessage::Disconnect).await,
+            Flow::Disconnect
+        );
+        assert!(server.clients.with(&alice.addr, |_| ()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_changes_are_made_when_due() {
+        let config = Config {
+            schedule: vec![
+                Scheduled {
+                    at: "30 9 * * 1-5".to_owned(),
+                    topic: Some("Standup at 10".to_owned()),
+                    motd: Some("Be nice".to_owned()),
+                    ..Scheduled::default()
+                },
+                Scheduled {
+                    at: "30 9 * * *".to_owned(),
+                    channel: Some("#Rust".to_owned()),
+                    topic: Some("Release day".to_owned()),
+                    ..Scheduled::default()
+                },
+            ],
+            ..Config::default()
+        };
+        let server = server(config);
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        send(
+            &server,
+            &mut alice,
+            RequestMessage::Join("#rust".to_owned()),
+        )
+        .await;
+        responses(&mut alice, &mut alice_peer).await;
+        messages(&mut alice);
+
+        let saturday = Utc.with_ymd_and_hms(2024, 7, 6, 9, 30, 0).unwrap();
+        server.run_scheduled(&saturday).await;
+        assert_eq!(server.topic(), "[No topic]");
+        assert_eq!(server.channels().get("#rust").unwrap().topic, "Release day");
+        assert_eq!(
+            delivered(&mut alice, &mut alice_peer).await,
+            vec![RES_CHANNEL_TOPIC, RES_CHANNEL_NOTICE]
+        );
+
+        let monday = Utc.with_ymd_and_hms(2024, 7, 8, 9, 30, 0).unwrap();
+        server.run_scheduled(&monday).await;
+        assert_eq!(server.topic(), "Standup at 10");
+        assert_eq!(server.scheduled_motd().as_deref(), Some("Be nice"));
+        assert_eq!(
+            delivered(&mut alice, &mut alice_peer).await,
+            vec![
+                RES_TOPIC_CHANGE,
+                RES_TOPIC_CHANGE_MESSAGE,
+                RES_MOTD,
+                RES_CHANNEL_TOPIC,
+                RES_CHANNEL_NOTICE
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_triggers_are_answered() {
+        let mut config = Config::default();
+        config.auto_responder.triggers = vec![Trigger {
+            pattern: "^!rules$".to_owned(),
+            reply: Some("{nick}: be nice".to_owned()),
+            ..Trigger::default()
+        }];
+        let server = server(config);
+        let (mut alice, mut alice_peer) = join(&server, 1, "alice", Level::Member).await;
+        let (mut bob, _) = join(&server, 2, "bob", Level::Member).await;
+
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::Message("!rules".to_owned()),
+        )
+        .await;
+        // Cooling down
+        send(
+            &server,
+            &mut bob,
+            RequestMessage::Message("!rules".to_owned()),
+        )
+        .await;
+
+        for message in messages(&mut alice) {
+            if let Message::Frame(frame) = &*message {
+                alice.res.feed(frame.clone()).await.unwrap();
+            }
+        }
+        SinkExt::<Response>::flush(&mut alice.res).await.unwrap();
+        let response = alice_peer.next().await.unwrap().unwrap();
+        assert_eq!(response.code, RES_CHAT_MESSAGE_OK);
+        assert_eq!(response.origin, "auto-responder");
+        assert_eq!(response.message, "bob: be nice");
+        assert!(responses(&mut alice, &mut alice_peer).await.is_empty());
+    }
+}
@@ -0,0 +1,635 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+use solace_message_parser::{MentionPolicy, ParserOptions};
+use solace_protocol::level::Level;
+
+use crate::channel::Permission;
+use crate::custom::CustomCommand;
+
+/// Server configuration, read from `$XDG_CONFIG_HOME/solace/server.toml`.
+///
+/// Every section is optional so that the server runs with sensible
+/// defaults when there is no config file at all.
+///
+/// # Fields
+///
+/// - `commands`: Custom commands by name, see `CustomCommand`. These are
+///   read again on SIGHUP, unlike everything else.
+/// - `schedule`: Changes the server makes by itself, see `Scheduled`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    pub(crate) quota: Quota,
+    pub(crate) journal: Journal,
+    pub(crate) history: History,
+    pub(crate) proxy: Proxy,
+    pub(crate) quic: Quic,
+    pub(crate) client_certs: ClientCerts,
+    pub(crate) unix: Unix,
+    pub(crate) channel: Channel,
+    pub(crate) bots: Bots,
+    pub(crate) previews: Previews,
+    pub(crate) attachments: Attachments,
+    pub(crate) delivery: Delivery,
+    pub(crate) welcome: Welcome,
+    pub(crate) rate_limit: RateLimit,
+    pub(crate) away_log: AwayLog,
+    pub(crate) logging: Logging,
+    pub(crate) nick_protection: NickProtection,
+    pub(crate) commands: BTreeMap<String, CustomCommand>,
+    pub(crate) schedule: Vec<Scheduled>,
+    pub(crate) auto_responder: AutoResponder,
+}
+
+/// Answers to chat and channel messages matching a pattern, given by the
+/// server itself as a bot, see `triggers::Triggers`.
+///
+/// # Fields
+///
+/// - `nick`: Who the answers are from. Clients badge it as a bot.
+/// - `cooldown_secs`: How long a trigger is quiet for in a channel after it
+///   fires there, so that it can't be used to flood it. `0` lets it fire on
+///   every message.
+/// - `triggers`: Given as `[[auto_responder.triggers]]` entries, of which
+///   the first matching a message fires.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct AutoResponder {
+    pub(crate) nick: String,
+    pub(crate) cooldown_secs: u64,
+    pub(crate) triggers: Vec<Trigger>,
+}
+
+impl Default for AutoResponder {
+    fn default() -> Self {
+        Self {
+            nick: "auto-responder".to_owned(),
+            cooldown_secs: 30,
+            triggers: vec![],
+        }
+    }
+}
+
+/// # Fields
+///
+/// - `pattern`: A regex searched for in each message, e.g. `(?i)^!rules$`.
+/// - `reply`: What to answer with, if anything. `$1` or `${name}` is
+///   replaced by what that group of `pattern` matched and `{nick}` by who
+///   sent the message.
+/// - `webhook`: A URL to post the message to as JSON, for answering it
+///   somewhere else. Only available when built with the `webhooks` feature.
+/// - `channels`: Where the trigger fires, such as `#help`, or everywhere
+///   including the main chat when empty. `main` is the main chat.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Trigger {
+    pub(crate) pattern: String,
+    pub(crate) reply: Option<String>,
+    pub(crate) webhook: Option<String>,
+    pub(crate) channels: Vec<String>,
+}
+
+/// A topic or message of the day for the server to set at certain times,
+/// given as `[[schedule]]` entries, see `schedule::Schedule`.
+///
+/// # Fields
+///
+/// - `at`: When, as the five fields of a crontab line in UTC, see
+///   `schedule::Cron`. For example `30 9 * * 1-5` for 09:30 on weekdays.
+/// - `channel`: The channel whose topic is set, the main chat's if unset.
+///   Nothing happens if nobody is in it.
+/// - `topic`: The topic to set.
+/// - `motd`: The message of the day from then on, in place of
+///   `welcome.motd`. It is sent to everyone connected as well as to those
+///   joining later. Empty to send none.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Scheduled {
+    pub(crate) at: String,
+    pub(crate) channel: Option<String>,
+    pub(crate) topic: Option<String>,
+    pub(crate) motd: Option<String>,
+}
+
+/// Daily traffic allowances, counted in bytes sent and received.
+/// A value of `0` means unlimited.
+///
+/// # Fields
+///
+/// - `daily_bytes_per_account`: Shared by every session of an account.
+/// - `daily_bytes_per_ip`: For connections which aren't logged in.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Quota {
+    pub(crate) daily_bytes_per_account: u64,
+    pub(crate) daily_bytes_per_ip: u64,
+}
+
+/// Accounts run by programs rather than people, whose messages are flagged
+/// so that clients can badge them.
+///
+/// # Fields
+///
+/// - `accounts`: The bot accounts.
+/// - `daily_bytes`: Traffic allowance for each bot account in place of
+///   `quota.daily_bytes_per_account`, as bots tend to need a different one.
+///   `0` means unlimited, unset uses the one for everyone.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Bots {
+    pub(crate) accounts: Vec<String>,
+    pub(crate) daily_bytes: Option<u64>,
+}
+
+/// The JSONL record of every connect, message, command and disconnect, see
+/// `journal::Journal`.
+///
+/// # Fields
+///
+/// - `path`: File to append events to, the journal is off without one.
+/// - `max_bytes`: Size past which the file is moved to `<path>.1`, pushing
+///   older ones along to `<path>.2` and so on. `0` never rotates.
+/// - `keep`: How many rotated files to keep before the oldest is deleted.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Journal {
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) max_bytes: u64,
+    pub(crate) keep: usize,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_bytes: 16 * 1024 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+/// Recent chat messages replayed to clients as they join.
+///
+/// # Fields
+///
+/// - `max_messages`: How many messages to keep, `0` turns replay off.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct History {
+    pub(crate) max_messages: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self { max_messages: 200 }
+    }
+}
+
+/// Load balancers passing connections on with the PROXY protocol, v1 or v2,
+/// so that clients are known by their real address rather than the proxy's.
+///
+/// # Fields
+///
+/// - `trusted`: Peers which must send a PROXY header, any other peer is
+///   taken to have connected directly. Empty turns the PROXY protocol off.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Proxy {
+    pub(crate) trusted: Vec<IpAddr>,
+}
+
+/// An experimental QUIC listener alongside the TCP one, only available when
+/// built with the `quic` feature.
+///
+/// # Fields
+///
+/// - `listen`: UDP address to accept QUIC connections on, off without one.
+/// - `cert`: PEM file holding the certificate chain to present.
+/// - `key`: PEM file holding the private key for `cert`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Quic {
+    pub(crate) listen: Option<SocketAddr>,
+    pub(crate) cert: PathBuf,
+    pub(crate) key: PathBuf,
+}
+
+/// Client certificates which log straight into an account, over TLS on the
+/// TCP port and over QUIC alike.
+///
+/// # Fields
+///
+/// - `accounts`: SHA-256 fingerprints of client certificates, in hex with
+///   or without colons, mapped to the account each one logs into.
+/// - `required`: Turn away TLS and QUIC clients without a certificate from
+///   `accounts`, rather than letting them connect anonymously. Plain TCP
+///   clients are still let in.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ClientCerts {
+    pub(crate) accounts: HashMap<String, String>,
+    pub(crate) required: bool,
+}
+
+/// A Unix socket listener alongside the TCP one, for local bots and tools.
+///
+/// # Fields
+///
+/// - `path`: Where to create the socket, off without one. Anything already
+///   there is removed first.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Unix {
+    pub(crate) path: Option<PathBuf>,
+}
+
+/// Titles and descriptions of pages linked to in chat messages, fetched by
+/// the server and sent on to clients after the message. Only available when
+/// built with the `previews` feature.
+///
+/// # Fields
+///
+/// - `enabled`: Off unless turned on, as it has the server make requests to
+///   whatever anyone links to. Addresses which aren't public never are.
+/// - `max_per_message`: Links past this many in one message go without.
+/// - `max_fetching`: How many messages can have their links fetched at once.
+/// - `max_queued`: How many messages can wait for a fetch to finish, those
+///   sent while the queue is full go without.
+/// - `timeout_secs`: How long a page has to respond, redirects included.
+/// - `max_bytes`: How much of a page is read looking for its title.
+/// - `cache_size`: How many pages to remember previews for, including the
+///   ones which didn't have any, so that a link posted again isn't fetched
+///   again.
+/// - `cache_secs`: How long a preview is remembered for.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Previews {
+    pub(crate) enabled: bool,
+    pub(crate) max_per_message: usize,
+    pub(crate) max_fetching: usize,
+    pub(crate) max_queued: usize,
+    pub(crate) timeout_secs: u64,
+    pub(crate) max_bytes: usize,
+    pub(crate) cache_size: usize,
+    pub(crate) cache_secs: u64,
+}
+
+impl Default for Previews {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_per_message: 3,
+            max_fetching: 8,
+            max_queued: 64,
+            timeout_secs: 5,
+            max_bytes: 256 * 1024,
+            cache_size: 512,
+            cache_secs: 60 * 60,
+        }
+    }
+}
+
+/// Files uploaded for others to download, kept by their contents in the
+/// `attachments` XDG data directory, see `attachments::Attachments`.
+///
+/// # Fields
+///
+/// - `max_bytes`: The largest file which can be uploaded, `0` turns uploads
+///   off.
+/// - `max_total_bytes`: How much the store may hold, the oldest files go
+///   first to make room. `0` means there is no limit.
+/// - `ttl_secs`: How long a file is kept after it was last uploaded.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Attachments {
+    pub(crate) max_bytes: u64,
+    pub(crate) max_total_bytes: u64,
+    pub(crate) ttl_secs: u64,
+}
+
+impl Default for Attachments {
+    fn default() -> Self {
+        Self {
+            max_bytes: 8 * 1024 * 1024,
+            max_total_bytes: 512 * 1024 * 1024,
+            ttl_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// How far behind on broadcasts a client may fall, for those which can't
+/// read as fast as the channel talks.
+///
+/// # Fields
+///
+/// - `lag_warning`: Messages waiting for a client before it is logged as
+///   lagging. `0` never warns.
+/// - `max_queued`: Messages waiting for a client before it is disconnected
+///   to keep its queue from growing without end. `0` never disconnects.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Delivery {
+    pub(crate) lag_warning: usize,
+    pub(crate) max_queued: usize,
+}
+
+impl Default for Delivery {
+    fn default() -> Self {
+        Self {
+            lag_warning: 1000,
+            max_queued: 10_000,
+        }
+    }
+}
+
+/// Mentions kept for nicks which aren't there to see them, see
+/// `away_log::AwayLog`.
+///
+/// # Fields
+///
+/// - `max_mentions`: How many to keep for each nick, the oldest go first.
+///   `0` keeps none.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct AwayLog {
+    pub(crate) max_mentions: usize,
+}
+
+impl Default for AwayLog {
+    fn default() -> Self {
+        Self { max_mentions: 50 }
+    }
+}
+
+/// What happens to connections using a registered nick without being
+/// logged into its account, such as one which had it before it was
+/// registered, see `nick_guard::NickGuard`.
+///
+/// # Fields
+///
+/// - `grace_secs`: How long they are given to log in or change nick after
+///   being warned, before they are renamed to a guest nick. `0` leaves
+///   them be.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct NickProtection {
+    pub(crate) grace_secs: u64,
+}
+
+impl Default for NickProtection {
+    fn default() -> Self {
+        Self { grace_secs: 60 }
+    }
+}
+
+/// What the server logs and where, see `logging::init`.
+///
+/// # Fields
+///
+/// - `level`: The least severe level logged, one of `error`, `warn`,
+///   `info`, `debug` or `trace`. Modules can be given their own, as in
+///   `info,solace_server::delivery=warn`. `RUST_LOG` overrides this.
+/// - `json_path`: A file to append every line logged to as well, as one
+///   JSON object each, for going through afterwards.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Logging {
+    pub(crate) level: String,
+    pub(crate) json_path: Option<PathBuf>,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            level: "info".to_owned(),
+            json_path: None,
+        }
+    }
+}
+
+/// How fast each account, or IP for those not logged in, may send requests,
+/// see `rate_limit::RateLimits`. Those over the limit are refused with
+/// `ERR_RATE_LIMITED` and the wait before the next is allowed.
+///
+/// # Fields
+///
+/// - `messages_per_sec`: Requests allowed a second on average. `0` turns
+///   the limit off.
+/// - `burst`: Requests which may be sent at once after a quiet spell.
+/// - `max_strikes`: Requests refused, without the client letting up for
+///   long enough to send a whole burst again, before it is disconnected.
+///   `0` never disconnects.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct RateLimit {
+    pub(crate) messages_per_sec: f64,
+    pub(crate) burst: u32,
+    pub(crate) max_strikes: u32,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            messages_per_sec: 5.0,
+            burst: 10,
+            max_strikes: 20,
+        }
+    }
+}
+
+/// What a client is sent on joining, see `welcome::WelcomeBuilder`.
+///
+/// # Fields
+///
+/// - `greeting`: The first thing sent, empty to send none.
+/// - `motd`: The message of the day, sent after the greeting. Empty to send
+///   none.
+/// - `motd_path`: File to read the message of the day from instead, on each
+///   join so that it can be changed without a restart.
+/// - `topic`: Whether to send the topic.
+/// - `command_list`: Whether to send the commands the server knows, which
+///   clients complete and offer help for.
+/// - `nick_list`: Whether to send who is in the channel, otherwise it
+///   arrives with the next join, part or nick change.
+/// - `tips`: Sent last to guests and to new accounts, one response each.
+/// - `tips_for_days`: How long after an account is first seen it is given
+///   tips. `0` never gives any.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Welcome {
+    pub(crate) greeting: String,
+    pub(crate) motd: String,
+    pub(crate) motd_path: Option<PathBuf>,
+    pub(crate) topic: bool,
+    pub(crate) command_list: bool,
+    pub(crate) nick_list: bool,
+    pub(crate) tips: Vec<String>,
+    pub(crate) tips_for_days: u64,
+}
+
+impl Default for Welcome {
+    fn default() -> Self {
+        Self {
+            greeting: "Welcome to solace!".to_owned(),
+            motd: String::new(),
+            motd_path: None,
+            topic: true,
+            command_list: true,
+            nick_list: true,
+            tips: vec![
+                "Press F1 for every command and keybinding".to_owned(),
+                "Keep your nick by logging into an account with /login <account>".to_owned(),
+                "Mention someone with @nick to notify them".to_owned(),
+            ],
+            tips_for_days: 7,
+        }
+    }
+}
+
+/// Who runs the channel.
+///
+/// # Fields
+///
+/// - `founders`: Accounts which found the channel, outranking everyone else.
+/// - `permissions`: The level needed for each of the things not everyone
+///   may do.
+/// - `max_mentions`: Messages mentioning more nicks than this need the
+///   `mass_mention` permission. `0` lets anyone mention any number.
+/// - `mass_mention_interval_secs`: How long after one of those messages
+///   the next one is held back for, so that the channel isn't pinged over
+///   and over.
+/// - `max_message_chars`: The longest chat message anyone may send, clients
+///   are told on joining so that they can offer to paste longer ones. `0`
+///   means there is no limit.
+/// - `references`: Whether a message mentioning another channel, such as
+///   `#rust`, is pointed out in that channel with a notice. Channels can
+///   opt out with `+r`.
+/// - `mentions_anywhere`: Whether `@nick` and `#channel` mention even in the
+///   middle of a word, as in `cc:@amy`, rather than only at the start of
+///   one, which leaves `me@example.com` alone. Clients are told on joining.
+/// - `persist`: Whether the topic and modes of channels are saved to the
+///   `channels` XDG data directory, to be restored when someone next joins,
+///   rather than going away with the last to leave.
+/// - `max_cold`: How many persisted channels nobody is in are kept in memory
+///   to be joined again, the longest cold are dropped beyond that.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Channel {
+    pub(crate) founders: Vec<String>,
+    pub(crate) permissions: Permissions,
+    pub(crate) max_mentions: usize,
+    pub(crate) mass_mention_interval_secs: u64,
+    pub(crate) max_message_chars: usize,
+    pub(crate) references: bool,
+    pub(crate) mentions_anywhere: bool,
+    pub(crate) persist: bool,
+    pub(crate) max_cold: usize,
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Self {
+            founders: vec![],
+            permissions: Permissions::default(),
+            max_mentions: 5,
+            mass_mention_interval_secs: 60,
+            max_message_chars: 4000,
+            references: false,
+            mentions_anywhere: false,
+            persist: false,
+            max_cold: 256,
+        }
+    }
+}
+
+impl Channel {
+    /// How chat messages are parsed for mentions.
+    pub(crate) fn parser_options(&self) -> ParserOptions {
+        ParserOptions {
+            mentions: if self.mentions_anywhere {
+                MentionPolicy::Anywhere
+            } else {
+                MentionPolicy::WordBoundary
+            },
+        }
+    }
+}
+
+/// The lowest level allowed to do each thing, one of `member`, `voice`,
+/// `op` or `founder`.
+///
+/// # Fields
+///
+/// - `topic`: Setting the topic, open to every member by default.
+/// - `kick`: Disconnecting nicks below one's own level.
+/// - `invite`: Inviting nicks in.
+/// - `mode`: Granting or taking away levels below one's own.
+/// - `mass_mention`: Mentioning more than `max_mentions` nicks at once, or
+///   everyone with `@here` or `@all`.
+/// - `upload`: Uploading attachments, open to every member by default.
+/// - `reference`: Having messages pointed out in the channels they mention,
+///   open to every member by default.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Permissions {
+    pub(crate) topic: Level,
+    pub(crate) kick: Level,
+    pub(crate) invite: Level,
+    pub(crate) mode: Level,
+    pub(crate) mass_mention: Level,
+    pub(crate) upload: Level,
+    pub(crate) reference: Level,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            topic: Level::Member,
+            kick: Level::Op,
+            invite: Level::Op,
+            mode: Level::Op,
+            mass_mention: Level::Op,
+            upload: Level::Member,
+            reference: Level::Member,
+        }
+    }
+}
+
+impl Permissions {
+    pub(crate) fn required(&self, permission: Permission) -> Level {
+        match permission {
+            Permission::Topic => self.topic,
+            Permission::Kick => self.kick,
+            Permission::Invite => self.invite,
+            Permission::Mode => self.mode,
+            Permission::MassMention => self.mass_mention,
+            Permission::Upload => self.upload,
+            Permission::Reference => self.reference,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn new() -> anyhow::Result<Self> {
+        let base_path = xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+
+        match base_path.find_config_file("server.toml") {
+            Some(path) => {
+                let config_raw = fs::read_to_string(&path)
+                    .with_context(|| format!("ERROR: Failed to read file: {path:?}"))?;
+
+                toml::from_str(&config_raw)
+                    .with_context(|| format!("ERROR: Failed to parse {path:?}"))
+            }
+            None => Ok(Self::default()),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use solace_protocol::command::CommandSpec;
+use tracing::{error, info};
+
+use crate::command;
+use crate::config::Config;
+use crate::{Message, Server};
+
+/// A command defined in the server's config which answers with some text,
+/// e.g. `/rules`, or expands its arguments into a template, e.g. `/issue
+/// <n>` giving a link to the issue.
+///
+/// # Fields
+///
+/// - `args`: Usage of the arguments, as in `CommandSpec`, e.g. `<n>`.
+/// - `help`: What the command does, in a line for `/help`.
+/// - `response`: What the command answers with, where `{name}` is replaced
+///   by the argument of that name.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct CustomCommand {
+    pub(crate) args: String,
+    pub(crate) help: String,
+    pub(crate) response: String,
+}
+
+/// The custom commands in use, kept apart from the rest of the config so
+/// that they can be reloaded without a restart.
+///
+/// # Fields
+///
+/// - `commands`: Usage and definition of each command, sorted by name.
+#[derive(Debug, Default)]
+pub(crate) struct CustomCommands {
+    commands: Vec<(CommandSpec, CustomCommand)>,
+}
+
+impl CustomCommands {
+    /// Skips any command which can't be used, saying why.
+    pub(crate) fn new<'a>(commands: impl Iterator<Item = (&'a String, &'a CustomCommand)>) -> Self {
+        let mut loaded = vec![];
+
+        for (name, command) in commands {
+            if command::find(name).is_some() {
+                error!("Custom command /{name} would replace a built in one, skipping");
+                continue;
+            }
+
+            match CommandSpec::parse(&format!("{name} {}", command.args)) {
+                Some(spec) if !name.contains(char::is_whitespace) => {
+                    loaded.push((spec, command.clone()));
+                }
+                _ => error!("Custom command /{name} has invalid args, skipping"),
+            }
+        }
+
+        loaded.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+
+        Self { commands: loaded }
+    }
+
+    pub(crate) fn specs_with_help(&self) -> impl Iterator<Item = (&CommandSpec, &str)> {
+        self.commands
+            .iter()
+            .map(|(spec, command)| (spec, command.help.as_str()))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// The response to `/name args`, or why there isn't one.
+    pub(crate) fn respond(&self, name: &str, args: &str) -> Result<String, String> {
+        let Some((spec, command)) = self.commands.iter().find(|(spec, _)| spec.name == name) else {
+            return Err(format!("Unknown command: /{name}"));
+        };
+
+        let values = split_args(args, spec.args.len());
+
+        if !spec.accepts(values.len()) {
+            return Err(format!("Usage: /{}", spec.usage()));
+        }
+
+        let mut response = command.response.clone();
+        for (i, arg) in spec.args.iter().enumerate() {
+            let value = values.get(i).copied().unwrap_or_default();
+            response = response.replace(&format!("{{{}}}", arg.name), value);
+        }
+
+        Ok(response.trim_end().to_owned())
+    }
+}
+
+/// Reads the custom commands from the config again whenever the server is
+/// sent SIGHUP, pushing the new command list to every client.
+#[cfg(unix)]
+pub(crate) async fn reload_on_hangup(server: Arc<Server>) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+
+    while hangups.recv().await.is_some() {
+        match Config::new() {
+            Ok(config) => {
+                let commands = CustomCommands::new(config.commands.iter());
+                info!("Reloaded {} custom commands", commands.len());
+
+                *server.custom_commands() = commands;
+                server.broadcast_all(Message::CommandsChanged).await;
+            }
+            Err(err) => error!("{err:#}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits `args` on whitespace into at most `max` values, the last of which
+/// keeps the rest so that a variadic argument gets all of it.
+fn split_args(args: &str, max: usize) -> Vec<&str> {
+    let mut values = vec![];
+    let mut rest = args.trim();
+
+    while !rest.is_empty() {
+        if values.len() + 1 == max {
+            values.push(rest);
+            break;
+        }
+
+        match rest.split_once(char::is_whitespace) {
+            Some((value, tail)) => {
+                values.push(value);
+                rest = tail.trim_start();
+            }
+            None => {
+                values.push(rest);
+                break;
+            }
+        }
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn commands() -> CustomCommands {
+        let mut commands = BTreeMap::new();
+        commands.insert(
+            "rules".to_owned(),
+            CustomCommand {
+                response: "Be nice".to_owned(),
+                ..CustomCommand::default()
+            },
+        );
+        commands.insert(
+            "issue".to_owned(),
+            CustomCommand {
+                args: "<n> [note...]".to_owned(),
+                response: "https://example.com/issues/{n} {note}".to_owned(),
+                ..CustomCommand::default()
+            },
+        );
+        commands.insert(
+            "ping".to_owned(),
+            CustomCommand {
+                response: "Shadowed".to_owned(),
+                ..CustomCommand::default()
+            },
+        );
+
+        CustomCommands::new(commands.iter())
+    }
+
+    #[test]
+    fn test_respond() {
+        let commands = commands();
+
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands.respond("rules", ""), Ok("Be nice".to_owned()));
+        assert_eq!(
+            commands.respond("issue", "12 see the  logs"),
+            Ok("https://example.com/issues/12 see the  logs".to_owned())
+        );
+        assert_eq!(
+            commands.respond("issue", "12"),
+            Ok("https://example.com/issues/12".to_owned())
+        );
+        assert_eq!(
+            commands.respond("issue", ""),
+            Err("Usage: /issue <n> [note...]".to_owned())
+        );
+        assert_eq!(
+            commands.respond("rules", "extra"),
+            Err("Usage: /rules".to_owned())
+        );
+        assert!(commands.respond("ping", "").is_err());
+    }
+}
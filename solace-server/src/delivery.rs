@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::Server;
+
+const REPORT_EVERY: Duration = Duration::from_secs(60);
+
+/// Counts of messages which didn't reach a client, kept for operators as
+/// every broadcast is otherwise fire and forget.
+///
+/// # Fields
+///
+/// - `failed`: Messages sent to a client whose task had already finished.
+/// - `lagged`: Times a client fell `lag_warning` messages behind.
+/// - `disconnected`: Clients dropped for falling `max_queued` messages
+///   behind, rather than letting their queue grow without end.
+#[derive(Debug, Default)]
+pub(crate) struct Delivery {
+    failed: AtomicU64,
+    lagged: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+impl Delivery {
+    pub(crate) fn failed(&self, nick: &str) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        warn!("Couldn't deliver a message to {nick}, who is disconnecting");
+    }
+
+    pub(crate) fn lagged(&self, nick: &str, queued: usize) {
+        self.lagged.fetch_add(1, Ordering::Relaxed);
+        warn!("{nick} is {queued} messages behind");
+    }
+
+    pub(crate) fn disconnected(&self, nick: &str, queued: usize) {
+        self.disconnected.fetch_add(1, Ordering::Relaxed);
+        warn!("Disconnecting {nick} for being {queued} messages behind");
+    }
+
+    pub(crate) fn summary(&self) -> String {
+        format!(
+            "{} undeliverable messages, {} lagging clients and {} disconnected for falling behind",
+            self.failed.load(Ordering::Relaxed),
+            self.lagged.load(Ordering::Relaxed),
+            self.disconnected.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// Logs the counts every so often, whenever they have changed.
+pub(crate) async fn report_periodically(server: Arc<Server>) {
+    let mut interval = tokio::time::interval(REPORT_EVERY);
+    let mut last = server.delivery.summary();
+
+    loop {
+        interval.tick().await;
+
+        let summary = server.delivery.summary();
+        if summary != last {
+            info!("Deliveries: {summary}");
+            last = summary;
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+use solace_protocol::bookmark::Bookmark;
+use solace_protocol::codec::SharedFrame;
+
+/// The most recent chat messages, kept so that they can be replayed to
+/// clients as they join.
+///
+/// # Fields
+///
+/// - `entries`: Oldest first, each numbered by the order it was sent in and
+///   kept as it would be bookmarked.
+/// - `next_seq`: The number given to the next message, starting from `1` so
+///   that `0` can mean nothing has been replayed.
+/// - `capacity`: How many messages to keep, `0` keeps none.
+pub(crate) struct Backlog {
+    entries: VecDeque<(u64, SharedFrame, Bookmark)>,
+    next_seq: u64,
+    capacity: usize,
+}
+
+impl Backlog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            // Grows as messages are sent rather than reserving `capacity` up
+            // front, as most servers never fill it
+            entries: VecDeque::new(),
+            next_seq: 1,
+            capacity,
+        }
+    }
+
+    /// The sequence number the next message pushed will be given.
+    pub(crate) fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Keeps `frame`, dropping the oldest message if full, and returns its
+    /// sequence number, which `bookmark` is given too.
+    pub(crate) fn push(&mut self, frame: SharedFrame, mut bookmark: Bookmark) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        if self.capacity > 0 {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+
+            bookmark.message_id = seq;
+            self.entries.push_back((seq, frame, bookmark));
+        }
+
+        seq
+    }
+
+    /// The message numbered `seq`, as long as it is still kept.
+    pub(crate) fn get(&self, seq: u64) -> Option<&Bookmark> {
+        self.entries
+            .iter()
+            .find(|(s, _, _)| *s == seq)
+            .map(|(_, _, bookmark)| bookmark)
+    }
+
+    /// Every message kept, oldest first, along with the sequence number of
+    /// the last message sent whether or not it was kept.
+    pub(crate) fn snapshot(&self) -> (VecDeque<SharedFrame>, u64) {
+        let frames = self
+            .entries
+            .iter()
+            .map(|(_, frame, _)| frame.clone())
+            .collect();
+
+        (frames, self.next_seq - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solace_protocol::response::ResponseBuilder;
+
+    use super::*;
+
+    fn frame(message: &str) -> SharedFrame {
+        SharedFrame::new(&ResponseBuilder::new(200, message.to_owned()).build()).unwrap()
+    }
+
+    #[test]
+    fn test_keeps_the_newest() {
+        let mut backlog = Backlog::new(2);
+
+        assert_eq!(backlog.push(frame("a"), Bookmark::default()), 1);
+        assert_eq!(backlog.push(frame("b"), Bookmark::default()), 2);
+        assert_eq!(backlog.push(frame("c"), Bookmark::default()), 3);
+
+        let (frames, last_seq) = backlog.snapshot();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(last_seq, 3);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut backlog = Backlog::new(2);
+        let bookmark = |message: &str| Bookmark {
+            message: message.to_owned(),
+            ..Bookmark::default()
+        };
+
+        backlog.push(frame("a"), bookmark("a"));
+        backlog.push(frame("b"), bookmark("b"));
+        backlog.push(frame("c"), bookmark("c"));
+
+        assert_eq!(backlog.next_seq(), 4);
+        assert!(backlog.get(1).is_none());
+        assert_eq!(backlog.get(2).unwrap().message, "b");
+        assert_eq!(backlog.get(2).unwrap().message_id, 2);
+    }
+
+    #[test]
+    fn test_disabled_still_counts() {
+        let mut backlog = Backlog::new(0);
+        backlog.push(frame("a"), Bookmark::default());
+
+        let (frames, last_seq) = backlog.snapshot();
+        assert!(frames.is_empty());
+        assert_eq!(last_seq, 1);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, RwLock, Weak};
+
+/// A handle to a nick held by an `Interner`, cheap to clone, compare and
+/// hash, so nicks can be passed around the server without allocating.
+///
+/// Handles are counted rather than `Copy` ids into a table, and the string
+/// is released once the last one is dropped, so a nick given up by a rename
+/// or disconnect doesn't stay around for the server's lifetime. A rename
+/// therefore hands the connection a new handle instead of changing what an
+/// id stands for.
+///
+/// Only nicks are interned: channel names, accounts and history records
+/// stay `String`s, as they are few or already stored once.
+#[derive(Clone)]
+pub(crate) struct Symbol(Arc<str>);
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        // Equal strings share one allocation while either is alive
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<u8>().hash(state);
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Symbol({:?})", &*self.0)
+    }
+}
+
+/// Hands out one `Symbol` per distinct string in use.
+#[derive(Default)]
+pub(crate) struct Interner {
+    inner: RwLock<Inner>,
+}
+
+/// # Fields
+///
+/// - `symbols`: Every string interned, by itself, including those whose
+///   symbols have all been dropped since the last prune.
+/// - `prune_at`: How many entries `symbols` may hold before the dead ones are
+///   pruned, double the number alive after the last prune so that pruning
+///   takes constant time per string interned.
+#[derive(Default)]
+struct Inner {
+    symbols: HashMap<Box<str>, Weak<str>>,
+    prune_at: usize,
+}
+
+/// The fewest entries worth pruning.
+const MIN_PRUNE_AT: usize = 64;
+
+impl Interner {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn intern(&self, string: &str) -> Symbol {
+        if let Some(symbol) = self.get(string) {
+            return symbol;
+        }
+
+        let mut inner = self.inner.write().expect("ERROR: Interner lock poisoned");
+
+        // Someone else may have interned it since we looked
+        if let Some(symbol) = inner.symbols.get(string).and_then(Weak::upgrade) {
+            return Symbol(symbol);
+        }
+
+        if inner.symbols.len() >= inner.prune_at.max(MIN_PRUNE_AT) {
+            inner.symbols.retain(|_, symbol| symbol.strong_count() > 0);
+            inner.prune_at = inner.symbols.len() * 2;
+        }
+
+        let symbol: Arc<str> = Arc::from(string);
+        inner
+            .symbols
+            .insert(Box::from(string), Arc::downgrade(&symbol));
+
+        Symbol(symbol)
+    }
+
+    /// Looks up `string` without interning it, e.g. for a nick which may not
+    /// belong to anyone.
+    pub(crate) fn get(&self, string: &str) -> Option<Symbol> {
+        self.inner
+            .read()
+            .expect("ERROR: Interner lock poisoned")
+            .symbols
+            .get(string)
+            .and_then(Weak::upgrade)
+            .map(Symbol)
+    }
+
+    pub(crate) fn resolve(&self, symbol: &Symbol) -> Arc<str> {
+        Arc::clone(&symbol.0)
+    }
+
+    /// How many strings are held, dead ones not yet pruned included.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.inner
+            .read()
+            .expect("ERROR: Interner lock poisoned")
+            .symbols
+            .len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_string_same_symbol() {
+        let interner = Interner::new();
+        let a = interner.intern("alice");
+        let b = interner.intern("bob");
+
+        assert_eq!(interner.intern("alice"), a);
+        assert_ne!(a, b);
+        assert_eq!(&*interner.resolve(&b), "bob");
+        assert_eq!(interner.get("carol"), None);
+    }
+
+    #[test]
+    fn test_releases_dropped_symbols() {
+        let interner = Interner::new();
+        let alice = interner.intern("alice");
+
+        drop(interner.intern("bob"));
+        assert_eq!(interner.get("bob"), None);
+
+        // Like a server handing every connection a random nick which it
+        // then changes
+        for i in 0..10_000 {
+            drop(interner.intern(&format!("guest{i}")));
+        }
+
+        assert!(interner.len() <= MIN_PRUNE_AT * 2);
+        assert_eq!(interner.get("alice"), Some(alice));
+    }
+}
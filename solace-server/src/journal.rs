@@ -0,0 +1,311 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use serde::Serialize;
+use solace_protocol::request::RequestMessage;
+use tracing::error;
+
+use crate::config;
+
+/// Something which happened on the server, written to the journal as one
+/// JSON object per line tagged by `event`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event<'a> {
+    Connect {
+        addr: SocketAddr,
+        nick: &'a str,
+    },
+    Message {
+        nick: &'a str,
+        message: &'a str,
+    },
+    /// Recorded apart from other commands, as are `Ghost`, `Register` and
+    /// `Auth`, so that the password is left out.
+    Login {
+        nick: &'a str,
+        account: &'a str,
+    },
+    Ghost {
+        nick: &'a str,
+        target: &'a str,
+    },
+    Register {
+        nick: &'a str,
+    },
+    Auth {
+        nick: &'a str,
+    },
+    /// An attachment by its name and size in bytes, as its data would swell
+    /// the journal.
+    Upload {
+        nick: &'a str,
+        name: &'a str,
+        size: usize,
+    },
+    /// Any request other than a chat message, an upload or one carrying a
+    /// password, as it was decoded.
+    Command {
+        nick: &'a str,
+        request: &'a RequestMessage,
+    },
+    Disconnect {
+        nick: &'a str,
+    },
+}
+
+impl<'a> Event<'a> {
+    pub(crate) fn for_request(nick: &'a str, request: &'a RequestMessage) -> Self {
+        match request {
+            RequestMessage::Message(message) => Event::Message { nick, message },
+            RequestMessage::Login { account, .. } => Event::Login { nick, account },
+            RequestMessage::Ghost { nick: target, .. } => Event::Ghost { nick, target },
+            RequestMessage::Register(_) => Event::Register { nick },
+            RequestMessage::Auth(_) => Event::Auth { nick },
+            // The data is hex encoded, so two characters to a byte
+            RequestMessage::Upload { name, data } => Event::Upload {
+                nick,
+                name,
+                size: data.len() / 2,
+            },
+            request => Event::Command { nick, request },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    seq: u64,
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// An append only record of every event, written on a thread of its own so
+/// that a slow disk never holds up a client. Turned off unless the config
+/// gives it a path.
+///
+/// # Fields
+///
+/// - `seq`: The number of the last event, so that they can be put back in
+///   order even where timestamps are equal.
+pub(crate) struct Journal {
+    tx: Option<mpsc::Sender<String>>,
+    seq: AtomicU64,
+}
+
+impl Journal {
+    pub(crate) fn new(config: &config::Journal) -> Self {
+        let tx = config.path.as_ref().map(|path| {
+            let (tx, rx) = mpsc::channel::<String>();
+            let mut writer = Writer::new(path.clone(), config.max_bytes, config.keep);
+
+            thread::spawn(move || {
+                for line in rx {
+                    if let Err(err) = writer.write(&line) {
+                        error!("Failed to write to the journal: {err}");
+                    }
+                }
+            });
+
+            tx
+        });
+
+        Self {
+            tx,
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record(&self, event: Event) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+
+        let entry = Entry {
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) + 1,
+            timestamp: crate::now(),
+            event: &event,
+        };
+
+        match serde_json::to_string(&entry) {
+            // Only fails once the writer thread has gone, which it never does
+            Ok(line) => _ = tx.send(line),
+            Err(err) => error!("Failed to encode journal event {event:?}: {err}"),
+        }
+    }
+}
+
+/// Appends lines to the journal file, moving it aside once it grows past
+/// `max_bytes` so that `<path>.1` is always the most recent of the rotated
+/// files. A `max_bytes` of `0` never rotates.
+struct Writer {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+impl Writer {
+    fn new(path: PathBuf, max_bytes: u64, keep: usize) -> Self {
+        Self {
+            path,
+            max_bytes,
+            keep,
+            file: None,
+            size: 0,
+        }
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.max_bytes > 0 && self.size + line.len() as u64 + 1 > self.max_bytes && self.size > 0
+        {
+            self.rotate()?;
+        }
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?;
+                self.size = file.metadata()?.len();
+                self.file.insert(file)
+            }
+        };
+
+        writeln!(file, "{line}")?;
+        self.size += line.len() as u64 + 1;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        self.size = 0;
+
+        if self.keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        for i in (1..self.keep).rev() {
+            let from = rotated(&self.path, i);
+
+            if from.exists() {
+                fs::rename(from, rotated(&self.path, i + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, rotated(&self.path, 1))
+    }
+}
+
+fn rotated(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{i}"));
+
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use solace_protocol::request::Secret;
+
+    use super::*;
+
+    #[test]
+    fn test_rotates_and_keeps_the_newest() {
+        let dir = std::env::temp_dir().join(format!("solace-journal-{}", std::process::id()));
+        let path = dir.join("journal.jsonl");
+        fs::create_dir_all(&dir).unwrap();
+
+        // Room for two lines of 9 bytes each, newline included
+        let mut writer = Writer::new(path.clone(), 18, 2);
+        for i in 0..7 {
+            writer.write(&format!("event {i}:")).unwrap();
+        }
+
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "event 6:\n");
+        assert_eq!(read(&rotated(&path, 1)), "event 4:\nevent 5:\n");
+        assert_eq!(read(&rotated(&path, 2)), "event 2:\nevent 3:\n");
+        assert!(!rotated(&path, 3).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_entry_shape() {
+        let request = RequestMessage::NewNick("bob".to_owned());
+        let event = Event::for_request("alice", &request);
+        let entry = Entry {
+            seq: 3,
+            timestamp: 100,
+            event: &event,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"seq":3,"timestamp":100,"event":"command","nick":"alice","request":{"NewNick":"bob"}}"#
+        );
+    }
+
+    #[test]
+    fn test_upload_leaves_out_data() {
+        let request = RequestMessage::Upload {
+            name: "notes.txt".to_owned(),
+            data: solace_protocol::attachment::encode_data(b"hello"),
+        };
+        let line = serde_json::to_string(&Event::for_request("bob", &request)).unwrap();
+
+        assert_eq!(
+            line,
+            r#"{"event":"upload","nick":"bob","name":"notes.txt","size":5}"#
+        );
+    }
+
+    #[test]
+    fn test_leaves_out_passwords() {
+        let secret = || Secret("hunter2".to_owned());
+        let cases = [
+            (
+                RequestMessage::Login {
+                    account: "alice".to_owned(),
+                    password: Some(secret()),
+                },
+                r#"{"event":"login","nick":"bob","account":"alice"}"#,
+            ),
+            (
+                RequestMessage::Ghost {
+                    nick: "alice".to_owned(),
+                    password: secret(),
+                },
+                r#"{"event":"ghost","nick":"bob","target":"alice"}"#,
+            ),
+            (
+                RequestMessage::Register(secret()),
+                r#"{"event":"register","nick":"bob"}"#,
+            ),
+            (
+                RequestMessage::Auth(secret()),
+                r#"{"event":"auth","nick":"bob"}"#,
+            ),
+        ];
+
+        for (request, expected) in cases {
+            let line = serde_json::to_string(&Event::for_request("bob", &request)).unwrap();
+            assert_eq!(line, expected);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::{fs::OpenOptions, io, sync::Mutex};
+
+use anyhow::Context;
+use tracing::Level;
+use tracing_subscriber::{
+    fmt, fmt::writer::MakeWriterExt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
+
+use crate::config;
+
+/// Logs warnings and errors to stderr and everything else to stdout, and
+/// each line as JSON to `json_path` too if it is set. Lines logged while a
+/// client is being served carry its address and nick, see `handle_client`.
+pub(crate) fn init(config: &config::Logging) -> anyhow::Result<()> {
+    let json = match &config.json_path {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("ERROR: Failed to open {path:?} to log to"))?;
+
+            Some(fmt::layer().json().with_writer(Mutex::new(file)))
+        }
+        None => None,
+    };
+
+    let console = io::stderr.with_max_level(Level::WARN).or_else(io::stdout);
+
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => filter(&config.level)?,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(console))
+        .with(json)
+        .try_init()
+        .context("ERROR: Failed to set up logging")
+}
+
+fn filter(level: &str) -> anyhow::Result<EnvFilter> {
+    EnvFilter::try_new(level).with_context(|| format!("ERROR: Invalid log level {level:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_are_checked() {
+        assert!(filter("warn,solace_server::delivery=debug").is_ok());
+        assert!(filter("solace_server=loud").is_err());
+    }
+}
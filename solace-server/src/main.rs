@@ -1,38 +1,122 @@
 #![allow(dead_code)]
 
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use clap::Parser;
 use futures::sink::SinkExt;
 use rand::Rng;
-use tokio::io::{split, ReadHalf, WriteHalf};
+use tokio::io::{split, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::mpsc;
 use tokio_stream::StreamExt;
 use tokio_util::codec::{FramedRead, FramedWrite};
+use tracing::{debug, error, info, warn, Span};
 
+use solace_message_parser::Everyone;
+use solace_protocol::bookmark::Bookmark;
+use solace_protocol::build_info::BuildInfo;
+use solace_protocol::capability::{COMMAND_HELP, EXPERIMENTAL};
+use solace_protocol::channel::ChannelText;
 use solace_protocol::code::{
-    ERR_WHO_IS, RES_ACK_MESSAGE, RES_CHAT_MESSAGE_OK, RES_COMMAND_LIST, RES_GOODBYE, RES_HELLO,
-    RES_NICK_CHANGE, RES_NICK_LIST, RES_PONG, RES_TOPIC_CHANGE, RES_TOPIC_CHANGE_MESSAGE,
-    RES_WELCOME, RES_WHO_IS, RES_YOUR_NICK,
+    ERR_AUTH_REQUIRED, ERR_PROTOCOL, ERR_QUOTA_EXCEEDED, ERR_RATE_LIMITED, ERR_WHO_IS,
+    RES_ACK_MESSAGE, RES_CHANNEL_MEMBERS, RES_CHANNEL_NOTICE, RES_CHANNEL_TOPIC, RES_COMMAND_LIST,
+    RES_DIRECT_MESSAGE, RES_DISCONNECTED, RES_EVERYONE_MENTIONED, RES_GOODBYE, RES_HELLO,
+    RES_MENTIONED, RES_MOTD, RES_NICK_CHANGE, RES_NICK_LIST, RES_SELF_DIRECT_MESSAGE,
+    RES_SELF_MESSAGE, RES_TOPIC_CHANGE, RES_TOPIC_CHANGE_MESSAGE, RES_WHO_IS, RES_YOUR_NICK,
 };
+use solace_protocol::codec::{FrameCodec, SharedFrame};
+use solace_protocol::level::Level;
+use solace_protocol::presence::{NickListEntry, Presence};
 use solace_protocol::request::{Request, RequestMessage};
 use solace_protocol::response::{Response, ResponseBuilder};
-use std::collections::HashMap;
+use solace_protocol::version::V1;
+use std::collections::VecDeque;
+use std::future;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
-type Tx = mpsc::UnboundedSender<Message>;
-type Rx = mpsc::UnboundedReceiver<Message>;
+use crate::accounts::Accounts;
+use crate::attachments::Attachments;
+use crate::away_log::AwayLog;
+use crate::bookmarks::Bookmarks;
+use crate::channel::Levels;
+use crate::channels::Channels;
+use crate::command::Flow;
+use crate::config::Config;
+use crate::custom::CustomCommands;
+use crate::delivery::Delivery;
+use crate::history::Backlog;
+use crate::interner::{Interner, Symbol};
+use crate::journal::{Event, Journal};
+use crate::nick_guard::NickGuard;
+use crate::rate_limit::{RateLimits, Verdict};
+use crate::receipts::Receipts;
+use crate::registry::ClientRegistry;
+use crate::schedule::Schedule;
+use crate::stats::Stats;
+use crate::transport::Stream;
+use crate::triggers::Triggers;
+use crate::usage::{DailyUsage, Identity, UsageTracker};
+use crate::welcome::WelcomeBuilder;
 
+mod accounts;
+mod attachments;
+mod away_log;
+mod bookmarks;
+mod channel;
+mod channels;
+mod cli;
+mod command;
+mod config;
+mod custom;
+mod delivery;
+mod history;
+mod interner;
+mod journal;
+mod logging;
+mod nick_guard;
+#[cfg(feature = "previews")]
+mod previews;
+mod proxy;
+#[cfg(feature = "quic")]
+mod quic;
+mod rate_limit;
+mod receipts;
+mod registry;
+mod schedule;
+mod sniff;
+mod stats;
+mod tls;
+mod transport;
+mod triggers;
+#[cfg(unix)]
+mod unix;
+mod usage;
+mod welcome;
+
+/// How many backlog messages are written to a joining client between flushes.
+const REPLAY_PAGE: usize = 64;
+
+// Messages are shared between every recipient of a broadcast rather than
+// being cloned once per connection.
+type Tx = mpsc::UnboundedSender<Arc<Message>>;
+type Rx = mpsc::UnboundedReceiver<Arc<Message>>;
+
+/// Queues a response for the client, it is written out on the next flush at
+/// the top of the client loop.
 macro_rules! respond {
     ($client: expr, $code: ident, $msg: expr) => {
         $client
             .res
-            .send(ResponseBuilder::new($code, $msg).build())
+            .feed(ResponseBuilder::new($code, $msg).build())
             .await?;
     };
     ($client: expr, $code: ident, $msg: expr, $origin :expr) => {
         $client
             .res
-            .send(
+            .feed(
                 ResponseBuilder::new($code, $msg)
                     .with_origin($origin)
                     .build(),
@@ -41,19 +125,27 @@ macro_rules! respond {
     };
 }
 
+pub(crate) use respond;
+
 #[derive(Clone, Debug)]
 struct MessageClient {
     addr: SocketAddr,
-    nick: String,
+    account: Option<String>,
+    nick: Symbol,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 enum Message {
-    ClientConnected(String),
-    ClientDisconnected(String),
+    /// A response encoded once and written as is to every recipient.
+    Frame(SharedFrame),
+    /// `frame` is the response for everyone other than the sender's own
+    /// sessions, which are told about it differently. `seq` orders it among
+    /// the messages replayed from the backlog.
     Sent {
         from: MessageClient,
         message: String,
+        frame: SharedFrame,
+        seq: u64,
     },
     TopicChanged {
         from: MessageClient,
@@ -61,83 +153,1002 @@ enum Message {
     },
     NickChanged {
         from: MessageClient,
-        new_nick: String,
+        new_nick: Symbol,
     },
     WhoIs {
         addr: Option<SocketAddr>,
         nick: String,
     },
+    Direct {
+        from: MessageClient,
+        message: String,
+    },
+    /// A direct message sent by another session of the same account.
+    DirectSynced {
+        to: String,
+        message: String,
+    },
+    Revoked,
+    /// The nick was taken back by the owner of the account by that name.
+    Ghosted,
+    /// The connection was given `nick` for using `account`'s nick without
+    /// logging in, see `Server::guard_nick`.
+    Renamed {
+        nick: Symbol,
+        account: String,
+    },
+    Kicked {
+        by: Symbol,
+        reason: Option<String>,
+    },
+    /// The custom commands were reloaded, so the command list is out of date.
+    CommandsChanged,
+}
+
+/// The server side view of a connected client, shared with the other
+/// client tasks so that they can reach it and describe it to others.
+///
+/// One account can be logged in from several connections at once, each of
+/// which is a separate session sharing the account's nick.
+///
+/// # Fields
+///
+/// - `session_id`: Identifies the session to the account's other devices.
+/// - `account`: The account this connection is logged into, if any.
+/// - `away`: The away reason, if the client has marked themselves as away.
+/// - `is_dnd`: The client doesn't want mentions or direct messages to make a
+///   sound, which is only ever set while not away.
+/// - `level`: The client's say over the channel. The first client to join an
+///   empty server is made an op, and logging in brings the account's level.
+/// - `connected_at`: Unix timestamp of when the connection was accepted.
+/// - `last_active`: Unix timestamp of the last request received from the client.
+struct Connection {
+    session_id: u32,
+    account: Option<String>,
+    nick: Symbol,
+    tx: Tx,
+    away: Option<String>,
+    is_dnd: bool,
+    level: Level,
+    connected_at: u64,
+    last_active: u64,
+}
+
+impl Connection {
+    fn new(session_id: u32, nick: Symbol, tx: Tx, level: Level) -> Self {
+        Self {
+            session_id,
+            account: None,
+            nick,
+            tx,
+            away: None,
+            is_dnd: false,
+            level,
+            connected_at: now(),
+            last_active: now(),
+        }
+    }
+
+    fn presence(&self) -> Presence {
+        match (&self.away, self.is_dnd) {
+            (Some(_), _) => Presence::Away,
+            (None, true) => Presence::DoNotDisturb,
+            (None, false) => Presence::Online,
+        }
+    }
 }
 
+/// State shared by every client task. Nothing here is behind one big lock,
+/// each piece is synchronised separately so that unrelated requests don't
+/// contend with each other.
+///
+/// # Fields
+///
+/// - `accounts`: Passwords of the accounts which have been claimed.
+/// - `levels`: Levels granted to accounts, see `Levels`.
+/// - `stats`: Messages, karma and so on by nick, see `Stats`.
+/// - `bookmarks`: Messages kept by each account, see `Bookmarks`.
+/// - `attachments`: Uploaded files, see `Attachments`.
+/// - `clients`: Every connection, see `ClientRegistry`.
+/// - `custom_commands`: Commands from the config, which can be reloaded.
+/// - `next_session_id`: The id handed to the next connection.
+/// - `nicks`: Every nick is interned so that it can be copied around freely.
+/// - `journal`: Where every event is recorded, if configured.
+/// - `backlog`: Recent chat messages to replay to clients as they join.
+/// - `usage`: Traffic counted towards the configured quotas.
+/// - `rate_limits`: How fast each account or IP has been sending requests.
+/// - `last_mass_mention`: When a message last mentioned more nicks than
+///   `max_mentions`, see `hold_mass_mention`.
+/// - `previews`: Links waiting to be previewed and those which have been.
+/// - `delivery`: Counts of broadcasts which didn't reach a client.
+/// - `channels`: The channels nicks have joined, see `Channels`.
+/// - `away_log`: Mentions of nicks which weren't there to see them.
+/// - `nick_guard`: Connections due to be renamed for using a registered
+///   nick, see `guard_nick`.
+/// - `receipts`: Who has read the messages in the backlog, see `Receipts`.
+/// - `schedule`: Changes from the config made at set times, see
+///   `run_scheduled`.
+/// - `scheduled_motd`: The message of the day last set by `schedule`, which
+///   replaces the configured one.
+/// - `triggers`: What the auto-responder answers, see `Triggers`.
+/// - `announcements`: Held from changing a nick, level or presence until
+///   everyone has been told, see `announcing`.
 struct Server {
-    clients: HashMap<SocketAddr, (String, Tx)>,
-    topic: String,
+    accounts: Mutex<Accounts>,
+    levels: Mutex<Levels>,
+    stats: Mutex<Stats>,
+    bookmarks: Mutex<Bookmarks>,
+    attachments: Mutex<Attachments>,
+    clients: ClientRegistry,
+    config: Config,
+    custom_commands: Mutex<CustomCommands>,
+    next_session_id: AtomicU32,
+    nicks: Interner,
+    journal: Journal,
+    backlog: Mutex<Backlog>,
+    topic: Mutex<String>,
+    usage: Mutex<UsageTracker>,
+    rate_limits: Mutex<RateLimits>,
+    last_mass_mention: Mutex<Option<Instant>>,
+    #[cfg(feature = "previews")]
+    previews: previews::LinkPreviews,
+    delivery: Delivery,
+    channels: Mutex<Channels>,
+    away_log: Mutex<AwayLog>,
+    nick_guard: Mutex<NickGuard>,
+    receipts: Mutex<Receipts>,
+    schedule: Schedule,
+    scheduled_motd: Mutex<Option<String>>,
+    triggers: Mutex<Triggers>,
+    announcements: Mutex<()>,
 }
 
+/// # Fields
+///
+/// - `replay`: Backlog still to be written to the client, followed by any
+///   messages sent since it joined, so that they arrive in order.
+/// - `replayed_up_to`: The sequence number of the last message in the
+///   backlog at the time of joining, anything up to it is already in
+///   `replay`.
+/// - `is_lagging`: Whether the client has been logged as too far behind on
+///   `rx`, until it catches up.
+/// - `wants_nick`: The registered nick last refused to the client, which
+///   `/auth` takes once given its password.
+/// - `session_key`: Keying material exported from the transport, for signing
+///   frames once the client asks to with the `frame-hmac` capability.
 struct Client {
+    account: Option<String>,
     addr: SocketAddr,
-    nick: String,
-    req: FramedRead<ReadHalf<TcpStream>, Request>,
-    res: FramedWrite<WriteHalf<TcpStream>, Response>,
+    capabilities: Vec<String>,
+    nick: Symbol,
+    req: FramedRead<ReadHalf<Stream>, FrameCodec<Request>>,
+    res: FramedWrite<WriteHalf<Stream>, FrameCodec<Response>>,
     rx: Rx,
     tx: Tx,
+    replay: VecDeque<SharedFrame>,
+    replayed_up_to: u64,
+    is_lagging: bool,
+    wants_nick: Option<String>,
+    session_key: Option<Vec<u8>>,
 }
 
 impl Server {
-    fn new() -> Self {
+    fn new(
+        config: Config,
+        accounts: Accounts,
+        levels: Levels,
+        stats: Stats,
+        bookmarks: Bookmarks,
+        attachments: Attachments,
+        channels: Channels,
+    ) -> Self {
         Server {
-            clients: HashMap::new(),
-            topic: "[No topic]".to_owned(),
+            accounts: Mutex::new(accounts),
+            levels: Mutex::new(levels),
+            stats: Mutex::new(stats),
+            bookmarks: Mutex::new(bookmarks),
+            attachments: Mutex::new(attachments),
+            clients: ClientRegistry::new(),
+            journal: Journal::new(&config.journal),
+            backlog: Mutex::new(Backlog::new(config.history.max_messages)),
+            receipts: Mutex::new(Receipts::new(config.history.max_messages)),
+            custom_commands: Mutex::new(CustomCommands::new(config.commands.iter())),
+            schedule: Schedule::new(&config.schedule),
+            triggers: Mutex::new(Triggers::new(&config.auto_responder)),
+            announcements: Mutex::new(()),
+            #[cfg(feature = "previews")]
+            previews: previews::LinkPreviews::new(&config.previews),
+            away_log: Mutex::new(AwayLog::new(config.away_log.max_mentions)),
+            rate_limits: Mutex::new(RateLimits::new(&config.rate_limit)),
+            config,
+            next_session_id: AtomicU32::new(1),
+            nicks: Interner::new(),
+            topic: Mutex::new("[No topic]".to_owned()),
+            usage: Mutex::new(UsageTracker::new()),
+            last_mass_mention: Mutex::new(None),
+            delivery: Delivery::default(),
+            channels: Mutex::new(channels),
+            nick_guard: Mutex::new(NickGuard::default()),
+            scheduled_motd: Mutex::new(None),
         }
     }
 
-    async fn broadcast_to(&mut self, message: Message, to: SocketAddr) {
-        if let Some((_, tx)) = self.clients.get(&to) {
-            let _ = tx.send(message.clone());
+    /// How much longer a message mentioning many nicks has to wait for the
+    /// last one to be far enough behind it, or `None` if it can go now, in
+    /// which case it becomes the last one.
+    fn hold_mass_mention(&self) -> Option<Duration> {
+        let interval = Duration::from_secs(self.config.channel.mass_mention_interval_secs);
+        let mut last = self
+            .last_mass_mention
+            .lock()
+            .expect("ERROR: Mass mention lock poisoned");
+
+        match last.map(|last| last.elapsed()) {
+            Some(elapsed) if elapsed < interval => Some(interval - elapsed),
+            _ => {
+                *last = Some(Instant::now());
+                None
+            }
         }
     }
 
-    async fn broadcast_all(&mut self, message: Message) {
-        for (_, tx) in self.clients.values_mut() {
-            let _ = tx.send(message.clone());
+    /// Held while a change which everyone is told about is made and told, so
+    /// that two changes racing each other reach everyone in the order they
+    /// were made. Only the `_now` broadcasts can be used under it, as it
+    /// mustn't be held across an `.await`.
+    fn announcing(&self) -> MutexGuard<'_, ()> {
+        self.announcements
+            .lock()
+            .expect("ERROR: Announcements lock poisoned")
+    }
+
+    fn channels(&self) -> MutexGuard<'_, Channels> {
+        self.channels.lock().expect("ERROR: Channels lock poisoned")
+    }
+
+    fn accounts(&self) -> MutexGuard<'_, Accounts> {
+        self.accounts.lock().expect("ERROR: Accounts lock poisoned")
+    }
+
+    fn custom_commands(&self) -> MutexGuard<'_, CustomCommands> {
+        self.custom_commands
+            .lock()
+            .expect("ERROR: Custom commands lock poisoned")
+    }
+
+    fn attachments(&self) -> MutexGuard<'_, Attachments> {
+        self.attachments
+            .lock()
+            .expect("ERROR: Attachments lock poisoned")
+    }
+
+    fn bookmarks(&self) -> MutexGuard<'_, Bookmarks> {
+        self.bookmarks
+            .lock()
+            .expect("ERROR: Bookmarks lock poisoned")
+    }
+
+    fn away_log(&self) -> MutexGuard<'_, AwayLog> {
+        self.away_log.lock().expect("ERROR: Away log lock poisoned")
+    }
+
+    fn receipts(&self) -> MutexGuard<'_, Receipts> {
+        self.receipts.lock().expect("ERROR: Receipts lock poisoned")
+    }
+
+    fn triggers(&self) -> MutexGuard<'_, Triggers> {
+        self.triggers.lock().expect("ERROR: Triggers lock poisoned")
+    }
+
+    fn nick_guard(&self) -> MutexGuard<'_, NickGuard> {
+        self.nick_guard
+            .lock()
+            .expect("ERROR: Nick guard lock poisoned")
+    }
+
+    fn stats(&self) -> MutexGuard<'_, Stats> {
+        self.stats.lock().expect("ERROR: Stats lock poisoned")
+    }
+
+    fn levels(&self) -> MutexGuard<'_, Levels> {
+        self.levels.lock().expect("ERROR: Levels lock poisoned")
+    }
+
+    fn level_of(&self, addr: SocketAddr) -> Level {
+        self.clients.with(&addr, |c| c.level).unwrap_or_default()
+    }
+
+    /// The highest level of any session using `nick`, `None` if nobody is.
+    fn level_of_nick(&self, nick: &Symbol) -> Option<Level> {
+        let mut level = None;
+
+        self.clients.for_each(|_, conn| {
+            if conn.nick == *nick {
+                level = level.max(Some(conn.level));
+            }
+        });
+
+        level
+    }
+
+    fn nick(&self, nick: &Symbol) -> Arc<str> {
+        self.nicks.resolve(nick)
+    }
+
+    fn topic(&self) -> String {
+        self.topic
+            .lock()
+            .expect("ERROR: Topic lock poisoned")
+            .clone()
+    }
+
+    /// Sets the topic and runs `announce` under the same lock, so that
+    /// everyone is told of topic changes in the order they were made.
+    fn set_topic(&self, topic: &str, announce: impl FnOnce()) {
+        let mut current = self.topic.lock().expect("ERROR: Topic lock poisoned");
+        topic.clone_into(&mut current);
+        announce();
+    }
+
+    fn scheduled_motd(&self) -> Option<String> {
+        self.scheduled_motd
+            .lock()
+            .expect("ERROR: Scheduled MOTD lock poisoned")
+            .clone()
+    }
+
+    fn record_usage(&self, client: &Client, bytes_in: u64, bytes_out: u64) {
+        self.usage
+            .lock()
+            .expect("ERROR: Usage lock poisoned")
+            .record(&client.identity(), bytes_in, bytes_out);
+    }
+
+    fn usage_of(&self, client: &Client) -> DailyUsage {
+        self.usage
+            .lock()
+            .expect("ERROR: Usage lock poisoned")
+            .get(&client.identity())
+    }
+
+    fn is_bot(&self, account: Option<&str>) -> bool {
+        account.is_some_and(|account| self.config.bots.accounts.iter().any(|bot| bot == account))
+    }
+
+    /// The daily byte allowance for `client`, `None` if unlimited.
+    fn quota_for(&self, client: &Client) -> Option<u64> {
+        let quota = match (&client.account, self.config.bots.daily_bytes) {
+            (Some(account), Some(daily_bytes)) if self.is_bot(Some(account)) => daily_bytes,
+            (Some(_), _) => self.config.quota.daily_bytes_per_account,
+            (None, _) => self.config.quota.daily_bytes_per_ip,
+        };
+
+        (quota > 0).then_some(quota)
+    }
+
+    fn is_over_quota(&self, client: &Client) -> bool {
+        match self.quota_for(client) {
+            Some(quota) => self.usage_of(client).total() >= quota,
+            None => false,
+        }
+    }
+
+    async fn remove_client(&self, addr: SocketAddr) {
+        let _announcing = self.announcing();
+
+        if let Some(conn) = self.clients.remove(&addr) {
+            let nick = self.nick(&conn.nick);
+            info!("Client {nick} disconnected");
+            self.journal.record(Event::Disconnect { nick: &nick });
+
+            // Other sessions of the same account are still around
+            if !self.clients.any(|_, c| c.nick == conn.nick) {
+                let goodbye =
+                    ResponseBuilder::new(RES_GOODBYE, format!("{nick} has left the channel"));
+                self.broadcast_all_now(Message::Frame(encode_once(goodbye.build())));
+            }
+
+            self.broadcast_nick_list_now();
+
+            let parted = self.channels().part_all(addr);
+            for channel in parted {
+                let notice = ChannelText::new(&channel, format!("{nick} has left"));
+                let notice = ResponseBuilder::new(RES_CHANNEL_NOTICE, notice.encode()).build();
+                self.broadcast_channel(&channel, Message::Frame(encode_once(notice)), None);
+                self.broadcast_channel_members(&channel);
+            }
+        }
+    }
+
+    fn allocate_session_id(&self) -> u32 {
+        self.next_session_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Every connection logged into `account`, including the caller's own.
+    fn sessions_of<R>(
+        &self,
+        account: &str,
+        mut f: impl FnMut(&SocketAddr, &Connection) -> R,
+    ) -> Vec<R> {
+        let mut sessions = vec![];
+
+        self.clients.for_each(|addr, conn| {
+            if conn.account.as_deref() == Some(account) {
+                sessions.push(f(addr, conn));
+            }
+        });
+
+        sessions
+    }
+
+    /// Queues `message` for `conn`, counting it if the connection's task has
+    /// already finished.
+    fn deliver(&self, conn: &Connection, message: Arc<Message>) {
+        if conn.tx.send(message).is_err() {
+            self.delivery.failed(&self.nick(&conn.nick));
         }
     }
 
-    async fn broadcast_others(&mut self, message: Message, sender: SocketAddr) {
-        for (addr, (_, tx)) in self.clients.iter_mut() {
+    async fn broadcast_account_others(&self, message: Message, account: &str, sender: SocketAddr) {
+        let message = Arc::new(message);
+
+        self.clients.for_each(|addr, conn| {
+            if *addr != sender && conn.account.as_deref() == Some(account) {
+                self.deliver(conn, Arc::clone(&message));
+            }
+        });
+    }
+
+    /// Sends to every connection using `nick`, which reaches all of the
+    /// sessions of an account. Returns whether anyone was there to receive it.
+    async fn broadcast_nick(&self, message: Message, nick: &Symbol) -> bool {
+        let message = Arc::new(message);
+        let mut delivered = false;
+
+        self.clients.for_each(|_, conn| {
+            if conn.nick == *nick {
+                self.deliver(conn, Arc::clone(&message));
+                delivered = true;
+            }
+        });
+
+        delivered
+    }
+
+    async fn broadcast_to(&self, message: Message, to: SocketAddr) {
+        self.clients.with(&to, |conn| {
+            self.deliver(conn, Arc::new(message));
+        });
+    }
+
+    async fn broadcast_all(&self, message: Message) {
+        self.broadcast_all_now(message);
+    }
+
+    fn broadcast_all_now(&self, message: Message) {
+        let message = Arc::new(message);
+
+        self.clients.for_each(|_, conn| {
+            self.deliver(conn, Arc::clone(&message));
+        });
+    }
+
+    async fn broadcast_others(&self, message: Message, sender: SocketAddr) {
+        self.broadcast_others_now(message, sender);
+    }
+
+    fn broadcast_others_now(&self, message: Message, sender: SocketAddr) {
+        let message = Arc::new(message);
+
+        self.clients.for_each(|addr, conn| {
             if *addr != sender {
-                let _ = tx.send(message.clone());
+                self.deliver(conn, Arc::clone(&message));
+            }
+        });
+    }
+
+    /// Keeps a chat message in the backlog and sends it to everyone else
+    /// under the same lock, so that every client sees messages in the order
+    /// they were numbered. `response` is given that number as its
+    /// `message_id`, which is returned.
+    async fn broadcast_chat_message(
+        &self,
+        from: MessageClient,
+        message: String,
+        response: ResponseBuilder,
+    ) -> u64 {
+        let mut backlog = self.backlog.lock().expect("ERROR: Backlog lock poisoned");
+        let sender = from.addr;
+        let frame = encode_once(response.with_message_id(backlog.next_seq()).build());
+        let bookmark = Bookmark {
+            nick: self.nick(&from.nick).to_string(),
+            timestamp: now(),
+            message: message.clone(),
+            ..Bookmark::default()
+        };
+        let seq = backlog.push(frame.clone(), bookmark);
+
+        self.broadcast_others_now(
+            Message::Sent {
+                from,
+                message,
+                frame,
+                seq,
+            },
+            sender,
+        );
+
+        seq
+    }
+
+    /// Tells each nick `everyone` reaches, other than the sender's own, that
+    /// they were mentioned by `from`, after the message itself.
+    fn notify_everyone(&self, from: &MessageClient, everyone: Everyone) {
+        let message = Arc::new(Message::Frame(encode_once(
+            ResponseBuilder::new(RES_EVERYONE_MENTIONED, everyone.name().to_owned())
+                .with_origin(self.nick(&from.nick).to_string())
+                .build(),
+        )));
+
+        self.clients.for_each(|addr, conn| {
+            let is_own =
+                *addr == from.addr || (from.account.is_some() && conn.account == from.account);
+            let is_reached = match everyone {
+                Everyone::Here => conn.away.is_none(),
+                Everyone::All => true,
+            };
+
+            if !is_own && is_reached {
+                self.deliver(conn, Arc::clone(&message));
+            }
+        });
+    }
+
+    /// The connected nick each of `names`, as mentioned in a message, is for.
+    /// Case only matters where ignoring it leaves more than one.
+    fn resolve_mentions<'a>(&self, names: impl Iterator<Item = &'a str>) -> Vec<Symbol> {
+        let mut nicks = vec![];
+        self.clients.for_each(|_, conn| {
+            if !nicks.contains(&conn.nick) {
+                nicks.push(conn.nick.clone());
+            }
+        });
+
+        let mut resolved = vec![];
+        for name in names {
+            let matches = nicks
+                .iter()
+                .filter(|nick| self.nick(nick).to_lowercase() == name.to_lowercase())
+                .collect::<Vec<&Symbol>>();
+            let nick = match matches.as_slice() {
+                [only] => Some(*only),
+                many => many.iter().copied().find(|nick| &*self.nick(nick) == name),
+            };
+
+            if let Some(nick) = nick.filter(|nick| !resolved.contains(*nick)) {
+                resolved.push(nick.clone());
             }
         }
+
+        resolved
     }
 
-    fn get_by_nick(&self, nick: &str) -> Option<&SocketAddr> {
+    /// Warns every connection using `account`'s nick without being logged
+    /// into it that it will be renamed unless it logs in or changes nick,
+    /// returning how many there are. Only accounts claimed with a password
+    /// are protected.
+    fn guard_nick(&self, account: &str) -> usize {
+        let grace_secs = self.config.nick_protection.grace_secs;
+        if grace_secs == 0 || self.accounts().claimant(account).is_none() {
+            return 0;
+        }
+
+        let lowercase = account.to_lowercase();
+        let mut intruders = vec![];
+        self.clients.for_each(|addr, conn| {
+            let is_logged_in = conn.account.as_deref() == Some(account);
+            if !is_logged_in && self.nick(&conn.nick).to_lowercase() == lowercase {
+                intruders.push((*addr, conn.tx.clone()));
+            }
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(grace_secs);
+        for (addr, tx) in &intruders {
+            if self.nick_guard().watch(*addr, account, deadline) {
+                let warning = ResponseBuilder::new(
+                    ERR_AUTH_REQUIRED,
+                    format!(
+                        "{account} is registered, /auth <password> or change nick within {grace_secs}s or you will be renamed"
+                    ),
+                );
+                let _ = tx.send(Arc::new(Message::Frame(encode_once(warning.build()))));
+            }
+        }
+
+        intruders.len()
+    }
+
+    /// Renames each connection whose time to stop using a registered nick
+    /// was up by `now`, if it still hasn't.
+    fn enforce_nick_guard(&self, now: Instant) {
+        for (addr, account) in self.nick_guard().expired(now) {
+            let _announcing = self.announcing();
+            let lowercase = account.to_lowercase();
+            let nick = self.nicks.intern(&Client::generate_nick());
+
+            let from = {
+                let mut clients = self.clients.lock_all();
+                let Some(conn) = clients.get_mut(&addr) else {
+                    continue;
+                };
+
+                let is_logged_in = conn.account.as_deref() == Some(account.as_str());
+                if is_logged_in || self.nick(&conn.nick).to_lowercase() != lowercase {
+                    continue;
+                }
+
+                info!(
+                    "Renaming {} to {} for using a registered nick",
+                    self.nick(&conn.nick),
+                    self.nick(&nick)
+                );
+                let was = std::mem::replace(&mut conn.nick, nick.clone());
+                let _ = conn.tx.send(Arc::new(Message::Renamed {
+                    nick: nick.clone(),
+                    account,
+                }));
+
+                MessageClient {
+                    addr,
+                    account: conn.account.clone(),
+                    nick: was,
+                }
+            };
+
+            self.broadcast_others_now(
+                Message::NickChanged {
+                    from,
+                    new_nick: nick,
+                },
+                addr,
+            );
+            self.broadcast_nick_list_now();
+            self.broadcast_channels_of(addr);
+        }
+    }
+
+    /// Makes the changes which `schedule` has due in the minute `at` falls
+    /// in, telling whoever they concern.
+    async fn run_scheduled(&self, at: &DateTime<Utc>) {
+        for scheduled in self.schedule.due(at) {
+            match (&scheduled.topic, &scheduled.channel) {
+                (Some(topic), Some(channel)) => self.set_channel_topic_on_schedule(channel, topic),
+                (Some(topic), None) => {
+                    info!("Topic was changed on schedule to: {topic}");
+
+                    self.set_topic(topic, || {
+                        for (code, message) in [
+                            (RES_TOPIC_CHANGE, topic.clone()),
+                            (
+                                RES_TOPIC_CHANGE_MESSAGE,
+                                format!("The channel topic was changed on schedule to: {topic}"),
+                            ),
+                        ] {
+                            let response = ResponseBuilder::new(code, message).build();
+                            self.broadcast_all_now(Message::Frame(encode_once(response)));
+                        }
+                    });
+                }
+                (None, _) => (),
+            }
+
+            if let Some(motd) = &scheduled.motd {
+                *self
+                    .scheduled_motd
+                    .lock()
+                    .expect("ERROR: Scheduled MOTD lock poisoned") = Some(motd.clone());
+                info!("MOTD was changed on schedule");
+
+                let motd = motd.trim_end();
+                if !motd.is_empty() {
+                    let response = ResponseBuilder::new(RES_MOTD, motd.to_owned()).build();
+                    self.broadcast_all(Message::Frame(encode_once(response)))
+                        .await;
+                }
+            }
+        }
+    }
+
+    fn set_channel_topic_on_schedule(&self, channel: &str, topic: &str) {
+        let name = {
+            let mut channels = self.channels();
+            let name = channels.get_mut(channel).map(|channel| {
+                topic.clone_into(&mut channel.topic);
+                channel.name.clone()
+            });
+
+            if let Err(err) = channels.save(channel) {
+                error!("{err}");
+            }
+            name
+        };
+        let Some(name) = name else {
+            debug!("Nobody is in {channel} to change the topic of on schedule");
+            return;
+        };
+        info!("Topic of {name} was changed on schedule to: {topic}");
+
+        let text = ChannelText::new(&name, topic.to_owned()).encode();
+        let notice = ChannelText::new(
+            &name,
+            format!("The topic was changed on schedule to: {topic}"),
+        );
+        for (code, message) in [
+            (RES_CHANNEL_TOPIC, text),
+            (RES_CHANNEL_NOTICE, notice.encode()),
+        ] {
+            let response = ResponseBuilder::new(code, message).build();
+            self.broadcast_channel(&name, Message::Frame(encode_once(response)), None);
+        }
+    }
+
+    /// Keeps the chat message `message_id` for each of `names` it mentions
+    /// who isn't there to see it: connected only while away, or registered
+    /// and not connected at all.
+    fn log_missed_mentions<'a>(
+        &self,
+        from: &MessageClient,
+        names: impl Iterator<Item = &'a str>,
+        message_id: u64,
+        message: &str,
+    ) {
+        let from_nick = self.nick(&from.nick).to_string();
+        let mention = Bookmark {
+            message_id,
+            nick: from_nick.clone(),
+            timestamp: now(),
+            message: message.to_owned(),
+        };
+        let mut logged = vec![from_nick.to_lowercase()];
+
+        for name in names {
+            let lowercase = name.to_lowercase();
+            if logged.contains(&lowercase) {
+                continue;
+            }
+
+            let (mut is_connected, mut is_here) = (false, false);
+            self.clients.for_each(|_, conn| {
+                if self.nick(&conn.nick).to_lowercase() == lowercase {
+                    is_connected = true;
+                    is_here |= conn.away.is_none();
+                }
+            });
+
+            let is_missed = match is_connected {
+                true => !is_here,
+                false => self.accounts().claimant(name).is_some(),
+            };
+            if is_missed {
+                self.away_log().record(name, mention.clone());
+            }
+
+            logged.push(lowercase);
+        }
+    }
+
+    /// Tells each of `mentioned`, other than the sender's own sessions, that
+    /// they were mentioned by `from` in the chat message `message_id`.
+    fn notify_mentioned(&self, from: &MessageClient, mentioned: &[Symbol], message_id: u64) {
+        let origin = self.nick(&from.nick).to_string();
+
+        for nick in mentioned {
+            let message = Arc::new(Message::Frame(encode_once(
+                ResponseBuilder::new(RES_MENTIONED, self.nick(nick).to_string())
+                    .with_origin(origin.clone())
+                    .with_message_id(message_id)
+                    .build(),
+            )));
+
+            self.clients.for_each(|addr, conn| {
+                let is_own =
+                    *addr == from.addr || (from.account.is_some() && conn.account == from.account);
+
+                if conn.nick == *nick && !is_own {
+                    self.deliver(conn, Arc::clone(&message));
+                }
+            });
+        }
+    }
+
+    async fn broadcast_nick_list(&self) {
+        self.broadcast_nick_list_now();
+    }
+
+    fn broadcast_nick_list_now(&self) {
+        let nick_list = ResponseBuilder::new(RES_NICK_LIST, self.nick_list()).build();
+        self.broadcast_all_now(Message::Frame(encode_once(nick_list)));
+    }
+
+    /// Sends to everyone in `channel` other than `except`.
+    fn broadcast_channel(&self, channel: &str, message: Message, except: Option<SocketAddr>) {
+        let members = self
+            .channels()
+            .get(channel)
+            .map(|channel| channel.members.keys().copied().collect::<Vec<SocketAddr>>())
+            .unwrap_or_default();
+        let message = Arc::new(message);
+
+        for addr in members.into_iter().filter(|addr| Some(*addr) != except) {
+            self.clients
+                .with(&addr, |conn| self.deliver(conn, Arc::clone(&message)));
+        }
+    }
+
+    fn broadcast_channel_members(&self, channel: &str) {
+        let Some((name, members)) = self.channels().get(channel).map(|channel| {
+            (
+                channel.name.clone(),
+                channel
+                    .members
+                    .clone()
+                    .into_iter()
+                    .collect::<Vec<(SocketAddr, bool)>>(),
+            )
+        }) else {
+            return;
+        };
+
+        let mut nicks = members
+            .into_iter()
+            .filter_map(|(addr, is_op)| {
+                let nick = self.nick(&self.clients.with(&addr, |conn| conn.nick.clone())?);
+                Some((nick.to_lowercase(), is_op, nick))
+            })
+            .collect::<Vec<(String, bool, Arc<str>)>>();
+        nicks.sort();
+
+        let nicks = nicks
+            .into_iter()
+            .map(|(_, is_op, nick)| match is_op {
+                true => format!("{}{nick}", Level::Op.prefix()),
+                false => nick.to_string(),
+            })
+            .collect::<Vec<String>>();
+        let members = ChannelText::new(&name, nicks.join(" "));
+        let members = ResponseBuilder::new(RES_CHANNEL_MEMBERS, members.encode()).build();
+        self.broadcast_channel(&name, Message::Frame(encode_once(members)), None);
+    }
+
+    /// Sends the members of every channel `addr` is in again, e.g. after its
+    /// nick changed.
+    fn broadcast_channels_of(&self, addr: SocketAddr) {
+        let channels = self.channels().of(addr);
+
+        for channel in channels {
+            self.broadcast_channel_members(&channel);
+        }
+    }
+
+    fn get_by_nick(&self, nick: &Symbol) -> Option<SocketAddr> {
         self.clients
-            .iter()
-            .find_map(|(k, v)| if v.0 == nick { Some(k) } else { None })
+            .find_map(|k, v| if v.nick == *nick { Some(*k) } else { None })
+    }
+
+    fn nick_list(&self) -> String {
+        let mut entries: Vec<(Symbol, NickListEntry)> = vec![];
+
+        // Sessions of the same account share a nick, so they are merged
+        self.clients.for_each(|_, conn| {
+            match entries.iter_mut().find(|(nick, _)| *nick == conn.nick) {
+                Some((_, entry)) => {
+                    entry.level = entry.level.max(conn.level);
+                    entry.is_away &= conn.away.is_some();
+                    entry.is_dnd &= conn.is_dnd;
+                    entry.last_active = entry.last_active.max(conn.last_active);
+                }
+                None => entries.push((
+                    conn.nick.clone(),
+                    NickListEntry {
+                        nick: self.nick(&conn.nick).to_string(),
+                        level: conn.level,
+                        is_away: conn.away.is_some(),
+                        is_dnd: conn.is_dnd,
+                        last_active: conn.last_active,
+                    },
+                )),
+            }
+        });
+
+        let entries = entries
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect::<Vec<NickListEntry>>();
+
+        NickListEntry::encode_list(&entries)
     }
 }
 
 impl Client {
-    async fn new(addr: SocketAddr, stream: TcpStream) -> anyhow::Result<Client> {
+    async fn new(addr: SocketAddr, stream: Stream, nick: Symbol) -> anyhow::Result<Client> {
         let (tx, rx) = mpsc::unbounded_channel();
 
         let (reader, writer) = split(stream);
 
-        let nick = Self::generate_nick();
-        let req = FramedRead::new(reader, Request::default());
-        let res = FramedWrite::new(writer, Response::default());
+        let req = FramedRead::new(reader, FrameCodec::default());
+        let mut res = FramedWrite::new(writer, FrameCodec::default());
+
+        // Until the client says which versions it reads, see `version`
+        res.encoder_mut().set_peer_version(V1);
 
         Ok(Client {
+            account: None,
             addr,
+            capabilities: vec![],
             nick,
             req,
             res,
             rx,
             tx,
+            replay: VecDeque::new(),
+            replayed_up_to: 0,
+            is_lagging: false,
+            wants_nick: None,
+            session_key: None,
         })
     }
 
+    /// Traffic and requests are accounted to the account when logged in,
+    /// otherwise to the IP.
+    fn identity(&self) -> Identity {
+        match &self.account {
+            Some(account) => Identity::Account(account.clone()),
+            None => Identity::Ip(self.addr.ip()),
+        }
+    }
+
+    fn message_client(&self) -> MessageClient {
+        MessageClient {
+            addr: self.addr,
+            account: self.account.clone(),
+            nick: self.nick.clone(),
+        }
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// The usage of every command the client may send, one per line and
+    /// followed by its help after a tab if it asked for that.
+    fn command_list(&self, server: &Server) -> String {
+        let with_help = self.has_capability(COMMAND_HELP);
+        let line = |usage: String, help: &str| {
+            if with_help {
+                format!("{usage}\t{help}")
+            } else {
+                usage
+            }
+        };
+
+        let builtin = command::COMMANDS
+            .iter()
+            .filter(|command| !command.is_experimental || self.has_capability(EXPERIMENTAL))
+            .map(|command| line(command.usage.to_owned(), command.help))
+            .collect::<Vec<String>>();
+        let custom = server
+            .custom_commands()
+            .specs_with_help()
+            .map(|(spec, help)| line(spec.usage(), help))
+            .collect::<Vec<String>>();
+
+        builtin
+            .into_iter()
+            .chain(custom)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
     fn generate_nick() -> String {
         let len = 16;
         let mut bytes = vec![0; len];
@@ -150,171 +1161,328 @@ impl Client {
     }
 }
 
-async fn handle_client(
-    server: Arc<Mutex<Server>>,
-    stream: TcpStream,
+/// Reads the PROXY header, if one is expected, and turns away anything which
+/// isn't speaking solace before handing the connection over. TLS is only
+/// spoken given `tls`, alongside plain solace on the same port.
+async fn handle_tcp(
+    server: Arc<Server>,
+    mut stream: TcpStream,
     addr: SocketAddr,
+    tls: Option<tls::Acceptor>,
 ) -> anyhow::Result<()> {
-    let mut client = Client::new(addr, stream).await?;
+    let addr = if server.config.proxy.trusted.contains(&addr.ip()) {
+        proxy::read_header(&mut stream).await?.unwrap_or(addr)
+    } else {
+        addr
+    };
 
-    println!("INFO: Client {} connected", client.nick.clone());
+    match (sniff::sniff(&stream).await, tls) {
+        (Some(sniff::Foreign::Tls), Some(tls)) => {
+            let stream = tls
+                .inner
+                .accept(stream)
+                .await
+                .with_context(|| format!("ERROR: TLS handshake with {addr} failed"))?;
+            let account = tls.account(stream.get_ref().1);
+            let session_key = tls::session_key(stream.get_ref().1);
 
-    respond!(client, RES_WELCOME, "Welcome to solace!".to_owned());
-    respond!(client, RES_YOUR_NICK, client.nick.clone());
+            handle_client(server, Box::new(stream), addr, account, session_key).await
+        }
+        (Some(foreign), _) => {
+            info!("Turned away {addr}, which isn't speaking solace ({foreign:?})");
+            stream.write_all(foreign.reply()).await?;
+            stream.shutdown().await?;
+
+            Ok(())
+        }
+        (None, _) => handle_client(server, Box::new(stream), addr, None, None).await,
+    }
+}
+
+/// Takes a token from the client's bucket for request `request_id`, and tells
+/// it if it has to wait or is being disconnected.
+async fn rate_limit(
+    server: &Server,
+    client: &mut Client,
+    request_id: u32,
+) -> anyhow::Result<Verdict> {
+    let verdict = server
+        .rate_limits
+        .lock()
+        .expect("ERROR: Rate limits lock poisoned")
+        .check(&client.identity(), Instant::now());
+
+    match verdict {
+        Verdict::Allowed => {}
+        Verdict::Limited(wait) => {
+            let response = ResponseBuilder::new(ERR_RATE_LIMITED, wait.as_millis().to_string())
+                .with_request_id(request_id)
+                .build();
+            client.res.feed(response).await?;
+        }
+        Verdict::Flooding => {
+            warn!("Disconnecting for flooding");
+            respond!(
+                client,
+                RES_DISCONNECTED,
+                "Disconnected for sending too fast".to_owned()
+            );
+        }
+    }
+
+    Ok(verdict)
+}
+
+/// Serves a client until it disconnects, logging it into `account` straight
+/// away if the transport has already authenticated it. `session_key` is
+/// what the transport exported for signing frames, if it is secure.
+/// Everything logged meanwhile is tagged with its address and current nick.
+#[tracing::instrument(name = "client", skip_all, fields(%addr, nick))]
+async fn handle_client(
+    server: Arc<Server>,
+    stream: Stream,
+    addr: SocketAddr,
+    account: Option<String>,
+    session_key: Option<Vec<u8>>,
+) -> anyhow::Result<()> {
+    let nick = server.nicks.intern(&Client::generate_nick());
+    let mut client = Client::new(addr, stream, nick).await?;
+    client.session_key = session_key;
+
+    Span::current().record("nick", &*server.nick(&client.nick));
+    info!("Client connected");
+    server.journal.record(Event::Connect {
+        addr,
+        nick: &server.nick(&client.nick),
+    });
 
     {
-        let mut server = server.lock().await;
-        server
-            .clients
-            .insert(addr, (client.nick.clone(), client.tx));
-        server
-            .broadcast_others(Message::ClientConnected(client.nick.clone()), addr)
-            .await;
-        respond!(client, RES_TOPIC_CHANGE, server.topic.clone());
-        respond!(
-            client,
-            RES_COMMAND_LIST,
-            ["ping", "nick", "topic", "whois", "disconnect"].join(" ")
-        );
-        respond!(
-            client,
-            RES_NICK_LIST,
-            server
-                .clients
-                .values()
-                .map(|v| v.0.clone())
-                .collect::<Vec<String>>()
-                .join(" ")
-        );
+        let session_id = server.allocate_session_id();
+        let nick_list = {
+            let _announcing = server.announcing();
+            {
+                let mut clients = server.clients.lock_all();
+                let level = if clients.is_empty() {
+                    Level::Op
+                } else {
+                    Level::Member
+                };
+                clients.insert(
+                    addr,
+                    Connection::new(session_id, client.nick.clone(), client.tx.clone(), level),
+                );
+            }
+            let hello = ResponseBuilder::new(
+                RES_HELLO,
+                format!("{} has joined", server.nick(&client.nick)),
+            );
+            server.broadcast_others_now(Message::Frame(encode_once(hello.build())), addr);
+
+            let nick_list = server.nick_list();
+            let response = ResponseBuilder::new(RES_NICK_LIST, nick_list.clone()).build();
+            server.broadcast_others_now(Message::Frame(encode_once(response)), addr);
+
+            nick_list
+        };
+
+        // Anyone without stats yet is as new as a guest
+        let first_seen = account
+            .as_deref()
+            .and_then(|account| server.stats().get(account).map(|stats| stats.first_seen));
+        let welcome = WelcomeBuilder::new(&server.config.welcome)
+            .motd(server.scheduled_motd())
+            .nick(&server.nick(&client.nick))
+            .topic(server.topic())
+            .command_list(client.command_list(&server))
+            .message_limit(server.config.channel.max_message_chars)
+            .mention_policy(server.config.channel.parser_options().mentions)
+            .nick_list(nick_list)
+            .tips(first_seen, now());
+
+        for response in welcome.build() {
+            client.res.feed(response).await?;
+        }
+
+        if let Some(account) = account {
+            command::log_in(&server, &mut client, &account).await?;
+        }
+
+        // Taken after joining so that nothing falls between the backlog and
+        // the messages sent to us live, anything in both is skipped later
+        (client.replay, client.replayed_up_to) = server
+            .backlog
+            .lock()
+            .expect("ERROR: Backlog lock poisoned")
+            .snapshot();
     }
 
     loop {
+        // Only flush once nothing else is waiting to be sent so that a burst
+        // of broadcasts goes out in a single write rather than one per
+        // response. `FramedWrite` flushes by itself if the buffer grows large.
+        if client.rx.is_empty() {
+            SinkExt::<Response>::flush(&mut client.res).await?;
+        }
+
+        let queued = client.rx.len();
+        let limits = &server.config.delivery;
+        if limits.max_queued > 0 && queued >= limits.max_queued {
+            server
+                .delivery
+                .disconnected(&server.nick(&client.nick), queued);
+            respond!(
+                client,
+                RES_DISCONNECTED,
+                "Too far behind on messages, reconnect to catch up".to_owned()
+            );
+            break;
+        } else if limits.lag_warning > 0 && queued >= limits.lag_warning {
+            if !client.is_lagging {
+                server.delivery.lagged(&server.nick(&client.nick), queued);
+                client.is_lagging = true;
+            }
+        } else if queued == 0 {
+            client.is_lagging = false;
+        }
+
+        {
+            let bytes_in = client.req.decoder_mut().take_bytes_decoded();
+            let bytes_out = client.res.encoder_mut().take_bytes_encoded();
+
+            server.record_usage(&client, bytes_in, bytes_out);
+        }
+
         #[rustfmt::skip]
         tokio::select! {
+            // Written a page at a time, waiting for each to be flushed, so
+            // that a slow client holds up its own replay rather than it
+            // piling up in memory
+            _ = future::ready(()), if !client.replay.is_empty() => {
+                let page = client.replay.len().min(REPLAY_PAGE);
+
+                for frame in client.replay.drain(..page) {
+                    client.res.feed(frame).await?;
+                }
+
+                SinkExt::<Response>::flush(&mut client.res).await?;
+            }
             result = client.req.next() => match result {
                 Some(Ok(req)) => {
+                    client.res.encoder_mut().set_peer_version(req.version);
                     respond!(client, RES_ACK_MESSAGE, req.id.to_string());
 
-                    println!("INFO: Message received: {:?}", req.message);
+                    server.clients.with_mut(&addr, |conn| conn.last_active = now());
 
-                    match req.message {
-                        RequestMessage::Ping => {
-                            respond!(client, RES_PONG, "Pong".to_owned());
-                        }
-                        RequestMessage::Message(message) => {
-                            let mut server = server.lock().await;
-
-                            server
-                                .broadcast_others(Message::Sent {
-                                    from: MessageClient {
-                                        addr,
-                                        nick: client.nick.clone(),
-                                    },
-                                    message
-                                }, addr)
-                                .await;
-                        }
-                        RequestMessage::NewTopic(topic) => {
-                            let mut server = server.lock().await;
-                            let trimmed = topic.trim();
-
-                            trimmed.clone_into(&mut server.topic);
-                            server
-                                .broadcast_all(
-                                    Message::TopicChanged {
-                                        from: MessageClient {
-                                            addr, nick: client.nick.clone(),
-                                        },
-                                        topic: trimmed.to_owned()
-                                    })
-                                .await;
-                        }
-                        RequestMessage::NewNick(nick) => {
-                            let mut server = server.lock().await;
-                            let was = client.nick.clone();
-                            let trimmed = nick.trim();
-
-                            trimmed.clone_into(&mut client.nick);
-                            trimmed.clone_into(&mut server.clients.get_mut(&addr).unwrap().0);
-
-                            server.broadcast_all(
-                                Message::NickChanged {
-                                    from: MessageClient {
-                                        addr,
-                                        nick: was,
-                                    },
-                                    new_nick: trimmed.to_owned(),
-                                })
-                            .await;
-                        }
-                        RequestMessage::Disconnect => {
-                            // @TODO: Respond with message on disconnect?
-                            let mut server = server.lock().await;
-                            server.clients.remove(&addr);
-                            server
-                                .broadcast_others(Message::ClientDisconnected(client.nick.clone()), addr)
-                                .await;
-                            println!("INFO: Client {} disconnected", client.nick.clone());
-                            break;
-                        }
-                        RequestMessage::WhoIs(target) => {
-                            let mut server = server.lock().await;
+                    debug!("Message received: {:?}", req.message);
+                    server.journal.record(Event::for_request(&server.nick(&client.nick), &req.message));
+
+                    let is_exempt_from_quota = matches!(
+                        req.message,
+                        RequestMessage::Ping | RequestMessage::Quota | RequestMessage::Disconnect
+                    );
 
-                            let maybe_addr = server.get_by_nick(&target).copied();
-                            server.broadcast_to(Message::WhoIs { addr: maybe_addr, nick: target }, addr).await;
+                    if !is_exempt_from_quota && server.is_over_quota(&client) {
+                        respond!(client, ERR_QUOTA_EXCEEDED, "Daily traffic quota exceeded, see /quota".to_owned());
+                        continue;
+                    }
+
+                    // Checked before anything is broadcast, so that a flood
+                    // never reaches anyone else. Clients mark messages read
+                    // by themselves as they arrive, so those don't count.
+                    if !matches!(req.message, RequestMessage::Disconnect | RequestMessage::MarkRead(_)) {
+                        match rate_limit(&server, &mut client, req.id).await? {
+                            Verdict::Allowed => {}
+                            Verdict::Limited(_) => continue,
+                            Verdict::Flooding => break,
                         }
                     }
+
+                    if command::dispatch(&server, &mut client, req.id, req.message).await? == Flow::Disconnect {
+                        break;
+                    }
+                }
+                Some(Err(err)) => {
+                    error!("Rejected frame: {err}");
+                    respond!(client, ERR_PROTOCOL, err.to_string());
+                    break;
                 }
                 None => break,
-                _ => break
             },
             Some(msg) = client.rx.recv() => {
-                match msg {
-                    Message::ClientConnected(nick) => {
-                        respond!(client, RES_HELLO, format!("{nick} has joined"));
-                    }
-                    Message::ClientDisconnected(nick) => {
-                        respond!(client, RES_GOODBYE, format!("{nick} has left the channel"));
+                match &*msg {
+                    Message::Frame(frame) => {
+                        client.res.feed(frame.clone()).await?;
                     }
-                    Message::Sent { message, from, .. } => {
-                        println!("INFO: Client {} sent message: {message:?}", from.nick);
-                        respond!(client, RES_CHAT_MESSAGE_OK, message, format!("{}", from.nick));
-                    }
-                    Message::TopicChanged{ from, topic } => {
-                        println!("INFO: Topic was changed by {} to: {topic}", from.nick);
-                        respond!(client, RES_TOPIC_CHANGE, topic.clone());
+                    Message::Sent { message, from, frame, seq } => {
+                        if *seq <= client.replayed_up_to {
+                            continue;
+                        }
 
-                        let message = if addr == from.addr {
-                            format!("You changed the channel topic to: {topic}")
-                        } else {
-                            format!("{} changed the channel topic to: {}", from.nick, topic.clone())
-                        };
-                        respond!(client, RES_TOPIC_CHANGE_MESSAGE, message);
-                    }
-                    Message::NickChanged{from, new_nick} => {
-                        let message = if addr == from.addr {
-                            format!("You are now known as {new_nick}")
+                        let from_nick = server.nick(&from.nick);
+                        debug!("Client {from_nick} sent message: {message:?}");
+
+                        let frame = if from.account.is_some() && from.account == client.account {
+                            let response = ResponseBuilder::new(RES_SELF_MESSAGE, message.clone())
+                                .with_origin(from_nick.to_string())
+                                .with_message_id(*seq)
+                                .build();
+                            encode_once(response)
                         } else {
-                            format!("{} is now known as {new_nick}", from.nick)
+                            frame.clone()
                         };
 
-                        if addr == from.addr {
-                            respond!(client, RES_YOUR_NICK, new_nick);
+                        if client.replay.is_empty() {
+                            client.res.feed(frame).await?;
+                        } else {
+                            client.replay.push_back(frame);
                         }
-
+                    }
+                    // The sender is told about its own changes as it makes them
+                    Message::TopicChanged{ from, topic } => {
+                        let from_nick = server.nick(&from.nick);
+                        respond!(client, RES_TOPIC_CHANGE, topic.clone());
+                        respond!(client, RES_TOPIC_CHANGE_MESSAGE, format!("{from_nick} changed the channel topic to: {topic}"));
+                    }
+                    Message::NickChanged{from, new_nick} => {
+                        let message = format!("{} is now known as {}", server.nick(&from.nick), server.nick(new_nick));
                         respond!(client, RES_NICK_CHANGE, message);
+                    }
+                    Message::Direct { from, message } => {
+                        let direct = ResponseBuilder::new(RES_DIRECT_MESSAGE, message.clone())
+                            .with_origin(server.nick(&from.nick).to_string())
+                            .from_bot(server.is_bot(from.account.as_deref()))
+                            .build();
+                        client.res.feed(direct).await?;
+                    }
+                    Message::DirectSynced { to, message } => {
+                        respond!(client, RES_SELF_DIRECT_MESSAGE, message.clone(), to.clone());
+                    }
+                    Message::Revoked => {
+                        respond!(client, RES_DISCONNECTED, "This session was revoked from another device".to_owned());
+                        break;
+                    }
+                    Message::Kicked { by, reason } => {
+                        let message = match reason {
+                            Some(reason) => format!("You were kicked by {}: {reason}", server.nick(by)),
+                            None => format!("You were kicked by {}", server.nick(by)),
+                        };
+                        respond!(client, RES_DISCONNECTED, message);
+                        break;
+                    }
+                    Message::CommandsChanged => {
+                        respond!(client, RES_COMMAND_LIST, client.command_list(&server));
+                    }
+                    Message::Renamed { nick, account } => {
+                        client.nick = nick.clone();
+                        client.wants_nick = Some(account.clone());
+                        Span::current().record("nick", &*server.nick(nick));
 
-                        let server = server.lock().await;
-                        respond!(
-                            client,
-                            RES_NICK_LIST,
-                            server
-                                .clients
-                                .values()
-                                .map(|v| v.0.clone())
-                                .collect::<Vec<String>>()
-                                .join(" ")
-                        );
+                        respond!(client, RES_YOUR_NICK, server.nick(nick).to_string());
+                        respond!(client, RES_NICK_CHANGE, format!("You were renamed to {} as {account} is registered, /auth <password> takes it back", server.nick(nick)));
+                    }
+                    Message::Ghosted => {
+                        respond!(client, RES_DISCONNECTED, "Your nick was taken back by the owner of its account".to_owned());
+                        break;
                     }
                     Message::WhoIs { addr, nick } => {
                         if let Some(addr) = addr {
@@ -328,18 +1496,44 @@ async fn handle_client(
         }
     }
 
-    {
-        let mut server = server.lock().await;
+    // Anything still queued, e.g. the reason for a revoke, is best effort as
+    // the client may already be gone.
+    let _ = SinkExt::<Response>::flush(&mut client.res).await;
+    server.remove_client(addr).await;
 
-        if let Some((nick, _)) = server.clients.remove(&addr) {
-            println!("INFO: Client {nick} disconnected");
-            server
-                .broadcast_others(Message::ClientDisconnected(client.nick.clone()), addr)
-                .await;
-        }
+    Ok(())
+}
+
+/// What this server was built from, which clients are told about if they ask.
+fn build_info() -> BuildInfo {
+    BuildInfo::ours(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+/// Lays out a response shared by many recipients just once.
+fn encode_once(response: Response) -> SharedFrame {
+    SharedFrame::new(&response).expect("ERROR: Failed to encode response")
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
     }
 
-    Ok(())
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn now() -> u64 {
+    u64::try_from(chrono::Utc::now().timestamp()).expect("ERROR: Timestamp exceeds u64::MAX")
 }
 
 #[tokio::main]
@@ -347,19 +1541,101 @@ async fn main() -> anyhow::Result<()> {
     const HOST: &str = "0.0.0.0";
     const PORT: i32 = 7878;
 
+    let args = cli::Args::parse();
+    let config = Config::new()?;
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::acceptor(cert, key, &config.client_certs)?),
+        _ => None,
+    };
     let addr = format!("{HOST}:{PORT}");
     let listener = TcpListener::bind(&addr).await?;
-    let server = Arc::new(Mutex::new(Server::new()));
+    logging::init(&config.logging)?;
+    let accounts = Accounts::load()?;
+    let levels = Levels::load(&config.channel.founders)?;
+    let stats = Stats::load()?;
+    let bookmarks = Bookmarks::load()?;
+    let attachments = Attachments::load()?;
+    let channels = Channels::load(&config.channel)?;
+    let server = Arc::new(Server::new(
+        config,
+        accounts,
+        levels,
+        stats,
+        bookmarks,
+        attachments,
+        channels,
+    ));
+
+    info!("Running {}", build_info());
+    info!("Server listening on {PORT}");
 
-    println!("INFO: Server listening on {PORT}");
+    if tls.is_some() {
+        info!("Accepting TLS on {PORT}");
+    }
+
+    if let Some(listen) = server.config.quic.listen {
+        #[cfg(feature = "quic")]
+        {
+            let server = Arc::clone(&server);
+
+            tokio::spawn(async move {
+                if let Err(e) = quic::listen(Arc::clone(&server), &server.config.quic, listen).await
+                {
+                    error!("{e}")
+                }
+            });
+        }
+
+        #[cfg(not(feature = "quic"))]
+        error!(
+            "Not listening for QUIC on {listen} as the server was built without the quic feature"
+        );
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = server.config.unix.path.clone() {
+        let server = Arc::clone(&server);
+
+        tokio::spawn(async move {
+            if let Err(e) = unix::listen(server, &path).await {
+                error!("{e}")
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    {
+        let server = Arc::clone(&server);
+
+        tokio::spawn(async move {
+            if let Err(e) = custom::reload_on_hangup(server).await {
+                error!("{e}")
+            }
+        });
+    }
+
+    tokio::spawn(stats::save_periodically(Arc::clone(&server)));
+    tokio::spawn(attachments::prune_periodically(Arc::clone(&server)));
+    tokio::spawn(delivery::report_periodically(Arc::clone(&server)));
+    tokio::spawn(nick_guard::enforce_periodically(Arc::clone(&server)));
+    tokio::spawn(schedule::run_periodically(Arc::clone(&server)));
+
+    if server.config.previews.enabled {
+        #[cfg(feature = "previews")]
+        tokio::spawn(previews::preview_queued(Arc::clone(&server)));
+
+        #[cfg(not(feature = "previews"))]
+        error!("Not previewing links as the server was built without the previews feature");
+    }
 
     loop {
         let (stream, addr) = listener.accept().await?;
         let server = Arc::clone(&server);
+        let tls = tls.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = handle_client(server, stream, addr).await {
-                eprintln!("ERROR: {e}")
+            if let Err(e) = handle_tcp(server, stream, addr, tls).await {
+                error!(%addr, "{e}")
             }
         });
     }
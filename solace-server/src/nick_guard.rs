@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::Server;
+
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+/// Connections using a registered nick without being logged into its
+/// account, each with the account and when it is renamed unless it logs in
+/// or changes nick first.
+#[derive(Debug, Default)]
+pub(crate) struct NickGuard {
+    deadlines: HashMap<SocketAddr, (String, Instant)>,
+}
+
+impl NickGuard {
+    /// Gives `addr` until `deadline` to stop using `account`'s nick,
+    /// returning whether it wasn't already being given time for it.
+    pub(crate) fn watch(&mut self, addr: SocketAddr, account: &str, deadline: Instant) -> bool {
+        match self.deadlines.get(&addr) {
+            Some((watched, _)) if watched == account => false,
+            _ => {
+                self.deadlines.insert(addr, (account.to_owned(), deadline));
+                true
+            }
+        }
+    }
+
+    /// Those whose time is up by `now`, with the accounts they were using
+    /// the nicks of, which are no longer watched.
+    pub(crate) fn expired(&mut self, now: Instant) -> Vec<(SocketAddr, String)> {
+        let expired: Vec<_> = self
+            .deadlines
+            .iter()
+            .filter(|(_, (_, deadline))| *deadline <= now)
+            .map(|(addr, (account, _))| (*addr, account.clone()))
+            .collect();
+
+        for (addr, _) in &expired {
+            self.deadlines.remove(addr);
+        }
+
+        expired
+    }
+}
+
+/// Renames whoever is still using a registered nick once their time is up,
+/// see `Server::guard_nick`.
+pub(crate) async fn enforce_periodically(server: Arc<Server>) {
+    let mut interval = tokio::time::interval(CHECK_EVERY);
+
+    loop {
+        interval.tick().await;
+        server.enforce_nick_guard(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadlines_are_kept_until_expired() {
+        let mut guard = NickGuard::default();
+        let addr = "127.0.0.1:1".parse().unwrap();
+        let now = Instant::now();
+        let later = now + Duration::from_secs(60);
+
+        assert!(guard.watch(addr, "carol", later));
+        assert!(!guard.watch(addr, "carol", later + Duration::from_secs(60)));
+        assert!(guard.expired(now).is_empty());
+
+        assert_eq!(guard.expired(later), vec![(addr, "carol".to_owned())]);
+        assert!(guard.expired(later).is_empty());
+    }
+}
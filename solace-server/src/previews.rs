@@ -0,0 +1,593 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use reqwest::{header, redirect, Url};
+use solace_protocol::code::RES_LINK_PREVIEW;
+use solace_protocol::link_preview::LinkPreview;
+use solace_protocol::response::ResponseBuilder;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::info;
+
+use crate::config;
+use crate::{encode_once, Message, Server};
+
+const MAX_REDIRECTS: usize = 3;
+const MAX_TITLE_CHARS: usize = 100;
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// Chat messages waiting for their links to be previewed, along with the
+/// previews fetched so far.
+///
+/// # Fields
+///
+/// - `queue`: Messages by `message_id`, taken by `preview_queued`. It holds
+///   `max_queued` at most, so a flood of links can't pile up unbounded.
+/// - `pending`: The other end of `queue`, until `preview_queued` starts.
+/// - `cache`: Previews by URL, `None` for pages which didn't have one.
+#[derive(Debug)]
+pub(crate) struct LinkPreviews {
+    queue: mpsc::Sender<(u64, String)>,
+    pending: Mutex<Option<mpsc::Receiver<(u64, String)>>>,
+    cache: Mutex<Cache>,
+}
+
+impl LinkPreviews {
+    pub(crate) fn new(config: &config::Previews) -> Self {
+        let (queue, pending) = mpsc::channel(config.max_queued.max(1));
+
+        Self {
+            queue,
+            pending: Mutex::new(Some(pending)),
+            cache: Mutex::new(Cache::new(
+                config.cache_size,
+                Duration::from_secs(config.cache_secs),
+            )),
+        }
+    }
+
+    /// Has the links in the chat message with `message_id` previewed, if
+    /// there are any, previews are enabled and the queue isn't full.
+    pub(crate) fn queue(&self, config: &config::Previews, message_id: u64, message: &str) {
+        if !config.enabled || urls(message).is_empty() {
+            return;
+        }
+
+        if let Err(mpsc::error::TrySendError::Full(_)) =
+            self.queue.try_send((message_id, message.to_owned()))
+        {
+            info!("No previews for message {message_id} as the queue is full");
+        }
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, Cache> {
+        self.cache
+            .lock()
+            .expect("ERROR: Preview cache lock poisoned")
+    }
+}
+
+/// Previews the links in each message queued with `LinkPreviews::queue`,
+/// sending a `RES_LINK_PREVIEW` to everyone for each link which has one.
+/// Messages are taken off the queue only as fast as `max_fetching` allows.
+pub(crate) async fn preview_queued(server: Arc<Server>) {
+    let pending = server
+        .previews
+        .pending
+        .lock()
+        .expect("ERROR: Preview queue lock poisoned")
+        .take();
+    let Some(mut pending) = pending else {
+        return;
+    };
+
+    let fetching = Arc::new(Semaphore::new(server.config.previews.max_fetching.max(1)));
+
+    while let Some((message_id, message)) = pending.recv().await {
+        let server = Arc::clone(&server);
+        let Ok(permit) = Arc::clone(&fetching).acquire_owned().await else {
+            return;
+        };
+
+        // One slow page mustn't hold up the links in other messages
+        tokio::spawn(async move {
+            preview_message(&server, message_id, &message).await;
+            drop(permit);
+        });
+    }
+}
+
+async fn preview_message(server: &Server, message_id: u64, message: &str) {
+    let config = &server.config.previews;
+
+    for url in urls(message).into_iter().take(config.max_per_message) {
+        let cached = server.previews.cache().get(&url);
+        let preview = match cached {
+            Some(preview) => preview,
+            None => {
+                let preview = match fetch(&url, config).await {
+                    Ok(preview) => preview,
+                    Err(err) => {
+                        info!("No preview for {url}: {err:#}");
+                        None
+                    }
+                };
+
+                server.previews.cache().insert(url, preview.clone());
+                preview
+            }
+        };
+
+        if let Some(preview) = preview {
+            let response = ResponseBuilder::new(RES_LINK_PREVIEW, preview.encode())
+                .with_message_id(message_id)
+                .build();
+
+            server
+                .broadcast_all(Message::Frame(encode_once(response)))
+                .await;
+        }
+    }
+}
+
+/// Fetches `url`, following redirects by hand so that every address along
+/// the way is checked with `is_public` before it is connected to.
+async fn fetch(url: &str, config: &config::Previews) -> anyhow::Result<Option<LinkPreview>> {
+    let original = url;
+    let mut url = Url::parse(url).with_context(|| format!("ERROR: Invalid URL {url}"))?;
+    let deadline = Instant::now() + Duration::from_secs(config.timeout_secs);
+
+    for _ in 0..=MAX_REDIRECTS {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let client = client_for(&url, timeout).await?;
+        let mut res = client.get(url.clone()).send().await?;
+
+        if res.status().is_redirection() {
+            let location = res
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .with_context(|| "ERROR: Redirect without a location")?;
+
+            url = url.join(location)?;
+            continue;
+        }
+
+        let is_html = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("text/html"));
+
+        if !res.status().is_success() || !is_html {
+            return Ok(None);
+        }
+
+        let mut body = vec![];
+
+        while let Some(chunk) = res.chunk().await? {
+            body.extend_from_slice(&chunk);
+
+            if body.len() >= config.max_bytes {
+                body.truncate(config.max_bytes);
+                break;
+            }
+        }
+
+        return Ok(read_preview(&String::from_utf8_lossy(&body), original));
+    }
+
+    anyhow::bail!("ERROR: More than {MAX_REDIRECTS} redirects")
+}
+
+/// A client which can only reach the address `url` resolves to now, so that
+/// the name can't be pointed somewhere private between checking and
+/// connecting.
+async fn client_for(url: &Url, timeout: Duration) -> anyhow::Result<reqwest::Client> {
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("ERROR: Not fetching {} URLs", url.scheme());
+    }
+
+    let host = url
+        .host_str()
+        .with_context(|| "ERROR: URL without a host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let builder = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .no_proxy()
+        .timeout(timeout)
+        .user_agent(concat!("solace-server/", env!("CARGO_PKG_VERSION")));
+
+    let builder = match url.host() {
+        Some(url::Host::Domain(domain)) => {
+            let addrs = tokio::net::lookup_host((domain, port))
+                .await?
+                .collect::<Vec<SocketAddr>>();
+
+            // A name mixing a private address in with public ones is most
+            // likely up to something, so it gets no preview at all
+            if addrs.is_empty() || !addrs.iter().all(|addr| is_public(addr.ip())) {
+                anyhow::bail!("ERROR: {host} isn't a public address");
+            }
+
+            builder.resolve(domain, addrs[0])
+        }
+        Some(url::Host::Ipv4(ip)) if is_public(ip.into()) => builder,
+        Some(url::Host::Ipv6(ip)) if is_public(ip.into()) => builder,
+        _ => anyhow::bail!("ERROR: {host} isn't a public address"),
+    };
+
+    Ok(builder.build()?)
+}
+
+/// Whether `ip` is on the internet, rather than the server's own machine or
+/// network where a request could reach something which trusts the server.
+pub(crate) fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            // Shared address space used by carrier-grade NAT
+            let is_shared = a == 100 && (64..128).contains(&b);
+            // Set aside for benchmarking networks
+            let is_benchmarking = a == 198 && (18..20).contains(&b);
+            // Reserved for future use, which includes the broadcast address
+            let is_reserved = a >= 240;
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || is_shared
+                || is_benchmarking
+                || is_reserved
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_public(mapped.into());
+            }
+
+            let segments = ip.segments();
+
+            // 6to4 reaches the IPv4 address in the next 32 bits
+            if segments[0] == 0x2002 {
+                let [a, b] = segments[1].to_be_bytes();
+                let [c, d] = segments[2].to_be_bytes();
+                return is_public(Ipv4Addr::new(a, b, c, d).into());
+            }
+
+            // The deprecated IPv4-compatible form reaches the last 32 bits,
+            // which covers `::` and `::1` too
+            if segments[..6] == [0; 6] {
+                let [.., a, b, c, d] = ip.octets();
+                return is_public(Ipv4Addr::new(a, b, c, d).into());
+            }
+
+            let first = segments[0];
+            let is_unique_local = first & 0xfe00 == 0xfc00;
+            let is_link_local = first & 0xffc0 == 0xfe80;
+            // NAT64 hands the last 32 bits to a translator, which may sit
+            // inside the server's network, as may any local-use one
+            let is_nat64 =
+                segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] || segments[..3] == [0x64, 0xff9b, 1];
+            // Teredo tunnels through a relay to a client behind NAT, which
+            // can't be told apart from one on the server's network
+            let is_teredo = segments[..2] == [0x2001, 0];
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || is_unique_local
+                || is_link_local
+                || is_nat64
+                || is_teredo)
+        }
+    }
+}
+
+/// The http and https links in `message`, each once, without any
+/// punctuation the sentence around them ends with.
+pub(crate) fn urls(message: &str) -> Vec<String> {
+    let mut urls: Vec<String> = vec![];
+
+    for word in message.split_whitespace() {
+        let word = word.trim_start_matches(['(', '<', '"', '\'']);
+
+        if !(word.starts_with("http://") || word.starts_with("https://")) {
+            continue;
+        }
+
+        let url = word.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '>', '"', '\'']);
+
+        if !urls.iter().any(|u| u == url) {
+            urls.push(url.to_owned());
+        }
+    }
+
+    urls
+}
+
+/// Reads the OpenGraph title and description from `html`, falling back to
+/// `<title>` and the description meta tag for pages without them.
+pub(crate) fn read_preview(html: &str, url: &str) -> Option<LinkPreview> {
+    // ASCII lowercasing keeps every byte where it was, so positions found in
+    // `lower` can be used to slice `html`
+    let lower = html.to_ascii_lowercase();
+    let mut meta: HashMap<String, String> = HashMap::new();
+    let mut at = 0;
+
+    while let Some(start) = lower[at..].find("<meta").map(|i| at + i) {
+        let end = lower[start..].find('>').map_or(lower.len(), |i| start + i);
+        let attrs = attributes(&html[start + 5..end]);
+        let key = attrs.get("property").or_else(|| attrs.get("name"));
+
+        if let (Some(key), Some(content)) = (key, attrs.get("content")) {
+            meta.entry(key.to_lowercase())
+                .or_insert_with(|| content.clone());
+        }
+
+        at = end;
+    }
+
+    let title_tag = lower.find("<title").and_then(|start| {
+        let open = start + lower[start..].find('>')? + 1;
+        let close = open + lower[open..].find("</title")?;
+
+        Some(html[open..close].to_owned())
+    });
+    let title = meta
+        .remove("og:title")
+        .or_else(|| meta.remove("twitter:title"))
+        .or(title_tag)
+        .map(|title| tidy(&title, MAX_TITLE_CHARS))
+        .filter(|title| !title.is_empty())?;
+    let description = meta
+        .remove("og:description")
+        .or_else(|| meta.remove("description"))
+        .map(|description| tidy(&description, MAX_DESCRIPTION_CHARS))
+        .unwrap_or_default();
+
+    Some(LinkPreview {
+        url: url.to_owned(),
+        title,
+        description,
+    })
+}
+
+/// The attributes of a tag, by lowercased name, from everything after its
+/// name up to the closing `>`.
+fn attributes(tag: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut rest = tag;
+
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq]
+            .split_whitespace()
+            .last()
+            .unwrap_or_default()
+            .to_lowercase();
+        let value = rest[eq + 1..].trim_start();
+
+        let (value, after) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => match value[1..].find(quote) {
+                Some(close) => (&value[1..close + 1], &value[close + 2..]),
+                None => (&value[1..], ""),
+            },
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+
+        attrs.insert(name, value.to_owned());
+        rest = after;
+    }
+
+    attrs
+}
+
+/// Decodes the common entities and collapses whitespace, tabs and newlines
+/// included as they can't be sent in a `LinkPreview`, then cuts `text` down
+/// to `max_chars`.
+fn tidy(text: &str, max_chars: usize) -> String {
+    let decoded = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    let collapsed = decoded.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    if collapsed.chars().count() > max_chars {
+        let cut = collapsed.chars().take(max_chars - 1).collect::<String>();
+        format!("{}…", cut.trim_end())
+    } else {
+        collapsed
+    }
+}
+
+/// Previews by URL, the oldest making way once there are `max` of them.
+///
+/// # Fields
+///
+/// - `order`: The URLs in `entries`, oldest first.
+#[derive(Debug)]
+struct Cache {
+    entries: HashMap<String, (Instant, Option<LinkPreview>)>,
+    order: VecDeque<String>,
+    max: usize,
+    ttl: Duration,
+}
+
+impl Cache {
+    fn new(max: usize, ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            max,
+            ttl,
+        }
+    }
+
+    /// `Some(None)` for a page known not to have a preview, `None` if it
+    /// needs fetching.
+    fn get(&self, url: &str) -> Option<Option<LinkPreview>> {
+        self.entries
+            .get(url)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < self.ttl)
+            .map(|(_, preview)| preview.clone())
+    }
+
+    fn insert(&mut self, url: String, preview: Option<LinkPreview>) {
+        if self.max == 0 {
+            return;
+        }
+
+        if self.entries.contains_key(&url) {
+            self.order.retain(|u| *u != url);
+        } else if self.entries.len() >= self.max {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.order.push_back(url.clone());
+        self.entries.insert(url, (Instant::now(), preview));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            urls("see (https://example.com/a), http://example.com/b. and https://example.com/a"),
+            vec!["https://example.com/a", "http://example.com/b"]
+        );
+        assert!(urls("ftp://example.com www.example.com").is_empty());
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "93.184.216.34",
+            "2606:2800:220:1::1",
+            "2002:5db8:d822::1",
+            "::93.184.216.34",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "198.18.0.1",
+            "198.19.255.255",
+            "240.0.0.1",
+            "255.255.255.255",
+            "64:ff9b::5db8:d822",
+            "2002:7f00:1::1",
+            "2002:a00:1::",
+            "2001:0:4136:e378:8000:63bf:3fff:fdd2",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b:1::5db8:d822",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_refuses_private_addresses() {
+        let config = config::Previews::default();
+
+        for url in [
+            "http://127.0.0.1:8080/",
+            "http://[::1]/",
+            "http://localhost/admin",
+            "file:///etc/passwd",
+        ] {
+            let err = fetch(url, &config).await.unwrap_err().to_string();
+            assert!(
+                err.contains("isn't a public address") || err.contains("Not fetching"),
+                "{url}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_preview() {
+        let html = r#"<html><head>
+            <TITLE>Fallback</TITLE>
+            <meta property="og:title" content="Tom &amp; Jerry">
+            <meta name='description' content='A cat
+              and a mouse'>
+        </head></html>"#;
+
+        assert_eq!(
+            read_preview(html, "https://example.com"),
+            Some(LinkPreview {
+                url: "https://example.com".to_owned(),
+                title: "Tom & Jerry".to_owned(),
+                description: "A cat and a mouse".to_owned(),
+            })
+        );
+        assert_eq!(
+            read_preview("<title> Just a title </title>", "https://example.com")
+                .map(|preview| preview.title),
+            Some("Just a title".to_owned())
+        );
+        assert_eq!(read_preview("<p>No title</p>", "https://example.com"), None);
+    }
+
+    #[test]
+    fn test_queue_is_bounded() {
+        let config = config::Previews {
+            enabled: true,
+            max_queued: 2,
+            ..config::Previews::default()
+        };
+        let previews = LinkPreviews::new(&config);
+
+        for message_id in 0..5 {
+            previews.queue(&config, message_id, "see https://example.com");
+        }
+
+        let mut pending = previews.pending.lock().unwrap().take().unwrap();
+        assert_eq!(pending.try_recv().map(|(id, _)| id), Ok(0));
+        assert_eq!(pending.try_recv().map(|(id, _)| id), Ok(1));
+        assert!(pending.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_cache_drops_oldest() {
+        let mut cache = Cache::new(2, Duration::from_secs(60));
+        cache.insert("a".to_owned(), None);
+        cache.insert("b".to_owned(), Some(LinkPreview::default()));
+        cache.insert("c".to_owned(), None);
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(Some(LinkPreview::default())));
+        assert_eq!(cache.get("c"), Some(None));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::Context;
+use tokio::{io::AsyncReadExt, net::TcpStream, time};
+
+/// The longest a v1 header can be, `\r\n` included.
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// How long a proxy has to send its header once connected.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reads the PROXY protocol header a load balancer sends ahead of the
+/// connection it is passing on, in either version, returning the address of
+/// the client behind it.
+///
+/// `None` means the proxy didn't say, e.g. for its own health checks, in
+/// which case the connection is taken to be from the proxy itself.
+pub(crate) async fn read_header(stream: &mut TcpStream) -> anyhow::Result<Option<SocketAddr>> {
+    time::timeout(HEADER_TIMEOUT, read_header_inner(stream))
+        .await
+        .context("ERROR: Timed out waiting for the PROXY protocol header")?
+}
+
+async fn read_header_inner(stream: &mut TcpStream) -> anyhow::Result<Option<SocketAddr>> {
+    let mut start = [0; 16];
+    stream.read_exact(&mut start[..6]).await?;
+
+    if &start[..6] == b"PROXY " {
+        let mut line = start[..6].to_vec();
+
+        while !line.ends_with(b"\r\n") {
+            anyhow::ensure!(
+                line.len() < V1_MAX_LEN,
+                "ERROR: PROXY protocol v1 header is too long"
+            );
+            line.push(stream.read_u8().await?);
+        }
+
+        return parse_v1(&line);
+    }
+
+    stream.read_exact(&mut start[6..]).await?;
+    anyhow::ensure!(
+        start.starts_with(V2_SIGNATURE),
+        "ERROR: Connection doesn't start with a PROXY protocol header"
+    );
+
+    let len = u16::from_be_bytes([start[14], start[15]]) as usize;
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await?;
+
+    parse_v2(&start, &body)
+}
+
+/// Parses a header such as `PROXY TCP4 192.0.2.1 192.0.2.2 56324 7878\r\n`.
+fn parse_v1(line: &[u8]) -> anyhow::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line)
+        .context("ERROR: PROXY protocol v1 header isn't ASCII")?
+        .trim_end_matches("\r\n");
+    let parts = line.split(' ').collect::<Vec<&str>>();
+
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _, port, _] => {
+            let ip = source
+                .parse::<IpAddr>()
+                .with_context(|| format!("ERROR: Invalid source address {source:?}"))?;
+            let port = port
+                .parse::<u16>()
+                .with_context(|| format!("ERROR: Invalid source port {port:?}"))?;
+
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => anyhow::bail!("ERROR: Malformed PROXY protocol v1 header: {line:?}"),
+    }
+}
+
+/// Parses the binary header, `start` being the fixed 16 bytes up to and
+/// including the length of `body`.
+fn parse_v2(start: &[u8; 16], body: &[u8]) -> anyhow::Result<Option<SocketAddr>> {
+    let version = start[12] >> 4;
+    let command = start[12] & 0x0F;
+    let family = start[13] >> 4;
+
+    anyhow::ensure!(
+        version == 2,
+        "ERROR: Unsupported PROXY protocol version {version}"
+    );
+
+    // LOCAL, sent by the proxy on its own behalf
+    if command == 0 {
+        return Ok(None);
+    }
+
+    anyhow::ensure!(
+        command == 1,
+        "ERROR: Unknown PROXY protocol command {command}"
+    );
+
+    let source = match family {
+        // AF_INET, source and destination addresses followed by their ports
+        1 if body.len() >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([body[8], body[9]]))
+        }
+        // AF_INET6
+        2 if body.len() >= 36 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&body[..16]);
+            let ip = Ipv6Addr::from(octets);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([body[32], body[33]]))
+        }
+        // AF_UNSPEC or AF_UNIX, neither of which has an address worth keeping
+        0 | 3 => return Ok(None),
+        _ => anyhow::bail!("ERROR: Malformed PROXY protocol v2 addresses"),
+    };
+
+    Ok(Some(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_v1() {
+        assert_eq!(
+            parse_v1(b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 7878\r\n").unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(
+            parse_v1(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 7878\r\n").unwrap(),
+            Some("[2001:db8::1]:4000".parse().unwrap())
+        );
+        assert_eq!(parse_v1(b"PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_v1(b"PROXY TCP4 nonsense\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_v2() {
+        let mut start = [0; 16];
+        start[..12].copy_from_slice(V2_SIGNATURE);
+        start[12] = 0x21;
+        start[13] = 0x11;
+
+        let body = [192, 0, 2, 1, 192, 0, 2, 2, 0xDC, 0x04, 0x1E, 0xC6];
+        assert_eq!(
+            parse_v2(&start, &body).unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+
+        start[12] = 0x20;
+        assert_eq!(parse_v2(&start, &[]).unwrap(), None);
+
+        start[12] = 0x31;
+        assert!(parse_v2(&start, &body).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Context;
+use quinn::crypto::rustls::QuicServerConfig;
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use solace_protocol::codec::{SESSION_KEY_CONTEXT, SESSION_KEY_LABEL, SESSION_KEY_LEN};
+use tracing::{error, info};
+
+use crate::tls::{self, Accounts};
+use crate::{config, handle_client, Server};
+
+/// Told apart from anything else on the same port during the handshake.
+const ALPN: &[u8] = b"solace";
+
+/// Accepts QUIC connections on `listen` until the endpoint closes.
+///
+/// Each connection carries its frames over the first bidirectional stream
+/// the client opens, which QUIC only announces once the client has written
+/// to it, so QUIC clients have to speak first.
+pub(crate) async fn listen(
+    server: Arc<Server>,
+    config: &config::Quic,
+    listen: SocketAddr,
+) -> anyhow::Result<()> {
+    let certs = CertificateDer::pem_file_iter(&config.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("ERROR: Failed to read certificates from {:?}", config.cert))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key)
+        .with_context(|| format!("ERROR: Failed to read private key from {:?}", config.key))?;
+    let accounts = tls::accounts(&server.config.client_certs);
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let algorithms = provider.signature_verification_algorithms;
+    let builder = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?;
+    let mut crypto = tls::client_auth(builder, &server.config.client_certs, &accounts, algorithms)
+        .with_single_cert(certs, key)
+        .with_context(|| "ERROR: Invalid QUIC certificate or key")?;
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+    let server_config = quinn::ServerConfig::with_crypto(Arc::new(
+        QuicServerConfig::try_from(crypto)
+            .with_context(|| "ERROR: Unusable TLS config for QUIC")?,
+    ));
+    let endpoint = quinn::Endpoint::server(server_config, listen)
+        .with_context(|| format!("ERROR: Failed to listen for QUIC on {listen}"))?;
+
+    info!("Server listening for QUIC on {listen}");
+
+    while let Some(incoming) = endpoint.accept().await {
+        let server = Arc::clone(&server);
+        let accounts = Arc::clone(&accounts);
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_quic(server, incoming, &accounts).await {
+                error!("{e}")
+            }
+        });
+    }
+
+    Ok(())
+}
+
+async fn handle_quic(
+    server: Arc<Server>,
+    incoming: quinn::Incoming,
+    accounts: &Accounts,
+) -> anyhow::Result<()> {
+    let connection = incoming.await?;
+    // Kept for the lifetime of the connection even if the client migrates
+    let addr = connection.remote_address();
+    let certs = connection
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok());
+    let account = tls::account(accounts, certs.as_deref().map(Vec::as_slice));
+    let mut session_key = vec![0; SESSION_KEY_LEN];
+    let session_key = connection
+        .export_keying_material(&mut session_key, SESSION_KEY_LABEL, SESSION_KEY_CONTEXT)
+        .ok()
+        .map(|()| session_key);
+    let (send, recv) = connection.accept_bi().await?;
+
+    handle_client(
+        server,
+        Box::new(tokio::io::join(recv, send)),
+        addr,
+        account,
+        session_key,
+    )
+    .await
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::usage::Identity;
+
+/// The fewest buckets worth pruning.
+const MIN_PRUNE_AT: usize = 64;
+
+/// What a client may do with a request it just sent.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Verdict {
+    Allowed,
+    /// Refused, and the client has to wait this long for the next one to be
+    /// allowed.
+    Limited(Duration),
+    /// Refused once too often without letting up, so the client is dropped.
+    Flooding,
+}
+
+/// A `RateLimiter` per `Identity`, so that every connection of an account or
+/// IP draws on the same bucket, and reconnecting doesn't refill it.
+///
+/// # Fields
+///
+/// - `prune_at`: How many buckets there may be before the full ones are
+///   dropped, as a full bucket is no different from a new one. Double the
+///   number left after the last prune.
+#[derive(Debug)]
+pub(crate) struct RateLimits {
+    config: config::RateLimit,
+    buckets: HashMap<Identity, RateLimiter>,
+    prune_at: usize,
+}
+
+impl RateLimits {
+    pub(crate) fn new(config: &config::RateLimit) -> Self {
+        Self {
+            config: config.clone(),
+            buckets: HashMap::new(),
+            prune_at: 0,
+        }
+    }
+
+    /// Takes a token from the bucket of `identity` for a request sent at
+    /// `now`.
+    pub(crate) fn check(&mut self, identity: &Identity, now: Instant) -> Verdict {
+        if !self.buckets.contains_key(identity)
+            && self.buckets.len() >= self.prune_at.max(MIN_PRUNE_AT)
+        {
+            self.buckets.retain(|_, bucket| !bucket.is_full(now));
+            self.prune_at = self.buckets.len() * 2;
+        }
+
+        self.buckets
+            .entry(identity.clone())
+            .or_insert_with(|| RateLimiter::new(&self.config))
+            .check(now)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+/// A token bucket for the requests of one `Identity`, which holds `burst`
+/// tokens and gains `per_sec` of them a second. Each request takes one, and
+/// one sent with the bucket empty is refused.
+///
+/// # Fields
+///
+/// - `strikes`: Requests refused since the bucket was last full, so that a
+///   client sending just a little too fast is dropped as well as one
+///   sending far too fast, only later.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    per_sec: f64,
+    burst: f64,
+    max_strikes: u32,
+    tokens: f64,
+    updated: Instant,
+    strikes: u32,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: &config::RateLimit) -> Self {
+        let burst = f64::from(config.burst.max(1));
+
+        Self {
+            per_sec: config.messages_per_sec,
+            burst,
+            max_strikes: config.max_strikes,
+            tokens: burst,
+            updated: Instant::now(),
+            strikes: 0,
+        }
+    }
+
+    /// Whether the bucket will have filled up again by `now`.
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.per_sec <= 0.0 || self.tokens + elapsed * self.per_sec >= self.burst
+    }
+
+    /// Takes a token for a request sent at `now`.
+    pub(crate) fn check(&mut self, now: Instant) -> Verdict {
+        if self.per_sec <= 0.0 {
+            return Verdict::Allowed;
+        }
+
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.burst);
+        self.updated = now;
+
+        if self.tokens >= self.burst {
+            self.strikes = 0;
+        }
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Verdict::Allowed;
+        }
+
+        self.strikes += 1;
+        if self.max_strikes > 0 && self.strikes >= self.max_strikes {
+            return Verdict::Flooding;
+        }
+
+        Verdict::Limited(Duration::from_secs_f64((1.0 - self.tokens) / self.per_sec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(messages_per_sec: f64, burst: u32, max_strikes: u32) -> RateLimiter {
+        RateLimiter::new(&config::RateLimit {
+            messages_per_sec,
+            burst,
+            max_strikes,
+        })
+    }
+
+    #[test]
+    fn test_bursts_then_refills() {
+        let mut limiter = limiter(2.0, 3, 0);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(start), Verdict::Allowed);
+        }
+        assert_eq!(
+            limiter.check(start),
+            Verdict::Limited(Duration::from_millis(500))
+        );
+
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.check(later), Verdict::Allowed);
+        assert!(matches!(limiter.check(later), Verdict::Limited(_)));
+    }
+
+    #[test]
+    fn test_strikes_add_up_until_the_bucket_is_full() {
+        let mut limiter = limiter(1.0, 2, 3);
+        let start = Instant::now();
+
+        assert_eq!(limiter.check(start), Verdict::Allowed);
+        assert_eq!(limiter.check(start), Verdict::Allowed);
+        assert!(matches!(limiter.check(start), Verdict::Limited(_)));
+
+        // Sending just as fast as allowed doesn't clear the strikes
+        let second = start + Duration::from_secs(1);
+        assert_eq!(limiter.check(second), Verdict::Allowed);
+        assert!(matches!(limiter.check(second), Verdict::Limited(_)));
+        assert_eq!(limiter.check(second), Verdict::Flooding);
+
+        let mut limiter = self::limiter(1.0, 2, 2);
+        limiter.check(start);
+        limiter.check(start);
+        assert!(matches!(limiter.check(start), Verdict::Limited(_)));
+
+        // Letting the bucket fill up again does
+        let quiet = start + Duration::from_secs(2);
+        assert_eq!(limiter.check(quiet), Verdict::Allowed);
+        assert_eq!(limiter.check(quiet), Verdict::Allowed);
+        assert!(matches!(limiter.check(quiet), Verdict::Limited(_)));
+    }
+
+    #[test]
+    fn test_reconnecting_keeps_the_bucket() {
+        let mut limits = RateLimits::new(&config::RateLimit {
+            messages_per_sec: 1.0,
+            burst: 2,
+            max_strikes: 0,
+        });
+        let alice = Identity::Account("alice".to_owned());
+        let now = Instant::now();
+
+        assert_eq!(limits.check(&alice, now), Verdict::Allowed);
+        assert_eq!(limits.check(&alice, now), Verdict::Allowed);
+        // Another session, or the same one again after reconnecting
+        assert!(matches!(limits.check(&alice, now), Verdict::Limited(_)));
+
+        let bob = Identity::Ip("192.0.2.1".parse().unwrap());
+        assert_eq!(limits.check(&bob, now), Verdict::Allowed);
+    }
+
+    #[test]
+    fn test_prunes_full_buckets() {
+        let mut limits = RateLimits::new(&config::RateLimit::default());
+        let now = Instant::now();
+
+        for i in 0..10_000u32 {
+            let ip = std::net::Ipv4Addr::from(i).into();
+            limits.check(&Identity::Ip(ip), now + Duration::from_secs(u64::from(i)));
+        }
+
+        assert!(limits.len() <= MIN_PRUNE_AT * 2);
+    }
+
+    #[test]
+    fn test_zero_rate_is_unlimited() {
+        let mut limiter = limiter(0.0, 1, 1);
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert_eq!(limiter.check(now), Verdict::Allowed);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::MessageClient;
+
+/// One of the latest chat messages, with who has read it so far in the
+/// order they did.
+#[derive(Debug)]
+struct Tracked {
+    message_id: u64,
+    from: MessageClient,
+    readers: Vec<String>,
+}
+
+/// Who has read each of the latest chat messages, so that their senders
+/// can be told. Readers say how far they have read rather than naming each
+/// message, so that catching up on many only takes one request.
+///
+/// # Fields
+///
+/// - `messages`: As many as the backlog keeps, oldest first.
+/// - `read_up_to`: The id of the last message each reader has read, by
+///   nick ignoring case.
+#[derive(Debug)]
+pub(crate) struct Receipts {
+    messages: VecDeque<Tracked>,
+    read_up_to: HashMap<String, u64>,
+    capacity: usize,
+}
+
+impl Receipts {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            read_up_to: HashMap::new(),
+            capacity,
+        }
+    }
+
+    pub(crate) fn record(&mut self, message_id: u64, from: MessageClient) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+
+        self.messages.push_back(Tracked {
+            message_id,
+            from,
+            readers: vec![],
+        });
+    }
+
+    /// Marks every message up to `message_id` as read by `reader` as
+    /// `nick`, returning those it hadn't read yet with their senders and
+    /// everyone who has read them now. Its own messages are left out.
+    pub(crate) fn mark_read(
+        &mut self,
+        reader: &MessageClient,
+        nick: &str,
+        message_id: u64,
+    ) -> Vec<(MessageClient, u64, Vec<String>)> {
+        let read_up_to = self.read_up_to.entry(nick.to_lowercase()).or_default();
+        let after = *read_up_to;
+        *read_up_to = after.max(message_id);
+
+        self.messages
+            .iter_mut()
+            .filter(|tracked| (after + 1..=message_id).contains(&tracked.message_id))
+            .filter(|tracked| {
+                let is_own = tracked.from.addr == reader.addr
+                    || (reader.account.is_some() && tracked.from.account == reader.account);
+
+                let lowercase = nick.to_lowercase();
+                !is_own
+                    && !tracked
+                        .readers
+                        .iter()
+                        .any(|read| read.to_lowercase() == lowercase)
+            })
+            .map(|tracked| {
+                tracked.readers.push(nick.to_owned());
+
+                (
+                    tracked.from.clone(),
+                    tracked.message_id,
+                    tracked.readers.clone(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interner::Interner;
+
+    use super::*;
+
+    fn client(port: u16, nick: &str, nicks: &Interner) -> MessageClient {
+        MessageClient {
+            addr: format!("127.0.0.1:{port}").parse().unwrap(),
+            account: None,
+            nick: nicks.intern(nick),
+        }
+    }
+
+    #[test]
+    fn test_readers_catch_up_once() {
+        let nicks = Interner::new();
+        let (alice, bob, carol) = (
+            client(1, "alice", &nicks),
+            client(2, "bob", &nicks),
+            client(3, "carol", &nicks),
+        );
+        let mut receipts = Receipts::new(2);
+        for message_id in 1..=3 {
+            receipts.record(message_id, alice.clone());
+        }
+        receipts.record(4, bob.clone());
+
+        let ids = |read: Vec<(MessageClient, u64, Vec<String>)>| {
+            read.into_iter().map(|(_, id, _)| id).collect::<Vec<_>>()
+        };
+
+        // Only the latest are kept, and nobody is a reader of their own
+        assert_eq!(ids(receipts.mark_read(&bob, "bob", 4)), vec![3]);
+        assert!(receipts.mark_read(&bob, "bob", 4).is_empty());
+
+        let read = receipts.mark_read(&carol, "carol", 3);
+        assert_eq!(read[0].1, 3);
+        assert_eq!(read[0].2, vec!["bob", "carol"]);
+        assert_eq!(ids(receipts.mark_read(&carol, "Carol", 4)), vec![4]);
+    }
+}
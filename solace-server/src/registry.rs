@@ -0,0 +1,259 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::Connection;
+
+const SHARD_COUNT: usize = 16;
+
+type Shard = HashMap<SocketAddr, Connection>;
+
+/// Every connected client, split over several independently locked shards so
+/// that lookups like `/whois` and building the nick list don't queue up
+/// behind broadcasts, or each other, on a single lock.
+///
+/// Locks are only ever held inside of the methods here or by a
+/// `LockedRegistry`, never across an `.await`.
+pub(crate) struct ClientRegistry {
+    hasher: RandomState,
+    shards: Vec<RwLock<Shard>>,
+}
+
+/// The whole registry locked for writing, for the rare operations which have
+/// to check and update several clients atomically.
+pub(crate) struct LockedRegistry<'a> {
+    registry: &'a ClientRegistry,
+    shards: Vec<RwLockWriteGuard<'a, Shard>>,
+}
+
+impl ClientRegistry {
+    pub(crate) fn new() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            shards: (0..SHARD_COUNT).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    fn shard_of(&self, addr: &SocketAddr) -> usize {
+        self.hasher.hash_one(addr) as usize % self.shards.len()
+    }
+
+    fn read(&self, shard: usize) -> RwLockReadGuard<'_, Shard> {
+        self.shards[shard]
+            .read()
+            .expect("ERROR: Client registry lock poisoned")
+    }
+
+    fn write(&self, shard: usize) -> RwLockWriteGuard<'_, Shard> {
+        self.shards[shard]
+            .write()
+            .expect("ERROR: Client registry lock poisoned")
+    }
+
+    pub(crate) fn remove(&self, addr: &SocketAddr) -> Option<Connection> {
+        self.write(self.shard_of(addr)).remove(addr)
+    }
+
+    /// Runs `f` on the connection at `addr`, if there is one.
+    pub(crate) fn with<R>(&self, addr: &SocketAddr, f: impl FnOnce(&Connection) -> R) -> Option<R> {
+        self.read(self.shard_of(addr)).get(addr).map(f)
+    }
+
+    pub(crate) fn with_mut<R>(
+        &self,
+        addr: &SocketAddr,
+        f: impl FnOnce(&mut Connection) -> R,
+    ) -> Option<R> {
+        self.write(self.shard_of(addr)).get_mut(addr).map(f)
+    }
+
+    /// Visits every client, one shard at a time, so this doesn't see a
+    /// consistent snapshot of the registry as a whole.
+    pub(crate) fn for_each(&self, mut f: impl FnMut(&SocketAddr, &Connection)) {
+        for shard in 0..self.shards.len() {
+            for (addr, conn) in self.read(shard).iter() {
+                f(addr, conn);
+            }
+        }
+    }
+
+    /// Returns the first `Some` produced by `f`, visiting shards in turn.
+    pub(crate) fn find_map<R>(
+        &self,
+        mut f: impl FnMut(&SocketAddr, &Connection) -> Option<R>,
+    ) -> Option<R> {
+        (0..self.shards.len()).find_map(|shard| self.read(shard).iter().find_map(|(a, c)| f(a, c)))
+    }
+
+    pub(crate) fn any(&self, mut f: impl FnMut(&SocketAddr, &Connection) -> bool) -> bool {
+        self.find_map(|a, c| f(a, c).then_some(())).is_some()
+    }
+
+    /// Locks every shard, always in the same order so that two callers can't
+    /// deadlock on each other.
+    pub(crate) fn lock_all(&self) -> LockedRegistry<'_> {
+        LockedRegistry {
+            registry: self,
+            shards: (0..self.shards.len())
+                .map(|shard| self.write(shard))
+                .collect(),
+        }
+    }
+}
+
+impl LockedRegistry<'_> {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&SocketAddr, &Connection)> {
+        self.shards.iter().flat_map(|shard| shard.iter())
+    }
+
+    pub(crate) fn get_mut(&mut self, addr: &SocketAddr) -> Option<&mut Connection> {
+        let shard = self.registry.shard_of(addr);
+        self.shards[shard].get_mut(addr)
+    }
+
+    pub(crate) fn insert(&mut self, addr: SocketAddr, conn: Connection) {
+        let shard = self.registry.shard_of(&addr);
+        self.shards[shard].insert(addr, conn);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Mutex, OnceLock};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use solace_protocol::level::Level;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::interner::Interner;
+
+    const CLIENTS: u16 = 2_000;
+    const BROADCASTERS: usize = 4;
+    const LOOKUPS: usize = 2_000;
+
+    fn addr(i: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], i))
+    }
+
+    fn connection(i: u16) -> Connection {
+        static NICKS: OnceLock<Interner> = OnceLock::new();
+
+        let (tx, _) = mpsc::unbounded_channel();
+        let nick = NICKS.get_or_init(Interner::new).intern(&format!("nick{i}"));
+        Connection::new(u32::from(i), nick, tx, Level::Member)
+    }
+
+    fn registry() -> ClientRegistry {
+        let registry = ClientRegistry::new();
+        let mut locked = registry.lock_all();
+
+        for i in 1..=CLIENTS {
+            locked.insert(addr(i), connection(i));
+        }
+
+        drop(locked);
+        registry
+    }
+
+    #[test]
+    fn test_lookups_reach_every_shard() {
+        let registry = registry();
+
+        for i in 1..=CLIENTS {
+            assert_eq!(
+                registry.with(&addr(i), |c| c.session_id),
+                Some(u32::from(i))
+            );
+        }
+
+        let mut count = 0;
+        registry.for_each(|_, _| count += 1);
+        assert_eq!(count, usize::from(CLIENTS));
+
+        assert!(registry.remove(&addr(1)).is_some());
+        assert!(!registry.any(|a, _| *a == addr(1)));
+    }
+
+    /// Measures `/whois` style lookups while other threads keep broadcasting
+    /// to everyone, against the single `Mutex<HashMap>` this replaced.
+    ///
+    /// Run with `cargo test -p solace-server -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_lookup_latency_during_broadcasts() {
+        // Stands in for the per recipient work of a broadcast
+        fn deliver(conn: &Connection) {
+            std::hint::black_box(format!("#{}: hello", conn.session_id));
+        }
+
+        fn p99(mut samples: Vec<Duration>) -> Duration {
+            samples.sort();
+            samples[samples.len() * 99 / 100]
+        }
+
+        fn run(broadcast: impl Fn() + Sync, lookup: impl Fn(u16)) -> Duration {
+            let done = AtomicBool::new(false);
+
+            thread::scope(|s| {
+                for _ in 0..BROADCASTERS {
+                    s.spawn(|| {
+                        while !done.load(Ordering::Relaxed) {
+                            broadcast();
+                        }
+                    });
+                }
+
+                let samples = (0..LOOKUPS)
+                    .map(|i| {
+                        // Give the broadcasters a chance to run in between
+                        thread::yield_now();
+
+                        let start = Instant::now();
+                        lookup(i as u16 % CLIENTS + 1);
+                        start.elapsed()
+                    })
+                    .collect();
+
+                done.store(true, Ordering::Relaxed);
+                p99(samples)
+            })
+        }
+
+        let mutex = Mutex::new(
+            (1..=CLIENTS)
+                .map(|i| (addr(i), connection(i)))
+                .collect::<HashMap<SocketAddr, Connection>>(),
+        );
+        let single_lock = run(
+            || {
+                for conn in mutex.lock().unwrap().values() {
+                    deliver(conn);
+                }
+            },
+            |i| {
+                std::hint::black_box(mutex.lock().unwrap().get(&addr(i)).map(|c| c.session_id));
+            },
+        );
+
+        let registry = registry();
+        let sharded = run(
+            || registry.for_each(|_, conn| deliver(conn)),
+            |i| {
+                std::hint::black_box(registry.with(&addr(i), |c| c.session_id));
+            },
+        );
+
+        // Reported rather than asserted, as timings on a busy machine are too
+        // noisy to fail a build on
+        println!("p99 lookup latency, single lock: {single_lock:?}, sharded: {sharded:?}");
+    }
+}
@@ -0,0 +1,245 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use tracing::error;
+
+use crate::config::Scheduled;
+use crate::Server;
+
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+/// When a scheduled change is made, as the five fields of a crontab line:
+/// minute, hour, day of the month, month and day of the week, in UTC. Each
+/// field is `*`, a number or a range such as `1-5`, or a list of those, and
+/// any of them can step with `/n` as in `*/15`. Sunday is `0` or `7`.
+///
+/// # Fields
+///
+/// - `minutes`, `hours`, `days`, `months` and `weekdays`: Bitmasks of the
+///   values each field matches.
+/// - `is_any_day` and `is_any_weekday`: Whether the field was `*`. As in
+///   cron, when neither is a day matching either one will do.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    is_any_day: bool,
+    is_any_weekday: bool,
+}
+
+impl Cron {
+    pub(crate) fn matches(&self, at: &DateTime<Utc>) -> bool {
+        let has = |mask: u64, value: u32| mask & (1 << value) != 0;
+        let day = has(self.days, at.day());
+        let weekday = has(self.weekdays, at.weekday().num_days_from_sunday());
+        let is_day = match (self.is_any_day, self.is_any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+
+        is_day
+            && has(self.minutes, at.minute())
+            && has(self.hours, at.hour())
+            && has(self.months, at.month())
+    }
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let &[minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(format!(
+                "Expected minute, hour, day, month and weekday, got {s:?}"
+            ));
+        };
+
+        // Sunday can be given as 7 as well as 0
+        let weekdays_mask = field(weekdays, 0, 7)?;
+        let weekdays_mask = (weekdays_mask | weekdays_mask >> 7) & 0x7f;
+
+        Ok(Self {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: weekdays_mask,
+            is_any_day: days == "*",
+            is_any_weekday: weekdays == "*",
+        })
+    }
+}
+
+/// The values from `min` to `max` which one field of a `Cron` matches.
+fn field(text: &str, min: u32, max: u32) -> Result<u64, String> {
+    let number = |n: &str| {
+        n.parse::<u32>()
+            .map_err(|_| format!("{n:?} isn't a number in {text:?}"))
+    };
+    let mut mask = 0;
+
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, number(step)?),
+            None => (item, 1),
+        };
+        let (start, end) = match (range, range.split_once('-')) {
+            ("*", _) => (min, max),
+            (_, Some((start, end))) => (number(start)?, number(end)?),
+            // A single value with a step runs on to the end, as in `5/15`
+            (_, None) if step > 1 => (number(range)?, max),
+            (_, None) => (number(range)?, number(range)?),
+        };
+
+        if step == 0 || start < min || end > max || start > end {
+            return Err(format!("{item:?} isn't within {min}-{max}"));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(mask)
+}
+
+/// The changes from the config which the server makes by itself, such as
+/// a topic for each day's standup or rules which rotate.
+#[derive(Debug, Default)]
+pub(crate) struct Schedule {
+    entries: Vec<(Cron, Scheduled)>,
+}
+
+impl Schedule {
+    /// Skips any entry which can't be used, saying why.
+    pub(crate) fn new(entries: &[Scheduled]) -> Self {
+        let mut loaded = vec![];
+
+        for entry in entries {
+            if entry.topic.is_none() && entry.motd.is_none() {
+                error!(
+                    "Scheduled entry at {:?} changes nothing, skipping",
+                    entry.at
+                );
+                continue;
+            }
+
+            match entry.at.parse::<Cron>() {
+                Ok(cron) => loaded.push((cron, entry.clone())),
+                Err(err) => error!("Scheduled entry has an invalid time, skipping: {err}"),
+            }
+        }
+
+        Self { entries: loaded }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries to be run in the minute `at` falls in, in the order they
+    /// were configured.
+    pub(crate) fn due(&self, at: &DateTime<Utc>) -> impl Iterator<Item = &Scheduled> {
+        let at = *at;
+
+        self.entries
+            .iter()
+            .filter(move |(cron, _)| cron.matches(&at))
+            .map(|(_, entry)| entry)
+    }
+}
+
+/// Runs the entries due in each minute once, as it begins, see
+/// `Server::run_scheduled`.
+pub(crate) async fn run_periodically(server: Arc<Server>) {
+    if server.schedule.is_empty() {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(CHECK_EVERY);
+    let mut last_minute = None;
+
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+        let minute = now.timestamp().div_euclid(60);
+        if last_minute.replace(minute) != Some(minute) {
+            server.run_scheduled(&now).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // 1 July 2024 was a Monday
+        Utc.with_ymd_and_hms(2024, 7, day, hour, minute, 30)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cron_matches() {
+        let standup: Cron = "30 9 * * 1-5".parse().unwrap();
+        assert!(standup.matches(&at(1, 9, 30)));
+        assert!(!standup.matches(&at(1, 9, 31)));
+        assert!(!standup.matches(&at(6, 9, 30)));
+
+        let quarterly: Cron = "*/15 * * * *".parse().unwrap();
+        assert!(quarterly.matches(&at(3, 14, 45)));
+        assert!(!quarterly.matches(&at(3, 14, 50)));
+
+        // Either day will do when both are given
+        let either: Cron = "0 0 2 * 0".parse().unwrap();
+        assert!(either.matches(&at(2, 0, 0)));
+        assert!(either.matches(&at(7, 0, 0)));
+        assert!(!either.matches(&at(3, 0, 0)));
+
+        let sunday: Cron = "0 12 * * 7".parse().unwrap();
+        assert!(sunday.matches(&at(7, 12, 0)));
+        assert_eq!(sunday, "0 12 * * 0".parse().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_crons_are_refused() {
+        for cron in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(cron.parse::<Cron>().is_err(), "{cron}");
+        }
+    }
+
+    #[test]
+    fn test_unusable_entries_are_skipped() {
+        let entry = |at: &str, topic: Option<&str>| Scheduled {
+            at: at.to_owned(),
+            topic: topic.map(str::to_owned),
+            ..Scheduled::default()
+        };
+        let schedule = Schedule::new(&[
+            entry("0 9 * * *", Some("Standup")),
+            entry("0 9 * * *", None),
+            entry("whenever", Some("Never")),
+        ]);
+
+        let due: Vec<_> = schedule.due(&at(1, 9, 0)).collect();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].topic.as_deref(), Some("Standup"));
+        assert_eq!(schedule.due(&at(1, 10, 0)).count(), 0);
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use solace_protocol::codec::is_frame_start;
+use tokio::{net::TcpStream, time};
+
+/// How long to wait for a new connection to send something before assuming
+/// that it is a solace client waiting to be welcomed.
+const WINDOW: Duration = Duration::from_millis(50);
+
+/// Something other than solace spoken on the solace port, most likely by
+/// someone pointing a browser at it or connecting with TLS.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Foreign {
+    Http,
+    Tls,
+    Other,
+}
+
+impl Foreign {
+    /// Tells whoever connected that this is a solace server in terms their
+    /// client will show them, as far as that is possible.
+    pub(crate) fn reply(&self) -> &'static [u8] {
+        match self {
+            Foreign::Http => {
+                b"HTTP/1.1 400 Bad Request\r\n\
+                Content-Type: text/plain\r\n\
+                Content-Length: 60\r\n\
+                Connection: close\r\n\
+                \r\n\
+                This is a solace chat server, connect with a solace client.\n"
+            }
+            // A fatal handshake_failure alert, as there is no way to show
+            // text to a client in the middle of a handshake
+            Foreign::Tls => &[0x15, 0x03, 0x01, 0x00, 0x02, 0x02, 0x28],
+            Foreign::Other => {
+                b"ERROR: This is a solace chat server, connect with a solace client.\r\n"
+            }
+        }
+    }
+}
+
+/// Which protocol `prefix` belongs to, if not solace.
+fn classify(prefix: &[u8]) -> Option<Foreign> {
+    let &first = prefix.first()?;
+
+    if is_frame_start(first) {
+        return None;
+    }
+
+    Some(match first {
+        // The record type of a handshake
+        0x16 => Foreign::Tls,
+        // Every HTTP method is in capitals
+        b'A'..=b'Z' => Foreign::Http,
+        _ => Foreign::Other,
+    })
+}
+
+/// Peeks at whatever `stream` sends first, without consuming it, to turn
+/// away clients which clearly aren't speaking solace.
+///
+/// Solace clients don't have to speak first, so waits no longer than
+/// `WINDOW` for them to.
+pub(crate) async fn sniff(stream: &TcpStream) -> Option<Foreign> {
+    let mut prefix = [0; 4];
+
+    match time::timeout(WINDOW, stream.peek(&mut prefix)).await {
+        Ok(Ok(n)) => classify(&prefix[..n]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(b"GET / HTTP/1.1\r\n"), Some(Foreign::Http));
+        assert_eq!(classify(&[0x16, 0x03, 0x01]), Some(Foreign::Tls));
+        assert_eq!(classify(b"hello"), Some(Foreign::Other));
+        assert_eq!(classify(&[0x01, 0x00]), None);
+        assert_eq!(classify(&[]), None);
+    }
+
+    #[test]
+    fn test_http_content_length() {
+        let reply = Foreign::Http.reply();
+        let body = reply
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|i| &reply[i + 4..])
+            .unwrap();
+
+        assert_eq!(body.len(), 60);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::BTreeMap, fs, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{now, Server};
+
+const STATS_FILE: &str = "stats.toml";
+const SAVE_EVERY: Duration = Duration::from_secs(30);
+
+/// # Fields
+///
+/// - `messages`: Chat messages sent, direct messages aren't counted.
+/// - `first_seen`: Unix timestamp of when the nick was first picked or first
+///   spoke, whichever came first.
+/// - `karma`: How many times others have sent `nick++`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub(crate) struct NickStats {
+    pub(crate) messages: u64,
+    pub(crate) first_seen: u64,
+    pub(crate) karma: u64,
+}
+
+/// Stats by nick, saved to `stats.toml` in the XDG data directory every so
+/// often rather than on every message, see `save_periodically`. Nicks the
+/// server generates are only counted once they say something.
+///
+/// # Fields
+///
+/// - `is_dirty`: Something changed since the last save.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    nicks: BTreeMap<String, NickStats>,
+    is_dirty: bool,
+}
+
+impl Stats {
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        let base_path = xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?;
+
+        let nicks = match base_path.find_data_file(STATS_FILE) {
+            Some(path) => {
+                let raw = fs::read_to_string(&path)
+                    .with_context(|| format!("ERROR: Failed to read file: {path:?}"))?;
+
+                toml::from_str(&raw).with_context(|| format!("ERROR: Failed to parse {path:?}"))?
+            }
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            nicks,
+            is_dirty: false,
+        })
+    }
+
+    pub(crate) fn get(&self, nick: &str) -> Option<&NickStats> {
+        self.nicks.get(nick)
+    }
+
+    pub(crate) fn seen(&mut self, nick: &str) -> &mut NickStats {
+        self.is_dirty = true;
+
+        self.nicks
+            .entry(nick.to_owned())
+            .or_insert_with(|| NickStats {
+                first_seen: now(),
+                ..NickStats::default()
+            })
+    }
+
+    /// Counts a chat message from `nick`, returning the nicks it gave karma
+    /// to. Only nicks with stats of their own can be given karma, and never
+    /// by themselves.
+    pub(crate) fn record_message(&mut self, nick: &str, message: &str) -> Vec<String> {
+        self.seen(nick).messages += 1;
+
+        let mut granted = vec![];
+
+        for to in karma_grants(message) {
+            if to == nick || granted.contains(&to) {
+                continue;
+            }
+
+            if let Some(stats) = self.nicks.get_mut(&to) {
+                stats.karma += 1;
+                granted.push(to);
+            }
+        }
+
+        granted
+    }
+
+    fn save(&mut self) -> anyhow::Result<()> {
+        let path = Self::path()?;
+
+        fs::write(&path, toml::to_string(&self.nicks)?)
+            .with_context(|| format!("ERROR: Failed to write {path:?}"))?;
+        self.is_dirty = false;
+
+        Ok(())
+    }
+
+    fn path() -> anyhow::Result<PathBuf> {
+        xdg::BaseDirectories::with_prefix("solace")
+            .with_context(|| "ERROR: Couldn't find XDG path for solace")?
+            .place_data_file(STATS_FILE)
+            .with_context(|| "ERROR: Couldn't create the data directory")
+    }
+}
+
+/// The nicks given karma in `message` with `nick++`, with or without the `@`
+/// and with any punctuation after it, e.g. `thanks @bob++!`.
+pub(crate) fn karma_grants(message: &str) -> Vec<String> {
+    message
+        .split_whitespace()
+        .filter_map(|word| {
+            let word = word.trim_end_matches(|ch: char| ch.is_ascii_punctuation() && ch != '+');
+            let nick = word.strip_suffix("++")?;
+            let nick = nick.strip_prefix('@').unwrap_or(nick);
+
+            (!nick.is_empty() && !nick.ends_with('+')).then(|| nick.to_owned())
+        })
+        .collect()
+}
+
+/// Writes the stats out whenever they have changed since the last time.
+pub(crate) async fn save_periodically(server: Arc<Server>) {
+    let mut interval = tokio::time::interval(SAVE_EVERY);
+
+    loop {
+        interval.tick().await;
+
+        let mut stats = server.stats();
+        if stats.is_dirty {
+            if let Err(err) = stats.save() {
+                error!("{err:#}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_karma_grants() {
+        assert_eq!(karma_grants("thanks bob++"), vec!["bob"]);
+        assert_eq!(
+            karma_grants("@amy++! and carol++, c++"),
+            vec!["amy", "carol", "c"]
+        );
+        assert_eq!(karma_grants("++ a+++ x+"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_record_message() {
+        let mut stats = Stats::default();
+        stats.seen("bob");
+
+        assert_eq!(
+            stats.record_message("amy", "bob++ amy++ carol++ bob++"),
+            vec!["bob"]
+        );
+        assert_eq!(stats.get("bob").unwrap().karma, 1);
+        assert_eq!(stats.get("amy").unwrap().karma, 0);
+        assert_eq!(stats.get("amy").unwrap().messages, 1);
+        assert!(stats.get("carol").is_none());
+    }
+}
@@ -0,0 +1,357 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use solace_protocol::codec::{SESSION_KEY_CONTEXT, SESSION_KEY_LABEL, SESSION_KEY_LEN};
+use tokio_rustls::{
+    rustls::{
+        self,
+        client::danger::HandshakeSignatureValid,
+        crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms},
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, UnixTime},
+        server::{
+            danger::{ClientCertVerified, ClientCertVerifier},
+            WantsServerCert,
+        },
+        CertificateError, ConfigBuilder, DigitallySignedStruct, DistinguishedName, ServerConfig,
+        SignatureScheme, WantsVerifier,
+    },
+    TlsAcceptor,
+};
+
+use crate::config;
+
+/// Accounts by the fingerprint of the client certificate which logs into it.
+pub(crate) type Accounts = HashMap<String, String>;
+
+/// What TLS connections to the TCP port are accepted with.
+///
+/// # Fields
+///
+/// - `accounts`: Who each client certificate `inner` accepts logs in as.
+#[derive(Clone)]
+pub(crate) struct Acceptor {
+    pub(crate) inner: TlsAcceptor,
+    accounts: Arc<Accounts>,
+}
+
+impl Acceptor {
+    /// The account the client on `connection` logs into with its
+    /// certificate, if it presented one.
+    pub(crate) fn account(&self, connection: &rustls::ServerConnection) -> Option<String> {
+        account(&self.accounts, connection.peer_certificates())
+    }
+}
+
+/// Builds what TLS connections to the TCP port are accepted with, presenting
+/// the certificate chain in `cert` signed by the private key in `key`, and
+/// asking for the client certificates in `client_certs`.
+pub(crate) fn acceptor(
+    cert: &Path,
+    key: &Path,
+    client_certs: &config::ClientCerts,
+) -> anyhow::Result<Acceptor> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("ERROR: Failed to read certificates from {cert:?}"))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("ERROR: Failed to read private key from {key:?}"))?;
+    let accounts = accounts(client_certs);
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let algorithms = provider.signature_verification_algorithms;
+    let builder =
+        ServerConfig::builder_with_provider(provider).with_safe_default_protocol_versions()?;
+    let config = client_auth(builder, client_certs, &accounts, algorithms)
+        .with_single_cert(certs, key)
+        .with_context(|| "ERROR: Invalid TLS certificate or key")?;
+
+    Ok(Acceptor {
+        inner: TlsAcceptor::from(Arc::new(config)),
+        accounts,
+    })
+}
+
+/// The client certificates in `config`, with their fingerprints in the form
+/// `fingerprint` gives.
+pub(crate) fn accounts(config: &config::ClientCerts) -> Arc<Accounts> {
+    Arc::new(
+        config
+            .accounts
+            .iter()
+            .map(|(fingerprint, account)| (normalize(fingerprint), account.clone()))
+            .collect(),
+    )
+}
+
+/// Asks clients for a certificate from `accounts`, unless there are none and
+/// `config` doesn't require one.
+pub(crate) fn client_auth(
+    builder: ConfigBuilder<ServerConfig, WantsVerifier>,
+    config: &config::ClientCerts,
+    accounts: &Arc<Accounts>,
+    algorithms: WebPkiSupportedAlgorithms,
+) -> ConfigBuilder<ServerConfig, WantsServerCert> {
+    if accounts.is_empty() && !config.required {
+        return builder.with_no_client_auth();
+    }
+
+    builder.with_client_cert_verifier(Arc::new(FingerprintVerifier {
+        accounts: Arc::clone(accounts),
+        is_required: config.required,
+        algorithms,
+    }))
+}
+
+/// The account `certs` log into, only ever for a certificate which
+/// `FingerprintVerifier` has already accepted.
+pub(crate) fn account(accounts: &Accounts, certs: Option<&[CertificateDer]>) -> Option<String> {
+    accounts.get(&fingerprint(certs?.first()?)).cloned()
+}
+
+/// The key for signing frames over `connection`, see `capability::FRAME_HMAC`.
+pub(crate) fn session_key(connection: &rustls::ServerConnection) -> Option<Vec<u8>> {
+    connection
+        .export_keying_material(
+            vec![0; SESSION_KEY_LEN],
+            SESSION_KEY_LABEL,
+            Some(SESSION_KEY_CONTEXT),
+        )
+        .ok()
+}
+
+/// Lower case hex of the SHA-256 of a DER encoded certificate.
+fn fingerprint(cert: &CertificateDer) -> String {
+    hex::encode(Sha256::digest(cert))
+}
+
+/// Puts a fingerprint from the config in the form `fingerprint` gives, as
+/// tools like `openssl x509 -fingerprint` print them upper case with colons.
+fn normalize(fingerprint: &str) -> String {
+    fingerprint.replace(':', "").to_lowercase()
+}
+
+/// Accepts client certificates by their fingerprint alone, so that they can
+/// be self signed rather than issued by a CA. The client still has to prove
+/// that it holds the certificate's key during the handshake.
+#[derive(Debug)]
+struct FingerprintVerifier {
+    accounts: Arc<Accounts>,
+    is_required: bool,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ClientCertVerifier for FingerprintVerifier {
+    fn client_auth_mandatory(&self) -> bool {
+        self.is_required
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        if self.accounts.contains_key(&fingerprint(end_entity)) {
+            Ok(ClientCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::{
+        rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+        TlsConnector,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_normalize_openssl_fingerprint() {
+        assert_eq!(normalize("AB:CD:0F"), "abcd0f");
+        assert_eq!(normalize("abcd0f"), "abcd0f");
+    }
+
+    #[tokio::test]
+    async fn test_handshake_with_pem_files() {
+        let dir = std::env::temp_dir().join(format!("solace-tls-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let signed = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        fs::write(dir.join("cert.pem"), signed.cert.pem()).unwrap();
+        fs::write(dir.join("key.pem"), signed.key_pair.serialize_pem()).unwrap();
+
+        let client_certs = config::ClientCerts::default();
+        assert!(acceptor(&dir.join("key.pem"), &dir.join("cert.pem"), &client_certs).is_err());
+        let acceptor =
+            acceptor(&dir.join("cert.pem"), &dir.join("key.pem"), &client_certs).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(signed.cert.der().clone()).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(config));
+
+        let (client, server) = tokio::io::duplex(4096);
+        let name = ServerName::try_from("localhost").unwrap();
+        let (client, server) = tokio::join!(
+            connector.connect(name, client),
+            acceptor.inner.accept(server)
+        );
+        let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+        client.write_all(b"hello").await.unwrap();
+        client.flush().await.unwrap();
+        let mut buf = [0; 5];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_key_matches_quic_export() {
+        let dir = std::env::temp_dir().join(format!("solace-tls-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let signed = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        fs::write(dir.join("cert.pem"), signed.cert.pem()).unwrap();
+        fs::write(dir.join("key.pem"), signed.key_pair.serialize_pem()).unwrap();
+        let acceptor = acceptor(
+            &dir.join("cert.pem"),
+            &dir.join("key.pem"),
+            &config::ClientCerts::default(),
+        )
+        .unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(signed.cert.der().clone()).unwrap();
+
+        for version in [&rustls::version::TLS12, &rustls::version::TLS13] {
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = ClientConfig::builder_with_provider(provider)
+                .with_protocol_versions(&[version])
+                .unwrap()
+                .with_root_certificates(roots.clone())
+                .with_no_client_auth();
+            let connector = TlsConnector::from(Arc::new(config));
+
+            let (client, server) = tokio::io::duplex(4096);
+            let name = ServerName::try_from("localhost").unwrap();
+            let (client, server) = tokio::join!(
+                connector.connect(name, client),
+                acceptor.inner.accept(server)
+            );
+            let (client, server) = (client.unwrap(), server.unwrap());
+
+            // quinn always passes its context on, so QUIC exports with
+            // `Some` even when the context is empty
+            let exported = |context| {
+                client
+                    .get_ref()
+                    .1
+                    .export_keying_material(vec![0; SESSION_KEY_LEN], SESSION_KEY_LABEL, context)
+                    .unwrap()
+            };
+            assert_eq!(
+                session_key(server.get_ref().1),
+                Some(exported(Some(SESSION_KEY_CONTEXT))),
+                "{version:?}"
+            );
+
+            // Where leaving it out would derive another key
+            if version == &rustls::version::TLS12 {
+                assert_ne!(session_key(server.get_ref().1), Some(exported(None)));
+            }
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_cert_logs_in() {
+        let dir = std::env::temp_dir().join(format!("solace-tls-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let signed = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        fs::write(dir.join("cert.pem"), signed.cert.pem()).unwrap();
+        fs::write(dir.join("key.pem"), signed.key_pair.serialize_pem()).unwrap();
+
+        let theirs = rcgen::generate_simple_self_signed(vec!["alice".to_owned()]).unwrap();
+        let client_certs = config::ClientCerts {
+            accounts: HashMap::from([(
+                fingerprint(theirs.cert.der()).to_uppercase(),
+                "alice".to_owned(),
+            )]),
+            required: true,
+        };
+        let acceptor =
+            acceptor(&dir.join("cert.pem"), &dir.join("key.pem"), &client_certs).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(signed.cert.der().clone()).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(
+                vec![theirs.cert.der().clone()],
+                PrivateKeyDer::Pkcs8(theirs.key_pair.serialize_der().into()),
+            )
+            .unwrap();
+        let connector = TlsConnector::from(Arc::new(config));
+
+        let (client, server) = tokio::io::duplex(4096);
+        let name = ServerName::try_from("localhost").unwrap();
+        let (client, server) = tokio::join!(
+            connector.connect(name, client),
+            acceptor.inner.accept(server)
+        );
+        client.unwrap();
+        let server = server.unwrap();
+
+        assert_eq!(
+            acceptor.account(server.get_ref().1),
+            Some("alice".to_owned())
+        );
+        assert!(session_key(server.get_ref().1).is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
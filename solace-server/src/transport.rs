@@ -0,0 +1,9 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Anything a client can be connected over, so that `handle_client` doesn't
+/// care whether it is TCP or something else underneath.
+pub(crate) trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+pub(crate) type Stream = Box<dyn Transport>;
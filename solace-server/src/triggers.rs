@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use tracing::{error, warn};
+
+use crate::config;
+
+/// How `Trigger::channels` names the main chat, which no channel can be
+/// called as they start with `#`.
+const MAIN_CHAT: &str = "main";
+
+#[cfg(feature = "webhooks")]
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a trigger does about a message it matched.
+///
+/// # Fields
+///
+/// - `reply`: The trigger's reply with the matches filled in.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Fired {
+    pub(crate) reply: Option<String>,
+    #[cfg_attr(not(feature = "webhooks"), allow(dead_code))]
+    pub(crate) webhook: Option<String>,
+}
+
+/// The auto-responder's triggers, for a lightweight FAQ bot which needs no
+/// process of its own.
+///
+/// # Fields
+///
+/// - `fired_at`: When each trigger, by index, last fired in each channel,
+///   by lowercase name, for as long as it is cooling down there.
+#[derive(Debug)]
+pub(crate) struct Triggers {
+    triggers: Vec<(Regex, config::Trigger)>,
+    cooldown: Duration,
+    fired_at: HashMap<(usize, String), Instant>,
+}
+
+impl Triggers {
+    /// Skips any trigger which can't be used, saying why.
+    pub(crate) fn new(config: &config::AutoResponder) -> Self {
+        let mut loaded = vec![];
+
+        for trigger in &config.triggers {
+            let mut trigger = trigger.clone();
+
+            if trigger.webhook.is_some() && cfg!(not(feature = "webhooks")) {
+                warn!(
+                    "Trigger {:?} has a webhook, which needs the webhooks feature, ignoring it",
+                    trigger.pattern
+                );
+                trigger.webhook = None;
+            }
+
+            if trigger.reply.is_none() && trigger.webhook.is_none() {
+                error!("Trigger {:?} does nothing, skipping", trigger.pattern);
+                continue;
+            }
+
+            match Regex::new(&trigger.pattern) {
+                Ok(regex) => loaded.push((regex, trigger)),
+                Err(err) => error!(
+                    "Trigger {:?} has an invalid pattern, skipping: {err}",
+                    trigger.pattern
+                ),
+            }
+        }
+
+        Self {
+            triggers: loaded,
+            cooldown: Duration::from_secs(config.cooldown_secs),
+            fired_at: HashMap::new(),
+        }
+    }
+
+    /// What the first trigger matching `message`, which `nick` sent to
+    /// `channel` or to the main chat if `None`, does about it. Nothing
+    /// happens if that trigger is still cooling down there, otherwise it
+    /// fires at `now`.
+    pub(crate) fn fire(
+        &mut self,
+        channel: Option<&str>,
+        nick: &str,
+        message: &str,
+        now: Instant,
+    ) -> Option<Fired> {
+        let channel = channel.unwrap_or(MAIN_CHAT).to_lowercase();
+        let (index, (regex, trigger)) =
+            self.triggers
+                .iter()
+                .enumerate()
+                .find(|(_, (regex, trigger))| {
+                    let is_here = trigger.channels.is_empty()
+                        || trigger.channels.iter().any(|c| c.to_lowercase() == channel);
+
+                    is_here && regex.is_match(message)
+                })?;
+
+        let cooldown = self.cooldown;
+        self.fired_at
+            .retain(|_, fired_at| now.saturating_duration_since(*fired_at) < cooldown);
+        if self.fired_at.contains_key(&(index, channel.clone())) {
+            return None;
+        }
+        if !cooldown.is_zero() {
+            self.fired_at.insert((index, channel), now);
+        }
+
+        let reply = trigger.reply.as_ref().map(|template| {
+            let mut reply = String::new();
+            if let Some(captures) = regex.captures(message) {
+                captures.expand(template, &mut reply);
+            }
+
+            reply.replace("{nick}", nick)
+        });
+
+        Some(Fired {
+            reply,
+            webhook: trigger.webhook.clone(),
+        })
+    }
+}
+
+/// Posts `payload` to the webhook at `url` in the background, logging
+/// anything which goes wrong as there is nobody to tell.
+#[cfg(feature = "webhooks")]
+pub(crate) fn post(url: String, payload: serde_json::Value) {
+    tokio::spawn(async move {
+        let result = reqwest::Client::new()
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload.to_string())
+            .timeout(WEBHOOK_TIMEOUT)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+
+        if let Err(err) = result {
+            warn!("Couldn't post to the webhook {url}: {err}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triggers(cooldown_secs: u64, triggers: Vec<config::Trigger>) -> Triggers {
+        Triggers::new(&config::AutoResponder {
+            cooldown_secs,
+            triggers,
+            ..config::AutoResponder::default()
+        })
+    }
+
+    fn trigger(pattern: &str, reply: &str, channels: &[&str]) -> config::Trigger {
+        config::Trigger {
+            pattern: pattern.to_owned(),
+            reply: Some(reply.to_owned()),
+            channels: channels.iter().map(|&c| c.to_owned()).collect(),
+            ..config::Trigger::default()
+        }
+    }
+
+    fn reply(fired: Option<Fired>) -> Option<String> {
+        fired.and_then(|fired| fired.reply)
+    }
+
+    #[test]
+    fn test_first_match_fires_with_groups_filled_in() {
+        let mut triggers = triggers(
+            0,
+            vec![
+                trigger(
+                    r"(?i)^!issue (?P<n>\d+)$",
+                    "{nick}: #$n is at /issues/${n}",
+                    &[],
+                ),
+                trigger("rules", "Be nice", &["#help", "main"]),
+                trigger("rules", "Never seen", &[]),
+                trigger("(", "Invalid", &[]),
+            ],
+        );
+        let now = Instant::now();
+
+        assert_eq!(
+            reply(triggers.fire(None, "bob", "!ISSUE 42", now)),
+            Some("bob: #42 is at /issues/42".to_owned())
+        );
+        assert_eq!(
+            reply(triggers.fire(Some("#Help"), "bob", "the rules?", now)),
+            Some("Be nice".to_owned())
+        );
+        assert_eq!(
+            reply(triggers.fire(None, "bob", "the rules?", now)),
+            Some("Be nice".to_owned())
+        );
+        assert_eq!(
+            reply(triggers.fire(Some("#rust"), "bob", "the rules?", now)),
+            Some("Never seen".to_owned())
+        );
+        assert_eq!(triggers.fire(None, "bob", "(", now), None);
+    }
+
+    #[test]
+    fn test_triggers_cool_down_in_each_channel() {
+        let mut triggers = triggers(30, vec![trigger("^!faq$", "See the wiki", &[])]);
+        let now = Instant::now();
+
+        assert!(triggers.fire(Some("#rust"), "bob", "!faq", now).is_some());
+        assert!(triggers.fire(Some("#rust"), "carol", "!faq", now).is_none());
+        assert!(triggers.fire(Some("#help"), "carol", "!faq", now).is_some());
+
+        let later = now + Duration::from_secs(30);
+        assert!(triggers
+            .fire(Some("#rust"), "carol", "!faq", later)
+            .is_some());
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    fs, io,
+    net::{Ipv6Addr, SocketAddr, SocketAddrV6},
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use tokio::net::UnixListener;
+use tracing::{error, info};
+
+use crate::{handle_client, Server};
+
+/// Accepts connections on a Unix socket at `path`, for bots and tools on
+/// the same machine which shouldn't need a network port.
+pub(crate) async fn listen(server: Arc<Server>, path: &Path) -> anyhow::Result<()> {
+    // Left behind by a previous run which didn't shut down cleanly
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            return Err(err)
+                .with_context(|| format!("ERROR: Failed to remove stale socket {path:?}"));
+        }
+        _ => (),
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("ERROR: Failed to listen on Unix socket {path:?}"))?;
+    let next_peer = AtomicU32::new(1);
+
+    info!("Server listening on Unix socket {path:?}");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let server = Arc::clone(&server);
+        let addr = peer_addr(next_peer.fetch_add(1, Ordering::Relaxed));
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(server, Box::new(stream), addr, None, None).await {
+                error!("{e}")
+            }
+        });
+    }
+}
+
+/// Unix socket peers have no address of their own, so each is given a made
+/// up loopback one. The port tells them apart in logs and `/whois`, and the
+/// flow label keeps them unique once the port wraps around.
+fn peer_addr(n: u32) -> SocketAddr {
+    SocketAddrV6::new(Ipv6Addr::LOCALHOST, n as u16, n, 0).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_addrs_are_unique() {
+        assert_ne!(peer_addr(1), peer_addr(1 + u16::MAX as u32 + 1));
+        assert_eq!(peer_addr(3).to_string(), "[::1]:3");
+    }
+}
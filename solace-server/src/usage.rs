@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use chrono::{NaiveDate, Utc};
+
+/// Who a client's usage is counted against, by traffic quotas and rate
+/// limiting alike, so that reconnecting starts neither afresh.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Identity {
+    Account(String),
+    /// For connections which aren't logged in.
+    Ip(IpAddr),
+}
+
+/// Bytes moved on behalf of one account or IP during a single UTC day.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct DailyUsage {
+    pub(crate) bytes_in: u64,
+    pub(crate) bytes_out: u64,
+}
+
+impl DailyUsage {
+    pub(crate) fn total(&self) -> u64 {
+        self.bytes_in + self.bytes_out
+    }
+}
+
+/// Tracks traffic per `Identity`, starting afresh for everyone when the UTC
+/// day rolls over.
+#[derive(Debug)]
+pub(crate) struct UsageTracker {
+    day: NaiveDate,
+    usage: HashMap<Identity, DailyUsage>,
+}
+
+impl UsageTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            day: Utc::now().date_naive(),
+            usage: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, identity: &Identity, bytes_in: u64, bytes_out: u64) {
+        self.roll_over();
+
+        let usage = self.usage.entry(identity.clone()).or_default();
+        usage.bytes_in += bytes_in;
+        usage.bytes_out += bytes_out;
+    }
+
+    pub(crate) fn get(&mut self, identity: &Identity) -> DailyUsage {
+        self.roll_over();
+
+        self.usage.get(identity).copied().unwrap_or_default()
+    }
+
+    fn roll_over(&mut self) {
+        let today = Utc::now().date_naive();
+
+        if today != self.day {
+            self.day = today;
+            self.usage.clear();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs;
+
+use solace_message_parser::MentionPolicy;
+use solace_protocol::code::{
+    RES_COMMAND_LIST, RES_MENTION_POLICY, RES_MESSAGE_LIMIT, RES_MOTD, RES_NICK_LIST, RES_TIP,
+    RES_TOPIC_CHANGE, RES_WELCOME, RES_YOUR_NICK,
+};
+use solace_protocol::response::{Response, ResponseBuilder};
+use tracing::warn;
+
+use crate::config::Welcome;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The burst of responses a client is sent on joining, with each optional
+/// part left out if the config turns it off. The nick, message limit and
+/// mention policy are always sent, as a client can't work without them.
+pub(crate) struct WelcomeBuilder<'a> {
+    config: &'a Welcome,
+    responses: Vec<Response>,
+}
+
+impl<'a> WelcomeBuilder<'a> {
+    pub(crate) fn new(config: &'a Welcome) -> Self {
+        let mut builder = Self {
+            config,
+            responses: vec![],
+        };
+
+        if !config.greeting.is_empty() {
+            builder.push(RES_WELCOME, config.greeting.clone());
+        }
+
+        builder
+    }
+
+    /// The message of the day, the `scheduled` one if there is one. Otherwise
+    /// it is from `motd_path` if there is one, falling back to `motd` if it
+    /// can't be read.
+    pub(crate) fn motd(mut self, scheduled: Option<String>) -> Self {
+        let from_file = || {
+            self.config.motd_path.as_ref().and_then(|path| {
+                fs::read_to_string(path)
+                    .inspect_err(|err| warn!("Couldn't read the MOTD from {path:?}: {err}"))
+                    .ok()
+            })
+        };
+        let motd = scheduled
+            .or_else(from_file)
+            .unwrap_or_else(|| self.config.motd.clone());
+        let motd = motd.trim_end();
+
+        if !motd.is_empty() {
+            self.push(RES_MOTD, motd.to_owned());
+        }
+        self
+    }
+
+    fn push(&mut self, code: u16, message: String) {
+        self.responses
+            .push(ResponseBuilder::new(code, message).build());
+    }
+
+    pub(crate) fn nick(mut self, nick: &str) -> Self {
+        self.push(RES_YOUR_NICK, nick.to_owned());
+        self
+    }
+
+    pub(crate) fn topic(mut self, topic: String) -> Self {
+        if self.config.topic {
+            self.push(RES_TOPIC_CHANGE, topic);
+        }
+        self
+    }
+
+    pub(crate) fn command_list(mut self, command_list: String) -> Self {
+        if self.config.command_list {
+            self.push(RES_COMMAND_LIST, command_list);
+        }
+        self
+    }
+
+    pub(crate) fn message_limit(mut self, max_chars: usize) -> Self {
+        self.push(RES_MESSAGE_LIMIT, max_chars.to_string());
+        self
+    }
+
+    pub(crate) fn mention_policy(mut self, policy: MentionPolicy) -> Self {
+        self.push(RES_MENTION_POLICY, policy.name().to_owned());
+        self
+    }
+
+    pub(crate) fn nick_list(mut self, nick_list: String) -> Self {
+        if self.config.nick_list {
+            self.push(RES_NICK_LIST, nick_list);
+        }
+        self
+    }
+
+    /// Tips for getting started, for guests and for accounts first seen
+    /// less than `tips_for_days` before `now`. `first_seen` is `None` for
+    /// guests.
+    pub(crate) fn tips(mut self, first_seen: Option<u64>, now: u64) -> Self {
+        let max_age = self.config.tips_for_days.saturating_mul(SECS_PER_DAY);
+        let is_new = first_seen.is_none_or(|first_seen| now.saturating_sub(first_seen) < max_age);
+
+        if is_new && max_age > 0 {
+            for tip in self.config.tips.clone() {
+                self.push(RES_TIP, tip);
+            }
+        }
+        self
+    }
+
+    pub(crate) fn build(self) -> Vec<Response> {
+        self.responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(builder: WelcomeBuilder) -> Vec<u16> {
+        builder.build().iter().map(|r| r.code).collect()
+    }
+
+    fn everything(config: &Welcome, first_seen: Option<u64>) -> Vec<u16> {
+        codes(
+            WelcomeBuilder::new(config)
+                .motd(None)
+                .nick("alice")
+                .topic("[No topic]".to_owned())
+                .command_list(String::new())
+                .message_limit(4000)
+                .mention_policy(MentionPolicy::WordBoundary)
+                .nick_list(String::new())
+                .tips(first_seen, 10 * SECS_PER_DAY),
+        )
+    }
+
+    #[test]
+    fn test_welcome_sequence() {
+        let config = Welcome {
+            motd: "Be nice\n".to_owned(),
+            tips: vec!["Say hi".to_owned()],
+            ..Welcome::default()
+        };
+
+        assert_eq!(
+            everything(&config, None),
+            vec![
+                RES_WELCOME,
+                RES_MOTD,
+                RES_YOUR_NICK,
+                RES_TOPIC_CHANGE,
+                RES_COMMAND_LIST,
+                RES_MESSAGE_LIMIT,
+                RES_MENTION_POLICY,
+                RES_NICK_LIST,
+                RES_TIP
+            ]
+        );
+
+        // Only new accounts are given tips
+        assert_eq!(
+            everything(&config, Some(9 * SECS_PER_DAY)).last(),
+            Some(&RES_TIP)
+        );
+        assert_ne!(everything(&config, Some(0)).last(), Some(&RES_TIP));
+
+        let quiet = Welcome {
+            greeting: String::new(),
+            motd: String::new(),
+            topic: false,
+            command_list: false,
+            nick_list: false,
+            tips_for_days: 0,
+            ..config
+        };
+        assert_eq!(
+            everything(&quiet, None),
+            vec![RES_YOUR_NICK, RES_MESSAGE_LIMIT, RES_MENTION_POLICY]
+        );
+    }
+
+    #[test]
+    fn test_motd_from_file() {
+        let path = std::env::temp_dir().join(format!("solace-motd-{}", std::process::id()));
+        fs::write(&path, "From the file\n\n").unwrap();
+
+        let config = Welcome {
+            motd: "From the config".to_owned(),
+            motd_path: Some(path.clone()),
+            ..Welcome::default()
+        };
+        let motd = |config: &Welcome, scheduled: Option<&str>| {
+            WelcomeBuilder::new(config)
+                .motd(scheduled.map(str::to_owned))
+                .build()[1]
+                .message
+                .clone()
+        };
+        assert_eq!(motd(&config, None), "From the file");
+        assert_eq!(motd(&config, Some("Scheduled")), "Scheduled");
+
+        // The config's is sent if the file goes missing
+        fs::remove_file(&path).unwrap();
+        assert_eq!(motd(&config, None), "From the config");
+    }
+}